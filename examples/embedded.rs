@@ -0,0 +1,62 @@
+//! Drives a recipe conversation programmatically, without the shell.
+//!
+//! Assistant text goes to a callback instead of stdout, the model is given
+//! a custom `pantry_contents` tool, and transmitted recipes are kept in
+//! memory.  Only the library's SDK-free surface is used, so this builds
+//! with `--no-default-features` too.
+//!
+//! Example:
+//!     cargo run --example embedded
+use std::sync::Arc;
+
+use recipes::{Config, Conversation, MemorySink, Preferences, ToolDef};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let pantry_tool = ToolDef::new(
+        "pantry_contents",
+        "lists the ingredients the user already has at home",
+    )
+    .optional(
+        "category",
+        "optional category to filter by, e.g. produce or spices",
+    )
+    .handler(|input| match input["category"].as_str() {
+        Some("spices") => Ok("cumin, smoked paprika, oregano".to_string()),
+        Some(category) => Err(format!("no pantry category called {}", category)),
+        None => Ok("rice, canned black beans, onions, garlic, cumin, eggs".to_string()),
+    });
+
+    let sink = Arc::new(MemorySink::new());
+    let config = Config::new()
+        .preferences(Preferences::new().household_size(2).diet("vegetarian"))
+        .tool(pantry_tool)
+        .artifacts(sink.clone());
+    let mut conversation = Conversation::connect(config).await?;
+
+    let on_text = |text: &str| println!("assistant: {}", text);
+    let mut prompt = "I'd like a quick vegetarian dinner using what's in my pantry.".to_string();
+    loop {
+        let reply = conversation.ask(&prompt, on_text).await?;
+        if !reply.tools_used.is_empty() {
+            println!("(used {})", reply.tools_used.join(", "));
+        }
+        if !reply.recipes.is_empty() {
+            break;
+        }
+        // the model asks questions before it transmits; answer them
+        prompt = String::new();
+        if std::io::stdin().read_line(&mut prompt)? == 0 {
+            break;
+        }
+    }
+
+    for recipe in sink.recipes() {
+        println!(
+            "transmitted: {}",
+            recipe.title.as_deref().unwrap_or("untitled")
+        );
+        println!("{}", recipe.details);
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+//! Generates one recipe without a conversation, e.g. for a meal-planning
+//! cron job.
+//!
+//! The constraints are taken from the command line, and the recipe and its
+//! image are saved in the current directory.
+//!
+//! Example:
+//!     cargo run --example oneshot -- a vegetarian dinner with what is in season
+use recipes::awsinit;
+use recipes::oneshot::{self, GenerateRequest};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let client = awsinit::runtime_client(None, None).await?;
+
+    let constraints = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    let request = GenerateRequest::new(oneshot::DEFAULT_MODEL, constraints)
+        .with_image(true)
+        .save_to(".");
+
+    let recipe = oneshot::generate(client, request).await?;
+    if !recipe.from_tool {
+        eprintln!("the model didn't use the tool, so there's no image");
+    }
+    println!("{}", recipe.recipe_details);
+    for file in &recipe.saved {
+        println!("saved {}", file);
+    }
+    Ok(())
+}
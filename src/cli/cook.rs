@@ -0,0 +1,199 @@
+//! Cook mode: step through a recipe's instructions one at a time, entirely offline
+use std::io::{self, BufRead, Write};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use recipes::bigtext;
+use recipes::recipe::{self, Section};
+use recipes::ui;
+
+/// Smallest terminal that [cook_big] will use; anything smaller gets [cook]
+const BIG_MIN_COLUMNS: u16 = 60;
+const BIG_MIN_ROWS: u16 = 20;
+
+/// How a cook mode session ended
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The user made it past the last step
+    Finished,
+    /// The user bailed out with `q`
+    Quit,
+}
+
+/// Walks the user through the recipe's steps.  Enter or `n` moves forward,
+/// `p` moves back, and `q` quits.
+pub fn cook(recipe_text: &str) -> io::Result<Outcome> {
+    let steps = steps(recipe_text)?;
+
+    if let Some(title) = recipe::title(recipe_text) {
+        println!("Cooking: {}", title);
+    }
+    println!("[enter/n] next  [p] previous  [q] quit\n");
+
+    let stdin = io::stdin();
+    let mut idx = 0;
+    while idx < steps.len() {
+        println!("Step {}/{}: {}", idx + 1, steps.len(), steps[idx]);
+        for timer in recipe::extract_timers(&steps[idx]) {
+            println!("  ⏲ timer: {}", timer);
+        }
+        print!("> ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(Outcome::Quit); // EOF
+        }
+        match line.trim() {
+            "" | "n" => idx += 1,
+            "p" => idx = idx.saturating_sub(1),
+            "q" => return Ok(Outcome::Quit),
+            other => println!("unrecognized input: {}", other),
+        }
+    }
+    println!("All done, enjoy!");
+    Ok(Outcome::Finished)
+}
+
+fn steps(recipe_text: &str) -> io::Result<Vec<String>> {
+    let instructions =
+        recipe::section(recipe_text, Section::Instructions).unwrap_or(recipe_text.to_string());
+    let steps = recipe::parse_steps(&instructions);
+    if steps.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "couldn't find any instructions in the recipe",
+        ));
+    }
+    Ok(steps)
+}
+
+/// Like [cook], but each step fills the screen in large type, with the
+/// ingredients it uses underneath, and single keys move between steps:
+/// space, enter, → or `n` forward, ← or `p` back, `q` or esc to quit.
+/// Falls back to [cook] when the terminal is too small.
+pub fn cook_big(recipe_text: &str) -> io::Result<Outcome> {
+    let (columns, rows) = terminal::size().unwrap_or((0, 0));
+    if columns < BIG_MIN_COLUMNS || rows < BIG_MIN_ROWS {
+        println!(
+            "the terminal is too small for large type ({}x{}, need {}x{}), using regular cook mode",
+            columns, rows, BIG_MIN_COLUMNS, BIG_MIN_ROWS
+        );
+        return cook(recipe_text);
+    }
+    let steps = steps(recipe_text)?;
+    let ingredients = recipe::section(recipe_text, Section::Ingredients).unwrap_or_default();
+    let title = recipe::title(recipe_text);
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    // put the terminal back however we leave, panics included
+    let restore = scopeguard::guard((), |_| {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    });
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+
+    let mut idx = 0;
+    let outcome = loop {
+        if idx >= steps.len() {
+            break Outcome::Finished;
+        }
+        draw_step(&mut stdout, title.as_deref(), &steps, idx, &ingredients)?;
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Right | KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Char('n') => {
+                    idx += 1
+                }
+                KeyCode::Left | KeyCode::Char('p') => idx = idx.saturating_sub(1),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Outcome::Quit
+                }
+                KeyCode::Esc | KeyCode::Char('q') => break Outcome::Quit,
+                _ => (),
+            },
+            // resizes and anything else just redraw
+            _ => (),
+        }
+    };
+    drop(restore);
+
+    if outcome == Outcome::Finished {
+        println!("All done, enjoy!");
+    }
+    Ok(outcome)
+}
+
+/// Draws one step centered on the screen, in large type if it fits
+fn draw_step(
+    out: &mut impl Write,
+    title: Option<&str>,
+    steps: &[String],
+    idx: usize,
+    ingredients: &str,
+) -> io::Result<()> {
+    let (columns, rows) = terminal::size()?;
+    let (columns, rows) = (columns as usize, rows as usize);
+    let step = &steps[idx];
+
+    let mut extras = vec![];
+    for line in recipe::ingredients_for_step(ingredients, step) {
+        extras.push(format!("• {}", line));
+    }
+    for timer in recipe::extract_timers(step) {
+        extras.push(format!("⏲ timer: {}", timer));
+    }
+    // header, footer, and a blank row on either side of the step
+    let available = rows.saturating_sub(4 + extras.len());
+    let body = bigtext::render(step, columns.saturating_sub(4))
+        .filter(|big| big.len() <= available)
+        .unwrap_or_else(|| {
+            ui::wrap(step, columns.saturating_sub(4))
+                .lines()
+                .map(str::to_string)
+                .collect()
+        });
+
+    let header = match title {
+        Some(title) => format!("Step {}/{}  ·  {}", idx + 1, steps.len(), title),
+        None => format!("Step {}/{}", idx + 1, steps.len()),
+    };
+    queue!(out, Clear(ClearType::All), MoveTo(0, 0), Print(header))?;
+
+    let body_width = body.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let left = columns.saturating_sub(body_width) / 2;
+    let top = 2 + available.saturating_sub(body.len()) / 2;
+    let blank = String::new();
+    let lines = body
+        .iter()
+        .chain(std::iter::once(&blank))
+        .chain(extras.iter())
+        .take(rows.saturating_sub(top + 1));
+    for (row, line) in lines.enumerate() {
+        queue!(out, MoveTo(left as u16, (top + row) as u16), Print(line))?;
+    }
+
+    queue!(
+        out,
+        MoveTo(0, rows.saturating_sub(1) as u16),
+        Print("[space/→] next  [←] previous  [q] quit")
+    )?;
+    out.flush()
+}
+
+/// Asks for a 1-5 rating, returning `None` if the user skips it
+#[cfg(feature = "history")]
+pub fn ask_rating() -> io::Result<Option<u8>> {
+    print!("How was it? rate 1-5 (enter to skip): ");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line
+        .trim()
+        .parse::<u8>()
+        .ok()
+        .filter(|r| (1..=5).contains(r)))
+}
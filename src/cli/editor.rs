@@ -0,0 +1,51 @@
+//! Compose a prompt in the user's $EDITOR
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(windows)]
+const FALLBACK_EDITOR: &str = "notepad";
+#[cfg(not(windows))]
+const FALLBACK_EDITOR: &str = "vi";
+
+/// Opens `$VISUAL`/`$EDITOR` on a scratch file pre-filled with `initial` and
+/// returns what the user saved.  Returns `None` if the saved file is blank,
+/// which callers should treat as "cancel".
+///
+/// This only runs between readline calls, when rustyline has already put the
+/// terminal back into cooked mode, so the child can take over the tty for as
+/// long as it likes.  We block until it exits before returning to the shell.
+pub fn edit_prompt(initial: &str, scratch_dir: &Path) -> io::Result<Option<String>> {
+    fs::create_dir_all(scratch_dir)?;
+    let path = scratch_dir.join(format!("prompt-{}.txt", std::process::id()));
+    fs::write(&path, initial)?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| FALLBACK_EDITOR.to_string());
+
+    // $EDITOR is allowed to carry arguments, e.g. "code --wait"
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(FALLBACK_EDITOR);
+    let status = Command::new(program).args(words).arg(&path).status();
+
+    let edited = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path),
+        Ok(status) => Err(io::Error::other(format!(
+            "{} exited with {}",
+            editor, status
+        ))),
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&path);
+
+    // notepad saves CRLF
+    let edited = edited?.replace("\r\n", "\n");
+    if edited.trim().is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(edited.trim_end().to_string()))
+    }
+}
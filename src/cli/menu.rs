@@ -0,0 +1,67 @@
+//! Numbered menus for questions the model asks with the ask_user tool
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use recipes::preferences::Preferences;
+
+/// What to answer when nobody is there to choose
+pub const NO_PREFERENCE: &str = "no preference";
+
+/// Shows `question` with numbered `choices` and returns the chosen one.  The
+/// user can also type an answer of their own, or press enter for no
+/// preference.  Without a terminal this answers `unattended` right away,
+/// so that piped input never hangs.
+pub fn choose(question: &str, choices: &[String], unattended: &str) -> io::Result<String> {
+    if !io::stdin().is_terminal() {
+        return Ok(unattended.to_string());
+    }
+    println!("{}", question);
+    for (idx, choice) in choices.iter().enumerate() {
+        println!("  {}) {}", idx + 1, choice);
+    }
+    print!(
+        "choose 1-{} (or type an answer, enter for no preference): ",
+        choices.len()
+    );
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    let answer = line.trim();
+    Ok(match answer.parse::<usize>() {
+        Ok(n) if (1..=choices.len()).contains(&n) => choices[n - 1].clone(),
+        _ if answer.is_empty() => NO_PREFERENCE.to_string(),
+        _ => answer.to_string(),
+    })
+}
+
+/// The choice the saved preferences settle, if any: the first that names
+/// one of the diets, or the only one left once allergies and dislikes are
+/// ruled out
+pub fn preferred(choices: &[String], preferences: &Preferences) -> Option<String> {
+    let names = |choice: &str, items: &[String]| {
+        let choice = format!(" {} ", words(choice));
+        items
+            .iter()
+            .map(|item| words(item))
+            .any(|item| !item.is_empty() && choice.contains(&format!(" {} ", item)))
+    };
+    if let Some(choice) = choices.iter().find(|c| names(c, &preferences.diet)) {
+        return Some(choice.clone());
+    }
+    let mut left = choices
+        .iter()
+        .filter(|c| !names(c, &preferences.allergies) && !names(c, &preferences.dislikes));
+    match (left.next(), left.next()) {
+        (Some(choice), None) if choices.len() > 1 => Some(choice.clone()),
+        _ => None,
+    }
+}
+
+/// `text` lowercased, with single spaces between its words
+fn words(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
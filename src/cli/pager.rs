@@ -0,0 +1,34 @@
+//! Showing long assistant output through `$PAGER`
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Used when `$PAGER` isn't set.  -F exits right away if the text fits.
+#[cfg(not(windows))]
+const DEFAULT_PAGER: &str = "less -FRX";
+#[cfg(windows)]
+const DEFAULT_PAGER: &str = "more";
+
+/// Pipes `text` through the pager and waits for it to exit, so it's done
+/// before the shell prompts again
+pub fn page(text: &str) -> io::Result<()> {
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or(DEFAULT_PAGER.to_string());
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the user may quit before reading everything
+        match stdin.write_all(text.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => (),
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
@@ -0,0 +1,185 @@
+//! Which tools a session may use.
+//!
+//! `tools.json` in the config directory, or `--tools`, lists the tools the
+//! model is offered, e.g. to keep a child's session from saving preferences:
+//!
+//! ```json
+//! {"enabled": ["transmit_recipe", "seasonal_produce"]}
+//! ```
+//!
+//! Without either, every tool is enabled.  Tools that aren't enabled are
+//! left out of the tool configuration, and the CLI refuses any call to them
+//! that the model makes anyway.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use aws_sdk_bedrockruntime::error::BuildError;
+use aws_sdk_bedrockruntime::types::ToolConfiguration;
+use serde::Deserialize;
+
+use crate::toolspec;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct ToolsConfig {
+    enabled: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowList {
+    /// `None` allows every tool
+    enabled: Option<Vec<String>>,
+}
+
+impl AllowList {
+    pub fn all() -> AllowList {
+        AllowList { enabled: None }
+    }
+
+    pub fn only(names: Vec<String>) -> AllowList {
+        AllowList {
+            enabled: Some(names),
+        }
+    }
+
+    /// Loads the `enabled` list from `path`, allowing everything if the
+    /// file doesn't exist or doesn't have one
+    pub fn load(path: &Path) -> io::Result<AllowList> {
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                let config: ToolsConfig = serde_json::from_str(&contents)?;
+                Ok(AllowList {
+                    enabled: config.enabled,
+                })
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(AllowList::all()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn allows(&self, tool: &str) -> bool {
+        self.enabled
+            .as_ref()
+            .is_none_or(|enabled| enabled.iter().any(|name| name == tool))
+    }
+
+    /// The enabled tools of `tools`, or `None` if none are enabled
+    pub fn apply(
+        &self,
+        tools: &ToolConfiguration,
+    ) -> Result<Option<ToolConfiguration>, BuildError> {
+        toolspec::retain(tools, |name| self.allows(name))
+    }
+
+    /// Names in the list that aren't tools in `tools`, probably typos
+    pub fn unknown(&self, tools: &ToolConfiguration) -> Vec<String> {
+        let known = toolspec::names(tools);
+        self.enabled
+            .iter()
+            .flatten()
+            .filter(|name| !known.contains(name))
+            .cloned()
+            .collect()
+    }
+}
+
+/// The error to answer a call to `tool` with when it isn't one of the
+/// `offered` tools, which covers names the model made up, or `None` if it is
+pub fn refusal(offered: &[String], tool: &str) -> Option<String> {
+    if offered.iter().any(|name| name == tool) {
+        return None;
+    }
+    Some(format!(
+        "{} isn't available in this session; the tools are: {}",
+        tool,
+        offered.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_bedrockruntime::types::ContentBlock;
+
+    use super::*;
+    use crate::conversation::Conversation;
+    use crate::scripted::{Reply, Scripted};
+    use crate::tools::{ToolDef, ToolRegistry};
+
+    fn tools() -> ToolConfiguration {
+        ToolRegistry::new()
+            .tool(ToolDef::new("transmit_recipe", "saves a recipe"))
+            .tool(ToolDef::new("seasonal_produce", "what's in season"))
+            .tool(ToolDef::new(
+                "save_preferences",
+                "remembers likes and dislikes",
+            ))
+            .configuration()
+            .unwrap()
+    }
+
+    #[test]
+    fn only_enabled_tools_are_offered() {
+        let allowed = AllowList::only(vec!["transmit_recipe".into(), "seasonal_produce".into()]);
+        let offered = allowed.apply(&tools()).unwrap().unwrap();
+        assert_eq!(
+            toolspec::names(&offered),
+            ["transmit_recipe", "seasonal_produce"]
+        );
+        assert_eq!(
+            toolspec::names(&AllowList::all().apply(&tools()).unwrap().unwrap()),
+            ["transmit_recipe", "seasonal_produce", "save_preferences"]
+        );
+    }
+
+    #[test]
+    fn tools_json_lists_the_enabled_tools() {
+        let path = std::env::temp_dir().join(format!("gourmand-tools-{}.json", ulid::Ulid::new()));
+        assert_eq!(AllowList::load(&path).unwrap(), AllowList::all());
+        fs::write(
+            &path,
+            r#"{"enabled": ["transmit_recipe", "transmit_recipes"]}"#,
+        )
+        .unwrap();
+        let allowed = AllowList::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(allowed.allows("transmit_recipe"));
+        assert!(!allowed.allows("save_preferences"));
+        assert_eq!(allowed.unknown(&tools()), ["transmit_recipes"]);
+    }
+
+    #[test]
+    fn calls_to_tools_that_arent_offered_are_refused() {
+        let offered = vec![
+            "transmit_recipe".to_string(),
+            "seasonal_produce".to_string(),
+        ];
+        assert_eq!(refusal(&offered, "transmit_recipe"), None);
+        assert_eq!(
+            refusal(&offered, "save_preferences").unwrap(),
+            "save_preferences isn't available in this session; \
+             the tools are: transmit_recipe, seasonal_produce"
+        );
+        // a tool that doesn't exist at all
+        assert!(refusal(&offered, "order_groceries").is_some());
+        assert!(refusal(&[], "transmit_recipe").is_some());
+    }
+
+    #[tokio::test]
+    async fn an_empty_list_sends_no_tool_configuration() {
+        let offered = AllowList::only(vec![]).apply(&tools()).unwrap();
+        assert!(offered.is_none());
+
+        let backend = Scripted::new([Reply::text("Try a frittata.")]);
+        let mut conversation = Conversation::builder(backend.client(), "amazon.nova-lite-v1:0")
+            .set_tools(offered)
+            .build();
+        conversation
+            .send(ContentBlock::Text("dinner?".into()))
+            .await
+            .unwrap();
+        let body = backend.last_body();
+        assert!(body.get("toolConfig").is_none(), "{}", body);
+        assert_eq!(body["messages"][0]["content"][0]["text"], "dinner?");
+    }
+}
@@ -0,0 +1,465 @@
+//! Keeping tool results small.
+//!
+//! Everything a tool returns is sent again with every later request, so
+//! results are summaries by default: counts, paths, and titles rather than
+//! whole recipes.  A tool can be given full detail with `--tool-detail
+//! transmit_recipe=full`.  Whatever still comes out over the cap
+//! (`--tool-result-tokens`) is kept here instead, and the model gets a
+//! reference it can follow with the `read_artifact` tool, a range of bytes
+//! at a time.  Saved recipes are registered too, so the model can check
+//! what it wrote without it being echoed back.
+//!
+//! A reference looks like
+//! `{"artifact": "a2", "about": "conversation_recap result", "bytes": 18234,
+//! "note": "..."}`, and reading one returns the text with its `offset`,
+//! `total_bytes`, and the `next_offset` to ask for, or null at the end.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use aws_sdk_bedrockruntime::error::BuildError;
+use aws_sdk_bedrockruntime::types::{ToolResultBlock, ToolResultContentBlock, ToolResultStatus};
+
+use crate::tokens;
+
+pub const TOOL_NAME: &str = "read_artifact";
+
+/// Results estimated at more tokens than this are replaced by a reference
+pub const DEFAULT_CAP_TOKENS: usize = 1000;
+
+/// How much a tool's result says
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DetailLevel {
+    /// Counts, paths, and titles
+    #[default]
+    Summary,
+    /// Everything the tool has, up to the cap
+    Full,
+}
+
+impl fmt::Display for DetailLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DetailLevel::Summary => write!(f, "summary"),
+            DetailLevel::Full => write!(f, "full"),
+        }
+    }
+}
+
+impl FromStr for DetailLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "summary" => Ok(DetailLevel::Summary),
+            "full" => Ok(DetailLevel::Full),
+            _ => Err(format!(
+                "unknown detail level: {} (expected summary or full)",
+                s
+            )),
+        }
+    }
+}
+
+/// `<tool>=<level>`, as given to `--tool-detail`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolDetail {
+    pub tool: String,
+    pub level: DetailLevel,
+}
+
+impl FromStr for ToolDetail {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((tool, level)) = s.split_once('=') else {
+            return Err(format!(
+                "expected <tool>=<level>, e.g. transmit_recipe=full: {}",
+                s
+            ));
+        };
+        Ok(ToolDetail {
+            tool: tool.trim().to_string(),
+            level: level.trim().parse()?,
+        })
+    }
+}
+
+/// How much each tool says, and where results turn into references
+#[derive(Debug, Clone)]
+pub struct ResultPolicy {
+    details: BTreeMap<String, DetailLevel>,
+    cap_tokens: usize,
+}
+
+impl Default for ResultPolicy {
+    fn default() -> Self {
+        ResultPolicy::new(&[], DEFAULT_CAP_TOKENS)
+    }
+}
+
+impl ResultPolicy {
+    pub fn new(details: &[ToolDetail], cap_tokens: usize) -> ResultPolicy {
+        ResultPolicy {
+            details: details.iter().map(|d| (d.tool.clone(), d.level)).collect(),
+            cap_tokens,
+        }
+    }
+
+    pub fn detail(&self, tool: &str) -> DetailLevel {
+        self.details.get(tool).copied().unwrap_or_default()
+    }
+
+    /// The cap in bytes, which is also the most one read returns
+    pub fn cap_bytes(&self) -> usize {
+        (self.cap_tokens as f64 * tokens::CHARS_PER_TOKEN) as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    File(PathBuf),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+struct Artifact {
+    id: String,
+    about: String,
+    source: Source,
+}
+
+impl Artifact {
+    async fn contents(&self) -> Result<String, String> {
+        match &self.source {
+            Source::File(path) => tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| format!("couldn't read {}: {}", self.about, e)),
+            Source::Text(text) => Ok(text.clone()),
+        }
+    }
+}
+
+/// What the model can read back in this session
+#[derive(Debug, Clone, Default)]
+pub struct Artifacts {
+    policy: ResultPolicy,
+    items: Vec<Artifact>,
+}
+
+impl Artifacts {
+    pub fn new(policy: ResultPolicy) -> Artifacts {
+        Artifacts {
+            policy,
+            items: vec![],
+        }
+    }
+
+    pub fn policy(&self) -> &ResultPolicy {
+        &self.policy
+    }
+
+    fn add(&mut self, about: &str, source: Source) -> String {
+        let id = format!("a{}", self.items.len() + 1);
+        self.items.push(Artifact {
+            id: id.clone(),
+            about: about.to_string(),
+            source,
+        });
+        id
+    }
+
+    /// Registers a saved file, returning its id
+    pub fn add_file(&mut self, about: &str, path: PathBuf) -> String {
+        self.add(about, Source::File(path))
+    }
+
+    /// `result`, or a reference to it if its text is over the cap.  Errors,
+    /// results that aren't a single text, and `read_artifact`'s own results
+    /// pass through.
+    pub fn cap(
+        &mut self,
+        tool: &str,
+        result: ToolResultBlock,
+    ) -> Result<ToolResultBlock, BuildError> {
+        let text = match result.content() {
+            [ToolResultContentBlock::Text(text)] => text,
+            _ => return Ok(result),
+        };
+        if tool == TOOL_NAME
+            || result.status() == Some(&ToolResultStatus::Error)
+            || text.len() <= self.policy.cap_bytes()
+        {
+            return Ok(result);
+        }
+        let bytes = text.len();
+        let about = format!("{} result", tool);
+        let id = self.add(&about, Source::Text(text.clone()));
+        let reference = serde_json::json!({
+            "artifact": id,
+            "about": about,
+            "bytes": bytes,
+            "note": format!(
+                "too long to include; read it with {}, up to {} bytes at a time",
+                TOOL_NAME,
+                self.policy.cap_bytes()
+            ),
+        });
+        ToolResultBlock::builder()
+            .tool_use_id(result.tool_use_id())
+            .content(ToolResultContentBlock::Text(reference.to_string()))
+            .build()
+    }
+
+    /// Up to `length` bytes of artifact `id` from `offset`, and never more
+    /// than the cap.  The range is narrowed to character boundaries, but
+    /// always moves forward.
+    pub async fn read(
+        &self,
+        id: &str,
+        offset: usize,
+        length: Option<usize>,
+    ) -> Result<serde_json::Value, String> {
+        let Some(artifact) = self.items.iter().find(|a| a.id == id) else {
+            let ids: Vec<&str> = self.items.iter().map(|a| a.id.as_str()).collect();
+            return Err(match ids.len() {
+                0 => format!("there's no artifact {}, nothing has been saved yet", id),
+                _ => format!("there's no artifact {}; there are {}", id, ids.join(", ")),
+            });
+        };
+        let text = artifact.contents().await?;
+        let total = text.len();
+        if offset > total {
+            return Err(format!(
+                "offset {} is past the end of {} ({} bytes)",
+                offset, id, total
+            ));
+        }
+        let length = length
+            .unwrap_or(usize::MAX)
+            .min(self.policy.cap_bytes())
+            .max(1);
+        let mut start = offset;
+        while !text.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = offset.saturating_add(length).min(total);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        // a range inside one character still returns that character
+        while end <= start && end < total {
+            end += 1;
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+        }
+        Ok(serde_json::json!({
+            "artifact": artifact.id,
+            "about": artifact.about,
+            "offset": start,
+            "bytes": end - start,
+            "total_bytes": total,
+            "next_offset": (end < total).then_some(end),
+            "text": &text[start..end],
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn text_result(id: &str, text: &str) -> ToolResultBlock {
+        ToolResultBlock::builder()
+            .tool_use_id(id)
+            .content(ToolResultContentBlock::Text(text.to_string()))
+            .build()
+            .unwrap()
+    }
+
+    fn text_of(result: &ToolResultBlock) -> &str {
+        match result.content() {
+            [ToolResultContentBlock::Text(text)] => text,
+            other => panic!("not one text: {:?}", other),
+        }
+    }
+
+    /// 10 tokens, so 40 bytes
+    fn small() -> Artifacts {
+        Artifacts::new(ResultPolicy::new(&[], 10))
+    }
+
+    #[test]
+    fn detail_per_tool() {
+        let details: Vec<ToolDetail> = ["transmit_recipe=full", " conversation_recap = Summary "]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let policy = ResultPolicy::new(&details, 250);
+        assert_eq!(policy.detail("transmit_recipe"), DetailLevel::Full);
+        assert_eq!(policy.detail("conversation_recap"), DetailLevel::Summary);
+        assert_eq!(policy.detail("seasonal_produce"), DetailLevel::Summary);
+        assert_eq!(policy.cap_bytes(), 1000);
+        assert!("transmit_recipe".parse::<ToolDetail>().is_err());
+        assert!("transmit_recipe=verbose".parse::<ToolDetail>().is_err());
+    }
+
+    #[test]
+    fn results_over_the_cap_become_references() {
+        let mut artifacts = small();
+        let long = "x".repeat(41);
+        let capped = artifacts
+            .cap("conversation_recap", text_result("tooluse_1", &long))
+            .unwrap();
+        assert_eq!(capped.tool_use_id(), "tooluse_1");
+        let reference: Value = serde_json::from_str(text_of(&capped)).unwrap();
+        assert_eq!(
+            reference,
+            json!({
+                "artifact": "a1",
+                "about": "conversation_recap result",
+                "bytes": 41,
+                "note": "too long to include; read it with read_artifact, up to 40 bytes at a time"
+            })
+        );
+
+        let second = artifacts
+            .cap("seasonal_produce", text_result("tooluse_2", &long))
+            .unwrap();
+        assert!(text_of(&second).contains(r#""artifact":"a2""#));
+    }
+
+    #[test]
+    fn what_fits_or_isnt_text_passes_through() {
+        let mut artifacts = small();
+        let long = "x".repeat(41);
+        let at_cap = text_result("tooluse_1", &"x".repeat(40));
+        let error = ToolResultBlock::builder()
+            .tool_use_id("tooluse_2")
+            .content(ToolResultContentBlock::Text(long.clone()))
+            .status(ToolResultStatus::Error)
+            .build()
+            .unwrap();
+        let json = ToolResultBlock::builder()
+            .tool_use_id("tooluse_3")
+            .content(ToolResultContentBlock::Json(
+                aws_smithy_types::Document::String(long.clone()),
+            ))
+            .build()
+            .unwrap();
+        let read = text_result("tooluse_4", &long);
+        for (tool, result) in [
+            ("conversation_recap", at_cap),
+            ("conversation_recap", error),
+            ("conversation_recap", json),
+            (TOOL_NAME, read),
+        ] {
+            assert_eq!(
+                artifacts.cap(tool, result.clone()).unwrap(),
+                result,
+                "{}",
+                tool
+            );
+        }
+        assert!(artifacts.items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reads_go_a_range_at_a_time() {
+        let mut artifacts = small();
+        let text: String = (0..100).map(|i| char::from(b'a' + i % 26)).collect();
+        artifacts
+            .cap("conversation_recap", text_result("tooluse_1", &text))
+            .unwrap();
+
+        let first = artifacts.read("a1", 0, None).await.unwrap();
+        assert_eq!(
+            first,
+            json!({
+                "artifact": "a1",
+                "about": "conversation_recap result",
+                "offset": 0,
+                "bytes": 40,
+                "total_bytes": 100,
+                "next_offset": 40,
+                "text": &text[..40],
+            })
+        );
+        let mut read = String::new();
+        let mut offset = Some(0);
+        while let Some(at) = offset {
+            let page = artifacts.read("a1", at, Some(25)).await.unwrap();
+            read.push_str(page["text"].as_str().unwrap());
+            offset = page["next_offset"].as_u64().map(|o| o as usize);
+        }
+        assert_eq!(read, text);
+
+        let cases = [
+            // (offset, length, bytes, next_offset)
+            (90, None, 10, None),
+            (10, Some(5), 5, Some(15)),
+            (10, Some(0), 1, Some(11)),
+            (10, Some(1000), 40, Some(50)),
+            (100, None, 0, None),
+        ];
+        for (offset, length, bytes, next) in cases {
+            let page = artifacts.read("a1", offset, length).await.unwrap();
+            assert_eq!(page["offset"], offset, "{} {:?}", offset, length);
+            assert_eq!(page["bytes"], bytes, "{} {:?}", offset, length);
+            assert_eq!(page["next_offset"], json!(next), "{} {:?}", offset, length);
+        }
+        let past = artifacts.read("a1", 101, None).await.unwrap_err();
+        assert_eq!(past, "offset 101 is past the end of a1 (100 bytes)");
+    }
+
+    #[tokio::test]
+    async fn ranges_keep_to_whole_characters() {
+        let mut artifacts = small();
+        // 2 bytes for each accented letter, 4 for the emoji
+        let text = "crème brûlée 🍮 ".repeat(4);
+        artifacts
+            .cap("conversation_recap", text_result("tooluse_1", &text))
+            .unwrap();
+        let cases = [
+            // inside è, so it starts at its first byte
+            (3, Some(3), 2, "ème"),
+            // a range inside 🍮 still returns it
+            (17, Some(1), 16, "🍮"),
+            // and one ending inside è stops before it
+            (0, Some(3), 0, "cr"),
+        ];
+        for (offset, length, start, expected) in cases {
+            let page = artifacts.read("a1", offset, length).await.unwrap();
+            assert_eq!(page["offset"], start, "{}", offset);
+            assert_eq!(page["text"], expected, "{}", offset);
+        }
+    }
+
+    #[tokio::test]
+    async fn saved_files_can_be_read_back() {
+        let path = std::env::temp_dir().join(format!("gourmand-artifact-{}", ulid::Ulid::new()));
+        std::fs::write(&path, "Leek Risotto\n\nIngredients:\n- 2 leeks\n").unwrap();
+        let mut artifacts = Artifacts::default();
+        let err = artifacts.read("a1", 0, None).await.unwrap_err();
+        assert_eq!(err, "there's no artifact a1, nothing has been saved yet");
+
+        let id = artifacts.add_file("the recipe Leek Risotto", path.clone());
+        let page = artifacts.read(&id, 14, Some(12)).await.unwrap();
+        assert_eq!(page["text"], "Ingredients:");
+        assert_eq!(page["next_offset"], 26);
+        let err = artifacts.read("a7", 0, None).await.unwrap_err();
+        assert_eq!(err, "there's no artifact a7; there are a1");
+
+        std::fs::remove_file(&path).unwrap();
+        let err = artifacts.read(&id, 0, None).await.unwrap_err();
+        assert!(
+            err.starts_with("couldn't read the recipe Leek Risotto: "),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! An auditable record of every tool call the model makes.
+//!
+//! Entries are kept small: argument values longer than a few dozen bytes
+//! (image prompts, recipe bodies) are replaced by their length unless the
+//! log is verbose.
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::Serialize;
+
+/// Argument values longer than this are elided from non-verbose entries
+const MAX_TERSE_ARG_LEN: usize = 64;
+
+/// Rotate the log once it grows past this size
+pub const DEFAULT_MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditEntry {
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+    pub session: String,
+    /// The shell turn behind the call, as in the recipe's metadata
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    pub tool: String,
+    pub tool_use_id: String,
+    pub args: BTreeMap<String, String>,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub files: Vec<String>,
+}
+
+impl AuditEntry {
+    pub fn new(session: &str, tool: &str, tool_use_id: &str) -> AuditEntry {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        AuditEntry {
+            timestamp,
+            session: session.to_string(),
+            correlation_id: None,
+            tool: tool.to_string(),
+            tool_use_id: tool_use_id.to_string(),
+            args: BTreeMap::new(),
+            status: Status::Ok,
+            error: None,
+            files: vec![],
+        }
+    }
+
+    pub fn correlation_id(mut self, id: Option<&str>) -> AuditEntry {
+        self.correlation_id = id.map(str::to_string);
+        self
+    }
+
+    pub fn arg(mut self, name: &str, value: &str) -> AuditEntry {
+        self.args.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn files(mut self, files: Vec<String>) -> AuditEntry {
+        self.files = files;
+        self
+    }
+
+    pub fn error(mut self, error: String) -> AuditEntry {
+        self.status = Status::Error;
+        self.error = Some(error);
+        self
+    }
+}
+
+pub trait AuditLog: Debug + Send + Sync {
+    /// Records the entry.  Failures are logged, never propagated.
+    fn record(&self, entry: AuditEntry);
+}
+
+/// Discards everything; the default when no `--audit-log` is given
+#[derive(Debug, Default)]
+pub struct NoopAuditLog;
+
+impl AuditLog for NoopAuditLog {
+    fn record(&self, _entry: AuditEntry) {}
+}
+
+/// Lets sessions that run side by side share one log
+impl<T: AuditLog + ?Sized> AuditLog for Arc<T> {
+    fn record(&self, entry: AuditEntry) {
+        (**self).record(entry)
+    }
+}
+
+/// Appends one JSON object per line, rotating to `<path>.1` when the file gets too big
+#[derive(Debug)]
+pub struct JsonlAuditLog {
+    path: PathBuf,
+    verbose: bool,
+    max_bytes: u64,
+}
+
+impl JsonlAuditLog {
+    pub fn new(path: PathBuf, verbose: bool) -> JsonlAuditLog {
+        JsonlAuditLog {
+            path,
+            verbose,
+            max_bytes: DEFAULT_MAX_LOG_BYTES,
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> JsonlAuditLog {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    fn rotate_if_needed(&self) -> io::Result<()> {
+        match fs::metadata(&self.path) {
+            Ok(meta) if meta.len() >= self.max_bytes => {
+                let mut rotated = self.path.clone().into_os_string();
+                rotated.push(".1");
+                fs::rename(&self.path, rotated)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn append(&self, mut entry: AuditEntry) -> io::Result<()> {
+        if !self.verbose {
+            for value in entry.args.values_mut() {
+                if value.len() > MAX_TERSE_ARG_LEN {
+                    *value = format!("<{} bytes>", value.len());
+                }
+            }
+        }
+        self.rotate_if_needed()?;
+        let line = serde_json::to_string(&entry)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+impl AuditLog for JsonlAuditLog {
+    fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.append(entry) {
+            warn!("couldn't write audit log {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn temp_log(verbose: bool) -> JsonlAuditLog {
+        let path = std::env::temp_dir().join(format!("gourmand-audit-{}.jsonl", ulid::Ulid::new()));
+        JsonlAuditLog::new(path, verbose)
+    }
+
+    fn lines(log: &JsonlAuditLog) -> Vec<Value> {
+        fs::read_to_string(&log.path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn entries_have_a_fixed_shape() {
+        let log = temp_log(false);
+        let recipe = "Rice and Beans\n\nIngredients:\n- 1 cup rice\n- 1 can black beans\n";
+        log.record(
+            AuditEntry::new("01J0SESSION", "transmit_recipe", "tooluse_1")
+                .correlation_id(Some("turn-3"))
+                .arg("title", "Rice and Beans")
+                .arg("recipe_details", &recipe.repeat(2))
+                .files(vec!["rice_and_beans.txt".to_string()]),
+        );
+        log.record(
+            AuditEntry::new("01J0SESSION", "seasonal_produce", "tooluse_2")
+                .error("no season for this locale".to_string()),
+        );
+
+        let mut entries = lines(&log);
+        assert_eq!(entries.len(), 2);
+        for entry in &mut entries {
+            assert!(entry["timestamp"].as_u64().unwrap() > 1_700_000_000);
+            entry.as_object_mut().unwrap().remove("timestamp");
+        }
+        assert_eq!(
+            entries[0],
+            json!({
+                "session": "01J0SESSION",
+                "correlation_id": "turn-3",
+                "tool": "transmit_recipe",
+                "tool_use_id": "tooluse_1",
+                "args": {
+                    "recipe_details": format!("<{} bytes>", recipe.len() * 2),
+                    "title": "Rice and Beans"
+                },
+                "status": "ok",
+                "files": ["rice_and_beans.txt"]
+            })
+        );
+        assert_eq!(
+            entries[1],
+            json!({
+                "session": "01J0SESSION",
+                "tool": "seasonal_produce",
+                "tool_use_id": "tooluse_2",
+                "args": {},
+                "status": "error",
+                "error": "no season for this locale",
+                "files": []
+            })
+        );
+        fs::remove_file(&log.path).unwrap();
+    }
+
+    #[test]
+    fn verbose_entries_keep_long_arguments() {
+        let log = temp_log(true);
+        let prompt = "a rustic bowl of rice and black beans, ".repeat(4);
+        log.record(AuditEntry::new("s", "generate_image", "t").arg("prompt", &prompt));
+        assert_eq!(lines(&log)[0]["args"]["prompt"], prompt.as_str());
+        fs::remove_file(&log.path).unwrap();
+    }
+
+    #[test]
+    fn the_log_rotates_when_full() {
+        let log = temp_log(false).with_max_bytes(200);
+        for n in 0..3 {
+            log.record(AuditEntry::new(
+                "s",
+                "check_pantry",
+                &format!("tooluse_{}", n),
+            ));
+        }
+        let mut rotated = log.path.clone().into_os_string();
+        rotated.push(".1");
+        let rotated = PathBuf::from(rotated);
+        assert!(rotated.exists());
+        assert!(fs::metadata(&log.path).unwrap().len() < 200);
+        fs::remove_file(&log.path).unwrap();
+        fs::remove_file(&rotated).unwrap();
+    }
+}
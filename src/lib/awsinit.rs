@@ -0,0 +1,470 @@
+//! Creating the Bedrock client, with errors a person can act on.
+//!
+//! The SDK resolves a profile lazily, so a misspelled `--aws-profile` or an
+//! SSO login that ran out overnight only shows up as a credentials error on
+//! the first request.  [runtime_client] reads the shared config and
+//! credentials files up front instead: an unknown profile is an error that
+//! lists the ones that do exist, and an SSO profile without a current token
+//! in the SSO cache gets the `aws sso login` command to run.
+//!
+//! The same goes for models: a new account hasn't enabled any, and
+//! [is_access_denied] lets the first request say where to do that instead
+//! of failing with the SDK's error.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use aws_config::{ConfigLoader, Region};
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ConversationRole, InferenceConfiguration, Message,
+};
+use aws_sdk_bedrockruntime::Client;
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+
+/// The shared config and credentials files, and the SSO token cache
+#[derive(Debug, Clone)]
+pub struct AwsFiles {
+    pub config: PathBuf,
+    pub credentials: PathBuf,
+    pub sso_cache: PathBuf,
+}
+
+impl AwsFiles {
+    /// Where the SDK looks, honoring `AWS_CONFIG_FILE` and
+    /// `AWS_SHARED_CREDENTIALS_FILE`
+    pub fn locate() -> AwsFiles {
+        let aws_dir = dirs::home_dir().unwrap_or_default().join(".aws");
+        let from_env = |var: &str, default: &str| {
+            std::env::var_os(var).map_or(aws_dir.join(default), PathBuf::from)
+        };
+        AwsFiles {
+            config: from_env("AWS_CONFIG_FILE", "config"),
+            credentials: from_env("AWS_SHARED_CREDENTIALS_FILE", "credentials"),
+            sso_cache: aws_dir.join("sso").join("cache"),
+        }
+    }
+
+    /// The profiles defined in either file, by name
+    pub fn profiles(&self) -> BTreeMap<String, Profile> {
+        let mut profiles = BTreeMap::new();
+        let config = read_ini(&self.config);
+        for (section, keys) in &config {
+            let name = match section.strip_prefix("profile ") {
+                Some(name) => name.trim(),
+                None if section == "default" => "default",
+                None => continue,
+            };
+            let sso_start_url = keys.get("sso_start_url").cloned().or_else(|| {
+                let session = keys.get("sso_session")?;
+                config
+                    .get(&format!("sso-session {}", session))?
+                    .get("sso_start_url")
+                    .cloned()
+            });
+            profiles.insert(
+                name.to_string(),
+                Profile {
+                    name: name.to_string(),
+                    sso_start_url,
+                },
+            );
+        }
+        // the credentials file has no "profile " prefix
+        for section in read_ini(&self.credentials).keys() {
+            profiles
+                .entry(section.trim().to_string())
+                .or_insert_with(|| Profile {
+                    name: section.trim().to_string(),
+                    sso_start_url: None,
+                });
+        }
+        profiles
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    /// Set for SSO profiles, directly or through an `sso-session` section
+    pub sso_start_url: Option<String>,
+}
+
+/// Sections of an INI file and their keys, or nothing if it can't be read
+fn read_ini(path: &Path) -> BTreeMap<String, BTreeMap<String, String>> {
+    let mut sections = BTreeMap::new();
+    let Ok(contents) = fs::read_to_string(path) else {
+        return sections;
+    };
+    let mut current: Option<String> = None;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let section = section.trim().to_string();
+            sections
+                .entry(section.clone())
+                .or_insert_with(BTreeMap::new);
+            current = Some(section);
+            continue;
+        }
+        let (Some(section), Some((key, value))) = (&current, line.split_once('=')) else {
+            continue;
+        };
+        sections
+            .entry(section.clone())
+            .or_insert_with(BTreeMap::new)
+            .insert(key.trim().to_string(), value.trim().to_string());
+    }
+    sections
+}
+
+/// Whether the SSO cache holds a token for `start_url` that hasn't expired.
+/// Tokens are matched by their `startUrl` rather than by file name, which
+/// is a hash that differs between `sso_session` and legacy profiles.
+pub fn sso_token_valid(sso_cache: &Path, start_url: &str, now: DateTime<Utc>) -> bool {
+    let Ok(entries) = fs::read_dir(sso_cache) else {
+        return false;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| fs::read_to_string(e.path()).ok())
+        .filter_map(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        .filter(|token| {
+            token
+                .get("startUrl")
+                .and_then(|u| u.as_str())
+                .map(|u| u.trim_end_matches('/'))
+                == Some(start_url.trim_end_matches('/'))
+        })
+        .filter_map(|token| {
+            let expires = token.get("expiresAt")?.as_str()?;
+            DateTime::parse_from_rfc3339(expires).ok()
+        })
+        .any(|expires| expires > now)
+}
+
+/// The command that refreshes an SSO login
+pub fn sso_login_command(profile: &str) -> String {
+    format!("aws sso login --profile {}", profile)
+}
+
+/// Whether an SDK error (its debug form, which includes the cause) means
+/// the SSO token is missing or expired
+pub fn is_sso_expired(error: &str) -> bool {
+    let error = error.to_lowercase();
+    (error.contains("sso") || error.contains("token"))
+        && (error.contains("expired")
+            || error.contains("unauthorizedexception")
+            || error.contains("invalidgrant")
+            || error.contains("no token"))
+}
+
+/// A model most accounts can use without asking, to suggest when the
+/// default isn't enabled
+pub const FALLBACK_MODEL: &str = "us.amazon.nova-lite-v1:0";
+
+/// Whether an SDK error (its debug form) means the account can't use the
+/// model, typically because access was never requested
+pub fn is_access_denied(error: &str) -> bool {
+    let error = error.to_lowercase();
+    error.contains("accessdeniedexception") || error.contains("don't have access to the model")
+}
+
+/// Where model access is requested in the Bedrock console
+pub fn model_access_url(region: &str) -> String {
+    format!(
+        "https://{0}.console.aws.amazon.com/bedrock/home?region={0}#/modelaccess",
+        region
+    )
+}
+
+/// Whether `model` answers a one-token request, which costs next to nothing
+pub async fn model_accessible(client: &Client, model: &str) -> bool {
+    let Ok(message) = Message::builder()
+        .role(ConversationRole::User)
+        .content(ContentBlock::Text("hi".to_string()))
+        .build()
+    else {
+        return false;
+    };
+    let result = client
+        .converse()
+        .model_id(model)
+        .messages(message)
+        .inference_config(InferenceConfiguration::builder().max_tokens(1).build())
+        .send()
+        .await;
+    debug!("{} accessible: {:?}", model, result.as_ref().map(|_| ()));
+    result.is_ok()
+}
+
+#[derive(Debug)]
+pub enum AwsInitError {
+    /// The profile isn't in the config or credentials file
+    UnknownProfile {
+        name: String,
+        available: Vec<String>,
+    },
+}
+
+impl fmt::Display for AwsInitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AwsInitError::UnknownProfile { name, available } if available.is_empty() => write!(
+                f,
+                "no aws profile named {}, and no profiles are configured",
+                name
+            ),
+            AwsInitError::UnknownProfile { name, available } => write!(
+                f,
+                "no aws profile named {}, the configured ones are: {}",
+                name,
+                available.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AwsInitError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileStatus {
+    Ready,
+    /// An SSO profile without a current token in the cache
+    SsoLoginNeeded,
+}
+
+/// Whether `name` is a profile in `files`, and if it's an SSO profile,
+/// whether it has a token that's still good at `now`
+pub fn check_profile(
+    files: &AwsFiles,
+    name: &str,
+    now: DateTime<Utc>,
+) -> Result<ProfileStatus, AwsInitError> {
+    let profiles = files.profiles();
+    debug!("aws profiles: {:?}", profiles.keys());
+    let Some(found) = profiles.get(name) else {
+        return Err(AwsInitError::UnknownProfile {
+            name: name.to_string(),
+            available: profiles.into_keys().collect(),
+        });
+    };
+    match &found.sso_start_url {
+        Some(start_url) if !sso_token_valid(&files.sso_cache, start_url, now) => {
+            Ok(ProfileStatus::SsoLoginNeeded)
+        }
+        _ => Ok(ProfileStatus::Ready),
+    }
+}
+
+/// The SDK's config loader, with `profile` and `region` in place of what
+/// its default chain would find
+pub fn loader(profile: Option<&str>, region: Option<&str>) -> ConfigLoader {
+    let mut loader = aws_config::from_env();
+    if let Some(name) = profile {
+        loader = loader.profile_name(name);
+    }
+    if let Some(region) = region {
+        loader = loader.region(Region::new(region.to_string()));
+    }
+    loader
+}
+
+/// A Bedrock runtime client from the SDK's default chain, or `profile`
+/// after checking that it exists.  An SSO profile without a current login
+/// is only warned about, since the cache may be somewhere this doesn't
+/// look.
+pub async fn runtime_client(
+    profile: Option<String>,
+    region: Option<String>,
+) -> Result<Client, AwsInitError> {
+    if let Some(name) = &profile {
+        let files = AwsFiles::locate();
+        if check_profile(&files, name, Utc::now())? == ProfileStatus::SsoLoginNeeded {
+            warn!(
+                "the sso login for {} has expired or is missing, run: {}",
+                name,
+                sso_login_command(name)
+            );
+        }
+    }
+    let config = loader(profile.as_deref(), region.as_deref()).load().await;
+    Ok(Client::new(&config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+[default]
+region = us-east-1
+
+# a legacy sso profile
+[profile family]
+sso_start_url = https://family.awsapps.com/start
+sso_region = us-east-1
+
+[profile work]
+sso_session = corp
+sso_account_id = 111122223333
+
+[sso-session corp]
+sso_start_url = https://corp.awsapps.com/start/
+sso_region = us-west-2
+
+[profile  spaced ]
+region = eu-west-1
+
+[services local]
+bedrock-runtime =
+";
+
+    const CREDENTIALS: &str = "\
+[default]
+aws_access_key_id = AKIDEXAMPLE
+
+[ci]
+aws_access_key_id = AKIDCI
+aws_secret_access_key = secret
+";
+
+    /// Config files under a new temp directory, with a cached SSO token
+    /// for each of `tokens` (start url, expiry)
+    fn files(config: &str, credentials: &str, tokens: &[(&str, &str)]) -> AwsFiles {
+        let dir = std::env::temp_dir().join(format!("gourmand-aws-{}", ulid::Ulid::new()));
+        let files = AwsFiles {
+            config: dir.join("config"),
+            credentials: dir.join("credentials"),
+            sso_cache: dir.join("sso").join("cache"),
+        };
+        fs::create_dir_all(&files.sso_cache).unwrap();
+        fs::write(&files.config, config).unwrap();
+        fs::write(&files.credentials, credentials).unwrap();
+        for (i, (start_url, expires)) in tokens.iter().enumerate() {
+            let token = serde_json::json!({
+                "startUrl": start_url,
+                "region": "us-east-1",
+                "accessToken": "token",
+                "expiresAt": expires,
+            });
+            fs::write(
+                files.sso_cache.join(format!("{:040x}.json", i)),
+                token.to_string(),
+            )
+            .unwrap();
+        }
+        files
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-10-16T12:00:00Z")
+            .unwrap()
+            .to_utc()
+    }
+
+    #[test]
+    fn lists_profiles_from_both_files() {
+        let files = files(CONFIG, CREDENTIALS, &[]);
+        let profiles = files.profiles();
+        assert_eq!(
+            profiles.keys().collect::<Vec<_>>(),
+            ["ci", "default", "family", "spaced", "work"]
+        );
+        assert_eq!(
+            profiles["family"].sso_start_url.as_deref(),
+            Some("https://family.awsapps.com/start")
+        );
+        assert_eq!(
+            profiles["work"].sso_start_url.as_deref(),
+            Some("https://corp.awsapps.com/start/"),
+            "through the sso-session section"
+        );
+        assert_eq!(profiles["default"].sso_start_url, None);
+        assert_eq!(profiles["ci"].sso_start_url, None);
+    }
+
+    #[test]
+    fn an_unknown_profile_lists_the_others() {
+        let files = files(CONFIG, CREDENTIALS, &[]);
+        let error = check_profile(&files, "famliy", now()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no aws profile named famliy, the configured ones are: ci, default, family, spaced, work"
+        );
+
+        let files = self::files("", "", &[]);
+        let error = check_profile(&files, "default", now()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no aws profile named default, and no profiles are configured"
+        );
+    }
+
+    #[test]
+    fn sso_profiles_need_a_current_token() {
+        let files = files(
+            CONFIG,
+            CREDENTIALS,
+            &[
+                // matched without the trailing slash
+                ("https://corp.awsapps.com/start", "2026-10-16T20:00:00Z"),
+                ("https://family.awsapps.com/start", "2026-10-15T20:00:00Z"),
+            ],
+        );
+        for (name, status) in [
+            ("default", ProfileStatus::Ready),
+            ("ci", ProfileStatus::Ready),
+            ("work", ProfileStatus::Ready),
+            ("family", ProfileStatus::SsoLoginNeeded),
+        ] {
+            assert_eq!(
+                check_profile(&files, name, now()).unwrap(),
+                status,
+                "{}",
+                name
+            );
+        }
+        let files = self::files(CONFIG, CREDENTIALS, &[]);
+        assert_eq!(
+            check_profile(&files, "work", now()).unwrap(),
+            ProfileStatus::SsoLoginNeeded,
+            "no token cached at all"
+        );
+    }
+
+    #[test]
+    fn recognizes_expired_sso_and_denied_access() {
+        for (error, expired, denied) in [
+            (
+                "DispatchFailure { source: ConnectorError { kind: Other, source: \
+                 TokenError { kind: ExpiredToken, message: \"the SSO session has expired\" } } }",
+                true,
+                false,
+            ),
+            (
+                "ProviderError(\"failed to load SSO token: no token found\")",
+                true,
+                false,
+            ),
+            (
+                "ServiceError { source: AccessDeniedException { message: \"You don't have \
+                 access to the model with the specified model ID.\" } }",
+                false,
+                true,
+            ),
+            ("ServiceError { source: ThrottlingException }", false, false),
+        ] {
+            assert_eq!(is_sso_expired(error), expired, "{}", error);
+            assert_eq!(is_access_denied(error), denied, "{}", error);
+        }
+        assert_eq!(
+            sso_login_command("family"),
+            "aws sso login --profile family"
+        );
+    }
+}
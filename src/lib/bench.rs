@@ -0,0 +1,209 @@
+//! A quick comparison of models on this workload.
+//!
+//! Every model gets the same short scenario through [crate::oneshot]: the
+//! saved-preferences step is a fixed [Preferences], the choice between
+//! recipes is left to the model, and the recipe has to come back through
+//! `transmit_recipe`, without an image.  The result is checked with the
+//! [crate::eval] assertions, so a model is compared on whether it used the
+//! tool, whether the recipe parses into its sections, and whether it kept to
+//! the prep and cook times, as well as on tokens, latency, and cost.
+use std::fmt::Write as _;
+use std::time::Instant;
+
+use aws_sdk_bedrockruntime::Client;
+
+use crate::cost;
+use crate::eval::{self, Assertions, Transcript};
+use crate::oneshot::{self, GenerateRequest};
+use crate::preferences::Preferences;
+
+pub const MAX_PREP_MINUTES: u32 = 20;
+pub const MAX_COOK_MINUTES: u32 = 30;
+
+/// What every model is asked for
+pub fn request(model: &str) -> GenerateRequest {
+    let constraints = format!(
+        "Dinner for two tonight: pick whichever of your ideas suits best and transmit it \
+         straight away.  At most {} minutes of prep and {} minutes of cooking, with both \
+         times stated in the recipe.",
+        MAX_PREP_MINUTES, MAX_COOK_MINUTES
+    );
+    GenerateRequest::new(model, constraints).preferences(Some(
+        Preferences::new()
+            .household_size(2)
+            .diet("vegetarian")
+            .dislike("mushrooms"),
+    ))
+}
+
+fn assertions() -> Assertions {
+    Assertions {
+        transmitted: Some(true),
+        max_prep_minutes: Some(MAX_PREP_MINUTES),
+        max_cook_minutes: Some(MAX_COOK_MINUTES),
+        forbidden_ingredients: vec!["mushroom".to_string()],
+        max_turns_before_transmit: None,
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BenchResult {
+    pub model: String,
+    /// The recipe came back through the tool rather than in the text
+    pub tool_call: bool,
+    /// The recipe has ingredients and instructions the parser can find
+    pub sections: bool,
+    pub transcript: Transcript,
+    pub assertions: Vec<eval::AssertionResult>,
+    /// `None` if the model isn't in [crate::cost]'s table
+    pub cost: Option<f64>,
+}
+
+impl BenchResult {
+    fn passed(&self, name: &str) -> bool {
+        self.assertions.iter().any(|a| a.name == name && a.passed)
+    }
+
+    /// Kept to both the prep and the cook time
+    pub fn compliant(&self) -> bool {
+        self.passed("max_prep_minutes") && self.passed("max_cook_minutes")
+    }
+
+    pub fn passed_all(&self) -> bool {
+        self.transcript.error.is_none()
+            && self.tool_call
+            && self.sections
+            && self.assertions.iter().all(|a| a.passed)
+    }
+}
+
+/// Runs the scenario against `model`.  Failures are recorded in the
+/// result rather than returned, so one model can't stop the others.
+pub async fn run_model(client: &Client, model: &str) -> BenchResult {
+    let started = Instant::now();
+    let generated = oneshot::generate(client.clone(), request(model)).await;
+    let latency = started.elapsed();
+    let mut result = BenchResult {
+        model: model.to_string(),
+        ..BenchResult::default()
+    };
+    let transcript = match generated {
+        Ok(generated) => {
+            let parsed = crate::parse::sections(&generated.recipe_details);
+            result.sections = parsed.ingredients.is_some() && parsed.instructions.is_some();
+            result.tool_call = generated.from_tool;
+            let usage = generated.usage.unwrap_or_default();
+            Transcript {
+                recipe: Some(generated.recipe_details),
+                turns_before_transmit: Some(1),
+                input_tokens: usage.input_tokens as i64,
+                output_tokens: usage.output_tokens as i64,
+                latency,
+                error: None,
+            }
+        }
+        Err(e) => Transcript {
+            latency,
+            error: Some(e.to_string().replace(['\n', ','], " ")),
+            ..Transcript::default()
+        },
+    };
+    result.cost = cost::price(model).map(|p| {
+        p.cost(
+            transcript.input_tokens.max(0) as u64,
+            transcript.output_tokens.max(0) as u64,
+        )
+    });
+    result.assertions = eval::evaluate(&assertions(), &transcript);
+    result.transcript = transcript;
+    result
+}
+
+/// The estimated cost of the results so far, counting unpriced models as free
+pub fn spent(results: &[BenchResult]) -> f64 {
+    results.iter().filter_map(|r| r.cost).sum()
+}
+
+fn yes_no(b: bool) -> &'static str {
+    if b {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn minutes(m: Option<u32>) -> String {
+    m.map_or("-".to_string(), |m| m.to_string())
+}
+
+fn dollars(amount: Option<f64>) -> String {
+    amount.map_or("?".to_string(), cost::format_dollars)
+}
+
+/// One row per model, lined up for the terminal, with errors underneath
+pub fn table(results: &[BenchResult]) -> String {
+    let width = results
+        .iter()
+        .map(|r| r.model.len())
+        .max()
+        .unwrap_or(0)
+        .max("model".len());
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:width$}  tool  sections  prep  cook  compliant  tokens  latency  cost",
+        "model"
+    );
+    for r in results {
+        let recipe = r.transcript.recipe.as_deref();
+        let _ = writeln!(
+            out,
+            "{:width$}  {:4}  {:8}  {:>4}  {:>4}  {:9}  {:>6}  {:>6.1}s  {}",
+            r.model,
+            yes_no(r.tool_call),
+            yes_no(r.sections),
+            minutes(recipe.and_then(eval::prep_minutes)),
+            minutes(recipe.and_then(eval::cook_minutes)),
+            yes_no(r.compliant()),
+            r.transcript.input_tokens + r.transcript.output_tokens,
+            r.transcript.latency.as_secs_f64(),
+            dollars(r.cost),
+        );
+    }
+    for r in results {
+        if let Some(e) = &r.transcript.error {
+            let _ = writeln!(out, "{}: error: {}", r.model, e);
+        }
+    }
+    out
+}
+
+pub fn to_csv(results: &[BenchResult]) -> String {
+    let mut out = String::from(
+        "model,tool_call,sections,prep_minutes,cook_minutes,compliant,input_tokens,\
+         output_tokens,latency_ms,cost,error\n",
+    );
+    for r in results {
+        let recipe = r.transcript.recipe.as_deref();
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            r.model,
+            r.tool_call,
+            r.sections,
+            recipe
+                .and_then(eval::prep_minutes)
+                .map_or(String::new(), |m| m.to_string()),
+            recipe
+                .and_then(eval::cook_minutes)
+                .map_or(String::new(), |m| m.to_string()),
+            r.compliant(),
+            r.transcript.input_tokens,
+            r.transcript.output_tokens,
+            r.transcript.latency.as_millis(),
+            r.cost.map_or(String::new(), |c| format!("{:.6}", c)),
+            r.transcript.error.as_deref().unwrap_or(""),
+        );
+    }
+    out
+}
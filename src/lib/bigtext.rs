@@ -0,0 +1,125 @@
+//! Large block letters for reading a recipe step from across the kitchen.
+//!
+//! A small built-in 5x5 font, so there's no dependency on figlet or its
+//! font files.  Letters are upper-cased; anything the font doesn't know is
+//! drawn as a blank.
+
+/// Rows in every glyph
+pub const HEIGHT: usize = 5;
+
+/// Blank columns between glyphs
+const SPACING: usize = 1;
+
+fn glyph(c: char) -> [&'static str; HEIGHT] {
+    match c.to_ascii_uppercase() {
+        'A' => [" ### ", "#   #", "#####", "#   #", "#   #"],
+        'B' => ["#### ", "#   #", "#### ", "#   #", "#### "],
+        'C' => [" ####", "#    ", "#    ", "#    ", " ####"],
+        'D' => ["#### ", "#   #", "#   #", "#   #", "#### "],
+        'E' => ["#####", "#    ", "#### ", "#    ", "#####"],
+        'F' => ["#####", "#    ", "#### ", "#    ", "#    "],
+        'G' => [" ####", "#    ", "#  ##", "#   #", " ####"],
+        'H' => ["#   #", "#   #", "#####", "#   #", "#   #"],
+        'I' => ["###", " # ", " # ", " # ", "###"],
+        'J' => ["  ###", "   # ", "   # ", "#  # ", " ##  "],
+        'K' => ["#   #", "#  # ", "###  ", "#  # ", "#   #"],
+        'L' => ["#    ", "#    ", "#    ", "#    ", "#####"],
+        'M' => ["#   #", "## ##", "# # #", "#   #", "#   #"],
+        'N' => ["#   #", "##  #", "# # #", "#  ##", "#   #"],
+        'O' => [" ### ", "#   #", "#   #", "#   #", " ### "],
+        'P' => ["#### ", "#   #", "#### ", "#    ", "#    "],
+        'Q' => [" ### ", "#   #", "# # #", "#  # ", " ## #"],
+        'R' => ["#### ", "#   #", "#### ", "#  # ", "#   #"],
+        'S' => [" ####", "#    ", " ### ", "    #", "#### "],
+        'T' => ["#####", "  #  ", "  #  ", "  #  ", "  #  "],
+        'U' => ["#   #", "#   #", "#   #", "#   #", " ### "],
+        'V' => ["#   #", "#   #", "#   #", " # # ", "  #  "],
+        'W' => ["#   #", "#   #", "# # #", "## ##", "#   #"],
+        'X' => ["#   #", " # # ", "  #  ", " # # ", "#   #"],
+        'Y' => ["#   #", " # # ", "  #  ", "  #  ", "  #  "],
+        'Z' => ["#####", "   # ", "  #  ", " #   ", "#####"],
+        '0' => [" ### ", "#  ##", "# # #", "##  #", " ### "],
+        '1' => [" # ", "## ", " # ", " # ", "###"],
+        '2' => [" ### ", "#   #", "  ## ", " #   ", "#####"],
+        '3' => ["#### ", "    #", " ### ", "    #", "#### "],
+        '4' => ["#   #", "#   #", "#####", "    #", "    #"],
+        '5' => ["#####", "#    ", "#### ", "    #", "#### "],
+        '6' => [" ### ", "#    ", "#### ", "#   #", " ### "],
+        '7' => ["#####", "   # ", "  #  ", " #   ", " #   "],
+        '8' => [" ### ", "#   #", " ### ", "#   #", " ### "],
+        '9' => [" ### ", "#   #", " ####", "    #", " ### "],
+        '.' => [" ", " ", " ", " ", "#"],
+        ',' => ["  ", "  ", "  ", " #", "# "],
+        ':' => [" ", "#", " ", "#", " "],
+        ';' => ["  ", " #", "  ", " #", "# "],
+        '!' => ["#", "#", "#", " ", "#"],
+        '?' => [" ### ", "#   #", "  ## ", "     ", "  #  "],
+        '\'' => ["#", "#", " ", " ", " "],
+        '"' => ["# #", "# #", "   ", "   ", "   "],
+        '-' => ["    ", "    ", "####", "    ", "    "],
+        '+' => ["     ", "  #  ", "#####", "  #  ", "     "],
+        '/' => ["    #", "   # ", "  #  ", " #   ", "#    "],
+        '(' => [" #", "# ", "# ", "# ", " #"],
+        ')' => ["# ", " #", " #", " #", "# "],
+        '%' => ["#   #", "   # ", "  #  ", " #   ", "#   #"],
+        '&' => [" ##  ", "#  # ", " ##  ", "#  # ", " ## #"],
+        '°' => [" # ", "# #", " # ", "   ", "   "],
+        _ => ["   ", "   ", "   ", "   ", "   "],
+    }
+}
+
+fn glyph_width(c: char) -> usize {
+    glyph(c)[0].chars().count()
+}
+
+/// Columns `word` takes up, without trailing spacing
+pub fn width(word: &str) -> usize {
+    let glyphs: usize = word.chars().map(glyph_width).sum();
+    glyphs + word.chars().count().saturating_sub(1) * SPACING
+}
+
+/// Draws one line of text, `HEIGHT` rows tall
+fn draw(line: &str) -> Vec<String> {
+    let mut rows = vec![String::new(); HEIGHT];
+    for (idx, c) in line.chars().enumerate() {
+        for (row, pattern) in rows.iter_mut().zip(glyph(c)) {
+            if idx > 0 {
+                row.push_str(&" ".repeat(SPACING));
+            }
+            row.extend(pattern.chars().map(|p| if p == '#' { '█' } else { ' ' }));
+        }
+    }
+    rows.iter().map(|row| row.trim_end().to_string()).collect()
+}
+
+/// Renders `text` in large letters, word-wrapped to `columns`, with a blank
+/// row between lines.  Returns `None` if a single word is too wide to fit.
+pub fn render(text: &str, columns: usize) -> Option<Vec<String>> {
+    let space = glyph_width(' ') + 2 * SPACING;
+    let mut lines: Vec<String> = vec![];
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        if width(word) > columns {
+            return None;
+        }
+        if !current.is_empty() && width(&current) + space + width(word) > columns {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    let mut rows = vec![];
+    for (idx, line) in lines.iter().enumerate() {
+        if idx > 0 {
+            rows.push(String::new());
+        }
+        rows.extend(draw(line));
+    }
+    Some(rows)
+}
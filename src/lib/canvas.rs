@@ -0,0 +1,173 @@
+//! Text to image with Nova Canvas.
+//!
+//! Called with InvokeModel directly so that the seed is ours: it's chosen
+//! here, recorded in the recipe's metadata, and given again by `reproduce`
+//! to get the same image back.
+use aws_sdk_bedrockruntime::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_bedrockruntime::operation::RequestId;
+use aws_sdk_bedrockruntime::primitives::Blob;
+use aws_sdk_bedrockruntime::Client;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::error::GourmandError;
+use crate::metadata::IMAGE_MODEL;
+
+/// The largest seed Canvas accepts
+pub const MAX_SEED: u64 = 858_993_459;
+
+const IMAGE_SIZE: u32 = 1024;
+
+/// A seed for an image that doesn't have to match an earlier one
+pub fn random_seed() -> u64 {
+    (Ulid::new().random() % (u128::from(MAX_SEED) + 1)) as u64
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    task_type: &'static str,
+    text_to_image_params: TextToImageParams<'a>,
+    image_generation_config: ImageGenerationConfig,
+}
+
+#[derive(Serialize, Debug)]
+struct TextToImageParams<'a> {
+    text: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ImageGenerationConfig {
+    number_of_images: u32,
+    width: u32,
+    height: u32,
+    seed: u64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Response {
+    images: Vec<String>,
+    error: Option<String>,
+}
+
+/// The InvokeModel body for one square image of `prompt`
+pub fn request_body(prompt: &str, seed: u64) -> Result<Vec<u8>, serde_json::Error> {
+    serde_json::to_vec(&Request {
+        task_type: "TEXT_IMAGE",
+        text_to_image_params: TextToImageParams { text: prompt },
+        image_generation_config: ImageGenerationConfig {
+            number_of_images: 1,
+            width: IMAGE_SIZE,
+            height: IMAGE_SIZE,
+            seed: seed.min(MAX_SEED),
+        },
+    })
+}
+
+/// What Canvas made of a prompt
+#[derive(Debug, Clone, Default)]
+pub struct Generated {
+    /// Base64, as Canvas returns them; empty when a content filter blocked
+    /// the prompt or the image
+    pub images: Vec<String>,
+    pub seed: u64,
+    /// Bedrock's id for the request, for AWS support
+    pub request_id: Option<String>,
+}
+
+/// Whether Canvas refused because of its content filters, which is an
+/// answer rather than a failure
+fn is_blocked(message: &str) -> bool {
+    message.contains("content filters")
+}
+
+pub async fn text_to_image(
+    client: &Client,
+    prompt: &str,
+    seed: u64,
+) -> Result<Generated, GourmandError> {
+    let body = request_body(prompt, seed).map_err(|e| GourmandError::ImageGen(e.to_string()))?;
+    let result = client
+        .invoke_model()
+        .model_id(IMAGE_MODEL)
+        .content_type("application/json")
+        .accept("application/json")
+        .body(Blob::new(body))
+        .send()
+        .await;
+    let output = match result {
+        Ok(output) => output,
+        Err(e) => {
+            let request_id = e.request_id().map(str::to_string);
+            if let SdkError::ServiceError(service) = &e {
+                if service.err().message().is_some_and(is_blocked) {
+                    return Ok(Generated {
+                        images: vec![],
+                        seed,
+                        request_id,
+                    });
+                }
+            }
+            return Err(GourmandError::aws(e));
+        }
+    };
+    let request_id = output.request_id().map(str::to_string);
+    let response: Response = serde_json::from_slice(output.body().as_ref())
+        .map_err(|e| GourmandError::ImageGen(format!("unreadable response: {}", e)))?;
+    match response.error {
+        Some(error) if !is_blocked(&error) => Err(GourmandError::ImageGen(error)),
+        _ => Ok(Generated {
+            images: response.images,
+            seed,
+            request_id,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn request_carries_the_seed() {
+        let body = request_body("a bowl of ramen", 42).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "taskType": "TEXT_IMAGE",
+                "textToImageParams": {"text": "a bowl of ramen"},
+                "imageGenerationConfig": {
+                    "numberOfImages": 1,
+                    "width": 1024,
+                    "height": 1024,
+                    "seed": 42,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn seeds_stay_in_range() {
+        for _ in 0..100 {
+            assert!(random_seed() <= MAX_SEED);
+        }
+        let body = request_body("toast", u64::MAX).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["imageGenerationConfig"]["seed"], json!(MAX_SEED));
+    }
+
+    #[test]
+    fn responses_without_images_read() {
+        let response: Response = serde_json::from_str(r#"{"images": []}"#).unwrap();
+        assert!(response.images.is_empty());
+        assert_eq!(response.error, None);
+        assert!(is_blocked(
+            "This request has been blocked by our content filters."
+        ));
+    }
+}
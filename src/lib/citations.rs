@@ -0,0 +1,390 @@
+//! Citations from document-grounded answers.
+//!
+//! When a document is attached with citations enabled, the model answers
+//! with `citationsContent` blocks: pieces of generated text, each with the
+//! passages of the document that support it.  [CitedText] is that block in
+//! a form of our own (the SDK's isn't serializable, and the same shape is
+//! what Converse sends on the wire, so fixtures can be pasted from a
+//! response), and [Sources] numbers the cited passages so the text can
+//! carry `[1]` markers and end with a numbered "Sources" footer.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use aws_sdk_bedrockruntime::primitives::Blob;
+use aws_sdk_bedrockruntime::types::{
+    CitationsConfig, CitationsContentBlock, ContentBlock, DocumentBlock, DocumentFormat,
+    DocumentSource,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::GourmandError;
+
+/// How much of a cited passage is shown
+pub const SNIPPET_CHARS: usize = 160;
+
+/// A piece of the answer and the passages it was drawn from
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CitedText {
+    pub content: Vec<TextPart>,
+    pub citations: Vec<Citation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct TextPart {
+    pub text: String,
+}
+
+/// One cited passage.  Where in the document it was (`location`) isn't
+/// kept: the title and the passage are what a reader can use.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Citation {
+    pub title: Option<String>,
+    pub source_content: Vec<TextPart>,
+}
+
+impl CitedText {
+    /// The generated text, without markers
+    pub fn text(&self) -> String {
+        self.content.iter().map(|part| part.text.as_str()).collect()
+    }
+}
+
+impl From<&CitationsContentBlock> for CitedText {
+    fn from(block: &CitationsContentBlock) -> CitedText {
+        CitedText {
+            content: block
+                .content()
+                .iter()
+                .filter_map(|c| c.as_text().ok())
+                .map(|text| TextPart { text: text.clone() })
+                .collect(),
+            citations: block
+                .citations()
+                .iter()
+                .map(|citation| Citation {
+                    title: citation.title().map(str::to_string),
+                    source_content: citation
+                        .source_content()
+                        .iter()
+                        .filter_map(|c| c.as_text().ok())
+                        .map(|text| TextPart { text: text.clone() })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A numbered source, as listed in the footer and in meta.json
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub title: String,
+    /// The start of the cited passage, [SNIPPET_CHARS] at most
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+impl Source {
+    fn from_citation(citation: &Citation) -> Source {
+        let passage = citation
+            .source_content
+            .iter()
+            .map(|part| part.text.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Source {
+            title: citation
+                .title
+                .as_deref()
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .unwrap_or("untitled document")
+                .to_string(),
+            snippet: (!passage.is_empty()).then(|| snippet(&passage)),
+        }
+    }
+}
+
+fn snippet(passage: &str) -> String {
+    if passage.chars().count() <= SNIPPET_CHARS {
+        return passage.to_string();
+    }
+    let cut: String = passage.chars().take(SNIPPET_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) if space > SNIPPET_CHARS / 2 => &cut[..space],
+        _ => cut.as_str(),
+    };
+    format!(
+        "{}…",
+        cut.trim_end_matches(|c: char| c.is_ascii_punctuation())
+    )
+}
+
+/// The sources cited so far, numbered from 1 in the order they were first
+/// cited.  A passage cited twice keeps its first number.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Sources(Vec<Source>);
+
+impl Sources {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Source> {
+        self.0.iter()
+    }
+
+    /// The number of `source`, adding it if it's new
+    pub fn add(&mut self, source: Source) -> usize {
+        match self.0.iter().position(|s| *s == source) {
+            Some(idx) => idx + 1,
+            None => {
+                self.0.push(source);
+                self.0.len()
+            }
+        }
+    }
+
+    /// Adds the sources of `cited` and returns its text followed by their
+    /// markers, e.g. `Rest the dough overnight.[1][2]`
+    pub fn cite(&mut self, cited: &CitedText) -> String {
+        let mut numbers: Vec<usize> = vec![];
+        for citation in &cited.citations {
+            let number = self.add(Source::from_citation(citation));
+            if !numbers.contains(&number) {
+                numbers.push(number);
+            }
+        }
+        let text = cited.text();
+        let (body, trailing) = text.split_at(text.trim_end().len());
+        let markers: String = numbers.iter().map(|n| format!("[{}]", n)).collect();
+        format!("{}{}{}", body, markers, trailing)
+    }
+
+    /// The footer shown after the answer, or nothing when there are no
+    /// sources
+    pub fn footer(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut footer = "Sources".to_string();
+        for (idx, source) in self.0.iter().enumerate() {
+            footer.push_str(&format!("\n  [{}] {}", idx + 1, source.title));
+            if let Some(snippet) = &source.snippet {
+                footer.push_str(&format!(": \"{}\"", snippet));
+            }
+        }
+        Some(footer)
+    }
+
+    /// The same footer for a saved recipe's markdown
+    pub fn markdown(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut markdown = "## Sources\n".to_string();
+        for (idx, source) in self.0.iter().enumerate() {
+            markdown.push_str(&format!("\n{}. **{}**", idx + 1, source.title));
+            if let Some(snippet) = &source.snippet {
+                markdown.push_str(&format!(": \"{}\"", snippet));
+            }
+        }
+        markdown.push('\n');
+        Some(markdown)
+    }
+}
+
+/// A document to send with the next prompt, with citations turned on so
+/// answers drawn from it say where
+pub fn attach(path: &Path) -> Result<ContentBlock, GourmandError> {
+    let format = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .and_then(|e| document_format(&e))
+        .ok_or_else(|| {
+            GourmandError::io(
+                path,
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "attach a pdf, txt, md, html, csv, doc(x), or xls(x) file",
+                ),
+            )
+        })?;
+    let bytes = fs::read(path).map_err(|e| GourmandError::io(path, e))?;
+    let document = DocumentBlock::builder()
+        .format(format)
+        .name(document_name(path))
+        .source(DocumentSource::Bytes(Blob::new(bytes)))
+        .citations(CitationsConfig::builder().enabled(true).build()?)
+        .build()?;
+    Ok(ContentBlock::Document(document))
+}
+
+fn document_format(extension: &str) -> Option<DocumentFormat> {
+    match extension {
+        "pdf" => Some(DocumentFormat::Pdf),
+        "txt" | "text" => Some(DocumentFormat::Txt),
+        "md" | "markdown" => Some(DocumentFormat::Md),
+        "html" | "htm" => Some(DocumentFormat::Html),
+        "csv" => Some(DocumentFormat::Csv),
+        "doc" => Some(DocumentFormat::Doc),
+        "docx" => Some(DocumentFormat::Docx),
+        "xls" => Some(DocumentFormat::Xls),
+        "xlsx" => Some(DocumentFormat::Xlsx),
+        _ => None,
+    }
+}
+
+/// Bedrock takes letters, digits, spaces, hyphens, parentheses, and
+/// square brackets in document names, without consecutive spaces.  The
+/// name is what citations give back as the title.
+pub fn document_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let cleaned: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-()[]".contains(c) {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    let name = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        "document".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Two blocks from a Converse response over an attached PDF, as
+    /// Bedrock sends them (the `location` is ignored)
+    const RESPONSE: &str = r#"[
+      {"citationsContent": {
+        "content": [{"text": "Bloom the yeast in water at about 40°C. "}],
+        "citations": [{
+          "title": "Bread Basics",
+          "sourceContent": [{"text": "Dissolve the yeast in warm water,\n  around 40 °C, and wait until it foams."}],
+          "location": {"documentPage": {"documentIndex": 0, "start": 3, "end": 4}}
+        }]
+      }},
+      {"citationsContent": {
+        "content": [{"text": "Then knead for ten minutes"}, {"text": " and let it rise."}],
+        "citations": [
+          {
+            "title": "Bread Basics",
+            "sourceContent": [{"text": "Dissolve the yeast in warm water,\n  around 40 °C, and wait until it foams."}]
+          },
+          {"title": "Grandma's notes", "sourceContent": [{"text": "Knead 10 min!"}]}
+        ]
+      }}
+    ]"#;
+
+    fn blocks(json: &str) -> Vec<CitedText> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Block {
+            citations_content: CitedText,
+        }
+        serde_json::from_str::<Vec<Block>>(json)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.citations_content)
+            .collect()
+    }
+
+    #[test]
+    fn text_is_marked_and_sources_numbered() {
+        let mut sources = Sources::default();
+        let text: Vec<String> = blocks(RESPONSE).iter().map(|b| sources.cite(b)).collect();
+        assert_eq!(
+            text,
+            [
+                "Bloom the yeast in water at about 40°C.[1] ",
+                "Then knead for ten minutes and let it rise.[1][2]",
+            ]
+        );
+        assert_eq!(
+            sources.footer().unwrap(),
+            "Sources\n  \
+             [1] Bread Basics: \"Dissolve the yeast in warm water, around 40 °C, and wait until it foams.\"\n  \
+             [2] Grandma's notes: \"Knead 10 min!\""
+        );
+    }
+
+    #[test]
+    fn markdown_footer_and_meta() {
+        let mut sources = Sources::default();
+        for block in blocks(RESPONSE) {
+            sources.cite(&block);
+        }
+        assert_eq!(
+            sources.markdown().unwrap(),
+            "## Sources\n\n\
+             1. **Bread Basics**: \"Dissolve the yeast in warm water, around 40 °C, and wait until it foams.\"\n\
+             2. **Grandma's notes**: \"Knead 10 min!\"\n"
+        );
+        let json = serde_json::to_value(&sources).unwrap();
+        assert_eq!(json[1]["title"], "Grandma's notes");
+        let back: Sources = serde_json::from_value(json).unwrap();
+        assert_eq!(back, sources);
+    }
+
+    #[test]
+    fn odd_blocks() {
+        // no citations, no title, no passage, and a very long passage
+        let long = "word ".repeat(100);
+        let json = format!(
+            r#"[
+              {{"citationsContent": {{"content": [{{"text": "Plain."}}]}}}},
+              {{"citationsContent": {{"content": [{{"text": "Untitled."}}],
+                "citations": [{{"sourceContent": []}}]}}}},
+              {{"citationsContent": {{"content": [{{"text": "Long."}}],
+                "citations": [{{"title": " Notes ", "sourceContent": [{{"text": "{}"}}]}}]}}}}
+            ]"#,
+            long
+        );
+        let mut sources = Sources::default();
+        let text: Vec<String> = blocks(&json).iter().map(|b| sources.cite(b)).collect();
+        assert_eq!(text, ["Plain.", "Untitled.[1]", "Long.[2]"]);
+        let all: Vec<&Source> = sources.iter().collect();
+        assert_eq!(all[0].title, "untitled document");
+        assert_eq!(all[0].snippet, None);
+        assert_eq!(all[1].title, "Notes");
+        let snippet = all[1].snippet.as_deref().unwrap();
+        assert!(snippet.ends_with("word…"), "{}", snippet);
+        assert!(snippet.chars().count() <= SNIPPET_CHARS + 1);
+        assert!(Sources::default().footer().is_none());
+        assert!(Sources::default().markdown().is_none());
+    }
+
+    #[test]
+    fn documents_are_named_the_way_bedrock_allows() {
+        assert_eq!(
+            document_name(&PathBuf::from("/tmp/Bread_Basics v2.final.pdf")),
+            "Bread Basics v2 final"
+        );
+        assert_eq!(document_name(&PathBuf::from("___.txt")), "document");
+        assert!(attach(&PathBuf::from("notes.exe")).is_err());
+    }
+}
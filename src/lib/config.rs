@@ -0,0 +1,177 @@
+//! What [crate::Conversation::connect] starts a conversation from.
+//!
+//! ```no_run
+//! # async fn f() -> Result<(), Box<dyn std::error::Error>> {
+//! use recipes::{Config, Conversation, DirSink, Preferences};
+//!
+//! let config = Config::new()
+//!     .preferences(Preferences::new().household_size(2).diet("vegetarian"))
+//!     .artifacts(DirSink::new("recipes"));
+//! let mut conversation = Conversation::connect(config).await?;
+//! let reply = conversation
+//!     .ask("Something quick with rice, please", |text| println!("{}", text))
+//!     .await?;
+//! println!("saved {:?}", reply.saved);
+//! # Ok(())
+//! # }
+//! ```
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_sdk_bedrockruntime::types::InferenceConfiguration;
+
+use crate::oneshot::DEFAULT_MODEL;
+use crate::preferences::Preferences;
+use crate::sink::{ArtifactSink, DirSink};
+use crate::system_prompts::{self, Preset, SystemPrompt};
+use crate::tools::{ToolDef, ToolRegistry};
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Config {
+    /// A model or inference profile id that supports Converse
+    pub model: String,
+    /// A profile from ~/.aws, or the SDK's default chain if unset
+    pub aws_profile: Option<String>,
+    /// The profile's region, or the default chain's, if unset
+    pub aws_region: Option<String>,
+    /// [Preset::Family] unless set: the interview, then `transmit_recipe`
+    pub system_prompt: SystemPrompt,
+    /// Added to the system prompt, so the model can skip asking
+    pub preferences: Option<Preferences>,
+    /// Model defaults if unset
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+    /// Gives up on a request that takes longer than this
+    pub timeout: Option<Duration>,
+    /// Offered alongside `transmit_recipe`
+    pub tools: ToolRegistry,
+    /// Where transmitted recipes are saved.  Without one they're only in
+    /// [crate::Reply::recipes].
+    pub artifacts: Option<Arc<dyn ArtifactSink>>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            model: DEFAULT_MODEL.to_string(),
+            aws_profile: None,
+            aws_region: None,
+            system_prompt: SystemPrompt::new(Preset::Family),
+            preferences: None,
+            temperature: None,
+            max_tokens: None,
+            timeout: None,
+            tools: ToolRegistry::new(),
+            artifacts: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config::default()
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Config {
+        self.model = model.into();
+        self
+    }
+
+    pub fn aws_profile(mut self, profile: impl Into<String>) -> Config {
+        self.aws_profile = Some(profile.into());
+        self
+    }
+
+    pub fn aws_region(mut self, region: impl Into<String>) -> Config {
+        self.aws_region = Some(region.into());
+        self
+    }
+
+    pub fn system_prompt(mut self, prompt: SystemPrompt) -> Config {
+        self.system_prompt = prompt;
+        self
+    }
+
+    pub fn preferences(mut self, preferences: Preferences) -> Config {
+        self.preferences = Some(preferences);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Config {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: i32) -> Config {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Config {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Offers `tool` too, replacing one with the same name
+    pub fn tool(mut self, tool: ToolDef) -> Config {
+        self.tools.register(tool);
+        self
+    }
+
+    pub fn tools(mut self, tools: ToolRegistry) -> Config {
+        self.tools = tools;
+        self
+    }
+
+    pub fn artifacts(mut self, sink: impl ArtifactSink + 'static) -> Config {
+        self.artifacts = Some(Arc::new(sink));
+        self
+    }
+
+    /// Saves transmitted recipes into `dir`, see [DirSink]
+    pub fn save_to(self, dir: impl Into<PathBuf>) -> Config {
+        self.artifacts(DirSink::new(dir))
+    }
+
+    /// The system prompt with the preferences in it
+    pub(crate) fn system(&self) -> SystemPrompt {
+        let mut system = self.system_prompt.clone();
+        if let Some(preferences) = self.preferences.as_ref().filter(|p| !p.is_empty()) {
+            system.add(system_prompts::preferences(&preferences.render()));
+        }
+        system
+    }
+
+    pub(crate) fn inference(&self) -> Option<InferenceConfiguration> {
+        if self.temperature.is_none() && self.max_tokens.is_none() {
+            return None;
+        }
+        Some(
+            InferenceConfiguration::builder()
+                .set_temperature(self.temperature)
+                .set_max_tokens(self.max_tokens)
+                .build(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferences_go_in_the_system_prompt() {
+        let config = Config::new();
+        assert_eq!(config.system().text(), Preset::Family.text());
+        assert!(config.inference().is_none());
+
+        let config = config
+            .preferences(Preferences::new().allergy("peanuts"))
+            .max_tokens(800);
+        assert!(config.system().text().contains("peanuts"));
+        assert_eq!(config.inference().unwrap().max_tokens(), Some(800));
+        assert_eq!(config.inference().unwrap().temperature(), None);
+    }
+}
@@ -0,0 +1,474 @@
+//! Restrictions stated in passing during a session ("we hate cilantro",
+//! "no more pasta this week", "my son is allergic to sesame"), so a recipe
+//! that ignores them is caught even though they never made it into
+//! `preferences.json`.  They last until the session ends.
+//!
+//! The parser is a handful of patterns and errs on the side of missing
+//! things: a phrase is only taken if it directly follows one of the
+//! [CUES], is at most [MAX_WORDS] words, and isn't one of the [NOT_FOOD]
+//! words those cues are also used with ("no rush", "no idea").  A bare
+//! "no" only counts at the start of a clause, as in "no mushrooms please"
+//! but not "there's no milk left".  Questions and negated cues ("I don't
+//! hate it") are skipped.  What's found is checked with [crate::dietary],
+//! so a stated "vegetarian" catches fish sauce the same way a saved diet
+//! does.
+use std::fmt;
+
+use crate::dietary::{self, Violation};
+use crate::preferences::{Preferences, Strictness};
+
+/// Phrases that introduce something to leave out, longest first so "no
+/// more" wins over "no"
+static CUES: &[(&str, Strictness)] = &[
+    ("is allergic to", Strictness::Strict),
+    ("are allergic to", Strictness::Strict),
+    ("am allergic to", Strictness::Strict),
+    ("allergic to", Strictness::Strict),
+    ("an allergy to", Strictness::Strict),
+    ("intolerant to", Strictness::Strict),
+    ("can't eat", Strictness::Strict),
+    ("cannot eat", Strictness::Strict),
+    ("not a fan of", Strictness::Avoid),
+    ("can't stand", Strictness::Avoid),
+    ("don't like", Strictness::Avoid),
+    ("doesn't like", Strictness::Avoid),
+    ("don't want", Strictness::Avoid),
+    ("no more", Strictness::Avoid),
+    ("dislike", Strictness::Avoid),
+    ("dislikes", Strictness::Avoid),
+    ("hate", Strictness::Avoid),
+    ("hates", Strictness::Avoid),
+    ("without", Strictness::Avoid),
+    ("no", Strictness::Avoid),
+];
+
+/// Cues that only count at the start of a clause, after one of
+/// [CLAUSE_STARTS] or nothing at all
+const CLAUSE_CUES: &[&str] = &["no", "no more"];
+
+const CLAUSE_STARTS: &[&str] = &[",", "and", "but", "please", "also", "just", "so", "then"];
+
+/// Words that turn the cue after them around, as in "I don't hate it"
+const NEGATIONS: &[&str] = &["not", "don't", "doesn't", "never", "didn't"];
+
+/// Cues for a diet, as in "we're vegetarian now"; only taken if every word
+/// after it is in [DIET_WORDS]
+const DIET_CUES: &[&str] = &["i'm", "i am", "we're", "we are", "we eat", "we keep", "is"];
+
+const DIET_WORDS: &[&str] = &[
+    "vegetarian",
+    "vegan",
+    "pescatarian",
+    "pescetarian",
+    "halal",
+    "kosher",
+    "keto",
+    "gluten",
+    "dairy",
+    "lactose",
+    "nut",
+    "free",
+    "low",
+    "carb",
+    "plant",
+    "based",
+    "celiac",
+    "coeliac",
+    "sober",
+];
+
+/// Longer phrases are more likely a clause than an ingredient
+const MAX_WORDS: usize = 3;
+
+/// Words the cues are used with that aren't food
+const NOT_FOOD: &[&str] = &[
+    "rush",
+    "idea",
+    "problem",
+    "problems",
+    "worries",
+    "worry",
+    "thanks",
+    "thank",
+    "way",
+    "time",
+    "need",
+    "one",
+    "thing",
+    "things",
+    "clue",
+    "matter",
+    "doubt",
+    "issue",
+    "issues",
+    "longer",
+    "less",
+    "more",
+    "than",
+    "much",
+    "big",
+    "it",
+    "that",
+    "this",
+    "them",
+    "those",
+    "these",
+    "you",
+    "me",
+    "us",
+    "having",
+    "being",
+    "using",
+    "too",
+    "restrictions",
+    "allergies",
+    "preference",
+    "preferences",
+    "leftovers",
+    "recipe",
+    "recipes",
+    "dessert",
+    "cooking",
+    "fuss",
+    "hurry",
+    "oven",
+    "stove",
+    "microwave",
+    "grill",
+    "cook",
+    "cooks",
+    "waiting",
+    "mess",
+    "cleanup",
+    "anything",
+    "something",
+    "everything",
+    "heavy",
+    "fancy",
+    "spicy",
+    "food",
+    "when",
+    "how",
+];
+
+/// Determiners dropped from the start of a phrase
+const DETERMINERS: &[&str] = &["the", "any", "some", "a", "an", "all"];
+
+/// Words that end a phrase
+const STOPS: &[&str] = &[
+    "and", "or", "but", "so", "because", "please", "this", "that", "tonight", "today", "anymore",
+    "at", "in", "for", "with", "on", "if", "though", "either", "again", "then",
+];
+
+/// Something the user said to leave out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stated {
+    pub restriction: String,
+    pub strictness: Strictness,
+    /// The sentence it came from
+    pub said: String,
+}
+
+impl fmt::Display for Stated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.restriction, self.strictness)
+    }
+}
+
+/// Lowercase words of a sentence, with apostrophes kept for "don't"
+fn tokens(sentence: &str) -> Vec<String> {
+    sentence
+        .to_lowercase()
+        .replace('\u{2019}', "'")
+        .split(|c: char| !(c.is_alphanumeric() || c == '\'' || c == ','))
+        .flat_map(|w| {
+            // commas end a phrase, so they're kept as tokens of their own
+            let mut parts = vec![];
+            for (idx, part) in w.split(',').enumerate() {
+                if idx > 0 {
+                    parts.push(",".to_string());
+                }
+                if !part.is_empty() {
+                    parts.push(part.to_string());
+                }
+            }
+            parts
+        })
+        .collect()
+}
+
+/// Where the words of `cue` start in `words`
+fn cue_positions(words: &[String], cue: &str) -> Vec<usize> {
+    let cue: Vec<&str> = cue.split(' ').collect();
+    (0..words.len())
+        .filter(|&idx| {
+            cue.iter()
+                .enumerate()
+                .all(|(offset, part)| words.get(idx + offset).is_some_and(|w| w == part))
+        })
+        .collect()
+}
+
+/// The phrase after a cue, or `None` if it doesn't look like food
+fn phrase(after: &[String]) -> Option<String> {
+    let mut words: Vec<&str> = after
+        .iter()
+        .map(String::as_str)
+        .take_while(|w| *w != "," && !STOPS.contains(w))
+        .collect();
+    while words.first().is_some_and(|w| DETERMINERS.contains(w)) {
+        words.remove(0);
+    }
+    // "this week" and the like end a phrase, but they're dropped above
+    if words.is_empty() || words.len() > MAX_WORDS {
+        return None;
+    }
+    let food = words.iter().all(|w| {
+        !NOT_FOOD.contains(w) && !w.chars().any(|c| c.is_ascii_digit()) && !w.contains('\'')
+    });
+    food.then(|| words.join(" "))
+}
+
+/// Every restriction stated in `text`, in order
+pub fn extract(text: &str) -> Vec<Stated> {
+    let mut found: Vec<Stated> = vec![];
+    for sentence in text.split_inclusive(['.', '!', '?', ';', '\n']) {
+        if sentence.trim_end().ends_with('?') {
+            continue;
+        }
+        let words = tokens(sentence);
+        let mut taken = vec![false; words.len()];
+        for (cue, strictness) in CUES {
+            let len = cue.split(' ').count();
+            for idx in cue_positions(&words, cue) {
+                if taken[idx] {
+                    continue;
+                }
+                let before = idx.checked_sub(1).map(|i| words[i].as_str());
+                if before.is_some_and(|w| NEGATIONS.contains(&w)) {
+                    continue;
+                }
+                if CLAUSE_CUES.contains(cue) && before.is_some_and(|w| !CLAUSE_STARTS.contains(&w))
+                {
+                    continue;
+                }
+                taken[idx..idx + len].iter_mut().for_each(|t| *t = true);
+                if let Some(restriction) = phrase(&words[idx + len..]) {
+                    push(&mut found, restriction, *strictness, sentence);
+                }
+            }
+        }
+        for cue in DIET_CUES {
+            let len = cue.split(' ').count();
+            for idx in cue_positions(&words, cue) {
+                let rest: Vec<&str> = words[idx + len..]
+                    .iter()
+                    .map(String::as_str)
+                    .take_while(|w| *w != "," && !STOPS.contains(w) && *w != "now")
+                    .collect();
+                let diet = !rest.is_empty()
+                    && rest.len() <= MAX_WORDS
+                    && rest.iter().all(|w| DIET_WORDS.contains(w));
+                if diet {
+                    push(&mut found, rest.join(" "), Strictness::Prefer, sentence);
+                }
+            }
+        }
+    }
+    found
+}
+
+fn push(found: &mut Vec<Stated>, restriction: String, strictness: Strictness, said: &str) {
+    if found.iter().any(|s| s.restriction == restriction) {
+        return;
+    }
+    found.push(Stated {
+        restriction,
+        strictness,
+        said: said.trim().to_string(),
+    });
+}
+
+/// What's been stated so far this session
+#[derive(Debug, Clone, Default)]
+pub struct Constraints {
+    stated: Vec<Stated>,
+}
+
+impl Constraints {
+    /// Notes the restrictions in something the user typed, returning the
+    /// ones that are new.  Stating one again with a firmer strictness
+    /// updates it.
+    pub fn add(&mut self, text: &str) -> Vec<Stated> {
+        let mut new = vec![];
+        for stated in extract(text) {
+            match self
+                .stated
+                .iter_mut()
+                .find(|s| s.restriction == stated.restriction)
+            {
+                Some(known) if stated.strictness < known.strictness => *known = stated,
+                Some(_) => (),
+                None => {
+                    self.stated.push(stated.clone());
+                    new.push(stated);
+                }
+            }
+        }
+        new
+    }
+
+    pub fn stated(&self) -> &[Stated] {
+        &self.stated
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stated.is_empty()
+    }
+
+    /// What was said about `restriction`, for a warning
+    pub fn said(&self, restriction: &str) -> Option<&str> {
+        self.stated
+            .iter()
+            .find(|s| s.restriction == restriction)
+            .map(|s| s.said.as_str())
+    }
+
+    /// The stated restrictions as preferences, each in the list whose
+    /// default strictness it has
+    pub fn as_preferences(&self) -> Preferences {
+        let mut preferences = Preferences::default();
+        for stated in &self.stated {
+            let list = match stated.strictness {
+                Strictness::Strict => &mut preferences.allergies,
+                Strictness::Prefer => &mut preferences.diet,
+                Strictness::Avoid => &mut preferences.dislikes,
+            };
+            list.push(stated.restriction.clone());
+        }
+        preferences
+    }
+
+    /// Every ingredient of `recipe` that breaks a stated restriction, read
+    /// with the allergen words of `language` too when it has them
+    pub fn check(&self, recipe: &str, language: Option<&str>) -> Vec<Violation> {
+        if self.stated.is_empty() {
+            return vec![];
+        }
+        dietary::check(&self.as_preferences(), recipe, language)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Strictness::{Avoid, Prefer, Strict};
+
+    /// Sentences as they'd be typed, with what should be taken from them.
+    /// Missing a restriction is better than inventing one, so most of the
+    /// negatives use a cue in a sentence that isn't about food.
+    const LABELED: &[(&str, &[(&str, Strictness)])] = &[
+        ("We hate cilantro.", &[("cilantro", Avoid)]),
+        ("no more pasta this week", &[("pasta", Avoid)]),
+        ("My son is allergic to sesame.", &[("sesame", Strict)]),
+        // only the first of a list is taken, since what follows "and"
+        // is as often a new clause
+        (
+            "I'm allergic to shellfish and tree nuts",
+            &[("shellfish", Strict)],
+        ),
+        ("She has an allergy to peanuts.", &[("peanuts", Strict)]),
+        ("He can't eat gluten", &[("gluten", Strict)]),
+        (
+            "Something quick, no mushrooms please",
+            &[("mushrooms", Avoid)],
+        ),
+        (
+            "no mushrooms, no olives",
+            &[("mushrooms", Avoid), ("olives", Avoid)],
+        ),
+        (
+            "Make it without the blue cheese.",
+            &[("blue cheese", Avoid)],
+        ),
+        ("The kids don't like spinach", &[("spinach", Avoid)]),
+        ("I’m not a fan of raw onion.", &[("raw onion", Avoid)]),
+        ("We're vegetarian now.", &[("vegetarian", Prefer)]),
+        ("We are gluten free", &[("gluten free", Prefer)]),
+        ("My wife is vegan", &[("vegan", Prefer)]),
+        // negatives
+        ("No rush, whenever.", &[]),
+        ("I have no idea what to cook.", &[]),
+        ("There's no milk left", &[]),
+        ("I don't hate cilantro, it's fine.", &[]),
+        ("Can you do it without cilantro?", &[]),
+        ("Is it allergic to anything?", &[]),
+        ("We don't want anything fancy", &[]),
+        ("No problem, thanks!", &[]),
+        ("I hate when the sauce splits", &[]),
+        ("no more than 30 minutes", &[]),
+        ("Dinner without the oven tonight", &[]),
+        ("I hate doing dishes after a long day at work", &[]),
+        ("The recipe is great", &[]),
+        ("We are out of eggs", &[]),
+    ];
+
+    #[test]
+    fn extracts_the_labeled_set() {
+        for (sentence, expected) in LABELED {
+            let found = extract(sentence);
+            let found: Vec<(&str, Strictness)> = found
+                .iter()
+                .map(|s| (s.restriction.as_str(), s.strictness))
+                .collect();
+            assert_eq!(found, *expected, "{:?}", sentence);
+        }
+    }
+
+    #[test]
+    fn remembers_what_was_said() {
+        let mut constraints = Constraints::default();
+        let new = constraints.add("We hate cilantro. Tacos tonight?");
+        assert_eq!(
+            new,
+            [Stated {
+                restriction: "cilantro".to_string(),
+                strictness: Avoid,
+                said: "We hate cilantro.".to_string(),
+            }]
+        );
+        assert!(constraints.add("Really, we hate cilantro").is_empty());
+        // said again more firmly, it's kept more firmly
+        assert!(constraints
+            .add("Actually she's allergic to cilantro.")
+            .is_empty());
+        assert_eq!(constraints.stated()[0].strictness, Strict);
+        assert_eq!(
+            constraints.said("cilantro"),
+            Some("Actually she's allergic to cilantro.")
+        );
+        assert_eq!(constraints.as_preferences().allergies, ["cilantro"]);
+    }
+
+    #[test]
+    fn recipes_are_checked_against_what_was_said() {
+        let recipe =
+            "Fish Tacos\n\nIngredients:\n- 1 lb cod\n- 8 tortillas\n- 1 bunch cilantro\n\n\
+                      Instructions:\n1. Grill the fish.\n";
+        let mut constraints = Constraints::default();
+        assert!(constraints.check(recipe, None).is_empty());
+        constraints.add("We hate cilantro. And we're vegetarian.");
+        let violations: Vec<(String, String)> = constraints
+            .check(recipe, None)
+            .into_iter()
+            .map(|v| (v.restriction, v.ingredient))
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                ("vegetarian".to_string(), "1 lb cod".to_string()),
+                ("cilantro".to_string(), "1 bunch cilantro".to_string()),
+            ]
+        );
+    }
+}
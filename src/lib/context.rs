@@ -0,0 +1,295 @@
+//! Local context (time of day, season) that helps the model suggest
+//! sensible recipes: breakfast at 7am, no strawberries in January.
+//!
+//! Everything here is computed locally and can be overridden so that a
+//! session is reproducible.
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, Local, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Meal {
+    Breakfast,
+    Lunch,
+    Dinner,
+}
+
+impl fmt::Display for Meal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Meal::Breakfast => write!(f, "breakfast"),
+            Meal::Lunch => write!(f, "lunch"),
+            Meal::Dinner => write!(f, "dinner"),
+        }
+    }
+}
+
+impl FromStr for Meal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "breakfast" => Ok(Meal::Breakfast),
+            "lunch" => Ok(Meal::Lunch),
+            "dinner" => Ok(Meal::Dinner),
+            _ => Err(format!(
+                "unknown meal: {} (expected breakfast, lunch, or dinner)",
+                s
+            )),
+        }
+    }
+}
+
+/// The hours at which we stop assuming one meal and start assuming the next
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MealCutoffs {
+    pub lunch_from: u32,
+    pub dinner_from: u32,
+}
+
+impl Default for MealCutoffs {
+    fn default() -> Self {
+        MealCutoffs {
+            lunch_from: 11,
+            dinner_from: 15,
+        }
+    }
+}
+
+impl fmt::Display for MealCutoffs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.lunch_from, self.dinner_from)
+    }
+}
+
+/// The hours lunch and dinner start, e.g. `11,15`
+impl FromStr for MealCutoffs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid meal cut-offs: {} (expected the hours lunch and dinner start, e.g. 11,15)",
+                s
+            )
+        };
+        let (lunch, dinner) = s.split_once(',').ok_or_else(invalid)?;
+        let hour = |h: &str| h.trim().parse::<u32>().map_err(|_| invalid());
+        let cutoffs = MealCutoffs {
+            lunch_from: hour(lunch)?,
+            dinner_from: hour(dinner)?,
+        };
+        // before 4am it's still last night's dinner
+        if cutoffs.lunch_from <= 4
+            || cutoffs.dinner_from > 23
+            || cutoffs.lunch_from >= cutoffs.dinner_from
+        {
+            return Err(invalid());
+        }
+        Ok(cutoffs)
+    }
+}
+
+impl MealCutoffs {
+    pub fn meal_at(&self, hour: u32) -> Meal {
+        if hour < 4 || hour >= self.dinner_from {
+            Meal::Dinner
+        } else if hour >= self.lunch_from {
+            Meal::Lunch
+        } else {
+            Meal::Breakfast
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hemisphere {
+    Northern,
+    Southern,
+}
+
+impl Hemisphere {
+    /// Guesses the hemisphere from a locale such as `en_AU` or `es-AR`,
+    /// defaulting to northern.
+    pub fn from_locale(locale: &str) -> Hemisphere {
+        let region = locale
+            .split(['_', '-', '.'])
+            .nth(1)
+            .unwrap_or("")
+            .to_uppercase();
+        match region.as_str() {
+            "AU" | "NZ" | "ZA" | "AR" | "CL" | "UY" | "PY" | "BR" | "BO" | "PE" | "LS" | "NA"
+            | "BW" | "ZW" | "MZ" | "MG" => Hemisphere::Southern,
+            _ => Hemisphere::Northern,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    Winter,
+    Spring,
+    Summer,
+    Autumn,
+}
+
+impl fmt::Display for Season {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Season::Winter => write!(f, "winter"),
+            Season::Spring => write!(f, "spring"),
+            Season::Summer => write!(f, "summer"),
+            Season::Autumn => write!(f, "autumn"),
+        }
+    }
+}
+
+/// `month` is 1-12
+pub fn season(month: u32, hemisphere: Hemisphere) -> Season {
+    let northern = match month {
+        3..=5 => Season::Spring,
+        6..=8 => Season::Summer,
+        9..=11 => Season::Autumn,
+        _ => Season::Winter,
+    };
+    match (hemisphere, northern) {
+        (Hemisphere::Northern, s) => s,
+        (Hemisphere::Southern, Season::Winter) => Season::Summer,
+        (Hemisphere::Southern, Season::Spring) => Season::Autumn,
+        (Hemisphere::Southern, Season::Summer) => Season::Winter,
+        (Hemisphere::Southern, Season::Autumn) => Season::Spring,
+    }
+}
+
+/// Produce at its best in a temperate northern climate, by month (January first)
+static NORTHERN_PRODUCE: [&str; 12] = [
+    "citrus, kale, leeks, cabbage, winter squash, root vegetables",
+    "citrus, kale, leeks, cabbage, parsnips, potatoes",
+    "asparagus, spinach, leeks, radishes, citrus, spring onions",
+    "asparagus, peas, spinach, radishes, rhubarb, artichokes",
+    "asparagus, peas, strawberries, rhubarb, lettuce, new potatoes",
+    "strawberries, cherries, zucchini, green beans, peas, lettuce",
+    "tomatoes, corn, zucchini, berries, peaches, cucumbers",
+    "tomatoes, corn, peppers, eggplant, peaches, melons",
+    "apples, tomatoes, peppers, corn, grapes, winter squash",
+    "apples, pears, pumpkin, winter squash, brussels sprouts, sweet potatoes",
+    "apples, pears, cranberries, brussels sprouts, sweet potatoes, kale",
+    "citrus, kale, brussels sprouts, winter squash, root vegetables, pomegranates",
+];
+
+/// `month` is 1-12
+pub fn seasonal_produce(month: u32, hemisphere: Hemisphere) -> Vec<&'static str> {
+    let idx = (month.clamp(1, 12) - 1) as usize;
+    let idx = match hemisphere {
+        Hemisphere::Northern => idx,
+        Hemisphere::Southern => (idx + 6) % 12,
+    };
+    NORTHERN_PRODUCE[idx].split(", ").collect()
+}
+
+/// Parses a month given as a number (`1`) or a name (`jan`, `January`)
+pub fn parse_month(s: &str) -> Option<u32> {
+    if let Ok(n) = s.trim().parse::<u32>() {
+        return (1..=12).contains(&n).then_some(n);
+    }
+    let names = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let lower = s.trim().to_lowercase();
+    names
+        .iter()
+        .position(|name| lower.starts_with(name))
+        .map(|idx| idx as u32 + 1)
+}
+
+/// A snapshot of the local context for one session
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub now: NaiveDateTime,
+    pub meal: Meal,
+    pub hemisphere: Hemisphere,
+    /// Today's theme, e.g. "tacos", see [crate::themes]
+    pub theme: Option<String>,
+}
+
+impl Context {
+    /// Builds the context from the local clock, honoring any overrides
+    pub fn local(meal: Option<Meal>, hemisphere: Hemisphere, cutoffs: MealCutoffs) -> Context {
+        Context::at(Local::now().naive_local(), meal, hemisphere, cutoffs)
+    }
+
+    pub fn at(
+        now: NaiveDateTime,
+        meal: Option<Meal>,
+        hemisphere: Hemisphere,
+        cutoffs: MealCutoffs,
+    ) -> Context {
+        Context {
+            now,
+            meal: meal.unwrap_or_else(|| cutoffs.meal_at(now.hour())),
+            hemisphere,
+            theme: None,
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Option<String>) -> Context {
+        self.theme = theme;
+        self
+    }
+
+    pub fn season(&self) -> Season {
+        season(self.now.month(), self.hemisphere)
+    }
+
+    pub fn seasonal_produce(&self) -> Vec<&'static str> {
+        seasonal_produce(self.now.month(), self.hemisphere)
+    }
+
+    /// Renders the context for inclusion in the system prompt
+    pub fn render(&self) -> String {
+        let mut text = format!(
+            "
+    For context, it is currently {} in {} where the user lives, so they are probably
+    planning {}.  Favor ingredients that are in season, such as: {}.
+",
+            self.now.format("%A %B %-d, %-I:%M %p"),
+            self.season(),
+            self.meal,
+            self.seasonal_produce().join(", "),
+        );
+        if let Some(theme) = &self.theme {
+            text.push_str(&format!(
+                "    Today's theme in this household is \"{}\": unless the user asks for something
+    else, only recommend recipes that fit it.
+",
+                theme
+            ));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meal_cutoffs_from_the_command_line() {
+        let late: MealCutoffs = "12, 17".parse().unwrap();
+        assert_eq!(late.to_string(), "12,17");
+        for (hour, meal) in [
+            (3, Meal::Dinner),
+            (11, Meal::Breakfast),
+            (12, Meal::Lunch),
+            (16, Meal::Lunch),
+            (17, Meal::Dinner),
+        ] {
+            assert_eq!(late.meal_at(hour), meal, "{}", hour);
+        }
+        for invalid in ["11", "15,11", "11,11", "3,15", "11,24", "noon,15", ""] {
+            assert!(invalid.parse::<MealCutoffs>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,1566 @@
+//! A conversation with a model over the Bedrock Converse API.
+//!
+//! [Conversation] owns the message history and sends one request per turn.
+//! Embedders start one with [Conversation::connect] and call
+//! [Conversation::ask], which runs their [ToolRegistry]'s handlers and
+//! saves transmitted recipes to their [ArtifactSink] until the model is
+//! done; see `examples/embedded.rs`.  The shell needs more control, so
+//! with the `bedrock` feature the `send` family leaves what to do with the
+//! response (print text, run tools) to the caller.
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use aws_sdk_bedrockruntime::error::BuildError;
+use aws_sdk_bedrockruntime::operation::converse::ConverseOutput as ConverseResponse;
+use aws_sdk_bedrockruntime::operation::RequestId;
+use aws_sdk_bedrockruntime::types::{
+    AnyToolChoice, ContentBlock, ConversationRole, ConverseMetrics, ConverseOutput,
+    InferenceConfiguration, Message, SpecificToolChoice, StopReason, SystemContentBlock,
+    TokenUsage, ToolConfiguration, ToolResultBlock, ToolResultContentBlock, ToolResultStatus,
+    ToolUseBlock,
+};
+use aws_sdk_bedrockruntime::Client;
+use log::{debug, error, warn};
+
+use crate::awsinit;
+use crate::config::Config;
+use crate::limits::{self, PayloadCheck};
+use crate::oneshot;
+use crate::pacing::Pacer;
+use crate::recipe::Recipe;
+use crate::recording::{self, Exchange, Recorder, Replayer, RequestShape};
+use crate::repair::{self, RepairMode};
+use crate::session;
+use crate::sink::ArtifactSink;
+use crate::system_prompts::Preset;
+use crate::tokens;
+use crate::toolinput;
+use crate::tools::{ToolRegistry, ToolResult};
+use crate::toolspec;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConversationError {
+    /// The Converse request itself failed (throttling, validation, access,
+    /// ...).  `request_id` is what AWS support asks for.  The turn was
+    /// rolled back.
+    Bedrock {
+        error: Box<dyn std::error::Error + Send + Sync>,
+        request_id: Option<String>,
+    },
+    /// The response didn't contain a message, or contained a kind of output
+    /// this version doesn't know.  The turn was rolled back.
+    NoOutput,
+    /// The response's message wasn't from the assistant but from this
+    /// role, e.g. because a proxy or guardrail rewrote it.  The turn was
+    /// rolled back.
+    UnexpectedRole(String),
+    /// No response within the deadline.  The turn was rolled back.
+    Timeout(Duration),
+    /// On the first turn, the model or region doesn't accept a tool
+    /// configuration.  The turn was rolled back, so it can be retried
+    /// without tools.
+    ToolsRejected(String),
+    /// The tool choice named a tool that isn't on offer.  Nothing was
+    /// sent, and the turn was rolled back.
+    UnknownTool(String),
+    /// The history has a tool use without a result and the repair mode is
+    /// strict.  The turn was rolled back.
+    CorruptHistory(String),
+    /// [Conversation::compact] couldn't run or the model didn't answer with
+    /// a summary.  The history is unchanged.
+    CompactionFailed(String),
+    /// Replaying a recording failed, usually because the requests no
+    /// longer match the recorded ones.  The turn was rolled back.
+    Replay(String),
+    /// The request would be larger than Bedrock accepts, so it wasn't sent.
+    /// The turn was rolled back.
+    PayloadTooLarge { bytes: usize, limit: usize },
+    /// A message couldn't be built, which is a bug.  The turn was rolled
+    /// back.
+    Build(String),
+    /// [Conversation::connect] couldn't make a client, e.g. for an unknown
+    /// AWS profile
+    Setup(String),
+    /// [Conversation::ask] gave up after this many rounds of tool uses.
+    /// The last round's tool uses are left unanswered, for the next turn's
+    /// repair.
+    ToolRounds(usize),
+}
+
+impl fmt::Display for ConversationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversationError::Bedrock {
+                error,
+                request_id: Some(id),
+            } => write!(f, "bedrock error: {} (request id {})", error, id),
+            ConversationError::Bedrock {
+                error,
+                request_id: None,
+            } => write!(f, "bedrock error: {}", error),
+            ConversationError::NoOutput => write!(f, "the model returned no output"),
+            ConversationError::UnexpectedRole(role) => {
+                write!(
+                    f,
+                    "the response came from {} instead of the assistant",
+                    role
+                )
+            }
+            ConversationError::Timeout(d) => {
+                write!(f, "no response from the model within {}s", d.as_secs())
+            }
+            ConversationError::ToolsRejected(msg) => {
+                write!(f, "the model doesn't support tools here: {}", msg)
+            }
+            ConversationError::UnknownTool(name) => {
+                write!(f, "no tool named {} is on offer", name)
+            }
+            ConversationError::CorruptHistory(msg) => write!(f, "corrupt history: {}", msg),
+            ConversationError::CompactionFailed(msg) => write!(f, "couldn't compact: {}", msg),
+            ConversationError::Replay(msg) => write!(f, "replay failed: {}", msg),
+            ConversationError::PayloadTooLarge { bytes, limit } => write!(
+                f,
+                "the request would be about {}, over the {} limit; compact the conversation, \
+                 remove attached images or use smaller ones",
+                limits::format_mb(*bytes),
+                limits::format_mb(*limit)
+            ),
+            ConversationError::Build(msg) => write!(f, "couldn't build the request: {}", msg),
+            ConversationError::Setup(msg) => write!(f, "couldn't connect: {}", msg),
+            ConversationError::ToolRounds(n) => {
+                write!(f, "the model was still using tools after {} rounds", n)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConversationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConversationError::Bedrock { error, .. } => Some(error.as_ref()),
+            ConversationError::NoOutput
+            | ConversationError::UnexpectedRole(_)
+            | ConversationError::Timeout(_)
+            | ConversationError::ToolsRejected(_)
+            | ConversationError::UnknownTool(_)
+            | ConversationError::CorruptHistory(_)
+            | ConversationError::CompactionFailed(_)
+            | ConversationError::Replay(_)
+            | ConversationError::PayloadTooLarge { .. }
+            | ConversationError::Build(_)
+            | ConversationError::Setup(_)
+            | ConversationError::ToolRounds(_) => None,
+        }
+    }
+}
+
+impl From<BuildError> for ConversationError {
+    fn from(e: BuildError) -> ConversationError {
+        ConversationError::Build(e.to_string())
+    }
+}
+
+/// Whether the model may, must, or mustn't use a tool for one request
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ToolChoice {
+    /// Up to the model
+    #[default]
+    Auto,
+    /// Some tool, the model picks which
+    Any,
+    /// This tool
+    Specific(String),
+    /// No tool.  Converse can't forbid tools, so the tool configuration is
+    /// left out of the request instead, which Bedrock only accepts while
+    /// the history has no tool uses or results.  With them, the tools stay
+    /// on offer.
+    None,
+}
+
+impl fmt::Display for ToolChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolChoice::Auto => write!(f, "auto"),
+            ToolChoice::Any => write!(f, "any"),
+            ToolChoice::Specific(name) => write!(f, "tool {}", name),
+            ToolChoice::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Models that honor `tool_choice`, matched as prefixes of the model id
+/// without its cross-region prefix, like [crate::cost]'s prices.  Others
+/// are sent the tools without one.
+static TOOL_CHOICE_MODELS: &[&str] = &["anthropic.claude", "amazon.nova", "mistral.mistral-large"];
+
+pub fn honors_tool_choice(model: &str) -> bool {
+    let model = ["us.", "eu.", "apac."]
+        .iter()
+        .find_map(|region| model.strip_prefix(region))
+        .unwrap_or(model);
+    TOOL_CHOICE_MODELS
+        .iter()
+        .any(|prefix| model.starts_with(prefix))
+}
+
+bedrock_pub! {
+    /// The assistant's side of a turn
+    #[derive(Debug, Clone)]
+    #[non_exhaustive]
+    struct Turn {
+        pub stop_reason: StopReason,
+        pub content: Vec<ContentBlock>,
+        /// Tokens billed for this request, if the model reported them
+        pub usage: Option<TokenUsage>,
+        /// Bedrock's x-amzn-RequestId for the request, not kept in recordings
+        /// made before they had it
+        pub request_id: Option<String>,
+    }
+}
+
+/// How many rounds of tool uses [Conversation::ask] answers before giving
+/// up on the model finishing
+pub const MAX_TOOL_ROUNDS: usize = 8;
+
+/// Tokens billed, over every request of a [Conversation::ask]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// Adds what the model reported for one request
+    pub(crate) fn add(&mut self, usage: &TokenUsage) {
+        self.input_tokens += usage.input_tokens().max(0) as u64;
+        self.output_tokens += usage.output_tokens().max(0) as u64;
+    }
+}
+
+/// What [Conversation::ask] got back
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct Reply {
+    /// The assistant's text, from every round, separated by blank lines
+    pub text: String,
+    /// What the model transmitted through `transmit_recipe`
+    pub recipes: Vec<Recipe>,
+    /// Where the [ArtifactSink] saved them
+    pub saved: Vec<String>,
+    /// The tools the model used, by name, in order
+    pub tools_used: Vec<String>,
+    /// Why the model stopped, e.g. end_turn or max_tokens
+    pub stop_reason: String,
+    pub usage: Usage,
+}
+
+/// The request metadata key for [Conversation::set_correlation_id]'s id
+pub const CORRELATION_ID_KEY: &str = "correlation_id";
+
+/// How many request ids [Conversation::request_ids] keeps
+pub const RECENT_REQUEST_IDS: usize = 5;
+
+/// Approximate serialized sizes of the last exchange, see
+/// [limits::request_bytes]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PayloadSizes {
+    pub request: usize,
+    pub response: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    client: Client,
+    model: String,
+    system: Option<Vec<SystemContentBlock>>,
+    tools: Option<ToolConfiguration>,
+    messages: Vec<Message>,
+    inference: Option<InferenceConfiguration>,
+    timeout: Option<Duration>,
+    retry_on_timeout: bool,
+    pacer: Option<Arc<Pacer>>,
+    repair: RepairMode,
+    recorder: Option<Arc<Recorder>>,
+    replayer: Option<Arc<Replayer>>,
+    last_sizes: Option<PayloadSizes>,
+    correlation_id: Option<String>,
+    request_ids: VecDeque<String>,
+    registry: ToolRegistry,
+    artifacts: Option<Arc<dyn ArtifactSink>>,
+}
+
+bedrock_pub! {
+    #[derive(Debug, Clone)]
+    struct ConversationBuilder {
+        client: Client,
+        model: String,
+        system: Option<Vec<SystemContentBlock>>,
+        tools: Option<ToolConfiguration>,
+        messages: Vec<Message>,
+        inference: Option<InferenceConfiguration>,
+        timeout: Option<Duration>,
+        retry_on_timeout: bool,
+        pacer: Option<Arc<Pacer>>,
+        repair: RepairMode,
+        recorder: Option<Arc<Recorder>>,
+        replayer: Option<Arc<Replayer>>,
+    }
+}
+
+impl ConversationBuilder {
+    /// Sets the system prompt
+    pub fn system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system = Some(vec![SystemContentBlock::Text(prompt.into())]);
+        self
+    }
+
+    /// Sets the system prompt from blocks, e.g.
+    /// [crate::system_prompts::SystemPrompt::blocks]
+    pub fn system(mut self, blocks: Vec<SystemContentBlock>) -> Self {
+        self.system = Some(blocks);
+        self
+    }
+
+    /// Like [ConversationBuilder::tools], where `None` sends no tool
+    /// configuration at all
+    pub fn set_tools(mut self, tools: Option<ToolConfiguration>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn tools(mut self, tools: ToolConfiguration) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Starts from an existing history, e.g. a resumed session
+    pub fn messages(mut self, messages: Vec<Message>) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    /// Temperature, max tokens, etc.  Model defaults are used if unset.
+    pub fn inference(mut self, inference: InferenceConfiguration) -> Self {
+        self.inference = Some(inference);
+        self
+    }
+
+    /// Gives up on a request that takes longer than this
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retries a request once if it times out.  Off by default since a retry
+    /// can double the cost of the turn.
+    pub fn retry_on_timeout(mut self, retry: bool) -> Self {
+        self.retry_on_timeout = retry;
+        self
+    }
+
+    /// Waits for the pacer's budget before each request
+    pub fn pacer(mut self, pacer: Arc<Pacer>) -> Self {
+        self.pacer = Some(pacer);
+        self
+    }
+
+    /// How to handle tool uses left without results, e.g. by a crash mid-turn
+    pub fn repair(mut self, mode: RepairMode) -> Self {
+        self.repair = mode;
+        self
+    }
+
+    /// Saves every request and response to the recorder
+    pub fn record(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(Arc::new(recorder));
+        self
+    }
+
+    /// Answers requests from a recording instead of the model.  The client
+    /// isn't used.
+    pub fn replay(mut self, replayer: Replayer) -> Self {
+        self.replayer = Some(Arc::new(replayer));
+        self
+    }
+
+    pub fn build(self) -> Conversation {
+        Conversation {
+            client: self.client,
+            model: self.model,
+            system: self.system,
+            tools: self.tools,
+            messages: self.messages,
+            inference: self.inference,
+            timeout: self.timeout,
+            retry_on_timeout: self.retry_on_timeout,
+            pacer: self.pacer,
+            repair: self.repair,
+            recorder: self.recorder,
+            replayer: self.replayer,
+            last_sizes: None,
+            correlation_id: None,
+            request_ids: VecDeque::new(),
+            registry: ToolRegistry::new(),
+            artifacts: None,
+        }
+    }
+}
+
+impl Conversation {
+    /// A conversation as `config` describes it, offering `transmit_recipe`
+    /// alongside the config's tools unless the system prompt is
+    /// [Preset::NoTools]
+    pub async fn connect(config: Config) -> Result<Conversation, ConversationError> {
+        let client = awsinit::runtime_client(config.aws_profile.clone(), config.aws_region.clone())
+            .await
+            .map_err(|e| ConversationError::Setup(e.to_string()))?;
+        let system = config.system();
+        let mut registry = config.tools.clone();
+        if system.preset() != Preset::NoTools && registry.get(oneshot::TRANSMIT_TOOL).is_none() {
+            registry.register(oneshot::transmission_def());
+        }
+        let mut builder = Conversation::builder(client, &config.model).system(system.blocks());
+        if !registry.is_empty() {
+            builder = builder.tools(registry.configuration()?);
+        }
+        if let Some(inference) = config.inference() {
+            builder = builder.inference(inference);
+        }
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let mut conversation = builder.build();
+        conversation.registry = registry;
+        conversation.artifacts = config.artifacts;
+        Ok(conversation)
+    }
+
+    /// Sends `prompt` and answers the model's tool uses until it's done:
+    /// registered tools through their handlers, and `transmit_recipe` by
+    /// saving the recipe to the [ArtifactSink].  `on_text` gets the
+    /// assistant's text as each response arrives.
+    pub async fn ask(
+        &mut self,
+        prompt: &str,
+        mut on_text: impl FnMut(&str),
+    ) -> Result<Reply, ConversationError> {
+        let mut reply = Reply::default();
+        let mut input = vec![ContentBlock::Text(prompt.to_string())];
+        for _ in 0..MAX_TOOL_ROUNDS {
+            let turn = self.send_blocks(input).await?;
+            reply.stop_reason = turn.stop_reason.as_str().to_string();
+            if let Some(usage) = &turn.usage {
+                reply.usage.add(usage);
+            }
+            let mut results = vec![];
+            for content in &turn.content {
+                match content {
+                    ContentBlock::Text(text) => {
+                        on_text(text);
+                        if !reply.text.is_empty() {
+                            reply.text.push_str("\n\n");
+                        }
+                        reply.text.push_str(text);
+                    }
+                    ContentBlock::ToolUse(tool_use) => {
+                        reply.tools_used.push(tool_use.name().to_string());
+                        let result = self.answer(tool_use, &mut reply).await?;
+                        results.push(ContentBlock::ToolResult(result));
+                    }
+                    _ => (),
+                }
+            }
+            if results.is_empty() {
+                return Ok(reply);
+            }
+            input = results;
+        }
+        Err(ConversationError::ToolRounds(MAX_TOOL_ROUNDS))
+    }
+
+    /// The result for one of the model's tool uses in [Conversation::ask]
+    async fn answer(
+        &self,
+        tool_use: &ToolUseBlock,
+        reply: &mut Reply,
+    ) -> Result<ToolResultBlock, ConversationError> {
+        let name = tool_use.name();
+        let answer = match toolinput::json(tool_use.input()) {
+            Ok(input) => match self.registry.call(name, &input) {
+                Some(answer) => answer,
+                None if name == oneshot::TRANSMIT_TOOL => self.transmit(&input, reply).await,
+                None => Err(format!("there's no tool named {}", name)),
+            },
+            Err(e) => Err(e),
+        };
+        let (text, status) = match answer {
+            Ok(text) => (text, ToolResultStatus::Success),
+            Err(text) => (text, ToolResultStatus::Error),
+        };
+        Ok(ToolResultBlock::builder()
+            .tool_use_id(tool_use.tool_use_id())
+            .content(ToolResultContentBlock::Text(text))
+            .status(status)
+            .build()?)
+    }
+
+    async fn transmit(&self, input: &serde_json::Value, reply: &mut Reply) -> ToolResult {
+        let recipe = Recipe::from_tool_input(input).ok_or_else(|| {
+            "recipe_details is missing; please send the whole recipe again".to_string()
+        })?;
+        let saved = match &self.artifacts {
+            Some(sink) => sink
+                .save(&recipe, &[])
+                .await
+                .map_err(|e| format!("couldn't save the recipe: {}", e))?,
+            None => vec![],
+        };
+        let answer = if saved.is_empty() {
+            "the recipe was received".to_string()
+        } else {
+            format!("the recipe was saved as {}", saved.join(", "))
+        };
+        reply.recipes.push(recipe);
+        reply.saved.extend(saved);
+        Ok(answer)
+    }
+
+    bedrock_pub! {
+        /// `model` is a model or inference profile id that supports Converse
+        fn builder(client: Client, model: impl Into<String>) -> ConversationBuilder {
+            ConversationBuilder {
+                client,
+                model: model.into(),
+                system: None,
+                tools: None,
+                messages: vec![],
+                inference: None,
+                timeout: None,
+                retry_on_timeout: false,
+                pacer: None,
+                repair: RepairMode::default(),
+                recorder: None,
+                replayer: None,
+            }
+        }
+    }
+
+    bedrock_pub! {
+        fn client(&self) -> &Client {
+            &self.client
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn set_model(&mut self, model: impl Into<String>) {
+        self.model = model.into();
+    }
+
+    bedrock_pub! {
+        fn inference(&self) -> Option<&InferenceConfiguration> {
+            self.inference.as_ref()
+        }
+    }
+
+    bedrock_pub! {
+        fn set_inference(&mut self, inference: Option<InferenceConfiguration>) {
+            self.inference = inference;
+        }
+    }
+
+    bedrock_pub! {
+        fn tools(&self) -> Option<&ToolConfiguration> {
+            self.tools.as_ref()
+        }
+    }
+
+    bedrock_pub! {
+        fn set_tools(&mut self, tools: Option<ToolConfiguration>) {
+            self.tools = tools;
+        }
+    }
+
+    pub fn set_system_prompt(&mut self, prompt: impl Into<String>) {
+        self.system = Some(vec![SystemContentBlock::Text(prompt.into())]);
+    }
+
+    bedrock_pub! {
+        fn set_system(&mut self, blocks: Vec<SystemContentBlock>) {
+            self.system = Some(blocks);
+        }
+    }
+
+    bedrock_pub! {
+        fn messages(&self) -> &[Message] {
+            &self.messages
+        }
+    }
+
+    bedrock_pub! {
+        fn set_messages(&mut self, messages: Vec<Message>) {
+            self.messages = messages;
+        }
+    }
+
+    /// Tags the requests that follow, in the debug log, in recordings, and
+    /// in the request's metadata, which Bedrock's invocation logs keep,
+    /// with the caller's id for what the user did, e.g. a shell turn
+    pub fn set_correlation_id(&mut self, id: Option<String>) {
+        self.correlation_id = id;
+    }
+
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_deref()
+    }
+
+    /// Bedrock's ids for the most recent requests, failed ones included,
+    /// oldest first
+    pub fn request_ids(&self) -> Vec<&str> {
+        self.request_ids.iter().map(String::as_str).collect()
+    }
+
+    fn note_request_id(&mut self, id: &str) {
+        debug!(
+            "request id {} (correlation id {})",
+            id,
+            self.correlation_id.as_deref().unwrap_or("none")
+        );
+        if self.request_ids.len() == RECENT_REQUEST_IDS {
+            self.request_ids.pop_front();
+        }
+        self.request_ids.push_back(id.to_string());
+    }
+
+    bedrock_pub! {
+        /// Sends `input` as the user's side of the turn, with the entire history,
+        /// and records both it and the assistant's response in the history.
+        async fn send(&mut self, input: ContentBlock) -> Result<Turn, ConversationError> {
+            self.send_blocks(vec![input]).await
+        }
+    }
+
+    bedrock_pub! {
+        /// Like [Conversation::send] with several blocks in the user's message,
+        /// e.g. the results of every tool the model asked for
+        async fn send_blocks(
+            &mut self,
+            input: Vec<ContentBlock>,
+        ) -> Result<Turn, ConversationError> {
+            self.send_blocks_with(input, None).await
+        }
+    }
+
+    bedrock_pub! {
+        /// Like [Conversation::send_blocks], with inference settings for this
+        /// request only, e.g. [crate::models::InferenceProfilePreset::Precise]
+        /// for a follow-up the program issues itself
+        async fn send_blocks_with(
+            &mut self,
+            input: Vec<ContentBlock>,
+            inference: Option<InferenceConfiguration>,
+        ) -> Result<Turn, ConversationError> {
+            self.send_blocks_choosing(input, inference, ToolChoice::Auto)
+                .await
+        }
+    }
+
+    bedrock_pub! {
+        /// Like [Conversation::send_blocks_with], with `choice` deciding
+        /// whether the model may use a tool for this request only
+        async fn send_blocks_choosing(
+            &mut self,
+            input: Vec<ContentBlock>,
+            inference: Option<InferenceConfiguration>,
+            choice: ToolChoice,
+        ) -> Result<Turn, ConversationError> {
+            let inference = inference.or_else(|| self.inference.clone());
+            debug!("model: {}", self.model);
+            debug!("{:?}", input);
+
+            let msg = Message::builder()
+                .role(ConversationRole::User)
+                .set_content(Some(input))
+                .build()?;
+            self.messages.push(msg);
+            match repair::repair(&mut self.messages, self.repair) {
+                Ok(repaired) => {
+                    for dangling in repaired {
+                        warn!("repaired history ({}): {}", self.repair, dangling);
+                    }
+                }
+                Err(dangling) => {
+                    self.messages.pop();
+                    return Err(ConversationError::CorruptHistory(dangling.to_string()));
+                }
+            }
+
+            let request = self.request_bytes();
+            match limits::check_request(request) {
+                PayloadCheck::Fits => {}
+                PayloadCheck::NearLimit => warn!(
+                    "the request is about {}, close to the {} limit; consider compacting",
+                    limits::format_mb(request),
+                    limits::format_mb(limits::REQUEST_BYTES)
+                ),
+                PayloadCheck::TooLarge => {
+                    self.messages.pop();
+                    return Err(ConversationError::PayloadTooLarge {
+                        bytes: request,
+                        limit: limits::REQUEST_BYTES,
+                    });
+                }
+            }
+
+            let tools = match self.tool_config(&choice) {
+                Ok(tools) => tools,
+                Err(e) => {
+                    self.messages.pop();
+                    return Err(e);
+                }
+            };
+            let mut retried = false;
+            let response = loop {
+                match self.converse(inference.clone(), tools.clone()).await {
+                    Ok(response) => break response,
+                    Err(ConversationError::Timeout(d)) if self.retry_on_timeout && !retried => {
+                        warn!("no response within {}s, retrying", d.as_secs());
+                        retried = true;
+                    }
+                    Err(e) => {
+                        if let ConversationError::Bedrock {
+                            request_id: Some(id),
+                            ..
+                        } = &e
+                        {
+                            self.note_request_id(id);
+                        }
+                        // roll back so the history doesn't end with an unanswered message
+                        self.messages.pop();
+                        return Err(e);
+                    }
+                }
+            };
+            debug!("{:?}", response);
+            let request_id = response.request_id().map(str::to_string);
+            if let Some(id) = &request_id {
+                self.note_request_id(id);
+            }
+            if let (Some(pacer), Some(usage)) = (&self.pacer, response.usage()) {
+                pacer.record_tokens(usage.total_tokens().max(0) as u32, Instant::now());
+            }
+
+            let stop_reason = response.stop_reason().clone();
+            // the output enum is non-exhaustive, so anything but a message is
+            // treated as no output rather than guessed at
+            let Some(ConverseOutput::Message(msg)) = response.output() else {
+                debug!("unexpected output: {:?}", response.output());
+                self.messages.pop();
+                return Err(ConversationError::NoOutput);
+            };
+            if msg.role() != &ConversationRole::Assistant {
+                debug!("message with unexpected role: {:?}", msg);
+                self.messages.pop();
+                return Err(ConversationError::UnexpectedRole(
+                    msg.role().as_str().to_string(),
+                ));
+            }
+
+            let sizes = PayloadSizes {
+                request,
+                response: limits::content_bytes(msg.content()),
+            };
+            debug!("payload sizes: {:?}", sizes);
+            self.last_sizes = Some(sizes);
+
+            // save assistant's response onto the message history
+            self.messages.push(msg.clone());
+            debug!("{:?}", msg);
+            debug!(">>> Stop Reason {} <<<", stop_reason);
+
+            Ok(Turn {
+                stop_reason,
+                content: msg.content().to_vec(),
+                usage: response.usage().cloned(),
+                request_id,
+            })
+        }
+    }
+
+    /// The tool configuration to send for `choice`, falling back to leaving
+    /// the choice to the model where the model can't honor it.  Choosing a
+    /// tool that isn't on offer is an error.
+    fn tool_config(
+        &self,
+        choice: &ToolChoice,
+    ) -> Result<Option<ToolConfiguration>, ConversationError> {
+        let Some(tools) = &self.tools else {
+            return Ok(None);
+        };
+        let choice = match choice {
+            ToolChoice::None => {
+                let tool_blocks = self.messages.iter().any(|m| {
+                    m.content()
+                        .iter()
+                        .any(|c| c.is_tool_use() || c.is_tool_result())
+                });
+                if !tool_blocks {
+                    debug!("tool choice: none, sending no tools");
+                    return Ok(None);
+                }
+                debug!("tool choice: none, but the history has tool uses, so the tools stay");
+                return Ok(Some(tools.clone()));
+            }
+            ToolChoice::Specific(name)
+                if !tools
+                    .tools()
+                    .iter()
+                    .any(|t| t.as_tool_spec().is_ok_and(|spec| spec.name() == name)) =>
+            {
+                return Err(ConversationError::UnknownTool(name.clone()));
+            }
+            ToolChoice::Any | ToolChoice::Specific(_) if !honors_tool_choice(&self.model) => {
+                debug!(
+                    "tool choice: {}, but {} doesn't take one, leaving it to the model",
+                    choice, self.model
+                );
+                ToolChoice::Auto
+            }
+            choice => choice.clone(),
+        };
+        debug!("tool choice: {}", choice);
+        let sdk_choice = match &choice {
+            ToolChoice::Auto | ToolChoice::None => return Ok(Some(tools.clone())),
+            ToolChoice::Any => {
+                aws_sdk_bedrockruntime::types::ToolChoice::Any(AnyToolChoice::builder().build())
+            }
+            ToolChoice::Specific(name) => aws_sdk_bedrockruntime::types::ToolChoice::Tool(
+                SpecificToolChoice::builder().name(name).build()?,
+            ),
+        };
+        Ok(Some(
+            ToolConfiguration::builder()
+                .set_tools(Some(tools.tools().to_vec()))
+                .tool_choice(sdk_choice)
+                .build()?,
+        ))
+    }
+
+    /// Characters sent with the next request: the system prompt, the tool
+    /// configuration, and the history
+    pub fn request_chars(&self) -> usize {
+        let system: usize = self
+            .system
+            .iter()
+            .flatten()
+            .map(|block| block.as_text().map_or(0, String::len))
+            .sum();
+        let tools = self
+            .tools
+            .as_ref()
+            .map_or(0, |t| toolspec::canonical_json(t).to_string().len());
+        let history: usize = self
+            .messages
+            .iter()
+            .map(|m| tokens::content_chars(m.content()))
+            .sum();
+        system + tools + history
+    }
+
+    /// Approximate serialized size of the next request, images included,
+    /// to compare with [limits::REQUEST_BYTES]
+    pub fn request_bytes(&self) -> usize {
+        limits::request_bytes(
+            self.system.as_deref().unwrap_or_default(),
+            self.tools.as_ref(),
+            &self.messages,
+        )
+    }
+
+    /// Sizes of the last request and response that went through
+    pub fn payload_sizes(&self) -> Option<PayloadSizes> {
+        self.last_sizes
+    }
+
+    /// A rough count of the tokens the next request will cost, at about four
+    /// characters per token.  See [tokens::Estimator] for a better one.
+    pub fn estimated_tokens(&self) -> usize {
+        tokens::Estimator::default().tokens(self.request_chars())
+    }
+
+    /// Removes the exchanges whose user prompt `is_aside` picks out: that
+    /// message and everything up to the next prompt from the user, tool
+    /// rounds included, so the history stays valid.  Returns how many
+    /// exchanges were removed.
+    pub fn drop_exchanges(&mut self, is_aside: impl Fn(&str) -> bool) -> usize {
+        let mut dropped = 0;
+        let mut dropping = false;
+        let mut kept = Vec::with_capacity(self.messages.len());
+        for msg in self.messages.drain(..) {
+            // tool results come back as user messages too, but without text
+            if msg.role() == &ConversationRole::User
+                && msg.content().iter().any(ContentBlock::is_text)
+            {
+                dropping = msg
+                    .content()
+                    .iter()
+                    .filter_map(|c| c.as_text().ok())
+                    .any(|text| is_aside(text));
+                if dropping {
+                    dropped += 1;
+                }
+            }
+            if !dropping {
+                kept.push(msg);
+            }
+        }
+        self.messages = kept;
+        dropped
+    }
+
+    bedrock_pub! {
+        /// Removes and returns everything before the last `keep` turns, where a
+        /// turn starts at a prompt from the user and runs through its tool
+        /// rounds, so the history stays valid.  Returns nothing while a tool use
+        /// is waiting for its result or the last message hasn't been answered.
+        fn split_off_turns(&mut self, keep: usize) -> Vec<Message> {
+            let pending = self
+                .messages
+                .last()
+                .is_some_and(|m| m.role() == &ConversationRole::User);
+            if pending || repair::find_dangling(&self.messages).is_some() {
+                return vec![];
+            }
+            // tool results come back as user messages too, but without text
+            let starts: Vec<usize> = self
+                .messages
+                .iter()
+                .enumerate()
+                .filter(|(_, msg)| {
+                    msg.role() == &ConversationRole::User
+                        && msg.content().iter().any(ContentBlock::is_text)
+                })
+                .map(|(idx, _)| idx)
+                .collect();
+            let keep = keep.max(1);
+            if starts.len() <= keep {
+                return vec![];
+            }
+            let cut = starts[starts.len() - keep];
+            self.messages.drain(..cut).collect()
+        }
+    }
+
+    bedrock_pub! {
+        /// Asks the model to summarize the conversation following `instruction`,
+        /// then replaces the whole history with that summary as a single
+        /// exchange.  Refuses while a tool use is waiting for its result.
+        /// Returns the summary.
+        async fn compact(
+            &mut self,
+            instruction: &str,
+            inference: Option<InferenceConfiguration>,
+        ) -> Result<String, ConversationError> {
+            let pending = self
+                .messages
+                .last()
+                .is_some_and(|m| m.role() == &ConversationRole::User);
+            if let Some(dangling) = repair::find_dangling(&self.messages) {
+                return Err(ConversationError::CompactionFailed(format!(
+                    "a tool round is in progress ({})",
+                    dangling
+                )));
+            }
+            if pending {
+                return Err(ConversationError::CompactionFailed(
+                    "the last message hasn't been answered".to_string(),
+                ));
+            }
+            if self.messages.is_empty() {
+                return Err(ConversationError::CompactionFailed(
+                    "nothing to compact".to_string(),
+                ));
+            }
+
+            let original = self.messages.clone();
+            let turn = self
+                .send_blocks_choosing(
+                    vec![ContentBlock::Text(instruction.to_string())],
+                    inference,
+                    ToolChoice::None,
+                )
+                .await;
+            let summary = match turn {
+                Ok(turn) if turn.content.iter().any(ContentBlock::is_tool_use) => {
+                    Err(ConversationError::CompactionFailed(
+                        "the model used a tool instead".to_string(),
+                    ))
+                }
+                Ok(turn) => {
+                    let text = turn
+                        .content
+                        .iter()
+                        .filter_map(|c| c.as_text().ok())
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join("\n\n");
+                    if text.trim().is_empty() {
+                        Err(ConversationError::CompactionFailed(
+                            "the model returned an empty summary".to_string(),
+                        ))
+                    } else {
+                        Ok(text)
+                    }
+                }
+                Err(e) => Err(e),
+            };
+            let summary = match summary {
+                Ok(summary) => summary,
+                Err(e) => {
+                    self.messages = original;
+                    return Err(e);
+                }
+            };
+
+            // a user/assistant pair keeps the history valid for the next turn
+            let pair = Message::builder()
+                .role(ConversationRole::User)
+                .content(ContentBlock::Text(format!(
+                    "Here is a summary of our conversation so far:\n\n{}",
+                    summary
+                )))
+                .build()
+                .and_then(|user| {
+                    let assistant = Message::builder()
+                        .role(ConversationRole::Assistant)
+                        .content(ContentBlock::Text(
+                            "Thanks, I'll pick up from there.".to_string(),
+                        ))
+                        .build()?;
+                    Ok(vec![user, assistant])
+                });
+            match pair {
+                Ok(pair) => self.messages = pair,
+                Err(e) => {
+                    self.messages = original;
+                    return Err(e.into());
+                }
+            }
+            Ok(summary)
+        }
+    }
+
+    /// Sends the whole history with `tools`, honoring the timeout
+    async fn converse(
+        &self,
+        inference: Option<InferenceConfiguration>,
+        tools: Option<ToolConfiguration>,
+    ) -> Result<ConverseResponse, ConversationError> {
+        let shape = RequestShape {
+            model: self.model.clone(),
+            message_count: self.messages.len(),
+            tools_sha256: recording::tools_sha256(tools.as_ref()),
+        };
+        if let Some(replayer) = &self.replayer {
+            let exchange = replayer
+                .next(&shape)
+                .map_err(|e| ConversationError::Replay(e.to_string()))?;
+            return replayed(exchange);
+        }
+        let response = self.send_request(inference, tools).await?;
+        if let Some(recorder) = &self.recorder {
+            self.record(recorder, shape, &response);
+        }
+        Ok(response)
+    }
+
+    fn record(&self, recorder: &Recorder, request: RequestShape, response: &ConverseResponse) {
+        let message = match response.output() {
+            Some(ConverseOutput::Message(msg)) => Some(session::to_stored_message(msg)),
+            _ => None,
+        };
+        let exchange = Exchange {
+            request,
+            last_message: self.messages.last().map(session::to_stored_message),
+            response: message,
+            stop_reason: response.stop_reason().as_str().to_string(),
+            input_tokens: response.usage().map_or(0, |u| u.input_tokens()),
+            output_tokens: response.usage().map_or(0, |u| u.output_tokens()),
+            request_id: response.request_id().map(str::to_string),
+            correlation_id: self.correlation_id.clone(),
+        };
+        if let Err(e) = recorder.record(&exchange) {
+            warn!("couldn't record the exchange: {}", e);
+        }
+    }
+
+    async fn send_request(
+        &self,
+        inference: Option<InferenceConfiguration>,
+        tools: Option<ToolConfiguration>,
+    ) -> Result<ConverseResponse, ConversationError> {
+        // only the first turn can tell that tools aren't supported: later,
+        // the history has tool uses that need the tools anyway
+        let first_turn = !self
+            .messages
+            .iter()
+            .any(|m| m.role() == &ConversationRole::Assistant);
+        let offered_tools = tools.is_some();
+        if let Some(pacer) = &self.pacer {
+            pacer.acquire().await;
+        }
+        let request = self
+            .client
+            .converse()
+            .model_id(self.model.clone())
+            .set_system(self.system.clone())
+            .set_messages(Some(self.messages.clone()))
+            .set_tool_config(tools)
+            .set_inference_config(inference)
+            .set_request_metadata(
+                self.correlation_id
+                    .as_ref()
+                    .map(|id| HashMap::from([(CORRELATION_ID_KEY.to_string(), id.clone())])),
+            )
+            .send();
+        let result = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, request)
+                .await
+                .map_err(|_| ConversationError::Timeout(timeout))?,
+            None => request.await,
+        };
+        result.map_err(|e| {
+            let request_id = e.request_id().map(str::to_string);
+            error!(
+                "{:?} (request id {}, correlation id {})",
+                e,
+                request_id.as_deref().unwrap_or("none"),
+                self.correlation_id.as_deref().unwrap_or("none")
+            );
+            let e: aws_sdk_bedrockruntime::Error = e.into();
+            match e {
+                aws_sdk_bedrockruntime::Error::ValidationException(v)
+                    if offered_tools && first_turn && rejects_tool_use(v.message()) =>
+                {
+                    ConversationError::ToolsRejected(v.message().unwrap_or_default().to_string())
+                }
+                other => ConversationError::Bedrock {
+                    error: Box::new(other),
+                    request_id,
+                },
+            }
+        })
+    }
+}
+
+/// Rebuilds the response Bedrock sent when the exchange was recorded
+fn replayed(exchange: &Exchange) -> Result<ConverseResponse, ConversationError> {
+    let invalid = |e: &dyn fmt::Display| ConversationError::Replay(e.to_string());
+    let msg = match &exchange.response {
+        Some(stored) => Some(
+            session::to_bedrock_message(stored)
+                .ok_or_else(|| invalid(&"the recorded response isn't a valid message"))?,
+        ),
+        None => None,
+    };
+    let usage = TokenUsage::builder()
+        .input_tokens(exchange.input_tokens)
+        .output_tokens(exchange.output_tokens)
+        .total_tokens(exchange.input_tokens + exchange.output_tokens)
+        .build()
+        .map_err(|e| invalid(&e))?;
+    let metrics = ConverseMetrics::builder()
+        .latency_ms(0)
+        .build()
+        .map_err(|e| invalid(&e))?;
+    ConverseResponse::builder()
+        .set_output(msg.map(ConverseOutput::Message))
+        .stop_reason(StopReason::from(exchange.stop_reason.as_str()))
+        .usage(usage)
+        .metrics(metrics)
+        .build()
+        .map_err(|e| invalid(&e))
+}
+
+/// Whether a validation error is the model or region refusing tool use
+/// altogether, e.g. "This model doesn't support tool use."  Other messages
+/// about tools, like a tool use without its result or input that doesn't
+/// match the schema, are about the request and leave the tools in place.
+fn rejects_tool_use(message: Option<&str>) -> bool {
+    let Some(message) = message else {
+        return false;
+    };
+    let message = message.to_lowercase();
+    [
+        "doesn't support tool use",
+        "does not support tool use",
+        "tool use is not supported",
+        "tool use isn't supported",
+    ]
+    .iter()
+    .any(|refusal| message.contains(refusal))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use aws_sdk_bedrockruntime::config::{BehaviorVersion, Region};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::scripted::{self, Scripted};
+    use crate::sink::MemorySink;
+    use crate::tools::ToolDef;
+
+    #[tokio::test]
+    async fn asks_through_a_recording() {
+        let recording =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lib/testdata/recordings/pantry");
+        let registry = ToolRegistry::new()
+            .tool(
+                ToolDef::new("check_pantry", "what's at home")
+                    .handler(|_| Ok("rice, black beans, cumin".to_string())),
+            )
+            .tool(ToolDef::new(oneshot::TRANSMIT_TOOL, "saves a recipe"));
+        // never used: every response comes from the recording
+        let client = Client::from_conf(
+            aws_sdk_bedrockruntime::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(Region::new("us-east-1"))
+                .build(),
+        );
+        let mut conversation = Conversation::builder(client, oneshot::DEFAULT_MODEL)
+            .tools(registry.configuration().unwrap())
+            .replay(Replayer::open(&recording).unwrap())
+            .build();
+        let sink = Arc::new(MemorySink::new());
+        conversation.registry = registry;
+        conversation.artifacts = Some(sink.clone());
+
+        let mut streamed = vec![];
+        let reply = conversation
+            .ask("What can I make tonight?", |text| {
+                streamed.push(text.to_string())
+            })
+            .await
+            .unwrap();
+        assert_eq!(reply.tools_used, ["check_pantry", oneshot::TRANSMIT_TOOL]);
+        assert_eq!(reply.saved, ["memory:rice_and_beans"]);
+        assert_eq!(reply.recipes, sink.recipes());
+        assert_eq!(reply.recipes[0].title.as_deref(), Some("Rice and Beans"));
+        assert_eq!(
+            streamed,
+            [
+                "Let me see what you have.",
+                "I saved Rice and Beans for you."
+            ]
+        );
+        assert_eq!(reply.text, streamed.join("\n\n"));
+        assert_eq!(reply.stop_reason, "end_turn");
+        assert_eq!(reply.usage.input_tokens, 412 + 470 + 612);
+        assert_eq!(reply.usage.output_tokens, 38 + 121 + 12);
+        assert_eq!(conversation.messages().len(), 6);
+
+        // one request more than was recorded
+        let err = conversation.ask("Thanks!", |_| ()).await.unwrap_err();
+        assert!(matches!(err, ConversationError::Replay(_)), "{}", err);
+        assert_eq!(conversation.messages().len(), 6);
+    }
+
+    /// Replays the one exchange in `testdata/recordings/<name>`, returning
+    /// the error and what's left of the history
+    async fn replay_one(name: &str) -> (ConversationError, usize) {
+        let recording = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/lib/testdata/recordings")
+            .join(name);
+        let backend = Scripted::new([]);
+        let mut conversation = Conversation::builder(backend.client(), oneshot::DEFAULT_MODEL)
+            .replay(Replayer::open(&recording).unwrap())
+            .build();
+        let err = conversation
+            .send(ContentBlock::Text("What can I make tonight?".into()))
+            .await
+            .unwrap_err();
+        assert!(backend.requests().is_empty());
+        (err, conversation.messages().len())
+    }
+
+    #[tokio::test]
+    async fn a_response_from_another_role_is_rolled_back() {
+        let (err, messages) = replay_one("wrong_role").await;
+        assert!(
+            matches!(&err, ConversationError::UnexpectedRole(role) if role == "user"),
+            "{}",
+            err
+        );
+        assert_eq!(messages, 0);
+    }
+
+    #[tokio::test]
+    async fn a_response_without_output_is_rolled_back() {
+        let (err, messages) = replay_one("no_output").await;
+        assert!(matches!(err, ConversationError::NoOutput), "{}", err);
+        assert_eq!(messages, 0);
+    }
+
+    fn pantry_tools() -> ToolConfiguration {
+        ToolRegistry::new()
+            .tool(ToolDef::new("check_pantry", "what's at home"))
+            .tool(ToolDef::new(oneshot::TRANSMIT_TOOL, "saves a recipe"))
+            .configuration()
+            .unwrap()
+    }
+
+    /// The request body sent for one turn with `choice`, as `model`
+    async fn sent_with(model: &str, choice: ToolChoice) -> serde_json::Value {
+        let backend = Scripted::new([scripted::Reply::text("Rice and beans?")]);
+        let mut conversation = Conversation::builder(backend.client(), model)
+            .tools(pantry_tools())
+            .build();
+        conversation
+            .send_blocks_choosing(vec![ContentBlock::Text("dinner?".into())], None, choice)
+            .await
+            .unwrap();
+        backend.last_body()
+    }
+
+    #[tokio::test]
+    async fn tool_choice_is_sent_as_asked() {
+        let tool_names = |body: &serde_json::Value| -> Vec<String> {
+            body["toolConfig"]["tools"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| t["toolSpec"]["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let model = oneshot::DEFAULT_MODEL;
+
+        let body = sent_with(model, ToolChoice::Auto).await;
+        assert_eq!(tool_names(&body), ["check_pantry", oneshot::TRANSMIT_TOOL]);
+        assert!(body["toolConfig"].get("toolChoice").is_none(), "{}", body);
+
+        let body = sent_with(model, ToolChoice::Any).await;
+        assert_eq!(tool_names(&body), ["check_pantry", oneshot::TRANSMIT_TOOL]);
+        assert_eq!(body["toolConfig"]["toolChoice"], json!({"any": {}}));
+
+        let body = sent_with(model, ToolChoice::Specific("check_pantry".into())).await;
+        assert_eq!(tool_names(&body), ["check_pantry", oneshot::TRANSMIT_TOOL]);
+        assert_eq!(
+            body["toolConfig"]["toolChoice"],
+            json!({"tool": {"name": "check_pantry"}})
+        );
+
+        let body = sent_with(model, ToolChoice::None).await;
+        assert!(body.get("toolConfig").is_none(), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn models_without_tool_choice_get_the_tools_alone() {
+        for choice in [ToolChoice::Any, ToolChoice::Specific("check_pantry".into())] {
+            let body = sent_with("meta.llama3-1-70b-instruct-v1:0", choice.clone()).await;
+            assert!(body["toolConfig"]["tools"].is_array(), "{}", choice);
+            assert!(
+                body["toolConfig"].get("toolChoice").is_none(),
+                "{}: {}",
+                choice,
+                body
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn choosing_a_tool_that_isnt_offered_is_an_error() {
+        let backend = Scripted::new([scripted::Reply::text("Rice and beans?")]);
+        let mut conversation = Conversation::builder(backend.client(), oneshot::DEFAULT_MODEL)
+            .tools(pantry_tools())
+            .build();
+        let err = conversation
+            .send_blocks_choosing(
+                vec![ContentBlock::Text("dinner?".into())],
+                None,
+                ToolChoice::Specific("order_groceries".into()),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, ConversationError::UnknownTool(name) if name == "order_groceries"),
+            "{}",
+            err
+        );
+        assert!(backend.requests().is_empty());
+        assert!(conversation.messages().is_empty());
+    }
+
+    /// A conversation that gives up on requests after 100ms
+    fn impatient(backend: &Scripted, retry: bool) -> Conversation {
+        Conversation::builder(backend.client(), oneshot::DEFAULT_MODEL)
+            .timeout(Duration::from_millis(100))
+            .retry_on_timeout(retry)
+            .build()
+    }
+
+    fn slow(text: &str) -> scripted::Reply {
+        scripted::Reply::text(text).after(Duration::from_secs(10))
+    }
+
+    #[tokio::test]
+    async fn a_timed_out_turn_is_rolled_back() {
+        let backend = Scripted::new([scripted::Reply::text("Rice and beans?"), slow("Tacos?")]);
+        let mut conversation = impatient(&backend, false);
+        conversation
+            .send(ContentBlock::Text("dinner?".into()))
+            .await
+            .unwrap();
+
+        let err = conversation
+            .send(ContentBlock::Text("something else?".into()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ConversationError::Timeout(_)), "{}", err);
+        assert_eq!(backend.requests().len(), 2);
+        assert_eq!(conversation.messages().len(), 2);
+        assert_eq!(
+            conversation.messages()[1].content()[0].as_text().unwrap(),
+            "Rice and beans?"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_timed_out_request_is_retried_once() {
+        let backend = Scripted::new([slow("Tacos?"), scripted::Reply::text("Rice and beans?")]);
+        let mut conversation = impatient(&backend, true);
+        let turn = conversation
+            .send(ContentBlock::Text("dinner?".into()))
+            .await
+            .unwrap();
+        assert_eq!(turn.content[0].as_text().unwrap(), "Rice and beans?");
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 2);
+        // the retry is the same request, not one with the user's message twice
+        assert_eq!(requests[0].body, requests[1].body);
+        assert_eq!(conversation.messages().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn a_retry_that_times_out_too_gives_up() {
+        let backend = Scripted::new([
+            slow("Tacos?"),
+            slow("Tacos?"),
+            scripted::Reply::text("Rice and beans?"),
+        ]);
+        let mut conversation = impatient(&backend, true);
+        let err = conversation
+            .send(ContentBlock::Text("dinner?".into()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ConversationError::Timeout(_)), "{}", err);
+        assert_eq!(backend.requests().len(), 2);
+        assert!(conversation.messages().is_empty());
+    }
+
+    #[test]
+    fn only_refusing_tool_use_rejects_the_tools() {
+        for message in [
+            "This model doesn't support tool use.",
+            "The model does not support tool use in this region",
+            "Tool use is not supported for this model",
+        ] {
+            assert!(rejects_tool_use(Some(message)), "{}", message);
+        }
+        for message in [
+            "Expected toolResult blocks at messages.2.content for the following Ids: tooluse_abc",
+            "The tool_use ids must have corresponding tool_result blocks",
+            "The value at toolConfig.tools.0.toolSpec.inputSchema.json.type must be one of the following: object",
+            "Malformed input request, please reformat your input and try again.",
+        ] {
+            assert!(!rejects_tool_use(Some(message)), "{}", message);
+        }
+        assert!(!rejects_tool_use(None));
+    }
+
+    #[tokio::test]
+    async fn request_ids_are_kept_and_the_correlation_id_is_sent() {
+        let dir = std::env::temp_dir().join(format!("gourmand-recording-{}", ulid::Ulid::new()));
+        let backend = Scripted::new([
+            scripted::Reply::text("Rice and beans?").request_id("req-1"),
+            scripted::Reply::error(403, "AccessDeniedException", "not allowed").request_id("req-2"),
+            scripted::Reply::text("Tacos?"),
+        ]);
+        let mut conversation = Conversation::builder(backend.client(), oneshot::DEFAULT_MODEL)
+            .record(crate::recording::Recorder::create(&dir).unwrap())
+            .build();
+
+        conversation.set_correlation_id(Some("01JTESTSESSION-1".to_string()));
+        let turn = conversation
+            .send(ContentBlock::Text("dinner?".into()))
+            .await
+            .unwrap();
+        assert_eq!(turn.request_id.as_deref(), Some("req-1"));
+        assert_eq!(
+            backend.last_body()["requestMetadata"],
+            json!({CORRELATION_ID_KEY: "01JTESTSESSION-1"})
+        );
+        let recorded: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("0001.json")).unwrap()).unwrap();
+        assert_eq!(recorded["request_id"], "req-1");
+        assert_eq!(recorded["correlation_id"], "01JTESTSESSION-1");
+
+        conversation.set_correlation_id(Some("01JTESTSESSION-2".to_string()));
+        let err = conversation
+            .send(ContentBlock::Text("lunch?".into()))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, ConversationError::Bedrock { request_id: Some(id), .. } if id == "req-2"),
+            "{:?}",
+            err
+        );
+        assert!(err.to_string().ends_with("(request id req-2)"), "{}", err);
+        assert_eq!(
+            backend.last_body()["requestMetadata"][CORRELATION_ID_KEY],
+            "01JTESTSESSION-2"
+        );
+
+        conversation.set_correlation_id(None);
+        let turn = conversation
+            .send(ContentBlock::Text("lunch?".into()))
+            .await
+            .unwrap();
+        assert_eq!(turn.request_id, None);
+        assert!(backend.last_body().get("requestMetadata").is_none());
+        assert_eq!(conversation.request_ids(), ["req-1", "req-2"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_the_latest_request_ids_are_kept() {
+        let replies = (1..=RECENT_REQUEST_IDS + 2)
+            .map(|n| scripted::Reply::text("Rice and beans?").request_id(&format!("req-{}", n)));
+        let backend = Scripted::new(replies);
+        let mut conversation =
+            Conversation::builder(backend.client(), oneshot::DEFAULT_MODEL).build();
+        for _ in 0..RECENT_REQUEST_IDS + 2 {
+            conversation
+                .send(ContentBlock::Text("dinner?".into()))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            conversation.request_ids(),
+            ["req-3", "req-4", "req-5", "req-6", "req-7"]
+        );
+    }
+}
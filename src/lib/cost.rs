@@ -0,0 +1,138 @@
+//! Rough dollar estimates for model and image requests, from on-demand list
+//! prices in us-east-1.  Good enough to ask before something expensive, not
+//! for reconciling a bill.
+use aws_sdk_bedrockruntime::types::TokenUsage;
+use serde::{Deserialize, Serialize};
+
+/// Dollars per thousand tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Price {
+    pub input_per_1k: f64,
+    pub output_per_1k: f64,
+}
+
+impl Price {
+    const fn new(input_per_1k: f64, output_per_1k: f64) -> Price {
+        Price {
+            input_per_1k,
+            output_per_1k,
+        }
+    }
+
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_per_1k + output_tokens as f64 * self.output_per_1k)
+            / 1000.0
+    }
+}
+
+/// Matched as prefixes of the model id, without its cross-region prefix
+/// (`us.`, `eu.`, `apac.`).  More specific ids come first.
+static PRICES: &[(&str, Price)] = &[
+    ("amazon.nova-micro", Price::new(0.000035, 0.00014)),
+    ("amazon.nova-lite", Price::new(0.00006, 0.00024)),
+    ("amazon.nova-pro", Price::new(0.0008, 0.0032)),
+    ("anthropic.claude-3-5-haiku", Price::new(0.0008, 0.004)),
+    ("anthropic.claude-3-haiku", Price::new(0.00025, 0.00125)),
+    ("anthropic.claude-3-5-sonnet", Price::new(0.003, 0.015)),
+    ("anthropic.claude-3-7-sonnet", Price::new(0.003, 0.015)),
+    ("anthropic.claude-3-sonnet", Price::new(0.003, 0.015)),
+    ("anthropic.claude-3-opus", Price::new(0.015, 0.075)),
+    ("meta.llama3-1-8b", Price::new(0.00022, 0.00022)),
+    ("meta.llama3-1-70b", Price::new(0.00072, 0.00072)),
+    ("meta.llama3-3-70b", Price::new(0.00072, 0.00072)),
+    ("mistral.mistral-large", Price::new(0.002, 0.006)),
+];
+
+/// One standard quality Nova Canvas image, up to 1024x1024
+pub const CANVAS_IMAGE: f64 = 0.04;
+
+/// Assumed length of a response when `--max-tokens` doesn't bound it
+pub const TYPICAL_RESPONSE_TOKENS: u64 = 800;
+
+/// The price of `model`, or `None` if it isn't in the table
+pub fn price(model: &str) -> Option<Price> {
+    let model = ["us.", "eu.", "apac."]
+        .iter()
+        .find_map(|region| model.strip_prefix(region))
+        .unwrap_or(model);
+    PRICES
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, price)| *price)
+}
+
+/// `$0.12`, or `<$0.01` for anything smaller
+pub fn format_dollars(dollars: f64) -> String {
+    if dollars < 0.01 {
+        "<$0.01".to_string()
+    } else {
+        format!("${:.2}", dollars)
+    }
+}
+
+/// What a session has spent so far
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SessionCost {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub images: u32,
+    pub dollars: f64,
+    /// Requests to models that aren't in the price table, left out of `dollars`
+    pub unpriced: u32,
+    /// Turns asked again with `redo`, whose cost is included above
+    pub redos: u32,
+}
+
+impl SessionCost {
+    pub fn record_turn(&mut self, model: &str, usage: &TokenUsage) {
+        let input = usage.input_tokens().max(0) as u64;
+        let output = usage.output_tokens().max(0) as u64;
+        self.input_tokens += input;
+        self.output_tokens += output;
+        match price(model) {
+            Some(price) => self.dollars += price.cost(input, output),
+            None => self.unpriced += 1,
+        }
+    }
+
+    pub fn record_redo(&mut self) {
+        self.redos += 1;
+    }
+
+    pub fn record_images(&mut self, count: u32) {
+        self.images += count;
+        self.dollars += f64::from(count) * CANVAS_IMAGE;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redos_are_counted_with_the_rest_of_the_usage() {
+        let mut cost = SessionCost::default();
+        let usage = TokenUsage::builder()
+            .input_tokens(1000)
+            .output_tokens(1000)
+            .total_tokens(2000)
+            .build()
+            .unwrap();
+        cost.record_turn("unknown-model", &usage);
+        cost.record_redo();
+        cost.record_turn("unknown-model", &usage);
+        assert_eq!(cost.redos, 1);
+        assert_eq!(cost.input_tokens, 2000);
+        assert_eq!(cost.unpriced, 2);
+    }
+
+    #[test]
+    fn usage_saved_before_redos_were_counted_loads() {
+        let cost: SessionCost =
+            serde_json::from_str(r#"{"input_tokens": 5, "output_tokens": 7, "dollars": 0.5}"#)
+                .unwrap();
+        assert_eq!(cost.redos, 0);
+        assert_eq!(cost.output_tokens, 7);
+    }
+}
@@ -0,0 +1,891 @@
+//! Local checks of a transmitted recipe's ingredients against the
+//! household's diets, allergies and dislikes.
+//!
+//! Ingredients are classified by keyword with [CATEGORIES], which knows
+//! the usual traps: fish sauce and worcestershire aren't vegetarian,
+//! gelatin isn't either, and parmesan is made with animal rennet.  Each
+//! restriction excludes some categories ([RESTRICTIONS]); one that isn't
+//! in the table, like an allergy to kiwi, is matched by its own words.
+//!
+//! Recipes written in another language (`--language`) are also checked
+//! with that language's [Vocabulary], where there is one, on top of the
+//! English keywords the model tends to keep ("tofu", "mozzarella").
+//!
+//! What happens to a recipe that breaks a restriction depends on its
+//! [Strictness]: a strict one sends the recipe back to the model, the
+//! others only put a note in the saved file.
+use std::fmt;
+
+use crate::preferences::{Preferences, Strictness};
+use crate::recipe::{self, Section};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Meat,
+    Pork,
+    Fish,
+    Shellfish,
+    Gelatin,
+    /// Cheeses traditionally made with calf rennet
+    AnimalRennet,
+    Dairy,
+    Egg,
+    Honey,
+    Gluten,
+    Peanut,
+    TreeNut,
+    Soy,
+    Sesame,
+    Alcohol,
+    Starch,
+    Sugar,
+}
+
+impl Category {
+    /// Whether "coconut milk" or "vegan sausage" is a stand-in for it
+    fn has_plant_versions(&self) -> bool {
+        matches!(
+            self,
+            Category::Meat | Category::Pork | Category::Dairy | Category::Egg
+        )
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Category::Meat => "meat",
+            Category::Pork => "pork",
+            Category::Fish => "fish",
+            Category::Shellfish => "shellfish",
+            Category::Gelatin => "gelatin",
+            Category::AnimalRennet => "animal rennet",
+            Category::Dairy => "dairy",
+            Category::Egg => "egg",
+            Category::Honey => "honey",
+            Category::Gluten => "gluten",
+            Category::Peanut => "peanut",
+            Category::TreeNut => "tree nuts",
+            Category::Soy => "soy",
+            Category::Sesame => "sesame",
+            Category::Alcohol => "alcohol",
+            Category::Starch => "starch",
+            Category::Sugar => "sugar",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+use Category::*;
+
+/// Keywords, matched at the start of words so "anchov" finds "anchovies",
+/// and what they contain
+pub static CATEGORIES: &[(&str, &[Category])] = &[
+    ("chicken", &[Meat]),
+    ("beef", &[Meat]),
+    ("steak", &[Meat]),
+    ("veal", &[Meat]),
+    ("lamb", &[Meat]),
+    ("mutton", &[Meat]),
+    ("turkey", &[Meat]),
+    ("duck", &[Meat]),
+    ("venison", &[Meat]),
+    ("mince", &[Meat]),
+    ("meatball", &[Meat]),
+    ("pork", &[Meat, Pork]),
+    ("bacon", &[Meat, Pork]),
+    ("ham", &[Meat, Pork]),
+    ("pancetta", &[Meat, Pork]),
+    ("prosciutto", &[Meat, Pork]),
+    ("chorizo", &[Meat, Pork]),
+    ("salami", &[Meat, Pork]),
+    ("pepperoni", &[Meat, Pork]),
+    ("sausage", &[Meat, Pork]),
+    ("lard", &[Meat, Pork]),
+    ("suet", &[Meat]),
+    ("bone broth", &[Meat]),
+    ("fish", &[Fish]),
+    ("anchov", &[Fish]),
+    ("worcestershire", &[Fish]),
+    ("salmon", &[Fish]),
+    ("tuna", &[Fish]),
+    ("cod", &[Fish]),
+    ("sardine", &[Fish]),
+    ("bonito", &[Fish]),
+    ("dashi", &[Fish]),
+    ("caesar dressing", &[Fish, Egg, Dairy]),
+    ("shrimp", &[Shellfish]),
+    ("prawn", &[Shellfish]),
+    ("crab", &[Shellfish]),
+    ("lobster", &[Shellfish]),
+    ("scallop", &[Shellfish]),
+    ("mussel", &[Shellfish]),
+    ("clam", &[Shellfish]),
+    ("oyster sauce", &[Shellfish]),
+    ("gelatin", &[Gelatin]),
+    ("marshmallow", &[Gelatin, Sugar]),
+    ("parmesan", &[Dairy, AnimalRennet]),
+    ("parmigiano", &[Dairy, AnimalRennet]),
+    ("pecorino", &[Dairy, AnimalRennet]),
+    ("grana padano", &[Dairy, AnimalRennet]),
+    ("gorgonzola", &[Dairy, AnimalRennet]),
+    ("milk", &[Dairy]),
+    ("butter", &[Dairy]),
+    ("ghee", &[Dairy]),
+    ("cream", &[Dairy]),
+    ("cheese", &[Dairy]),
+    ("yogurt", &[Dairy]),
+    ("yoghurt", &[Dairy]),
+    ("feta", &[Dairy]),
+    ("mozzarella", &[Dairy]),
+    ("ricotta", &[Dairy]),
+    ("paneer", &[Dairy]),
+    ("egg", &[Egg]),
+    ("mayonnaise", &[Egg]),
+    ("aioli", &[Egg]),
+    ("honey", &[Honey]),
+    ("flour", &[Gluten, Starch]),
+    ("bread", &[Gluten, Starch]),
+    ("pasta", &[Gluten, Starch]),
+    ("spaghetti", &[Gluten, Starch]),
+    ("noodle", &[Gluten, Starch]),
+    ("couscous", &[Gluten, Starch]),
+    ("barley", &[Gluten, Starch]),
+    ("wheat", &[Gluten, Starch]),
+    ("tortilla", &[Gluten, Starch]),
+    ("soy sauce", &[Soy, Gluten]),
+    ("beer", &[Gluten, Alcohol]),
+    ("peanut", &[Peanut]),
+    ("almond", &[TreeNut]),
+    ("cashew", &[TreeNut]),
+    ("walnut", &[TreeNut]),
+    ("pecan", &[TreeNut]),
+    ("pistachio", &[TreeNut]),
+    ("hazelnut", &[TreeNut]),
+    ("pine nut", &[TreeNut]),
+    ("pesto", &[TreeNut, Dairy, AnimalRennet]),
+    ("tofu", &[Soy]),
+    ("tempeh", &[Soy]),
+    ("edamame", &[Soy]),
+    ("miso", &[Soy]),
+    ("soy", &[Soy]),
+    ("sesame", &[Sesame]),
+    ("tahini", &[Sesame]),
+    ("wine", &[Alcohol]),
+    ("mirin", &[Alcohol, Sugar]),
+    ("rum", &[Alcohol]),
+    ("brandy", &[Alcohol]),
+    ("rice", &[Starch]),
+    ("potato", &[Starch]),
+    ("corn", &[Starch]),
+    ("oats", &[Starch]),
+    ("sugar", &[Sugar]),
+    ("syrup", &[Sugar]),
+];
+
+/// Words that start like a keyword but aren't that ingredient
+const FALSE_FRIENDS: &[&str] = &[
+    "eggplant",
+    "butternut",
+    "cornichon",
+    "rump",
+    "hamburger",
+    "honeydew",
+    "nutmeg",
+];
+
+/// Phrases that contain a keyword but not the ingredient, removed before
+/// classifying
+const NOT_INGREDIENTS: &[&str] = &[
+    "cream of tartar",
+    "sugar snap",
+    "lamb's lettuce",
+    "wine vinegar",
+];
+
+/// Words before a keyword that make it a plant-based stand-in, as in
+/// "coconut milk" or "vegan sausage"
+const PLANT_BASED: &[&str] = &[
+    "coconut",
+    "almond",
+    "oat",
+    "soy",
+    "rice",
+    "cashew",
+    "peanut",
+    "vegan",
+    "plant",
+    "vegetable",
+    "veggie",
+    "meatless",
+    "mushroom",
+    "vegetarian",
+];
+
+/// Which categories each restriction excludes, by words in its name
+pub static RESTRICTIONS: &[(&[&str], &[Category])] = &[
+    (
+        &["vegan", "plant based"],
+        &[
+            Meat,
+            Pork,
+            Fish,
+            Shellfish,
+            Gelatin,
+            AnimalRennet,
+            Dairy,
+            Egg,
+            Honey,
+        ],
+    ),
+    (
+        &["vegetarian", "veggie", "meatless"],
+        &[Meat, Pork, Fish, Shellfish, Gelatin, AnimalRennet],
+    ),
+    (&["pescatarian", "pescetarian"], &[Meat, Pork, Gelatin]),
+    (&["halal"], &[Pork, Gelatin, Alcohol]),
+    (&["kosher"], &[Pork, Shellfish]),
+    (&["dairy", "lactose", "milk"], &[Dairy]),
+    (&["gluten", "celiac", "coeliac", "wheat"], &[Gluten]),
+    (&["peanut"], &[Peanut]),
+    (&["tree nut", "nut free", "nuts"], &[TreeNut, Peanut]),
+    (&["shellfish"], &[Shellfish]),
+    (&["fish"], &[Fish]),
+    (&["egg"], &[Egg]),
+    (&["soy"], &[Soy]),
+    (&["sesame"], &[Sesame]),
+    (&["alcohol", "sober"], &[Alcohol]),
+    (&["pork"], &[Pork]),
+    (&["low carb", "keto"], &[Starch, Sugar]),
+    (&["sugar"], &[Sugar]),
+];
+
+/// Keywords for recipes in another language
+#[derive(Debug)]
+pub struct Vocabulary {
+    /// ISO 639-1
+    pub code: &'static str,
+    /// What the language is called, in English and in itself
+    pub names: &'static [&'static str],
+    /// Like [CATEGORIES].  Keywords shorter than four letters only match
+    /// whole words, so Spanish "pan" doesn't find "panceta".
+    pub keywords: &'static [(&'static str, &'static [Category])],
+    /// Words that match a keyword but aren't that ingredient, unless
+    /// they're a keyword themselves
+    pub false_friends: &'static [&'static str],
+    /// Like [NOT_INGREDIENTS]
+    pub not_ingredients: &'static [&'static str],
+    /// Words near a keyword, before or after it, that make it a stand-in:
+    /// "leche de coco"
+    pub plant_based: &'static [&'static str],
+    /// German writes "Vollmilch" and "Hähnchenbrust" as one word, so a
+    /// keyword may also end a word, and a word starting with one of
+    /// `plant_based` ("Kokosmilch") is a stand-in
+    pub compounds: bool,
+}
+
+pub static SPANISH: Vocabulary = Vocabulary {
+    code: "es",
+    names: &["spanish", "español", "espanol", "castellano"],
+    keywords: &[
+        ("pollo", &[Meat]),
+        ("carne", &[Meat]),
+        ("ternera", &[Meat]),
+        ("cordero", &[Meat]),
+        ("pavo", &[Meat]),
+        ("pato", &[Meat]),
+        ("cerdo", &[Meat, Pork]),
+        ("tocino", &[Meat, Pork]),
+        ("beicon", &[Meat, Pork]),
+        ("jamón", &[Meat, Pork]),
+        ("jamon", &[Meat, Pork]),
+        ("panceta", &[Meat, Pork]),
+        ("chorizo", &[Meat, Pork]),
+        ("salchicha", &[Meat, Pork]),
+        ("pescado", &[Fish]),
+        ("anchoa", &[Fish]),
+        ("salmón", &[Fish]),
+        ("atún", &[Fish]),
+        ("atun", &[Fish]),
+        ("bacalao", &[Fish]),
+        ("sardina", &[Fish]),
+        ("gamba", &[Shellfish]),
+        ("camarón", &[Shellfish]),
+        ("camaron", &[Shellfish]),
+        ("langostino", &[Shellfish]),
+        ("cangrejo", &[Shellfish]),
+        ("langosta", &[Shellfish]),
+        ("mejillón", &[Shellfish]),
+        ("mejillon", &[Shellfish]),
+        ("almeja", &[Shellfish]),
+        ("vieira", &[Shellfish]),
+        ("gelatina", &[Gelatin]),
+        ("parmesano", &[Dairy, AnimalRennet]),
+        ("leche", &[Dairy]),
+        ("mantequilla", &[Dairy]),
+        ("nata", &[Dairy]),
+        ("crema", &[Dairy]),
+        ("queso", &[Dairy]),
+        ("yogur", &[Dairy]),
+        ("huevo", &[Egg]),
+        ("mayonesa", &[Egg]),
+        ("miel", &[Honey]),
+        ("harina", &[Gluten, Starch]),
+        ("pan", &[Gluten, Starch]),
+        ("espagueti", &[Gluten, Starch]),
+        ("fideo", &[Gluten, Starch]),
+        ("trigo", &[Gluten, Starch]),
+        ("salsa de soja", &[Soy, Gluten]),
+        ("cerveza", &[Gluten, Alcohol]),
+        ("cacahuete", &[Peanut]),
+        ("maní", &[Peanut]),
+        ("almendra", &[TreeNut]),
+        ("nuez", &[TreeNut]),
+        ("nueces", &[TreeNut]),
+        ("anacardo", &[TreeNut]),
+        ("pistacho", &[TreeNut]),
+        ("avellana", &[TreeNut]),
+        ("piñón", &[TreeNut]),
+        ("piñones", &[TreeNut]),
+        ("soja", &[Soy]),
+        ("sésamo", &[Sesame]),
+        ("sesamo", &[Sesame]),
+        ("vino", &[Alcohol]),
+        ("ron", &[Alcohol]),
+        ("arroz", &[Starch]),
+        ("patata", &[Starch]),
+        ("papa", &[Starch]),
+        ("maíz", &[Starch]),
+        ("avena", &[Starch]),
+        ("azúcar", &[Sugar]),
+        ("azucar", &[Sugar]),
+        ("sirope", &[Sugar]),
+    ],
+    false_friends: &["papaya"],
+    not_ingredients: &["nuez moscada", "crémor tártaro", "vinagre de vino"],
+    plant_based: &[
+        "coco",
+        "almendra",
+        "almendras",
+        "soja",
+        "avena",
+        "arroz",
+        "anacardo",
+        "vegetal",
+        "vegana",
+        "vegano",
+        "vegetariana",
+        "vegetariano",
+        "cacahuete",
+    ],
+    compounds: false,
+};
+
+pub static GERMAN: Vocabulary = Vocabulary {
+    code: "de",
+    names: &["german", "deutsch"],
+    keywords: &[
+        ("hähnchen", &[Meat]),
+        ("hühner", &[Meat]),
+        ("huhn", &[Meat]),
+        ("rind", &[Meat]),
+        ("kalb", &[Meat]),
+        ("lamm", &[Meat]),
+        ("pute", &[Meat]),
+        ("ente", &[Meat]),
+        ("fleisch", &[Meat]),
+        ("hackfleisch", &[Meat]),
+        ("schwein", &[Meat, Pork]),
+        ("speck", &[Meat, Pork]),
+        ("schinken", &[Meat, Pork]),
+        ("wurst", &[Meat, Pork]),
+        ("salami", &[Meat, Pork]),
+        ("schmalz", &[Meat, Pork]),
+        ("fisch", &[Fish]),
+        ("sardelle", &[Fish]),
+        ("lachs", &[Fish]),
+        ("kabeljau", &[Fish]),
+        ("garnele", &[Shellfish]),
+        ("krabbe", &[Shellfish]),
+        ("hummer", &[Shellfish]),
+        ("muschel", &[Shellfish]),
+        ("gelatine", &[Gelatin]),
+        ("parmesan", &[Dairy, AnimalRennet]),
+        ("milch", &[Dairy]),
+        ("butter", &[Dairy]),
+        ("butterschmalz", &[Dairy]),
+        ("sahne", &[Dairy]),
+        ("rahm", &[Dairy]),
+        ("schmand", &[Dairy]),
+        ("käse", &[Dairy]),
+        ("quark", &[Dairy]),
+        ("joghurt", &[Dairy]),
+        ("ei", &[Egg]),
+        ("eier", &[Egg]),
+        ("eigelb", &[Egg]),
+        ("eiweiß", &[Egg]),
+        ("honig", &[Honey]),
+        ("mehl", &[Gluten, Starch]),
+        ("brot", &[Gluten, Starch]),
+        ("nudel", &[Gluten, Starch]),
+        ("weizen", &[Gluten, Starch]),
+        ("gerste", &[Gluten, Starch]),
+        ("sojasauce", &[Soy, Gluten]),
+        ("sojasoße", &[Soy, Gluten]),
+        ("bier", &[Gluten, Alcohol]),
+        ("erdnuss", &[Peanut]),
+        ("erdnüsse", &[Peanut]),
+        ("mandel", &[TreeNut]),
+        ("walnuss", &[TreeNut]),
+        ("haselnuss", &[TreeNut]),
+        ("pistazie", &[TreeNut]),
+        ("soja", &[Soy]),
+        ("sesam", &[Sesame]),
+        ("wein", &[Alcohol]),
+        ("reis", &[Starch]),
+        ("kartoffel", &[Starch]),
+        ("mais", &[Starch]),
+        ("hafer", &[Starch]),
+        ("zucker", &[Sugar]),
+        ("sirup", &[Sugar]),
+    ],
+    false_friends: &[
+        "fruchtfleisch",
+        "butterschmalz",
+        "muskatnuss",
+        "weinessig",
+        "weintraube",
+        "weintrauben",
+        "rumpsteak",
+        "rinde",
+        "muschelnudeln",
+    ],
+    not_ingredients: &[],
+    plant_based: &[
+        "kokos",
+        "hafer",
+        "soja",
+        "mandel",
+        "reis",
+        "cashew",
+        "erdnuss",
+        "pflanz",
+        "vegan",
+        "vegetarisch",
+    ],
+    compounds: true,
+};
+
+pub static VOCABULARIES: &[&Vocabulary] = &[&SPANISH, &GERMAN];
+
+/// The vocabulary for `language`, by code ("es", "de-AT") or name
+/// ("Spanish", "Deutsch"), if there is one
+pub fn vocabulary(language: &str) -> Option<&'static Vocabulary> {
+    let language = language.trim().to_lowercase();
+    let code = language.split(['-', '_']).next().unwrap_or("");
+    VOCABULARIES
+        .iter()
+        .copied()
+        .find(|v| v.code == code || v.names.contains(&language.as_str()))
+}
+
+impl Vocabulary {
+    fn matches(&self, word: &str, keyword: &str) -> bool {
+        if word == keyword {
+            return true;
+        }
+        if keyword.chars().count() < 4 || self.false_friends.contains(&word) {
+            return false;
+        }
+        word.starts_with(keyword) || (self.compounds && self.ends_with(word, keyword))
+    }
+
+    /// Whether `word` ends with `keyword` and not with a longer keyword
+    /// that does, so "Wildschwein" is pork but not wine
+    fn ends_with(&self, word: &str, keyword: &str) -> bool {
+        word.ends_with(keyword)
+            && !self.keywords.iter().any(|(longer, _)| {
+                longer.len() > keyword.len() && longer.ends_with(keyword) && word.ends_with(longer)
+            })
+    }
+
+    /// Where in `words` the words of `keyword` start, like [find]
+    fn find(&self, words: &[String], keyword: &str) -> Option<usize> {
+        let keyword: Vec<&str> = keyword.split(' ').collect();
+        (0..words.len()).find(|&idx| {
+            keyword.iter().enumerate().all(|(offset, part)| {
+                words
+                    .get(idx + offset)
+                    .is_some_and(|w| self.matches(w, part))
+            })
+        })
+    }
+
+    /// Whether the keyword found at `idx` is a stand-in, from the word
+    /// before it, the two after it, or how it starts in a compound
+    fn plant_based(&self, words: &[String], idx: usize, keyword: &str) -> bool {
+        let word = &words[idx];
+        if self.compounds && word != keyword && !word.starts_with(keyword) {
+            return self.plant_based.iter().any(|p| word.starts_with(p));
+        }
+        let near = idx.saturating_sub(1)..(idx + 3).min(words.len());
+        near.filter(|&i| i != idx)
+            .any(|i| self.plant_based.contains(&words[i].as_str()))
+    }
+
+    fn classify(&self, ingredient: &str, found: &mut Vec<Category>) {
+        let ingredient = self
+            .not_ingredients
+            .iter()
+            .fold(ingredient.to_lowercase(), |line, phrase| {
+                line.replace(phrase, "")
+            });
+        let words = words(&ingredient);
+        for (keyword, categories) in self.keywords {
+            let Some(idx) = self.find(&words, keyword) else {
+                continue;
+            };
+            let plant_based = self.plant_based(&words, idx, keyword);
+            for category in categories.iter() {
+                if plant_based && category.has_plant_versions() {
+                    continue;
+                }
+                if !found.contains(category) {
+                    found.push(*category);
+                }
+            }
+        }
+    }
+}
+
+/// The categories `restriction` excludes, or `None` if it isn't in the table
+pub fn excluded(restriction: &str) -> Option<Vec<Category>> {
+    let restriction = words(restriction);
+    let excluded: Vec<Category> = RESTRICTIONS
+        .iter()
+        .filter(|(names, _)| names.iter().any(|n| find(&restriction, n).is_some()))
+        .flat_map(|(_, categories)| categories.iter().copied())
+        .collect();
+    (!excluded.is_empty()).then_some(excluded)
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Where in `words` the words of `keyword` start, each word of the
+/// keyword starting a word of the text
+fn find(words: &[String], keyword: &str) -> Option<usize> {
+    let keyword: Vec<&str> = keyword.split(' ').collect();
+    (0..words.len()).find(|&idx| {
+        keyword.iter().enumerate().all(|(offset, part)| {
+            words
+                .get(idx + offset)
+                .is_some_and(|w| w.starts_with(part) && !FALSE_FRIENDS.contains(&w.as_str()))
+        })
+    })
+}
+
+/// What one ingredient line contains, as far as the table knows
+pub fn classify(ingredient: &str) -> Vec<Category> {
+    classify_in(ingredient, None)
+}
+
+/// Like [classify], for a recipe written in `language` (see [vocabulary])
+pub fn classify_in(ingredient: &str, language: Option<&str>) -> Vec<Category> {
+    let ingredient = NOT_INGREDIENTS
+        .iter()
+        .fold(ingredient.to_lowercase(), |line, phrase| {
+            line.replace(phrase, "")
+        });
+    let words = words(&ingredient);
+    let mut found: Vec<Category> = vec![];
+    for (keyword, categories) in CATEGORIES {
+        let Some(idx) = find(&words, keyword) else {
+            continue;
+        };
+        let plant_based = idx > 0 && PLANT_BASED.contains(&words[idx - 1].as_str());
+        for category in categories.iter() {
+            if plant_based && category.has_plant_versions() {
+                continue;
+            }
+            if !found.contains(category) {
+                found.push(*category);
+            }
+        }
+    }
+    if let Some(vocabulary) = language.and_then(vocabulary) {
+        vocabulary.classify(&ingredient, &mut found);
+    }
+    found
+}
+
+/// The restriction's own words, without "allergy", "no" and plurals, for
+/// restrictions not in the table
+fn own_words(restriction: &str) -> String {
+    words(restriction)
+        .into_iter()
+        .filter(|w| !["no", "allergy", "allergic", "free", "to"].contains(&w.as_str()))
+        .map(
+            |w| match w.strip_suffix("es").or_else(|| w.strip_suffix('s')) {
+                Some(stem) if stem.len() >= 3 => stem.to_string(),
+                _ => w,
+            },
+        )
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// An ingredient that breaks a restriction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub restriction: String,
+    pub strictness: Strictness,
+    /// The ingredient line, as written
+    pub ingredient: String,
+    /// What about it breaks the restriction, e.g. "fish"
+    pub reason: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\"{}\" has {}, which isn't {} ({})",
+            self.ingredient, self.reason, self.restriction, self.strictness
+        )
+    }
+}
+
+/// Every ingredient of `recipe` that breaks one of the restrictions in
+/// `preferences`, strict ones first.  `language` is what the recipe was
+/// asked for in, if not English.
+pub fn check(preferences: &Preferences, recipe: &str, language: Option<&str>) -> Vec<Violation> {
+    let ingredients = recipe::section(recipe, Section::Ingredients).unwrap_or_default();
+    let lines: Vec<&str> = ingredients
+        .lines()
+        .map(|l| l.trim().trim_start_matches(['-', '*', '•']).trim())
+        .filter(|l| !l.is_empty())
+        .collect();
+    check_lines(preferences, &lines, language)
+}
+
+/// Like [check], for ingredient lines on their own, e.g. what a `tweak`
+/// would add
+pub fn check_lines(
+    preferences: &Preferences,
+    lines: &[&str],
+    language: Option<&str>,
+) -> Vec<Violation> {
+    let mut violations = vec![];
+    for (restriction, strictness) in preferences.restrictions() {
+        for line in lines {
+            let reason = match excluded(&restriction) {
+                Some(excluded) => classify_in(line, language)
+                    .into_iter()
+                    .find(|c| excluded.contains(c))
+                    .map(|c| c.to_string()),
+                None => {
+                    let own = own_words(&restriction);
+                    (!own.is_empty() && find(&words(line), &own).is_some()).then_some(own)
+                }
+            };
+            if let Some(reason) = reason {
+                violations.push(Violation {
+                    restriction: restriction.clone(),
+                    strictness,
+                    ingredient: line.to_string(),
+                    reason,
+                });
+            }
+        }
+    }
+    violations.sort_by_key(|v| v.strictness);
+    violations
+}
+
+/// The notes section appended to a saved recipe that breaks non-strict
+/// restrictions, under a heading of its own so it stays out of the
+/// shopping list
+pub fn annotate(violations: &[Violation]) -> String {
+    let mut note = "\n\nDietary notes:\n".to_string();
+    for violation in violations {
+        note.push_str(&format!("- {}\n", violation));
+    }
+    note
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPANISH_RECIPE: &str = "\
+Arroz con pollo
+
+Ingredientes (para 4 personas):
+- 500 g de muslos de pollo
+- 1,5 tazas de arroz
+- 400 ml de leche de coco
+- 2 cucharadas de salsa de soja
+- 1 pizca de nuez moscada
+- 2 huevos
+
+Preparación:
+1. Dorar el pollo.
+2. Añadir el arroz y la leche de coco.
+";
+
+    const GERMAN_RECIPE: &str = "\
+Käsespätzle
+
+Zutaten:
+- 400 g Weizenmehl
+- 4 Eier
+- 200 g geriebener Bergkäse
+- 250 ml Vollmilch
+- 100 ml Kokosmilch
+- 1 Prise Muskatnuss
+- 2 EL Butterschmalz
+
+Zubereitung:
+1. Teig rühren und Spätzle schaben.
+";
+
+    fn preferences(diet: &[&str], allergies: &[&str]) -> Preferences {
+        Preferences {
+            diet: diet.iter().map(|s| s.to_string()).collect(),
+            allergies: allergies.iter().map(|s| s.to_string()).collect(),
+            ..Preferences::default()
+        }
+    }
+
+    fn flagged(violations: &[Violation]) -> Vec<(&str, &str)> {
+        violations
+            .iter()
+            .map(|v| (v.ingredient.as_str(), v.reason.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn english_keywords() {
+        assert_eq!(classify("2 tbsp fish sauce"), [Fish]);
+        assert_eq!(classify("1 cup coconut milk"), []);
+        assert_eq!(classify("1 eggplant"), []);
+        assert_eq!(classify("1 tsp cream of tartar"), []);
+        assert_eq!(classify("grated parmesan"), [Dairy, AnimalRennet]);
+        assert_eq!(classify("1 packet gelatin"), [Gelatin]);
+        assert_eq!(classify("2 tsp Worcestershire sauce"), [Fish]);
+    }
+
+    #[test]
+    fn only_strict_restrictions_send_the_recipe_back() {
+        let recipe = "\
+Shepherd's pie
+
+Ingredients:
+- 500 g lamb mince
+- 1 tbsp worcestershire sauce
+- 800 g potatoes
+
+Method:
+1. Brown the lamb.
+";
+        let strict = Preferences::new().restriction("vegetarian", Strictness::Strict);
+        let violations = check(&strict, recipe, None);
+        assert_eq!(
+            flagged(&violations),
+            [
+                ("500 g lamb mince", "meat"),
+                ("1 tbsp worcestershire sauce", "fish"),
+            ]
+        );
+        assert!(violations
+            .iter()
+            .all(|v| v.strictness == Strictness::Strict));
+
+        for strictness in [Strictness::Prefer, Strictness::Avoid] {
+            let lenient = Preferences::new().restriction("vegetarian", strictness);
+            let violations = check(&lenient, recipe, None);
+            assert_eq!(violations.len(), 2);
+            assert!(violations.iter().all(|v| v.strictness == strictness));
+            let note = annotate(&violations);
+            assert!(note.starts_with("\n\nDietary notes:\n"));
+            assert!(note.contains(&format!(
+                "- \"500 g lamb mince\" has meat, which isn't vegetarian ({})",
+                strictness
+            )));
+        }
+    }
+
+    #[test]
+    fn vocabularies_by_code_or_name() {
+        assert_eq!(vocabulary("es").map(|v| v.code), Some("es"));
+        assert_eq!(vocabulary("es-MX").map(|v| v.code), Some("es"));
+        assert_eq!(vocabulary("Spanish").map(|v| v.code), Some("es"));
+        assert_eq!(vocabulary("Deutsch").map(|v| v.code), Some("de"));
+        assert!(vocabulary("fr").is_none());
+    }
+
+    #[test]
+    fn spanish_keywords_only_for_spanish_recipes() {
+        assert_eq!(classify_in("pan rallado", Some("es")), [Gluten, Starch]);
+        assert!(classify_in("pan rallado", None).is_empty());
+        // short keywords only match whole words
+        assert_eq!(classify_in("100 g de panceta", Some("es")), [Meat, Pork]);
+        assert_eq!(classify_in("leche de almendras", Some("es")), [TreeNut]);
+        assert_eq!(classify_in("queso vegano", Some("es")), []);
+        assert_eq!(classify_in("1 pizca de nuez moscada", Some("es")), []);
+        assert_eq!(classify_in("1 papaya", Some("es")), []);
+    }
+
+    #[test]
+    fn german_compounds() {
+        assert_eq!(classify_in("250 ml Vollmilch", Some("de")), [Dairy]);
+        assert_eq!(classify_in("2 Hähnchenbrustfilets", Some("de")), [Meat]);
+        assert_eq!(classify_in("100 ml Kokosmilch", Some("de")), []);
+        assert_eq!(classify_in("1 Wildschwein", Some("de")), [Meat, Pork]);
+        assert_eq!(classify_in("2 EL Erdnussbutter", Some("de")), [Peanut]);
+        assert_eq!(classify_in("1 Ei", Some("de")), [Egg]);
+        assert_eq!(classify_in("1 Eisbergsalat", Some("de")), []);
+        assert_eq!(classify_in("200 g Muschelnudeln", Some("de")), []);
+        assert_eq!(classify_in("1 EL Rotweinessig", Some("de")), []);
+    }
+
+    #[test]
+    fn spanish_fixture() {
+        let prefs = preferences(&["vegetarian"], &["egg", "gluten"]);
+        let violations = check(&prefs, SPANISH_RECIPE, Some("Spanish"));
+        assert_eq!(
+            flagged(&violations),
+            [
+                ("2 huevos", "egg"),
+                ("2 cucharadas de salsa de soja", "gluten"),
+                ("500 g de muslos de pollo", "meat"),
+            ]
+        );
+        assert!(violations[..2]
+            .iter()
+            .all(|v| v.strictness == Strictness::Strict));
+        // without the language, only the English keywords are known
+        assert!(check(&prefs, SPANISH_RECIPE, None).is_empty());
+    }
+
+    #[test]
+    fn german_fixture() {
+        let prefs = preferences(&["vegan"], &[]);
+        let violations = check(&prefs, GERMAN_RECIPE, Some("de"));
+        assert_eq!(
+            flagged(&violations),
+            [
+                ("4 Eier", "egg"),
+                ("200 g geriebener Bergkäse", "dairy"),
+                ("250 ml Vollmilch", "dairy"),
+                ("2 EL Butterschmalz", "dairy"),
+            ]
+        );
+    }
+}
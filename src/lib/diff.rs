@@ -0,0 +1,195 @@
+//! What changed between two versions of a recipe, e.g. after "make it dairy-free".
+use std::collections::BTreeSet;
+
+use crate::parse;
+use crate::recipe::{self, Section};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change<'a> {
+    Same(&'a str),
+    Added(&'a str),
+    Removed(&'a str),
+}
+
+/// A line level diff (longest common subsequence), ignoring blank lines,
+/// surrounding whitespace, and list markers, including step numbers.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<Change<'a>> {
+    let old: Vec<&str> = items(old);
+    let new: Vec<&str> = items(new);
+
+    // lcs[i][j] is the LCS length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if same(old[i], new[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && same(old[i], new[j]) {
+            changes.push(Change::Same(new[j]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            changes.push(Change::Removed(old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(new[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+/// A short description of each change, pairing up adjacent removals and
+/// additions as swaps: `butter → olive oil`, `removed parmesan`.
+pub fn summarize(changes: &[Change]) -> Vec<String> {
+    let mut removed = vec![];
+    let mut added = vec![];
+    let mut summary = vec![];
+    let flush = |removed: &mut Vec<&str>, added: &mut Vec<&str>, summary: &mut Vec<String>| {
+        let pairs = removed.len().min(added.len());
+        for k in 0..pairs {
+            summary.push(format!("{} → {}", removed[k], added[k]));
+        }
+        summary.extend(removed[pairs..].iter().map(|r| format!("removed {}", r)));
+        summary.extend(added[pairs..].iter().map(|a| format!("added {}", a)));
+        removed.clear();
+        added.clear();
+    };
+    for change in changes {
+        match change {
+            Change::Removed(line) => removed.push(*line),
+            Change::Added(line) => added.push(*line),
+            Change::Same(_) => flush(&mut removed, &mut added, &mut summary),
+        }
+    }
+    flush(&mut removed, &mut added, &mut summary);
+    summary
+}
+
+/// How alike two titles are, from 0 (nothing in common) to 1 (same words)
+pub fn title_similarity(a: &str, b: &str) -> f64 {
+    let words = |s: &str| -> BTreeSet<String> {
+        s.to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.len() > 2)
+            .map(str::to_string)
+            .collect()
+    };
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+/// A markdown report of the ingredient and instruction changes between two
+/// recipes, or `None` if neither changed.
+pub fn changes_markdown(old: &str, new: &str) -> Option<String> {
+    let (old_parsed, new_parsed) = (parse::sections(old), parse::sections(new));
+    let mut out = String::new();
+    for (heading, section) in [
+        ("Ingredients", Section::Ingredients),
+        ("Instructions", Section::Instructions),
+    ] {
+        let changes = diff_lines(
+            old_parsed.get(section).unwrap_or_default(),
+            new_parsed.get(section).unwrap_or_default(),
+        );
+        if changes.iter().all(|c| matches!(c, Change::Same(_))) {
+            continue;
+        }
+        out.push_str(&format!("## {}\n\n```diff\n", heading));
+        for change in changes {
+            match change {
+                Change::Same(line) => out.push_str(&format!("  {}\n", line)),
+                Change::Added(line) => out.push_str(&format!("+ {}\n", line)),
+                Change::Removed(line) => out.push_str(&format!("- {}\n", line)),
+            }
+        }
+        out.push_str("```\n\n");
+    }
+    (!out.is_empty()).then(|| {
+        let title = new_parsed.title().unwrap_or("Recipe");
+        format!("# Changes to {}\n\n{}", title, out.trim_end())
+    })
+}
+
+/// Lines without bullets or step numbers, so that inserting a step doesn't
+/// renumber every step after it into a change
+fn items(text: &str) -> Vec<&str> {
+    text.lines()
+        .map(str::trim)
+        .map(|line| {
+            recipe::strip_list_marker(line)
+                .unwrap_or_else(|| line.trim_start_matches(['-', '*', '•']).trim())
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+fn same(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserted_step_is_the_only_change() {
+        let old = "1. Boil the pasta.\n2. Drain it.\n3. Toss with butter.";
+        let new = "1. Boil the pasta.\n2. Reserve a cup of the water.\n3. Drain it.\n4. Toss with butter.";
+        let changes = diff_lines(old, new);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Same("Boil the pasta."),
+                Change::Added("Reserve a cup of the water."),
+                Change::Same("Drain it."),
+                Change::Same("Toss with butter."),
+            ]
+        );
+        assert_eq!(
+            summarize(&changes),
+            vec!["added Reserve a cup of the water."]
+        );
+    }
+
+    #[test]
+    fn step_markers_are_ignored() {
+        let changes = diff_lines(
+            "Step 1: Heat the oil.\n2) Add garlic.",
+            "1. Heat the oil.\n- Add garlic.",
+        );
+        assert!(changes.iter().all(|c| matches!(c, Change::Same(_))));
+    }
+
+    #[test]
+    fn swaps_and_removals() {
+        let old = "- 2 tbsp butter\n- 1 cup pasta\n- parmesan";
+        let new = "- 2 tbsp olive oil\n- 1 cup pasta";
+        assert_eq!(
+            summarize(&diff_lines(old, new)),
+            vec!["2 tbsp butter → 2 tbsp olive oil", "removed parmesan"]
+        );
+    }
+
+    #[test]
+    fn title_similarity_ignores_case_and_short_words() {
+        assert_eq!(
+            title_similarity("Creamy Tomato Pasta", "creamy tomato pasta"),
+            1.0
+        );
+        assert_eq!(title_similarity("Tomato Soup", "Banana Bread"), 0.0);
+        assert!(title_similarity("Creamy Tomato Pasta", "Dairy-Free Tomato Pasta") >= 0.4);
+    }
+}
@@ -0,0 +1,506 @@
+//! Health checks for `recipes doctor`.
+//!
+//! Each check is a separate function taking what it needs, so that they
+//! can be run (and tested) independently.  The checks that call AWS go
+//! through [AwsChecks], which [AwsClients] implements with the real clients
+//! and tests with a fake.
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+
+use aws_sdk_bedrockruntime::operation::RequestId;
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ConversationRole, InferenceConfiguration, Message, Tool, ToolConfiguration,
+    ToolInputSchema,
+};
+
+use crate::allowlist::AllowList;
+use crate::awsinit;
+use crate::imagestyle::{ImagePromptProcessor, ImageStyle};
+use crate::macros::Macros;
+use crate::notify::NotifyConfig;
+use crate::paths::Paths;
+use crate::session::document_to_json;
+use crate::shopping::AisleClassifier;
+use crate::themes::Themes;
+use crate::toolspec;
+use crate::tweaks::Tweaks;
+
+/// Warn when the serialized tool specs get larger than this
+pub const TOOL_CONFIG_WARN_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Pass => write!(f, "PASS"),
+            Status::Warn => write!(f, "WARN"),
+            Status::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    pub fn new(name: &str, status: Status, detail: impl Into<String>) -> Check {
+        Check {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// One line of the checklist, e.g. `[PASS] config aisles.json: ...`
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.status, self.name, self.detail)
+    }
+}
+
+/// Whether nothing failed
+pub fn passed(checks: &[Check]) -> bool {
+    checks.iter().all(|c| c.status != Status::Fail)
+}
+
+type ParseConfig = fn(&Path) -> io::Result<()>;
+
+/// The files in the config directory, each with what parses it
+const CONFIG_FILES: &[(&str, ParseConfig)] = &[
+    ("aisles.json", |p| AisleClassifier::with_config(p).map(drop)),
+    ("themes.json", |p| Themes::with_config(p).map(drop)),
+    ("tools.json", |p| AllowList::load(p).map(drop)),
+    ("macros.json", |p| Macros::with_config(p).map(drop)),
+    ("tweaks.json", |p| Tweaks::with_config(p).map(drop)),
+    ("notify.json", |p| NotifyConfig::load(p).map(drop)),
+    ("brands.json", |p| {
+        ImagePromptProcessor::with_config(ImageStyle::default(), p).map(drop)
+    }),
+    #[cfg(feature = "caldav")]
+    ("caldav.json", |p| {
+        crate::export::caldav::CalDavConfig::load(p).map(drop)
+    }),
+];
+
+/// Whether each config file that's there parses; one check per file, or
+/// one saying there are none
+pub fn check_config(paths: &Paths) -> Vec<Check> {
+    let mut checks = vec![];
+    for (file, load) in CONFIG_FILES {
+        let path = paths.config_dir().join(file);
+        if !path.exists() {
+            continue;
+        }
+        let name = format!("config {}", file);
+        checks.push(match load(&path) {
+            Ok(()) => Check::new(&name, Status::Pass, format!("{} ok", path.display())),
+            Err(e) => Check::new(&name, Status::Fail, format!("{}: {}", path.display(), e)),
+        });
+    }
+    if checks.is_empty() {
+        checks.push(Check::new(
+            "config",
+            Status::Pass,
+            format!(
+                "no files in {}, using defaults",
+                paths.config_dir().display()
+            ),
+        ));
+    }
+    checks
+}
+
+/// In the state directory: Bedrock's ids for the last session's most
+/// recent requests, one per line, rewritten after every turn
+pub const REQUEST_IDS_FILE: &str = "request_ids.txt";
+
+/// The ids to quote to AWS support about a request that went wrong
+pub fn check_request_ids(paths: &Paths) -> Check {
+    let name = "recent request ids";
+    let path = paths.state_dir().join(REQUEST_IDS_FILE);
+    match fs::read_to_string(&path) {
+        Ok(text) => {
+            let ids: Vec<&str> = text
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .collect();
+            match ids.len() {
+                0 => Check::new(name, Status::Pass, "none recorded yet"),
+                _ => Check::new(name, Status::Pass, ids.join(", ")),
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Check::new(name, Status::Pass, "none recorded yet")
+        }
+        Err(e) => Check::new(name, Status::Warn, format!("{}: {}", path.display(), e)),
+    }
+}
+
+pub fn check_dir_writable(name: &str, dir: &Path) -> Check {
+    let probe = dir.join(".doctor-probe");
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&probe, b"ok"))
+        .and_then(|_| fs::remove_file(&probe));
+    match result {
+        Ok(()) => Check::new(name, Status::Pass, format!("{} is writable", dir.display())),
+        Err(e) => Check::new(name, Status::Fail, format!("{}: {}", dir.display(), e)),
+    }
+}
+
+/// A failed AWS call, as much of it as the checks report
+#[derive(Debug, Clone, Default)]
+pub struct CallError {
+    pub message: String,
+    pub request_id: Option<String>,
+    /// The sso login behind the credentials has expired
+    pub sso_expired: bool,
+}
+
+impl CallError {
+    fn new(e: &impl std::error::Error, request_id: Option<&str>) -> CallError {
+        CallError {
+            message: e.to_string(),
+            request_id: request_id.map(str::to_string),
+            sso_expired: awsinit::is_sso_expired(&format!("{:?}", e)),
+        }
+    }
+}
+
+/// The AWS calls the checks make
+pub trait AwsChecks {
+    /// The account and the caller's arn
+    fn caller_identity(&self) -> impl Future<Output = Result<(String, String), CallError>> + Send;
+
+    /// Whether the model id exists in the region
+    fn foundation_model(&self, model: &str) -> impl Future<Output = Result<(), CallError>> + Send;
+
+    /// Whether the cross-region inference profile id exists in the region
+    fn inference_profile(&self, id: &str) -> impl Future<Output = Result<(), CallError>> + Send;
+
+    /// Sends `prompt` to `model`, returning the request id
+    fn converse(
+        &self,
+        model: &str,
+        prompt: &str,
+    ) -> impl Future<Output = Result<Option<String>, CallError>> + Send;
+}
+
+/// [AwsChecks] with the real clients
+#[cfg(feature = "bedrock")]
+#[derive(Debug, Clone)]
+pub struct AwsClients {
+    pub sts: aws_sdk_sts::Client,
+    pub bedrock: aws_sdk_bedrock::Client,
+    pub runtime: aws_sdk_bedrockruntime::Client,
+}
+
+#[cfg(feature = "bedrock")]
+impl AwsChecks for AwsClients {
+    async fn caller_identity(&self) -> Result<(String, String), CallError> {
+        match self.sts.get_caller_identity().send().await {
+            Ok(identity) => Ok((
+                identity.account().unwrap_or("?").to_string(),
+                identity.arn().unwrap_or("?").to_string(),
+            )),
+            Err(e) => Err(CallError::new(&e, e.request_id())),
+        }
+    }
+
+    async fn foundation_model(&self, model: &str) -> Result<(), CallError> {
+        match self
+            .bedrock
+            .get_foundation_model()
+            .model_identifier(model)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(CallError::new(&e, e.request_id())),
+        }
+    }
+
+    async fn inference_profile(&self, id: &str) -> Result<(), CallError> {
+        match self
+            .bedrock
+            .get_inference_profile()
+            .inference_profile_identifier(id)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(CallError::new(&e, e.request_id())),
+        }
+    }
+
+    async fn converse(&self, model: &str, prompt: &str) -> Result<Option<String>, CallError> {
+        let msg = Message::builder()
+            .role(ConversationRole::User)
+            .content(ContentBlock::Text(prompt.to_string()))
+            .build()
+            .map_err(|e| CallError::new(&e, None))?;
+        let result = self
+            .runtime
+            .converse()
+            .model_id(model)
+            .messages(msg)
+            .inference_config(InferenceConfiguration::builder().max_tokens(10).build())
+            .send()
+            .await;
+        match result {
+            Ok(output) => Ok(output.request_id().map(str::to_string)),
+            Err(e) => Err(CallError::new(&e, e.request_id())),
+        }
+    }
+}
+
+/// `profile` is only used to suggest the `aws sso login` command
+pub async fn check_credentials(aws: &impl AwsChecks, profile: Option<&str>) -> Check {
+    let name = "aws credentials";
+    match aws.caller_identity().await {
+        Ok((account, arn)) => Check::new(
+            name,
+            Status::Pass,
+            format!("account {} as {}", account, arn),
+        ),
+        Err(e) if e.sso_expired => Check::new(
+            name,
+            Status::Fail,
+            format!(
+                "the sso login has expired, run: {}",
+                awsinit::sso_login_command(profile.unwrap_or("default"))
+            ),
+        ),
+        Err(e) => Check::new(name, Status::Fail, e.message),
+    }
+}
+
+/// Whether `model` (a model id or cross-region inference profile id) exists in the region
+pub async fn check_model_access(name: &str, aws: &impl AwsChecks, model: &str) -> Check {
+    let is_profile = ["us.", "eu.", "apac.", "us-gov."]
+        .iter()
+        .any(|prefix| model.starts_with(prefix));
+    let result = if is_profile {
+        aws.inference_profile(model).await
+    } else {
+        aws.foundation_model(model).await
+    };
+    match result {
+        Ok(()) => Check::new(name, Status::Pass, format!("{} is available", model)),
+        Err(e) => Check::new(name, Status::Fail, format!("{}: {}", model, e.message)),
+    }
+}
+
+pub fn check_tool_config(tools: &ToolConfiguration) -> Check {
+    let name = "tool configuration";
+    let problems = toolspec::validate(tools);
+    if !problems.is_empty() {
+        return Check::new(name, Status::Fail, problems.join("; "));
+    }
+    let mut bytes = 0;
+    for tool in tools.tools() {
+        let Tool::ToolSpec(spec) = tool else {
+            continue;
+        };
+        bytes += spec.name().len() + spec.description().map_or(0, str::len);
+        if let Some(ToolInputSchema::Json(schema)) = spec.input_schema() {
+            bytes += document_to_json(schema).to_string().len();
+        }
+    }
+    let detail = format!("{} tools, {} bytes", tools.tools().len(), bytes);
+    if bytes > TOOL_CONFIG_WARN_BYTES {
+        Check::new(name, Status::Warn, format!("{} (large)", detail))
+    } else {
+        Check::new(name, Status::Pass, detail)
+    }
+}
+
+/// Sends one tiny, paid, request to the model
+pub async fn check_live_converse(aws: &impl AwsChecks, model: &str) -> Check {
+    let name = "live converse";
+    // the request id is what AWS support asks for when this fails oddly
+    let quote = |id: Option<String>| id.map_or(String::new(), |id| format!(" (request id {})", id));
+    match aws.converse(model, "Reply with the word ok.").await {
+        Ok(request_id) => Check::new(
+            name,
+            Status::Pass,
+            format!("{} responded{}", model, quote(request_id)),
+        ),
+        Err(e) => Check::new(
+            name,
+            Status::Fail,
+            format!("{}: {}{}", model, e.message, quote(e.request_id)),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Answers every call from what it was given, and remembers the calls
+    #[derive(Default)]
+    struct FakeAws {
+        identity: Option<(String, String)>,
+        error: CallError,
+        models: Vec<String>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl FakeAws {
+        fn called(&self, call: String) {
+            self.calls.lock().unwrap().push(call);
+        }
+
+        fn model(&self, model: &str) -> Result<(), CallError> {
+            if self.models.iter().any(|m| m == model) {
+                Ok(())
+            } else {
+                Err(self.error.clone())
+            }
+        }
+    }
+
+    impl AwsChecks for FakeAws {
+        async fn caller_identity(&self) -> Result<(String, String), CallError> {
+            self.called("caller_identity".to_string());
+            self.identity.clone().ok_or_else(|| self.error.clone())
+        }
+
+        async fn foundation_model(&self, model: &str) -> Result<(), CallError> {
+            self.called(format!("foundation_model {}", model));
+            self.model(model)
+        }
+
+        async fn inference_profile(&self, id: &str) -> Result<(), CallError> {
+            self.called(format!("inference_profile {}", id));
+            self.model(id)
+        }
+
+        async fn converse(&self, model: &str, _prompt: &str) -> Result<Option<String>, CallError> {
+            self.called(format!("converse {}", model));
+            self.model(model).map(|()| Some("req-1".to_string()))
+        }
+    }
+
+    fn denied() -> CallError {
+        CallError {
+            message: "access denied".to_string(),
+            request_id: Some("req-2".to_string()),
+            sso_expired: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn credentials_name_the_account() {
+        let aws = FakeAws {
+            identity: Some(("1234".to_string(), "arn:aws:iam::1234:user/me".to_string())),
+            ..FakeAws::default()
+        };
+        let check = check_credentials(&aws, None).await;
+        assert_eq!(check.status, Status::Pass);
+        assert_eq!(check.detail, "account 1234 as arn:aws:iam::1234:user/me");
+    }
+
+    #[tokio::test]
+    async fn expired_sso_suggests_logging_in() {
+        let aws = FakeAws {
+            error: CallError {
+                sso_expired: true,
+                ..denied()
+            },
+            ..FakeAws::default()
+        };
+        let check = check_credentials(&aws, Some("kitchen")).await;
+        assert_eq!(check.status, Status::Fail);
+        assert!(check.detail.contains("kitchen"), "{}", check.detail);
+    }
+
+    #[tokio::test]
+    async fn inference_profiles_and_models_are_looked_up_differently() {
+        let aws = FakeAws {
+            models: vec!["us.anthropic.claude".to_string()],
+            error: denied(),
+            ..FakeAws::default()
+        };
+        let profile = check_model_access("model", &aws, "us.anthropic.claude").await;
+        let model = check_model_access("image model", &aws, "amazon.nova-canvas-v1:0").await;
+        assert_eq!(profile.status, Status::Pass);
+        assert_eq!(model.status, Status::Fail);
+        assert_eq!(model.detail, "amazon.nova-canvas-v1:0: access denied");
+        assert_eq!(
+            *aws.calls.lock().unwrap(),
+            [
+                "inference_profile us.anthropic.claude",
+                "foundation_model amazon.nova-canvas-v1:0"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn live_converse_quotes_the_request_id() {
+        let aws = FakeAws {
+            models: vec!["good".to_string()],
+            error: denied(),
+            ..FakeAws::default()
+        };
+        let pass = check_live_converse(&aws, "good").await;
+        let fail = check_live_converse(&aws, "bad").await;
+        assert_eq!(pass.detail, "good responded (request id req-1)");
+        assert_eq!(fail.status, Status::Fail);
+        assert_eq!(fail.detail, "bad: access denied (request id req-2)");
+    }
+
+    fn temp_paths(name: &str) -> Paths {
+        let root =
+            std::env::temp_dir().join(format!("gourmand-doctor-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let paths = Paths::resolve(Some(&root));
+        fs::create_dir_all(paths.config_dir()).unwrap();
+        paths
+    }
+
+    #[test]
+    fn no_config_files_passes() {
+        let paths = temp_paths("empty");
+        let checks = check_config(&paths);
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, Status::Pass);
+        assert!(passed(&checks));
+    }
+
+    #[test]
+    fn every_config_file_is_checked() {
+        let paths = temp_paths("files");
+        fs::write(paths.config_dir().join("tools.json"), r#"{"enabled": []}"#).unwrap();
+        fs::write(paths.config_dir().join("tweaks.json"), "{not json").unwrap();
+        let checks = check_config(&paths);
+        let names: Vec<&str> = checks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["config tools.json", "config tweaks.json"]);
+        assert_eq!(checks[0].status, Status::Pass);
+        assert_eq!(checks[1].status, Status::Fail);
+        assert!(!passed(&checks));
+    }
+
+    #[test]
+    fn checklist_lines() {
+        let check = Check::new("config", Status::Warn, "odd");
+        assert_eq!(check.to_string(), "[WARN] config: odd");
+    }
+}
@@ -0,0 +1,196 @@
+//! Which appliances a recipe cooks with, so a recipe for a grill isn't
+//! saved without a word in a kitchen that doesn't have one.
+//!
+//! Appliances have names like `air_fryer` and `instant_pot`, as given to
+//! `prefs set equipment air_fryer,instant_pot`, and are found in a recipe
+//! by keywords in its instructions: "broil" means the oven's broiler and
+//! "pressure cook" means an instant pot.  Every kitchen is taken to have an
+//! oven, a broiler and a stovetop, so only the rest can be missing, and
+//! nothing is missing until the household has listed what it has.
+use crate::recipe::{self, Section};
+
+/// Keywords by appliance, checked against the instructions.  Every match counts.
+static APPLIANCES: &[(&str, &str)] = &[
+    (
+        "instant_pot",
+        "instant pot, pressure cook, pressure release, natural release",
+    ),
+    ("air_fryer", "air fry, air-fry"),
+    ("slow_cooker", "slow cook, slow-cook, crock pot, crockpot"),
+    ("grill", "grill, barbecue, charcoal"),
+    ("smoker", "smoker, wood chips"),
+    ("sous_vide", "sous vide, immersion circulator"),
+    ("broiler", "broil"),
+    ("oven", "oven, bake, roast"),
+    ("microwave", "microwave"),
+    ("food_processor", "food processor"),
+    ("blender", "blender"),
+    ("stand_mixer", "stand mixer, dough hook"),
+];
+
+/// Phrases that contain a keyword without needing the appliance
+static NOT_APPLIANCES: &[&str] = &["grill pan", "grilled cheese", "dutch oven"];
+
+/// Every kitchen has these
+static ASSUMED: &[&str] = &["oven", "broiler"];
+
+/// Other names for an appliance, for what the household types
+static ALIASES: &[(&str, &str)] = &[
+    ("pressure_cooker", "instant_pot"),
+    ("instapot", "instant_pot"),
+    ("instantpot", "instant_pot"),
+    ("airfryer", "air_fryer"),
+    ("crock_pot", "slow_cooker"),
+    ("crockpot", "slow_cooker"),
+    ("bbq", "grill"),
+    ("barbecue", "grill"),
+    ("broil", "broiler"),
+    ("mixer", "stand_mixer"),
+];
+
+/// `name` as an appliance name: lowercase with underscores, and an alias
+/// replaced by what it's an alias for.  Names outside the table are kept,
+/// so a household can list a wok.
+pub fn normalize(name: &str) -> String {
+    let name = name
+        .trim()
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, appliance)| appliance.to_string())
+}
+
+/// A comma separated list of appliances, normalized, e.g. from `prefs set
+/// equipment air_fryer,instant_pot`
+pub fn parse_list(list: &str) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for name in list.split(',').map(normalize) {
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// An appliance name as it reads in a sentence, e.g. "air fryer"
+pub fn label(name: &str) -> String {
+    name.replace('_', " ")
+}
+
+/// The appliances a recipe's instructions call for, in table order.  A
+/// recipe without an instructions section is checked whole.
+pub fn detect(recipe_details: &str) -> Vec<&'static str> {
+    let mut text = recipe::section(recipe_details, Section::Instructions)
+        .unwrap_or_else(|| recipe_details.to_string())
+        .to_lowercase();
+    for phrase in NOT_APPLIANCES {
+        text = text.replace(phrase, " ");
+    }
+    APPLIANCES
+        .iter()
+        .filter(|(_, words)| words.split(", ").any(|w| text.contains(w)))
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+/// What of `used` the household doesn't have.  Nothing, if it hasn't said
+/// what it has.
+pub fn missing(owned: &[String], used: &[&'static str]) -> Vec<&'static str> {
+    if owned.is_empty() {
+        return vec![];
+    }
+    let owned: Vec<String> = owned.iter().map(|o| normalize(o)).collect();
+    used.iter()
+        .filter(|name| !ASSUMED.contains(name) && !owned.iter().any(|o| o == *name))
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_imply_appliances() {
+        let cases: &[(&str, &[&str])] = &[
+            ("Broil for 3 minutes until charred.", &["broiler"]),
+            (
+                "Pressure cook on high for 25 minutes, then let it natural release.",
+                &["instant_pot"],
+            ),
+            ("Seal the Instant Pot lid.", &["instant_pot"]),
+            ("Air-fry at 400F for 12 minutes.", &["air_fryer"]),
+            ("Slow cook on low for 8 hours.", &["slow_cooker"]),
+            ("Put everything in the crockpot.", &["slow_cooker"]),
+            ("Grill over high heat, 4 minutes a side.", &["grill"]),
+            ("Light the charcoal.", &["grill"]),
+            ("Soak the wood chips and heat the smoker.", &["smoker"]),
+            ("Cook sous vide at 57C for 2 hours.", &["sous_vide"]),
+            ("Roast at 425F for 40 minutes.", &["oven"]),
+            ("Bake in the oven until golden.", &["oven"]),
+            ("Microwave for 2 minutes.", &["microwave"]),
+            ("Pulse in a food processor.", &["food_processor"]),
+            ("Blend until smooth in a blender.", &["blender"]),
+            ("Knead with the dough hook.", &["stand_mixer"]),
+            (
+                "Sear in a grill pan, then finish in the oven and broil.",
+                &["broiler", "oven"],
+            ),
+            ("Make grilled cheese in a skillet.", &[]),
+            ("Simmer in a dutch oven for an hour.", &[]),
+            ("Simmer, stirring, for 10 minutes.", &[]),
+            ("", &[]),
+        ];
+        for (instructions, expected) in cases {
+            assert_eq!(detect(instructions), *expected, "{:?}", instructions);
+        }
+    }
+
+    #[test]
+    fn only_the_instructions_are_checked() {
+        let recipe = "Grilled Peaches\n\nIngredients:\n- 4 peaches\n- a splash of oven-roasted \
+                      honey\n\nInstructions:\n1. Halve the peaches.\n2. Air fry for 8 minutes.\n";
+        assert_eq!(detect(recipe), ["air_fryer"]);
+    }
+
+    #[test]
+    fn names_are_normalized() {
+        let cases = [
+            ("air fryer", "air_fryer"),
+            (" Air-Fryer ", "air_fryer"),
+            ("Instapot", "instant_pot"),
+            ("pressure cooker", "instant_pot"),
+            ("BBQ", "grill"),
+            ("crock pot", "slow_cooker"),
+            ("wok", "wok"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(normalize(name), expected, "{:?}", name);
+        }
+        assert_eq!(
+            parse_list("air_fryer, instant pot,, airfryer,wok"),
+            ["air_fryer", "instant_pot", "wok"]
+        );
+        assert_eq!(label("instant_pot"), "instant pot");
+    }
+
+    #[test]
+    fn missing_appliances() {
+        let used = ["instant_pot", "grill", "broiler", "oven"];
+        assert_eq!(
+            missing(&[], &used),
+            Vec::<&str>::new(),
+            "nothing listed yet"
+        );
+        assert_eq!(missing(&["Pressure Cooker".to_string()], &used), ["grill"]);
+        assert_eq!(
+            missing(&["bbq".to_string(), "instapot".to_string()], &used),
+            Vec::<&str>::new()
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! The library's error type for everything outside a single conversation
+//! turn, which has [ConversationError] of its own.
+//!
+//! The library returns these and never prints them or exits; the binary
+//! decides what the user sees, and the process's exit code from
+//! [GourmandError::exit_code].
+use std::io;
+use std::path::{Path, PathBuf};
+
+use aws_sdk_bedrockruntime::error::BuildError;
+use thiserror::Error;
+
+use crate::conversation::ConversationError;
+
+/// Exit codes, from sysexits.h so scripts can tell the failures apart
+pub mod exit {
+    /// Something that can't happen did: a bug
+    pub const SOFTWARE: i32 = 70;
+    /// AWS or the model couldn't be reached or refused the request
+    pub const UNAVAILABLE: i32 = 69;
+    /// A file couldn't be read or written
+    pub const IO: i32 = 74;
+    /// The model's tool input couldn't be used
+    pub const DATA: i32 = 65;
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum GourmandError {
+    /// An SDK builder was missing a required field
+    #[error("couldn't build a request: {0}")]
+    Build(String),
+    /// A call to AWS other than a conversation turn failed
+    #[error("AWS error: {source}")]
+    Aws {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Reading or writing `path` failed
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    /// The model's tool input couldn't be used at all
+    #[error("unusable tool input: {0}")]
+    ToolInput(String),
+    /// Generating an image failed
+    #[error("image generation failed: {0}")]
+    ImageGen(String),
+    /// A conversation turn failed
+    #[error("{0}")]
+    Conversation(#[from] ConversationError),
+}
+
+impl GourmandError {
+    pub fn io(path: &Path, source: io::Error) -> GourmandError {
+        GourmandError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub fn aws(source: impl std::error::Error + Send + Sync + 'static) -> GourmandError {
+        GourmandError::Aws {
+            source: Box::new(source),
+        }
+    }
+
+    /// What the process should exit with, see [exit]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GourmandError::Build(_) => exit::SOFTWARE,
+            GourmandError::Aws { .. } | GourmandError::ImageGen(_) => exit::UNAVAILABLE,
+            GourmandError::Io { .. } => exit::IO,
+            GourmandError::ToolInput(_) => exit::DATA,
+            GourmandError::Conversation(e) => conversation_exit_code(e),
+        }
+    }
+}
+
+fn conversation_exit_code(e: &ConversationError) -> i32 {
+    match e {
+        ConversationError::Build(_) | ConversationError::UnknownTool(_) => exit::SOFTWARE,
+        _ => exit::UNAVAILABLE,
+    }
+}
+
+/// The exit code for any error the binary ends with, by its type; 1 for
+/// errors this module doesn't know
+pub fn exit_code(e: &(dyn std::error::Error + 'static)) -> i32 {
+    if let Some(e) = e.downcast_ref::<GourmandError>() {
+        e.exit_code()
+    } else if let Some(e) = e.downcast_ref::<ConversationError>() {
+        conversation_exit_code(e)
+    } else if e.is::<io::Error>() {
+        exit::IO
+    } else {
+        1
+    }
+}
+
+impl From<BuildError> for GourmandError {
+    fn from(e: BuildError) -> GourmandError {
+        GourmandError::Build(e.to_string())
+    }
+}
@@ -0,0 +1,332 @@
+//! A small harness for comparing system prompt variants.
+//!
+//! A script is a YAML list of scenarios, each a list of user turns plus
+//! assertions about the recipe the model transmits:
+//!
+//! ```yaml
+//! - name: weeknight vegan
+//!   turns:
+//!     - "A vegan main course please, no nuts"
+//!     - "The first one"
+//!   assert:
+//!     transmitted: true
+//!     max_prep_minutes: 10
+//!     max_cook_minutes: 30
+//!     forbidden_ingredients: [peanut, almond, cashew]
+//!     max_turns_before_transmit: 3
+//! ```
+//!
+//! Tools are answered locally and nothing is written to disk.  Assertions
+//! are checked against the transmitted recipe with the section parser.
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, StopReason, ToolResultBlock, ToolResultContentBlock,
+};
+use serde::Deserialize;
+
+use crate::conversation::{Conversation, ConversationError};
+use crate::recipe::{self, Section};
+use crate::system_prompts::{self, Preset};
+use crate::toolinput;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    pub turns: Vec<String>,
+    #[serde(default, rename = "assert")]
+    pub assertions: Assertions,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Assertions {
+    pub transmitted: Option<bool>,
+    pub max_prep_minutes: Option<u32>,
+    pub max_cook_minutes: Option<u32>,
+    pub forbidden_ingredients: Vec<String>,
+    pub max_turns_before_transmit: Option<usize>,
+}
+
+pub fn load_scenarios(path: &Path) -> Result<Vec<Scenario>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    serde_yaml::from_str(&contents).map_err(|e| format!("can't parse {}: {}", path.display(), e))
+}
+
+/// A system prompt to evaluate, named after a built-in prompt or a file
+#[derive(Debug, Clone)]
+pub struct PromptVariant {
+    pub name: String,
+    pub text: String,
+}
+
+impl PromptVariant {
+    /// `family`, `family-v1`, and `minimal` are built in.  Anything else is
+    /// read as a file, named by its stem.
+    pub fn resolve(spec: &str) -> Result<PromptVariant, String> {
+        let builtin = match spec {
+            "family" => Some(Preset::Family.text()),
+            "family-v1" => Some(system_prompts::SYS_PROMPT1.to_string()),
+            "minimal" => Some(Preset::Minimal.text()),
+            _ => None,
+        };
+        if let Some(text) = builtin {
+            return Ok(PromptVariant {
+                name: spec.to_string(),
+                text,
+            });
+        }
+        let path = Path::new(spec);
+        let text = fs::read_to_string(path).map_err(|e| {
+            format!(
+                "{} is neither a built-in prompt (family, family-v1, minimal) nor a readable file: {}",
+                spec, e
+            )
+        })?;
+        let name = path
+            .file_stem()
+            .map_or(spec.to_string(), |s| s.to_string_lossy().to_string());
+        // prompt files saved on Windows
+        let text = text.replace("\r\n", "\n");
+        Ok(PromptVariant { name, text })
+    }
+}
+
+/// What happened when a scenario was run against one prompt
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    /// The first transmitted recipe, if any
+    pub recipe: Option<String>,
+    /// Number of user turns sent before the recipe was transmitted
+    pub turns_before_transmit: Option<usize>,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub latency: Duration,
+    /// Set if the conversation failed partway through
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AssertionResult {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScenarioResult {
+    pub scenario: String,
+    pub prompt: String,
+    pub transcript: Transcript,
+    pub assertions: Vec<AssertionResult>,
+}
+
+impl ScenarioResult {
+    pub fn passed(&self) -> bool {
+        self.transcript.error.is_none() && self.assertions.iter().all(|a| a.passed)
+    }
+}
+
+/// Plays the scenario's user turns through `conversation`, which should
+/// already have its system prompt and tools set.
+pub async fn run_scenario(mut conversation: Conversation, scenario: &Scenario) -> Transcript {
+    let mut transcript = Transcript::default();
+    let started = Instant::now();
+    for (idx, turn_text) in scenario.turns.iter().enumerate() {
+        let mut input = vec![ContentBlock::Text(turn_text.clone())];
+        loop {
+            let turn = match conversation.send_blocks(input.clone()).await {
+                Ok(turn) => turn,
+                Err(e) => {
+                    transcript.error = Some(describe(&e));
+                    transcript.latency = started.elapsed();
+                    return transcript;
+                }
+            };
+            if let Some(usage) = &turn.usage {
+                transcript.input_tokens += i64::from(usage.input_tokens());
+                transcript.output_tokens += i64::from(usage.output_tokens());
+            }
+            let tool_uses: Vec<_> = turn
+                .content
+                .iter()
+                .filter_map(|c| c.as_tool_use().ok())
+                .collect();
+            if turn.stop_reason != StopReason::ToolUse || tool_uses.is_empty() {
+                break;
+            }
+            let results: Result<Vec<ContentBlock>, _> = tool_uses
+                .into_iter()
+                .map(|tool_use| {
+                    let input = toolinput::object(tool_use.input()).ok();
+                    let details = input
+                        .as_ref()
+                        .and_then(|(input, _)| input.get("recipe_details"))
+                        .and_then(|doc| doc.as_string());
+                    if let (Some(details), None) = (details, &transcript.recipe) {
+                        transcript.recipe = Some(details.to_string());
+                        transcript.turns_before_transmit = Some(idx + 1);
+                    }
+                    let reply = match tool_use.name() {
+                        "transmit_recipe" => "saved to eval.txt and eval.png".to_string(),
+                        "ask_user" => "no preference".to_string(),
+                        name => format!("{} is unavailable during evaluation", name),
+                    };
+                    ToolResultBlock::builder()
+                        .tool_use_id(tool_use.tool_use_id())
+                        .content(ToolResultContentBlock::Text(reply))
+                        .build()
+                        .map(ContentBlock::ToolResult)
+                })
+                .collect();
+            input = match results {
+                Ok(results) => results,
+                Err(e) => {
+                    transcript.error = Some(describe(&ConversationError::from(e)));
+                    transcript.latency = started.elapsed();
+                    return transcript;
+                }
+            };
+        }
+    }
+    transcript.latency = started.elapsed();
+    transcript
+}
+
+fn describe(e: &ConversationError) -> String {
+    e.to_string().replace(['\n', ','], " ")
+}
+
+/// Checks the transcript against each assertion the scenario sets
+pub fn evaluate(assertions: &Assertions, transcript: &Transcript) -> Vec<AssertionResult> {
+    let mut results = vec![];
+    let recipe = transcript.recipe.as_deref();
+    if let Some(expected) = assertions.transmitted {
+        results.push(AssertionResult {
+            name: "transmitted",
+            passed: recipe.is_some() == expected,
+            detail: format!("transmitted={}", recipe.is_some()),
+        });
+    }
+    if let Some(max) = assertions.max_prep_minutes {
+        let prep = recipe.and_then(prep_minutes);
+        results.push(AssertionResult {
+            name: "max_prep_minutes",
+            passed: prep.is_some_and(|p| p <= max),
+            detail: prep.map_or("no prep time found".to_string(), |p| format!("{} min", p)),
+        });
+    }
+    if let Some(max) = assertions.max_cook_minutes {
+        let cook = recipe.and_then(cook_minutes);
+        results.push(AssertionResult {
+            name: "max_cook_minutes",
+            passed: cook.is_some_and(|c| c <= max),
+            detail: cook.map_or("no cook time found".to_string(), |c| format!("{} min", c)),
+        });
+    }
+    if !assertions.forbidden_ingredients.is_empty() {
+        let ingredients = recipe
+            .and_then(|r| recipe::section(r, Section::Ingredients))
+            .unwrap_or_default()
+            .to_lowercase();
+        let found: Vec<&str> = assertions
+            .forbidden_ingredients
+            .iter()
+            .filter(|i| ingredients.contains(&i.to_lowercase()))
+            .map(String::as_str)
+            .collect();
+        results.push(AssertionResult {
+            name: "forbidden_ingredients",
+            passed: recipe.is_some() && found.is_empty(),
+            detail: if found.is_empty() {
+                "none found".to_string()
+            } else {
+                found.join(" ")
+            },
+        });
+    }
+    if let Some(max) = assertions.max_turns_before_transmit {
+        let turns = transcript.turns_before_transmit;
+        results.push(AssertionResult {
+            name: "max_turns_before_transmit",
+            passed: turns.is_some_and(|t| t <= max),
+            detail: turns.map_or("never transmitted".to_string(), |t| format!("{} turns", t)),
+        });
+    }
+    results
+}
+
+/// Finds a line like "Prep time: 10 minutes" or "Prep: 1 hour 5 min"
+pub fn prep_minutes(recipe: &str) -> Option<u32> {
+    recipe::labeled_minutes(recipe, "prep")
+}
+
+/// Finds a line like "Cook time: 25 minutes"
+pub fn cook_minutes(recipe: &str) -> Option<u32> {
+    recipe::labeled_minutes(recipe, "cook")
+}
+
+/// One row per scenario and prompt, with every assertion as `name=PASS|FAIL`
+pub fn to_csv(results: &[ScenarioResult]) -> String {
+    let mut out = String::from(
+        "scenario,prompt,passed,assertions,input_tokens,output_tokens,latency_ms,error\n",
+    );
+    for r in results {
+        let assertions: Vec<String> = r
+            .assertions
+            .iter()
+            .map(|a| format!("{}={}", a.name, if a.passed { "PASS" } else { "FAIL" }))
+            .collect();
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            r.scenario.replace(',', " "),
+            r.prompt.replace(',', " "),
+            r.passed(),
+            assertions.join(" "),
+            r.transcript.input_tokens,
+            r.transcript.output_tokens,
+            r.transcript.latency.as_millis(),
+            r.transcript.error.as_deref().unwrap_or(""),
+        );
+    }
+    out
+}
+
+/// Pass counts per prompt, followed by every failure
+pub fn summary(results: &[ScenarioResult]) -> String {
+    let mut out = String::new();
+    let mut prompts: Vec<&str> = results.iter().map(|r| r.prompt.as_str()).collect();
+    prompts.dedup();
+    for prompt in prompts {
+        let runs: Vec<&ScenarioResult> = results.iter().filter(|r| r.prompt == prompt).collect();
+        let passed = runs.iter().filter(|r| r.passed()).count();
+        let tokens: i64 = runs
+            .iter()
+            .map(|r| r.transcript.input_tokens + r.transcript.output_tokens)
+            .sum();
+        let latency: Duration = runs.iter().map(|r| r.transcript.latency).sum();
+        let _ = writeln!(
+            out,
+            "{}: {}/{} scenarios passed, {} tokens, {:.1}s",
+            prompt,
+            passed,
+            runs.len(),
+            tokens,
+            latency.as_secs_f64()
+        );
+        for r in runs.iter().filter(|r| !r.passed()) {
+            if let Some(e) = &r.transcript.error {
+                let _ = writeln!(out, "  {}: error: {}", r.scenario, e);
+            }
+            for a in r.assertions.iter().filter(|a| !a.passed) {
+                let _ = writeln!(out, "  {}: {} failed ({})", r.scenario, a.name, a.detail);
+            }
+        }
+    }
+    out
+}
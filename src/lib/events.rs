@@ -0,0 +1,92 @@
+//! What happens while a prompt is answered, as a stream of events for
+//! front ends that don't read the terminal.
+//!
+//! A turn that only calls a tool prints nothing, which is fine at the
+//! shell but leaves a programmatic caller unable to tell that a tool round
+//! happened.  The shell emits a [TurnEvent] for each piece of text, each
+//! tool use and its result, and the end of the turn to the [EventSink] it
+//! was given.
+use std::fmt::Debug;
+use std::sync::{Mutex, PoisonError};
+
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Serialized as `{"type": "text_emitted", "data": ...}`, e.g. for
+/// [crate::rpc]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum TurnEvent {
+    /// Text from the assistant, as printed
+    TextEmitted(String),
+    ToolInvoked {
+        name: String,
+        tool_use_id: String,
+    },
+    ToolCompleted {
+        name: String,
+        tool_use_id: String,
+        /// The first line of the result
+        summary: String,
+        ok: bool,
+    },
+    /// The model stopped, e.g. `end_turn`, or the request failed
+    TurnEnded {
+        stop_reason: String,
+    },
+}
+
+pub trait EventSink: Debug + Send + Sync {
+    /// Delivers the event.  Failures are dropped, never propagated.
+    fn emit(&self, event: TurnEvent);
+}
+
+/// Discards everything; the default at the shell
+#[derive(Debug, Default)]
+pub struct NoopEventSink;
+
+impl EventSink for NoopEventSink {
+    fn emit(&self, _event: TurnEvent) {}
+}
+
+/// Sends events down a channel to another task.  Events sent after the
+/// receiver is gone are dropped.
+#[derive(Debug)]
+pub struct ChannelEventSink {
+    sender: UnboundedSender<TurnEvent>,
+}
+
+impl ChannelEventSink {
+    pub fn new(sender: UnboundedSender<TurnEvent>) -> ChannelEventSink {
+        ChannelEventSink { sender }
+    }
+}
+
+impl EventSink for ChannelEventSink {
+    fn emit(&self, event: TurnEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Keeps events in memory until they're taken, e.g. to build a reply once
+/// the turn is over
+#[derive(Debug, Default)]
+pub struct CollectingEventSink {
+    events: Mutex<Vec<TurnEvent>>,
+}
+
+impl CollectingEventSink {
+    /// The events so far, oldest first, leaving none behind
+    pub fn take(&self) -> Vec<TurnEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl EventSink for CollectingEventSink {
+    fn emit(&self, event: TurnEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(event);
+    }
+}
@@ -0,0 +1,7 @@
+//! Saved recipes in formats meant for sharing
+#[cfg(feature = "caldav")]
+pub mod caldav;
+pub mod digest;
+pub mod html;
+#[cfg(feature = "export-s3")]
+pub mod s3;
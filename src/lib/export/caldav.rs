@@ -0,0 +1,422 @@
+//! Shopping lists as tasks on a CalDAV server, which is how iOS Reminders,
+//! Nextcloud Tasks, and Fastmail share to-do lists.
+//!
+//! Only what's needed to create tasks is implemented: one `PUT` of a VTODO
+//! per item into an existing collection, with basic auth over TLS.  The
+//! server and credentials come from `caldav.json` in the config directory,
+//! never from the command line, so the password doesn't end up in shell
+//! history:
+//!
+//! ```json
+//! {
+//!   "url": "https://caldav.example.com/dav/calendars/me/groceries/",
+//!   "username": "me@example.com",
+//!   "password": "an app-specific password"
+//! }
+//! ```
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use ulid::Ulid;
+
+#[derive(Deserialize, Clone)]
+pub struct CalDavConfig {
+    /// The task collection, e.g. a Reminders list
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+// keeps the password out of debug logs
+impl std::fmt::Debug for CalDavConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CalDavConfig")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CalDavConfig {
+    /// Loads the config from `path`, or `None` if it doesn't exist
+    pub fn load(path: &Path) -> io::Result<Option<CalDavConfig>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The items of a shopping list, without bullets or the aisle headings
+/// that [crate::shopping::AisleClassifier::render_grouped] adds
+pub fn items(shopping_list: &str) -> Vec<String> {
+    shopping_list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.ends_with(':'))
+        .map(|line| line.trim_start_matches(['-', '*', '•']).trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Escapes a TEXT value (RFC 5545 3.3.11)
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => (),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Folds a content line at 75 octets, without splitting a character
+fn fold(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + line.len() / 74 * 3);
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out
+}
+
+/// A VCALENDAR holding a single VTODO
+pub fn todo_ics(uid: &str, summary: &str, description: &str) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut ics = String::new();
+    for line in [
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//gourmand//{}//EN", env!("CARGO_PKG_VERSION")),
+        "BEGIN:VTODO".to_string(),
+        format!("UID:{}", uid),
+        format!("DTSTAMP:{}", stamp),
+        format!("SUMMARY:{}", escape(summary)),
+        format!("DESCRIPTION:{}", escape(description)),
+        "STATUS:NEEDS-ACTION".to_string(),
+        "END:VTODO".to_string(),
+        "END:VCALENDAR".to_string(),
+    ] {
+        let _ = write!(ics, "{}\r\n", fold(&line));
+    }
+    ics
+}
+
+/// How creating one task went
+#[derive(Debug, Clone)]
+pub struct ItemResult {
+    pub item: String,
+    pub result: Result<(), String>,
+}
+
+pub struct Client {
+    config: CalDavConfig,
+    http: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(config: CalDavConfig) -> Result<Client, String> {
+        if !config.url.starts_with("https://") {
+            return Err(format!(
+                "{} isn't https, refusing to send the password in the clear",
+                config.url
+            ));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(15))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Client { config, http })
+    }
+
+    /// Creates one task per item, with `title` (the recipe's) as the note
+    pub async fn push(&self, title: &str, items: &[String]) -> Vec<ItemResult> {
+        let mut results = vec![];
+        for item in items {
+            let result = self.create(item, title).await;
+            results.push(ItemResult {
+                item: item.clone(),
+                result,
+            });
+        }
+        results
+    }
+
+    async fn create(&self, summary: &str, description: &str) -> Result<(), String> {
+        let uid = format!("gourmand-{}", Ulid::new());
+        let url = format!("{}/{}.ics", self.config.url.trim_end_matches('/'), uid);
+        let body = todo_ics(&uid, summary, description);
+
+        // If-None-Match makes this a create and never an overwrite; some
+        // servers insist on it, and a few don't understand it at all
+        let mut status = self.put(&url, &body, true).await?;
+        if status == StatusCode::BAD_REQUEST || status == StatusCode::NOT_IMPLEMENTED {
+            status = self.put(&url, &body, false).await?;
+        }
+        match status {
+            s if s.is_success() => Ok(()),
+            StatusCode::PRECONDITION_FAILED => {
+                Err("a task with this id already exists".to_string())
+            }
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(format!("{}: check the username and password", status))
+            }
+            s => Err(format!("server returned {}", s)),
+        }
+    }
+
+    async fn put(&self, url: &str, body: &str, if_none_match: bool) -> Result<StatusCode, String> {
+        let mut request = self
+            .http
+            .put(url)
+            .basic_auth(&self.config.username, Some(&self.config.password))
+            .header("Content-Type", "text/calendar; charset=utf-8")
+            .body(body.to_string());
+        if if_none_match {
+            request = request.header("If-None-Match", "*");
+        }
+        request
+            .send()
+            .await
+            .map(|r| r.status())
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    use super::*;
+
+    /// A PUT the mock server was sent
+    #[derive(Debug)]
+    struct Received {
+        path: String,
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl Received {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// A CalDAV server on localhost that answers each request with the
+    /// next of `statuses`, and returns what it was sent once they run out
+    async fn mock_server(statuses: Vec<u16>) -> (CalDavConfig, JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = CalDavConfig {
+            url: format!(
+                "http://{}/dav/me/groceries/",
+                listener.local_addr().unwrap()
+            ),
+            username: "me@example.com".to_string(),
+            password: "app password".to_string(),
+        };
+        let server = tokio::spawn(async move {
+            let mut received = vec![];
+            for status in statuses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                let path = line.split(' ').nth(1).unwrap().to_string();
+                let mut headers = vec![];
+                loop {
+                    line.clear();
+                    stream.read_line(&mut line).await.unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    headers.push((name.to_lowercase(), value.to_string()));
+                }
+                let length: usize = headers
+                    .iter()
+                    .find(|(name, _)| name == "content-length")
+                    .map_or(0, |(_, v)| v.parse().unwrap());
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                stream
+                    .get_mut()
+                    .write_all(response.as_bytes())
+                    .await
+                    .unwrap();
+                received.push(Received {
+                    path,
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+            }
+            received
+        });
+        (config, server)
+    }
+
+    /// [Client::new] only takes https; the mock server is plain http
+    fn client(config: CalDavConfig) -> Client {
+        Client {
+            config,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn owned(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn refuses_plain_http() {
+        let config = CalDavConfig {
+            url: "http://caldav.example.com/dav/".to_string(),
+            username: "me".to_string(),
+            password: "secret".to_string(),
+        };
+        assert!(Client::new(config).is_err());
+    }
+
+    #[tokio::test]
+    async fn creates_one_task_per_item() {
+        let (config, server) = mock_server(vec![201, 204]).await;
+        let results = client(config)
+            .push(
+                "Leek Risotto",
+                &owned(&["2 leeks", "1 cup arborio rice, or carnaroli"]),
+            )
+            .await;
+        assert!(results.iter().all(|r| r.result.is_ok()), "{:?}", results);
+
+        let received = server.await.unwrap();
+        let auth = base64::engine::general_purpose::STANDARD.encode("me@example.com:app password");
+        for (request, summary) in received.iter().zip([
+            "SUMMARY:2 leeks",
+            "SUMMARY:1 cup arborio rice\\, or carnaroli",
+        ]) {
+            assert!(
+                request.path.starts_with("/dav/me/groceries/gourmand-")
+                    && request.path.ends_with(".ics"),
+                "{}",
+                request.path
+            );
+            assert_eq!(request.header("if-none-match"), Some("*"));
+            assert_eq!(
+                request.header("authorization"),
+                Some(format!("Basic {}", auth).as_str())
+            );
+            assert_eq!(
+                request.header("content-type"),
+                Some("text/calendar; charset=utf-8")
+            );
+            let lines: Vec<&str> = request.body.split("\r\n").collect();
+            assert!(lines.contains(&summary), "{}", request.body);
+            assert!(
+                lines.contains(&"DESCRIPTION:Leek Risotto"),
+                "{}",
+                request.body
+            );
+            assert!(lines.contains(&"BEGIN:VTODO"), "{}", request.body);
+        }
+        assert_ne!(
+            received[0].path, received[1].path,
+            "each task has its own uid"
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_without_if_none_match_when_the_server_rejects_it() {
+        let (config, server) = mock_server(vec![400, 201]).await;
+        let results = client(config)
+            .push("Leek Risotto", &owned(&["2 leeks"]))
+            .await;
+        assert!(results[0].result.is_ok(), "{:?}", results);
+
+        let received = server.await.unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].header("if-none-match"), Some("*"));
+        assert_eq!(received[1].header("if-none-match"), None);
+        assert_eq!(received[0].path, received[1].path);
+        assert_eq!(received[0].body, received[1].body);
+    }
+
+    #[tokio::test]
+    async fn reports_each_item_on_its_own() {
+        let (config, server) = mock_server(vec![201, 403, 412, 507]).await;
+        let results = client(config)
+            .push(
+                "Leek Risotto",
+                &owned(&["leeks", "rice", "stock", "parmesan"]),
+            )
+            .await;
+        let outcomes: Vec<(&str, Result<(), String>)> = results
+            .iter()
+            .map(|r| (r.item.as_str(), r.result.clone()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("leeks", Ok(())),
+                (
+                    "rice",
+                    Err("403 Forbidden: check the username and password".to_string())
+                ),
+                (
+                    "stock",
+                    Err("a task with this id already exists".to_string())
+                ),
+                (
+                    "parmesan",
+                    Err("server returned 507 Insufficient Storage".to_string())
+                ),
+            ]
+        );
+        assert_eq!(server.await.unwrap().len(), 4);
+    }
+
+    #[test]
+    fn long_lines_are_folded_and_text_is_escaped() {
+        let ics = todo_ics("uid", &"a".repeat(100), "salt; pepper\nto taste");
+        assert!(ics.lines().all(|line| line.len() <= 76), "{}", ics);
+        assert!(ics.contains("\r\n a"), "{}", ics);
+        assert!(
+            ics.contains("DESCRIPTION:salt\\; pepper\\nto taste\r\n"),
+            "{}",
+            ics
+        );
+    }
+
+    #[test]
+    fn items_leave_out_headings_and_bullets() {
+        assert_eq!(
+            items("Produce:\n- 2 leeks\n* 1 lemon\n\nDairy:\n• parmesan\n"),
+            ["2 leeks", "1 lemon", "parmesan"]
+        );
+    }
+}
@@ -0,0 +1,405 @@
+//! A week of planned meals as one HTML email: each day's recipe with its
+//! picture and where to find it, then a single shopping list for the week,
+//! combined and grouped by aisle.
+//!
+//! A plan is a directory of saved recipes, taken in the order they were
+//! saved, one per day.  The pictures travel inside the message as
+//! `multipart/related` parts that the HTML refers to by `cid:`, since most
+//! mail clients block `data:` images.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use chrono::Weekday;
+
+use crate::export::html::escape;
+use crate::imageformat::ImageFormat;
+use crate::recipe::{self, Section};
+use crate::shopping::{Aisle, AisleClassifier};
+use crate::themes;
+use crate::tidy::{self, Kind};
+use crate::units;
+
+/// Base64 lines in the message are wrapped at this many characters
+const LINE_LEN: usize = 76;
+
+const STYLE: &str = "
+body { font-family: Georgia, serif; max-width: 40em; margin: 1em auto; color: #222; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.2em; border-bottom: 1px solid #ccc; margin-top: 1.5em; }
+.day { margin: 1em 0; overflow: hidden; }
+.day img { float: left; width: 160px; height: 160px; object-fit: cover; margin-right: 1em; border-radius: 6px; }
+.day .when { font-size: 0.8em; text-transform: uppercase; color: #666; }
+.day .path { font-size: 0.8em; color: #666; }
+";
+
+/// One day of the plan
+#[derive(Debug, Clone)]
+pub struct PlannedMeal {
+    pub day: Weekday,
+    pub title: String,
+    /// The recipe's `.txt`
+    pub path: PathBuf,
+    pub text: String,
+    /// The first image, as saved
+    pub image: Option<(Vec<u8>, ImageFormat)>,
+}
+
+/// Loads the recipes in `dir`, oldest first, assigning days from `start`
+pub fn load_plan(dir: &Path, start: Weekday) -> io::Result<Vec<PlannedMeal>> {
+    let mut recipes = vec![];
+    for group in tidy::group(dir)?.into_values() {
+        let Some(txt) = group.get(Kind::Text) else {
+            continue;
+        };
+        let text = fs::read_to_string(txt)?;
+        let Some(title) = recipe::title(&text) else {
+            continue;
+        };
+        let saved = fs::metadata(txt)?.modified()?;
+        let image = match group.images().next() {
+            Some(path) => Some((fs::read(path)?, ImageFormat::of(path).unwrap_or_default())),
+            None => None,
+        };
+        recipes.push((saved, title, txt.to_path_buf(), text, image));
+    }
+    recipes.sort_by_key(|(saved, ..)| *saved);
+    let mut day = start;
+    let mut meals = vec![];
+    for (_, title, path, text, image) in recipes {
+        meals.push(PlannedMeal {
+            day,
+            title,
+            path,
+            text,
+            image,
+        });
+        day = day.succ();
+    }
+    Ok(meals)
+}
+
+#[derive(Debug, Clone)]
+pub struct Digest {
+    pub subject: String,
+    pub meals: Vec<PlannedMeal>,
+    /// The week's shopping, combined across recipes
+    pub shopping: BTreeMap<Aisle, Vec<String>>,
+}
+
+impl Digest {
+    pub fn new(
+        subject: impl Into<String>,
+        meals: Vec<PlannedMeal>,
+        aisles: &AisleClassifier,
+    ) -> Digest {
+        let mut items: Vec<String> = vec![];
+        for meal in &meals {
+            // the ingredients, for a recipe saved without a shopping list
+            let list = recipe::section(&meal.text, Section::ShoppingList)
+                .or_else(|| recipe::section(&meal.text, Section::Ingredients))
+                .unwrap_or_default();
+            items.extend(
+                list.lines()
+                    .map(|line| line.trim().trim_start_matches(['-', '*', '•']).trim())
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        let items: Vec<&str> = items.iter().map(String::as_str).collect();
+        let combined = units::consolidate(&items);
+        let combined: Vec<&str> = combined.iter().map(String::as_str).collect();
+        let shopping = aisles
+            .group(&combined)
+            .into_iter()
+            .map(|(aisle, items)| (aisle, items.into_iter().map(str::to_string).collect()))
+            .collect();
+        Digest {
+            subject: subject.into(),
+            meals,
+            shopping,
+        }
+    }
+
+    fn content_id(idx: usize) -> String {
+        format!("meal-{}@gourmand", idx + 1)
+    }
+
+    /// The email's HTML, with pictures as `cid:` references to the parts
+    /// [Digest::eml] adds
+    pub fn html(&self) -> String {
+        let mut body = format!("<h1>{}</h1>\n", escape(&self.subject));
+        for (idx, meal) in self.meals.iter().enumerate() {
+            body.push_str("<div class=\"day\">\n");
+            if meal.image.is_some() {
+                body.push_str(&format!(
+                    "<img alt=\"{}\" src=\"cid:{}\">\n",
+                    escape(&meal.title),
+                    Self::content_id(idx)
+                ));
+            }
+            body.push_str(&format!(
+                "<div class=\"when\">{}</div>\n<h2>{}</h2>\n<div class=\"path\"><a href=\"{}\">{}</a></div>\n",
+                escape(themes::day_name(meal.day)),
+                escape(&meal.title),
+                escape(&file_url(&meal.path)),
+                escape(&crate::paths::display(&meal.path))
+            ));
+            body.push_str("</div>\n");
+        }
+        if !self.shopping.is_empty() {
+            body.push_str("<h2>Shopping list</h2>\n");
+            for (aisle, items) in &self.shopping {
+                let items: String = items
+                    .iter()
+                    .map(|item| format!("<li>{}</li>\n", escape(item)))
+                    .collect();
+                body.push_str(&format!("<h3>{}</h3>\n<ul>\n{}</ul>\n", aisle, items));
+            }
+        }
+        format!(
+            "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<title>{}</title>
+<style>{}</style>
+</head>
+<body>
+{}</body>
+</html>
+",
+            escape(&self.subject),
+            STYLE,
+            body
+        )
+    }
+
+    /// The whole message in RFC 5322 form, ready for a `.eml` file or
+    /// SES's raw send.  `date` is an RFC 2822 date.
+    pub fn eml(&self, from: &str, to: &str, date: &str) -> String {
+        let html = self.html();
+        let boundary = format!("gourmand-{}", &crate::metadata::sha256_hex(&html)[..24]);
+        let mut out = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+             Content-Type: multipart/related; boundary=\"{}\"; type=\"text/html\"\r\n\r\n",
+            from,
+            to,
+            encode_header(&self.subject),
+            date,
+            boundary
+        );
+        out.push_str(&format!(
+            "--{}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+            boundary,
+            wrapped_base64(html.as_bytes())
+        ));
+        for (idx, meal) in self.meals.iter().enumerate() {
+            let Some((bytes, format)) = &meal.image else {
+                continue;
+            };
+            let name = meal
+                .path
+                .with_extension(format.extension())
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| format!("recipe.{}", format.extension()));
+            out.push_str(&format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Transfer-Encoding: base64\r\n\
+                 Content-ID: <{}>\r\nContent-Disposition: inline; filename=\"{}\"\r\n\r\n{}",
+                boundary,
+                format.mime_type(),
+                Self::content_id(idx),
+                name.replace('"', ""),
+                wrapped_base64(bytes)
+            ));
+        }
+        out.push_str(&format!("--{}--\r\n", boundary));
+        out
+    }
+}
+
+fn wrapped_base64(bytes: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / LINE_LEN * 2 + 2);
+    for chunk in encoded.as_bytes().chunks(LINE_LEN) {
+        out.push_str(&String::from_utf8_lossy(chunk));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// `text` as an RFC 2047 encoded word if it isn't plain ASCII
+fn encode_header(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_string();
+    }
+    format!(
+        "=?utf-8?B?{}?=",
+        base64::engine::general_purpose::STANDARD.encode(text)
+    )
+}
+
+/// A `file://` link to `path`, with the characters links can't hold escaped
+fn file_url(path: &Path) -> String {
+    let mut url = "file://".to_string();
+    for c in path.display().to_string().chars() {
+        match c {
+            ' ' => url.push_str("%20"),
+            '#' => url.push_str("%23"),
+            '?' => url.push_str("%3F"),
+            '%' => url.push_str("%25"),
+            c => url.push(c),
+        }
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::golden;
+
+    const FROM: &str = "gourmand@example.com";
+    const TO: &str = "family@example.com";
+    const DATE: &str = "Fri, 16 Oct 2026 18:00:00 +0000";
+
+    fn meal(day: Weekday, stem: &str, text: &str, image: Option<&[u8]>) -> PlannedMeal {
+        PlannedMeal {
+            day,
+            title: recipe::title(text).unwrap(),
+            path: PathBuf::from(format!("/srv/recipes/{}.txt", stem)),
+            text: text.to_string(),
+            image: image.map(|bytes| (bytes.to_vec(), ImageFormat::Png)),
+        }
+    }
+
+    /// Three days: two with pictures, one with a shopping list and one
+    /// with ingredients only, sharing some of what they need
+    fn week() -> Digest {
+        let meals = vec![
+            meal(
+                Weekday::Mon,
+                "leek-risotto",
+                "Leek Risotto\n\nIngredients:\n- 2 leeks\n- 1 cup arborio rice\n- 1 onion\n\n\
+                 Instructions:\n1. Simmer.\n\nShopping List:\n- 2 leeks\n- 1 cup arborio rice\n- 1 onion\n",
+                Some(&[0x89, b'P', b'N', b'G', 0, 1, 2, 3]),
+            ),
+            meal(
+                Weekday::Tue,
+                "chana-masala",
+                "Chana Masala\n\nIngredients:\n- 2 cans chickpeas\n- 1 onion\n- 1 tsp garam masala\n\n\
+                 Instructions:\n1. Simmer.\n",
+                None,
+            ),
+            meal(
+                Weekday::Wed,
+                "fish & chips",
+                "Fish & Chips\n\nIngredients:\n- 1 lb cod\n- 2 lb potatoes\n\nInstructions:\n1. Fry.\n",
+                Some(&[0x89, b'P', b'N', b'G', 9, 8, 7]),
+            ),
+        ];
+        Digest::new(
+            "Dinners for the week of 19 Oct",
+            meals,
+            &AisleClassifier::default(),
+        )
+    }
+
+    #[test]
+    fn writes_the_week_as_an_eml_file() {
+        golden::check(&week().eml(FROM, TO, DATE), "digest/week.eml");
+    }
+
+    #[test]
+    fn pictures_are_related_parts_the_html_refers_to() {
+        let digest = week();
+        let html = digest.html();
+        let eml = digest.eml(FROM, TO, DATE);
+        for id in ["meal-1@gourmand", "meal-3@gourmand"] {
+            assert!(html.contains(&format!("src=\"cid:{}\"", id)), "{}", id);
+            assert!(eml.contains(&format!("Content-ID: <{}>\r\n", id)), "{}", id);
+        }
+        assert!(
+            !html.contains("meal-2@gourmand"),
+            "chana masala has no picture"
+        );
+        assert!(eml.contains("Content-Disposition: inline; filename=\"fish & chips.png\""));
+
+        let boundary = eml
+            .split("boundary=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+        assert_eq!(eml.matches(&format!("--{}\r\n", boundary)).count(), 3);
+        assert!(eml.ends_with(&format!("--{}--\r\n", boundary)));
+        assert!(eml.lines().all(|line| line.len() <= 998));
+    }
+
+    #[test]
+    fn one_shopping_list_for_the_week() {
+        let items: Vec<String> = week().shopping.into_values().flatten().collect();
+        assert_eq!(
+            items.iter().filter(|item| item.contains("onion")).count(),
+            1,
+            "{:?}",
+            items
+        );
+        assert!(
+            items.iter().any(|item| item.contains("chickpeas")),
+            "{:?}",
+            items
+        );
+        assert!(items.iter().any(|item| item.contains("cod")), "{:?}", items);
+    }
+
+    #[test]
+    fn headers_and_links_are_encoded() {
+        assert_eq!(encode_header("Dinners"), "Dinners");
+        assert_eq!(encode_header("Menú"), "=?utf-8?B?TWVuw7o=?=");
+        assert_eq!(
+            file_url(Path::new("/srv/my recipes/#1 100%?.txt")),
+            "file:///srv/my%20recipes/%231%20100%25%3F.txt"
+        );
+        let wrapped = wrapped_base64(&[0u8; 100]);
+        let lines: Vec<usize> = wrapped.split("\r\n").map(str::len).collect();
+        assert_eq!(lines, [76, 60, 0]);
+    }
+
+    #[test]
+    fn plans_are_taken_in_the_order_they_were_saved() {
+        let dir = std::env::temp_dir().join(format!("gourmand-digest-{}", ulid::Ulid::new()));
+        fs::create_dir_all(&dir).unwrap();
+        let saved = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        for (minutes, stem) in [(2, "tacos"), (1, "soup"), (3, "curry")] {
+            let path = dir.join(format!("{}.txt", stem));
+            let title = format!("{}{}", stem[..1].to_uppercase(), &stem[1..]);
+            fs::write(&path, format!("{}\n\nIngredients:\n- 1 thing\n", title)).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(saved + Duration::from_secs(minutes * 60))
+                .unwrap();
+        }
+        fs::write(dir.join("tacos-0.jpg"), b"jpeg").unwrap();
+        fs::write(dir.join("notes.md"), "not a recipe").unwrap();
+
+        let plan = load_plan(&dir, Weekday::Sat).unwrap();
+        let days: Vec<(Weekday, &str)> = plan.iter().map(|m| (m.day, m.title.as_str())).collect();
+        assert_eq!(
+            days,
+            [
+                (Weekday::Sat, "Soup"),
+                (Weekday::Sun, "Tacos"),
+                (Weekday::Mon, "Curry")
+            ]
+        );
+        assert_eq!(plan[1].image, Some((b"jpeg".to_vec(), ImageFormat::Jpeg)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
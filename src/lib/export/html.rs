@@ -0,0 +1,217 @@
+//! A single self-contained HTML page for a recipe: the image is embedded,
+//! the QR code is inline SVG, and nothing is fetched from anywhere, so the
+//! file can be sent to whoever is cooking tonight.
+//!
+//! Everything in the page except our own markup came from the model, so all
+//! of it is escaped.
+use base64::Engine;
+use qrcode::render::svg;
+use qrcode::QrCode;
+
+use crate::imageformat::ImageFormat;
+use crate::recipe::{self, Section};
+
+/// QR codes hold less than 3KB, and dense ones are hard to scan
+const MAX_QR_BYTES: usize = 1024;
+
+const STYLE: &str = "
+body { font-family: Georgia, serif; max-width: 40em; margin: 2em auto; padding: 0 1em; color: #222; }
+h1 { font-size: 1.8em; margin-bottom: 0.5em; }
+h2 { font-size: 1.2em; border-bottom: 1px solid #ccc; margin-top: 1.5em; }
+img.dish { width: 100%; border-radius: 6px; }
+ul.check { list-style: none; padding-left: 0; }
+ul.check li { margin: 0.3em 0; }
+ol li { margin: 0.5em 0; }
+.qr { margin-top: 2em; text-align: center; font-size: 0.8em; color: #666; }
+.qr svg { width: 10em; height: 10em; }
+pre { white-space: pre-wrap; font-family: inherit; }
+@media print {
+  body { margin: 0; max-width: none; }
+  img.dish { max-height: 12em; width: auto; }
+  h2 { page-break-after: avoid; }
+  li { page-break-inside: avoid; }
+}
+";
+
+/// Escapes text for use in HTML element content and attribute values
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn list_items(section: &str) -> Vec<String> {
+    section
+        .lines()
+        .map(|line| line.trim().trim_start_matches(['-', '*', '•']).trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn checklist(heading: &str, items: &[String]) -> String {
+    let items: String = items
+        .iter()
+        .map(|item| {
+            format!(
+                "<li><label><input type=\"checkbox\"> {}</label></li>\n",
+                escape(item)
+            )
+        })
+        .collect();
+    format!(
+        "<h2>{}</h2>\n<ul class=\"check\">\n{}</ul>\n",
+        escape(heading),
+        items
+    )
+}
+
+/// The shopping list (or ingredients) as an inline SVG QR code, so a phone
+/// can pick the list up without any network
+fn qr_svg(items: &[String]) -> Option<String> {
+    if items.is_empty() {
+        return None;
+    }
+    let mut text = items.join("\n");
+    crate::limits::truncate_silently(&mut text, MAX_QR_BYTES);
+    let code = QrCode::new(text.as_bytes()).ok()?;
+    Some(
+        code.render::<svg::Color>()
+            .min_dimensions(160, 160)
+            .quiet_zone(true)
+            .build(),
+    )
+}
+
+/// Renders `text` (as transmitted by the model) with an optional image.
+/// Falls back to the text as-is when it doesn't have recognizable sections.
+pub fn render(text: &str, image: Option<(&[u8], ImageFormat)>) -> String {
+    let title = recipe::title(text).unwrap_or_else(|| "Recipe".to_string());
+    let ingredients = recipe::section(text, Section::Ingredients).map(|s| list_items(&s));
+    let instructions =
+        recipe::section(text, Section::Instructions).map(|s| recipe::parse_steps(&s));
+    let shopping = recipe::section(text, Section::ShoppingList).map(|s| list_items(&s));
+
+    let mut body = format!("<h1>{}</h1>\n", escape(&title));
+    if let Some((bytes, format)) = image {
+        body.push_str(&format!(
+            "<img class=\"dish\" alt=\"{}\" src=\"data:{};base64,{}\">\n",
+            escape(&title),
+            format.mime_type(),
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        ));
+    }
+    if ingredients.is_none() && instructions.is_none() {
+        body.push_str(&format!("<pre>{}</pre>\n", escape(text)));
+    }
+    if let Some(items) = &ingredients {
+        body.push_str(&checklist("Ingredients", items));
+    }
+    if let Some(steps) = &instructions {
+        let steps: String = steps
+            .iter()
+            .map(|step| format!("<li>{}</li>\n", escape(step)))
+            .collect();
+        body.push_str(&format!("<h2>Instructions</h2>\n<ol>\n{}</ol>\n", steps));
+    }
+    if let Some(items) = &shopping {
+        body.push_str(&checklist("Shopping List", items));
+    }
+    let qr_items = shopping.as_ref().or(ingredients.as_ref());
+    if let Some(svg) = qr_items.and_then(|items| qr_svg(items)) {
+        body.push_str(&format!(
+            "<div class=\"qr\">{}<p>Scan for the shopping list</p></div>\n",
+            svg
+        ));
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<meta http-equiv=\"Content-Security-Policy\" content=\"default-src 'none'; img-src data:; style-src 'unsafe-inline'\">
+<title>{}</title>
+<style>{}</style>
+</head>
+<body>
+{}</body>
+</html>
+",
+        escape(&title),
+        STYLE,
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden;
+
+    const RECIPE: &str = "Lemon Garlic Chicken
+
+Ingredients:
+- 4 chicken thighs
+- 1 lemon
+- 3 cloves garlic
+
+Instructions:
+1. Zest and juice the lemon.
+2. Rub the thighs with garlic, zest & salt.
+3. Roast at 200°C for 35 minutes.
+
+Shopping List:
+- chicken thighs
+- lemon
+";
+
+    #[test]
+    fn renders_the_golden_page() {
+        let html = render(RECIPE, Some((b"not really a png", ImageFormat::Png)));
+        golden::check(&html, "export/lemon_garlic_chicken.html");
+    }
+
+    #[test]
+    fn scripts_from_the_model_are_escaped() {
+        let recipe = "<script>alert('title')</script>
+
+Ingredients:
+- <img src=x onerror=\"alert('ingredient')\">
+
+Instructions:
+1. Stir.</li><script>alert('step')</script>
+
+Shopping List:
+- lemons</label><script>alert('list')</script>
+";
+        let unstructured = "<script>alert('text')</script> just some text";
+        for html in [render(recipe, None), render(unstructured, None)] {
+            assert!(!html.contains("<script"), "{}", html);
+            assert!(!html.contains("<img src=x"), "{}", html);
+        }
+        let html = render(recipe, None);
+        for escaped in [
+            "<title>&lt;script&gt;alert(&#39;title&#39;)&lt;/script&gt;</title>",
+            "<h1>&lt;script&gt;alert(&#39;title&#39;)&lt;/script&gt;</h1>",
+            "&lt;img src=x onerror=&quot;alert(&#39;ingredient&#39;)&quot;&gt;",
+            "<li>Stir.&lt;/li&gt;&lt;script&gt;alert(&#39;step&#39;)&lt;/script&gt;</li>",
+            "lemons&lt;/label&gt;&lt;script&gt;alert(&#39;list&#39;)&lt;/script&gt;</label>",
+        ] {
+            assert!(html.contains(escaped), "{} not in {}", escaped, html);
+        }
+        assert!(render(unstructured, None).contains(
+            "<pre>&lt;script&gt;alert(&#39;text&#39;)&lt;/script&gt; just some text</pre>"
+        ));
+    }
+}
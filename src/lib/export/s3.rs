@@ -0,0 +1,142 @@
+//! A saved recipe and its files copied to an S3 bucket, e.g. one behind a
+//! static site or shared with the rest of the household.
+//!
+//! Everything goes under one prefix, keeping the file names it has on disk:
+//! the `.txt`, its `.meta.json`, and the images the metadata lists.
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client;
+
+use crate::error::GourmandError;
+use crate::metadata::RecipeMetadata;
+
+/// Where to copy to, from `s3://bucket/prefix`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Uri {
+    pub bucket: String,
+    /// Without slashes at either end; empty for the top of the bucket
+    pub prefix: String,
+}
+
+impl S3Uri {
+    pub fn parse(uri: &str) -> Result<S3Uri, String> {
+        let Some(rest) = uri.strip_prefix("s3://") else {
+            return Err(format!("{}: expected s3://bucket/prefix", uri));
+        };
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("{}: no bucket", uri));
+        }
+        Ok(S3Uri {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        })
+    }
+
+    /// The key `file` is copied to
+    pub fn key(&self, file: &Path) -> String {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        if self.prefix.is_empty() {
+            name.into_owned()
+        } else {
+            format!("{}/{}", self.prefix, name)
+        }
+    }
+}
+
+impl fmt::Display for S3Uri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "s3://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+/// The recipe at `txt`, then its metadata and whatever else the metadata
+/// lists that still exists
+pub fn files(txt: &Path) -> Vec<PathBuf> {
+    let mut files = vec![txt.to_path_buf()];
+    let meta_path = RecipeMetadata::locate(txt);
+    if let Ok(meta) = RecipeMetadata::load(&meta_path) {
+        files.push(meta_path);
+        for file in meta.files.iter().map(PathBuf::from) {
+            if file.exists() && !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    files
+}
+
+/// Copies `files` under `to`, returning the keys written.  Stops at the
+/// first failure.
+pub async fn upload(
+    client: &Client,
+    to: &S3Uri,
+    files: &[PathBuf],
+) -> Result<Vec<String>, GourmandError> {
+    let mut keys = vec![];
+    for file in files {
+        let key = to.key(file);
+        let body = ByteStream::from_path(file)
+            .await
+            .map_err(GourmandError::aws)?;
+        client
+            .put_object()
+            .bucket(&to.bucket)
+            .key(&key)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| GourmandError::aws(aws_sdk_s3::Error::from(e)))?;
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uris() {
+        let uri = S3Uri::parse("s3://family-recipes/2025/march/").unwrap();
+        assert_eq!(uri.bucket, "family-recipes");
+        assert_eq!(uri.prefix, "2025/march");
+        assert_eq!(
+            uri.key(Path::new("/home/me/recipes/pasta.txt")),
+            "2025/march/pasta.txt"
+        );
+
+        let top = S3Uri::parse("s3://family-recipes").unwrap();
+        assert_eq!(top.prefix, "");
+        assert_eq!(top.key(Path::new("pasta_0.png")), "pasta_0.png");
+
+        assert!(S3Uri::parse("family-recipes/2025").is_err());
+        assert!(S3Uri::parse("s3:///2025").is_err());
+    }
+
+    #[test]
+    fn files_come_from_the_metadata() {
+        let dir = std::env::temp_dir().join(format!("gourmand-s3-{}", ulid::Ulid::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let txt = dir.join("pasta.txt");
+        let image = dir.join("pasta_0.png");
+        std::fs::write(&txt, "Pasta").unwrap();
+        std::fs::write(&image, b"png").unwrap();
+        assert_eq!(files(&txt), [txt.clone()]);
+
+        let meta = RecipeMetadata {
+            files: vec![
+                image.display().to_string(),
+                dir.join("gone_1.png").display().to_string(),
+                txt.display().to_string(),
+            ],
+            ..RecipeMetadata::default()
+        };
+        let meta_path = RecipeMetadata::locate(&txt);
+        meta.save(&meta_path).unwrap();
+        assert_eq!(files(&txt), [txt, meta_path, image]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
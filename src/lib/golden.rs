@@ -0,0 +1,42 @@
+//! Golden files for tests: output is compared with a file under
+//! `src/lib/testdata`, or with [UPDATE_SNAPSHOTS] set the file is rewritten
+//! instead, for review in the diff.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::toolspec::UPDATE_SNAPSHOTS;
+
+/// `src/lib/testdata/<name>`
+pub fn path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/lib/testdata")
+        .join(name)
+}
+
+/// Panics, saying where, if `actual` isn't what `testdata/<name>` holds
+pub fn check(actual: &str, name: &str) {
+    let path = path(name);
+    if std::env::var_os(UPDATE_SNAPSHOTS).is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected =
+        fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    if expected == actual {
+        return;
+    }
+    let line = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.lines().count().min(actual.lines().count()));
+    panic!(
+        "{} differs from line {}; if that's intended, rerun with {}=1 and review the diff.  \
+         It's now:\n{}",
+        path.display(),
+        line + 1,
+        UPDATE_SNAPSHOTS,
+        actual
+    );
+}
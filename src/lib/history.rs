@@ -0,0 +1,548 @@
+//! A log of the recipes we've generated and cooked, kept as JSON lines in the state directory.
+//!
+//! The state directory may live in a folder synced between machines
+//! (Dropbox, iCloud Drive, ...) so a household shares one history.  Every
+//! write replaces the file atomically, and every entry has a unique id.
+//! When two machines write at about the same time, the sync service keeps
+//! one version and saves the other next to it as a conflicted copy
+//! (`history (conflicted copy).jsonl`, `history 2.jsonl`).  Reading merges
+//! the log with all of its copies, and the next write folds the copies back
+//! in and removes them.
+//!
+//! Only built with the `history` feature.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::lock::{self, DirLock};
+use crate::paths::{self, Paths};
+
+const FILE_NAME: &str = "history.jsonl";
+
+/// The version of the entry format written by this build.  Entries from
+/// before it was versioned read as 0.  2 added [Event::Skipped] and
+/// [Event::Archived], and 3 added [Event::Favorited], which older versions
+/// keep as unreadable lines.
+pub const SCHEMA: u32 = 3;
+
+/// How far back `review` looks for recipes that were never cooked
+pub const REVIEW_DAYS: u64 = 7;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// The model transmitted a recipe and we saved it
+    Generated,
+    /// The user stepped through the recipe in cook mode
+    Cooked,
+    /// An existing recipe file was brought in with `import`
+    Imported,
+    /// The user said in `review` that they won't be cooking it
+    Skipped,
+    /// Hidden from `review` and from the report's repeats
+    Archived,
+    /// Pinned with `favorite`, for `more-like` and the report
+    Favorited,
+}
+
+impl Event {
+    /// Whether the entry is about a recipe being added, rather than what
+    /// happened to one later
+    pub fn adds_recipe(&self) -> bool {
+        matches!(self, Event::Generated | Event::Imported)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// A ULID, unique across machines.  Empty in entries written before
+    /// entries had ids.
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub schema: u32,
+    /// The machine that wrote the entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub writer: Option<String>,
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+    pub event: Event,
+    pub title: Option<String>,
+    /// Where the recipe text was saved
+    pub path: String,
+    /// 1-5, if the user rated it after cooking
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
+}
+
+impl HistoryEntry {
+    pub fn now(event: Event, title: Option<String>, path: String) -> HistoryEntry {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        HistoryEntry {
+            id: Ulid::new().to_string(),
+            schema: SCHEMA,
+            writer: writer(),
+            timestamp,
+            event,
+            title,
+            path,
+            rating: None,
+        }
+    }
+
+    /// What identifies the entry when merging.  Entries without an id are
+    /// identified by their contents, which is enough to drop exact copies.
+    fn key(&self) -> String {
+        if self.id.is_empty() {
+            format!("{}/{:?}/{}", self.timestamp, self.event, self.path)
+        } else {
+            self.id.clone()
+        }
+    }
+}
+
+/// Recipes generated at or after `since` (seconds since the epoch) that
+/// nothing has been recorded for since: not cooked, skipped or archived.
+/// Oldest first, one entry per recipe.
+pub fn awaiting_review(entries: &[HistoryEntry], since: u64) -> Vec<&HistoryEntry> {
+    let closed: HashSet<&str> = entries
+        .iter()
+        .filter(|e| !e.event.adds_recipe() && e.event != Event::Favorited)
+        .map(|e| e.path.as_str())
+        .collect();
+    let mut seen = HashSet::new();
+    entries
+        .iter()
+        .filter(|e| e.event == Event::Generated && e.timestamp >= since)
+        .filter(|e| !closed.contains(e.path.as_str()) && seen.insert(e.path.as_str()))
+        .collect()
+}
+
+/// The paths of archived recipes
+pub fn archived(entries: &[HistoryEntry]) -> HashSet<&str> {
+    entries
+        .iter()
+        .filter(|e| e.event == Event::Archived)
+        .map(|e| e.path.as_str())
+        .collect()
+}
+
+/// The paths of favorite recipes
+pub fn favorites(entries: &[HistoryEntry]) -> HashSet<&str> {
+    entries
+        .iter()
+        .filter(|e| e.event == Event::Favorited)
+        .map(|e| e.path.as_str())
+        .collect()
+}
+
+/// The most recent recipe whose file stem (`2026-10-16-leek-risotto`), path,
+/// or title (ignoring case) is `key`
+pub fn find<'a>(entries: &'a [HistoryEntry], key: &str) -> Option<&'a HistoryEntry> {
+    let key = key.trim();
+    let lower = key.to_lowercase();
+    entries
+        .iter()
+        .rev()
+        .filter(|e| e.event.adds_recipe())
+        .find(|e| {
+            let path = Path::new(&e.path);
+            e.path == key
+                || path.file_stem().is_some_and(|stem| stem == key)
+                || e.title
+                    .as_deref()
+                    .is_some_and(|title| title.trim().to_lowercase() == lower)
+        })
+}
+
+/// This machine's name, recorded in each entry so merges can be explained
+fn writer() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// What [History::check] found
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub entries: usize,
+    /// Entries per machine, with `unknown` for entries that don't say
+    pub writers: BTreeMap<String, usize>,
+    /// Conflicted copies that haven't been folded back in yet
+    pub copies: Vec<PathBuf>,
+    /// Entries with the same id and different contents, as (kept, dropped).
+    /// The one written last is kept.
+    pub conflicts: Vec<(HistoryEntry, HistoryEntry)>,
+    /// Entries written by a newer version, which this one may misread
+    pub newer_schema: usize,
+    /// Lines that aren't an entry this version understands, by file and
+    /// line number.  They're kept when the log is rewritten.
+    pub unreadable: Vec<(PathBuf, usize)>,
+}
+
+impl SyncReport {
+    /// Whether anything needs the user's attention.  Conflicts and copies
+    /// are resolved on their own.
+    pub fn healthy(&self) -> bool {
+        self.newer_schema == 0 && self.unreadable.is_empty()
+    }
+}
+
+/// The log and its copies, merged
+#[derive(Debug, Default)]
+struct Merged {
+    entries: Vec<HistoryEntry>,
+    /// Lines that didn't parse, to be written back unchanged
+    unreadable: Vec<String>,
+    report: SyncReport,
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn open(paths: &Paths) -> io::Result<History> {
+        Ok(History {
+            path: paths.state_file(FILE_NAME)?,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// [History::append] for async callers, waiting on other sessions off
+    /// the runtime's workers
+    pub async fn append_async(&self, entry: HistoryEntry) -> io::Result<()> {
+        let history = self.clone();
+        lock::unblock(move || history.append(&entry)).await
+    }
+
+    /// Adds an entry.  Rewrites the whole log, folding in any conflicted
+    /// copies, so that the sync service only ever sees complete files.
+    pub fn append(&self, entry: &HistoryEntry) -> io::Result<()> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        // other sessions on this machine; other machines are handled by merging
+        let _lock = DirLock::acquire(dir)?;
+        let mut merged = self.merge()?;
+        merged.entries.push(entry.clone());
+
+        let mut contents = String::new();
+        for entry in &merged.entries {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        for line in &merged.unreadable {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        paths::write_atomic(&self.path, contents)?;
+        for copy in &merged.report.copies {
+            fs::remove_file(copy)?;
+        }
+        Ok(())
+    }
+
+    /// All entries from the log and its copies, oldest first.  Lines that
+    /// don't parse are skipped.
+    pub fn entries(&self) -> io::Result<Vec<HistoryEntry>> {
+        Ok(self.merge()?.entries)
+    }
+
+    /// Looks for conflicts, copies, and entries this version can't read
+    pub fn check(&self) -> io::Result<SyncReport> {
+        Ok(self.merge()?.report)
+    }
+
+    /// Conflicted copies of the log left by a sync service
+    fn copies(&self) -> io::Result<Vec<PathBuf>> {
+        let Some(dir) = self.path.parent() else {
+            return Ok(vec![]);
+        };
+        let stem = FILE_NAME.trim_end_matches(".jsonl");
+        let mut copies = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name != FILE_NAME && name.starts_with(stem) && name.ends_with(".jsonl") {
+                copies.push(path);
+            }
+        }
+        Ok(copies)
+    }
+
+    fn merge(&self) -> io::Result<Merged> {
+        let copies = self.copies()?;
+        // oldest file first, so the last write wins
+        let mut files = vec![];
+        for path in std::iter::once(&self.path).chain(&copies) {
+            match fs::metadata(path) {
+                Ok(meta) => files.push((meta.modified()?, path.clone())),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+        }
+        files.sort();
+
+        let mut merged = Merged::default();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for (_, path) in &files {
+            let contents = fs::read_to_string(path)?;
+            for (idx, line) in contents.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let Ok(entry) = serde_json::from_str::<HistoryEntry>(line) else {
+                    merged.report.unreadable.push((path.clone(), idx + 1));
+                    if !merged.unreadable.iter().any(|l| l == line) {
+                        merged.unreadable.push(line.to_string());
+                    }
+                    continue;
+                };
+                match seen.get(&entry.key()) {
+                    Some(&existing) if merged.entries[existing] == entry => (),
+                    Some(&existing) => {
+                        let dropped = std::mem::replace(&mut merged.entries[existing], entry);
+                        merged
+                            .report
+                            .conflicts
+                            .push((merged.entries[existing].clone(), dropped));
+                    }
+                    None => {
+                        seen.insert(entry.key(), merged.entries.len());
+                        merged.entries.push(entry);
+                    }
+                }
+            }
+        }
+        merged.entries.sort_by_key(|e| e.timestamp);
+
+        let report = &mut merged.report;
+        report.entries = merged.entries.len();
+        for entry in &merged.entries {
+            let writer = entry.writer.as_deref().unwrap_or("unknown");
+            *report.writers.entry(writer.to_string()).or_default() += 1;
+            if entry.schema > SCHEMA {
+                report.newer_schema += 1;
+            }
+        }
+        report.copies = copies;
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_sessions_keep_every_entry() {
+        let root = std::env::temp_dir().join(format!("gourmand-history-{}", Ulid::new()));
+        let history = History::open(&Paths::resolve(Some(&root))).unwrap();
+        let tasks: Vec<_> = (0..2)
+            .map(|session| {
+                let history = history.clone();
+                tokio::spawn(async move {
+                    for n in 0..5 {
+                        let path = format!("session{}_{}.txt", session, n);
+                        history
+                            .append_async(HistoryEntry::now(Event::Generated, None, path))
+                            .await
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let mut paths: Vec<String> = history
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths.len(), 10);
+        paths.dedup();
+        assert_eq!(paths.len(), 10);
+        assert!(history.check().unwrap().copies.is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn entry(timestamp: u64, event: Event, path: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp,
+            ..HistoryEntry::now(event, Some(path.to_string()), path.to_string())
+        }
+    }
+
+    fn with_id(id: &str, timestamp: u64) -> HistoryEntry {
+        HistoryEntry {
+            id: id.to_string(),
+            ..entry(timestamp, Event::Generated, &format!("{}.txt", id))
+        }
+    }
+
+    /// `entries` and `extra` lines as a log file last written at `modified`
+    fn write_log(path: &Path, entries: &[&HistoryEntry], extra: &[&str], modified: SystemTime) {
+        let mut lines: Vec<String> = entries
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap())
+            .collect();
+        lines.extend(extra.iter().map(|l| l.to_string()));
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn conflicted_copies_are_merged_and_folded_in() {
+        let root = std::env::temp_dir().join(format!("gourmand-history-{}", Ulid::new()));
+        let history = History::open(&Paths::resolve(Some(&root))).unwrap();
+        let copy = history
+            .path()
+            .with_file_name("history (conflicted copy).jsonl");
+        let (a, b, c, d, e) = (
+            with_id("a", 100),
+            with_id("b", 300),
+            with_id("c", 500),
+            with_id("d", 200),
+            with_id("e", 400),
+        );
+        let rated = HistoryEntry {
+            rating: Some(5),
+            ..c.clone()
+        };
+        let now = SystemTime::now();
+        write_log(
+            history.path(),
+            &[&a, &b, &c],
+            &["{not json"],
+            now - Duration::from_secs(60),
+        );
+        // the copy was written last, so its version of `c` wins
+        write_log(&copy, &[&d, &a, &rated, &e], &["{not json", "garbage"], now);
+
+        let report = history.check().unwrap();
+        assert_eq!(report.entries, 5);
+        assert_eq!(report.copies, std::slice::from_ref(&copy));
+        assert_eq!(report.conflicts, [(rated.clone(), c.clone())]);
+        assert_eq!(
+            report.unreadable,
+            [
+                (history.path().to_path_buf(), 4),
+                (copy.clone(), 5),
+                (copy.clone(), 6)
+            ]
+        );
+        let ids = |entries: Vec<HistoryEntry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.id).collect()
+        };
+        assert_eq!(ids(history.entries().unwrap()), ["a", "d", "b", "e", "c"]);
+
+        history.append(&with_id("f", 600)).unwrap();
+        assert!(!copy.exists());
+        let report = history.check().unwrap();
+        assert!(report.copies.is_empty());
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.unreadable.len(), 2);
+        let entries = history.entries().unwrap();
+        assert_eq!(entries[4].rating, Some(5));
+        assert_eq!(ids(entries), ["a", "d", "b", "e", "c", "f"]);
+        let written = fs::read_to_string(history.path()).unwrap();
+        assert!(written.ends_with("{not json\ngarbage\n"), "{}", written);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn pending(entries: &[HistoryEntry], since: u64) -> Vec<&str> {
+        awaiting_review(entries, since)
+            .into_iter()
+            .map(|e| e.path.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn marks_close_a_review() {
+        let generated = entry(1000, Event::Generated, "risotto.txt");
+        for (then, open) in [
+            (None, true),
+            (Some(Event::Cooked), false),
+            (Some(Event::Skipped), false),
+            (Some(Event::Archived), false),
+            // pinning isn't cooking
+            (Some(Event::Favorited), true),
+            // nor is importing it again
+            (Some(Event::Imported), true),
+        ] {
+            let mut entries = vec![generated.clone()];
+            entries.extend(then.map(|event| entry(2000, event, "risotto.txt")));
+            let expected: &[&str] = if open { &["risotto.txt"] } else { &[] };
+            assert_eq!(pending(&entries, 0), expected, "{:?}", then);
+        }
+    }
+
+    #[test]
+    fn reviews_recent_generated_recipes_once_each() {
+        let entries = [
+            entry(500, Event::Generated, "old.txt"),
+            entry(1000, Event::Generated, "risotto.txt"),
+            entry(1100, Event::Imported, "imported.txt"),
+            entry(1200, Event::Generated, "tacos.txt"),
+            entry(1300, Event::Generated, "risotto.txt"),
+            entry(1400, Event::Cooked, "tacos.txt"),
+            entry(1500, Event::Generated, "soup.txt"),
+        ];
+        assert_eq!(pending(&entries, 1000), ["risotto.txt", "soup.txt"]);
+    }
+
+    #[test]
+    fn archived_recipes_stay_archived() {
+        let entries = [
+            entry(1000, Event::Generated, "risotto.txt"),
+            entry(1100, Event::Generated, "tacos.txt"),
+            entry(1200, Event::Archived, "risotto.txt"),
+            entry(1300, Event::Favorited, "tacos.txt"),
+        ];
+        assert_eq!(archived(&entries), HashSet::from(["risotto.txt"]));
+        assert_eq!(favorites(&entries), HashSet::from(["tacos.txt"]));
+    }
+
+    #[test]
+    fn entries_from_before_schema_versions_still_load() {
+        let entry: HistoryEntry = serde_json::from_str(
+            r#"{"timestamp":1000,"event":"generated","title":"Risotto","path":"risotto.txt"}"#,
+        )
+        .unwrap();
+        assert_eq!((entry.schema, entry.id.as_str()), (0, ""));
+        assert_eq!(entry.rating, None);
+        assert_eq!(entry.key(), "1000/Generated/risotto.txt");
+        let written = serde_json::to_value(HistoryEntry::now(
+            Event::Skipped,
+            None,
+            "risotto.txt".to_string(),
+        ))
+        .unwrap();
+        assert_eq!(written["schema"], SCHEMA);
+        assert_eq!(written["event"], "skipped");
+    }
+}
@@ -0,0 +1,282 @@
+//! What saved recipe images are encoded as.
+//!
+//! Canvas returns PNGs of 1.5-3MB each.  With `--image-format jpeg` or
+//! `webp` they're transcoded before they're written, which takes a tenth of
+//! the space for JPEG at the default quality.  WebP is written lossless, so
+//! `--image-quality` only applies to JPEG.  A recipe's images are
+//! `<stem>-N.<ext>` whatever the format, and its `meta.json` records which
+//! one was used.
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use base64::Engine;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+
+/// JPEG quality when `--image-quality` isn't given
+pub const DEFAULT_QUALITY: u8 = 85;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    /// As Canvas returns it
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageFormat::Png => write!(f, "png"),
+            ImageFormat::Jpeg => write!(f, "jpeg"),
+            ImageFormat::Webp => write!(f, "webp"),
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+            "webp" => Ok(ImageFormat::Webp),
+            _ => Err(format!(
+                "unknown image format: {} (expected png, jpeg, or webp)",
+                s
+            )),
+        }
+    }
+}
+
+/// Every extension a saved image may have
+pub const EXTENSIONS: &[&str] = &["png", "jpg", "webp"];
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+
+    /// The format of a saved image, from its extension
+    pub fn of(path: &Path) -> Option<ImageFormat> {
+        let ext = path.extension()?.to_str()?;
+        ImageFormat::from_str(ext).ok()
+    }
+}
+
+/// `<stem>-<idx>.<ext>`
+pub fn image_path(stem: &str, idx: usize, format: ImageFormat) -> PathBuf {
+    PathBuf::from(format!("{}-{}.{}", stem, idx, format.extension()))
+}
+
+/// The first image saved for the recipe at `stem`, in whichever format
+pub fn first_image(stem: &str) -> Option<(PathBuf, ImageFormat)> {
+    EXTENSIONS.iter().find_map(|ext| {
+        let path = PathBuf::from(format!("{}-0.{}", stem, ext));
+        let format = ImageFormat::of(&path)?;
+        path.is_file().then_some((path, format))
+    })
+}
+
+/// Re-encodes a PNG
+#[cfg(feature = "images")]
+pub fn transcode(png: &[u8], format: ImageFormat, quality: u8) -> Result<Vec<u8>, String> {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::codecs::webp::WebPEncoder;
+    use image::DynamicImage;
+
+    if format == ImageFormat::Png {
+        return Ok(png.to_vec());
+    }
+    let decoded = image::load_from_memory_with_format(png, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    let mut out = vec![];
+    let encoded = match format {
+        ImageFormat::Png => unreachable!("returned above"),
+        // JPEG has no alpha channel
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(decoded.to_rgb8()).write_with_encoder(
+            JpegEncoder::new_with_quality(&mut out, quality.clamp(1, 100)),
+        ),
+        ImageFormat::Webp => DynamicImage::ImageRgba8(decoded.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut out)),
+    };
+    encoded.map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+#[cfg(not(feature = "images"))]
+pub fn transcode(_png: &[u8], _format: ImageFormat, _quality: u8) -> Result<Vec<u8>, String> {
+    Err("built without image support (feature images)".to_string())
+}
+
+/// Decodes a base64 PNG from Canvas and writes it as `<stem>-<idx>` in
+/// `format`, falling back to the PNG with a warning if it can't be
+/// transcoded.  Returns the path written and its format.  Transcoding takes
+/// a while for a large image, so it runs on a blocking thread.
+pub async fn save(
+    stem: &str,
+    idx: usize,
+    data: &str,
+    format: ImageFormat,
+    quality: u8,
+) -> io::Result<(PathBuf, ImageFormat)> {
+    let png = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let (bytes, format) = match format {
+        ImageFormat::Png => (png, ImageFormat::Png),
+        format => {
+            let transcoded =
+                tokio::task::spawn_blocking(move || (transcode(&png, format, quality), png))
+                    .await
+                    .map_err(io::Error::other)?;
+            match transcoded {
+                (Ok(bytes), _) => (bytes, format),
+                (Err(e), png) => {
+                    warn!(
+                        "couldn't convert the image to {}, saving the PNG: {}",
+                        format, e
+                    );
+                    (png, ImageFormat::Png)
+                }
+            }
+        }
+    };
+    let path = image_path(stem, idx, format);
+    paths::write_atomic(&path, bytes)?;
+    Ok((path, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// A 96x96 photo of a plate of risotto, as far as anyone can tell
+    #[cfg(feature = "images")]
+    fn fixture() -> Vec<u8> {
+        fs::read(crate::golden::path("images/risotto.png")).unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        for (text, format, ext) in [
+            ("png", ImageFormat::Png, "png"),
+            ("JPEG", ImageFormat::Jpeg, "jpg"),
+            ("jpg", ImageFormat::Jpeg, "jpg"),
+            ("webp", ImageFormat::Webp, "webp"),
+        ] {
+            assert_eq!(text.parse::<ImageFormat>(), Ok(format), "{}", text);
+            assert_eq!(format.extension(), ext);
+            assert_eq!(
+                ImageFormat::of(Path::new(&format!("a/b-0.{}", ext))),
+                Some(format)
+            );
+        }
+        assert!("gif".parse::<ImageFormat>().is_err());
+        assert_eq!(ImageFormat::of(Path::new("b-0.gif")), None);
+        assert_eq!(
+            image_path("out/risotto", 2, ImageFormat::Jpeg),
+            PathBuf::from("out/risotto-2.jpg")
+        );
+    }
+
+    #[cfg(feature = "images")]
+    #[test]
+    fn transcoding_the_fixture_saves_space() {
+        let png = fixture();
+        let jpeg = transcode(&png, ImageFormat::Jpeg, DEFAULT_QUALITY).unwrap();
+        let small = transcode(&png, ImageFormat::Jpeg, 30).unwrap();
+        let webp = transcode(&png, ImageFormat::Webp, DEFAULT_QUALITY).unwrap();
+        assert!(
+            jpeg.len() * 2 < png.len(),
+            "jpeg {} bytes, png {}",
+            jpeg.len(),
+            png.len()
+        );
+        assert!(small.len() < jpeg.len(), "quality 30 {} bytes", small.len());
+        assert!(webp.len() < png.len(), "webp {} bytes", webp.len());
+        assert_eq!(transcode(&png, ImageFormat::Png, 1).unwrap(), png);
+        for (bytes, format) in [
+            (&jpeg, image::ImageFormat::Jpeg),
+            (&webp, image::ImageFormat::WebP),
+        ] {
+            assert_eq!(image::guess_format(bytes).unwrap(), format);
+            let decoded = image::load_from_memory(bytes).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (96, 96));
+        }
+    }
+
+    #[cfg(feature = "images")]
+    #[tokio::test]
+    async fn saves_with_the_formats_extension() {
+        let dir = std::env::temp_dir().join(format!("gourmand-images-{}", ulid::Ulid::new()));
+        fs::create_dir_all(&dir).unwrap();
+        let stem = dir.join("risotto").to_string_lossy().into_owned();
+        let png = fixture();
+        let data = base64::engine::general_purpose::STANDARD.encode(&png);
+
+        let (path, format) = save(&stem, 0, &data, ImageFormat::Jpeg, DEFAULT_QUALITY)
+            .await
+            .unwrap();
+        assert_eq!(
+            (path.clone(), format),
+            (dir.join("risotto-0.jpg"), ImageFormat::Jpeg)
+        );
+        assert!(fs::metadata(&path).unwrap().len() * 2 < png.len() as u64);
+        assert_eq!(first_image(&stem), Some((path, ImageFormat::Jpeg)));
+
+        let (path, format) = save(&stem, 1, &data, ImageFormat::Png, DEFAULT_QUALITY)
+            .await
+            .unwrap();
+        assert_eq!(
+            (path.clone(), format),
+            (dir.join("risotto-1.png"), ImageFormat::Png)
+        );
+        assert_eq!(fs::read(&path).unwrap(), png);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn what_cant_be_transcoded_is_saved_as_it_came() {
+        let dir = std::env::temp_dir().join(format!("gourmand-images-{}", ulid::Ulid::new()));
+        fs::create_dir_all(&dir).unwrap();
+        let stem = dir.join("risotto").to_string_lossy().into_owned();
+        let not_a_png = b"not a png at all";
+        let data = base64::engine::general_purpose::STANDARD.encode(not_a_png);
+
+        let (path, format) = save(&stem, 0, &data, ImageFormat::Webp, DEFAULT_QUALITY)
+            .await
+            .unwrap();
+        assert_eq!(
+            (path.clone(), format),
+            (dir.join("risotto-0.png"), ImageFormat::Png)
+        );
+        assert_eq!(fs::read(&path).unwrap(), not_a_png);
+
+        let invalid = save(&stem, 1, "not base64!", ImageFormat::Jpeg, DEFAULT_QUALITY).await;
+        assert_eq!(invalid.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(!dir.join("risotto-1.jpg").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+//! Post-processing of the model's image prompt before it goes to Nova Canvas.
+//!
+//! Canvas tends to paint garbled lettering onto plates and jars when a prompt
+//! mentions brands or labels, so brand names are stripped and every prompt
+//! ends with a style suffix that asks for no text.  The result is
+//! deterministic, so a saved prompt regenerates the same way.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Nova Canvas rejects longer prompts
+pub const CANVAS_MAX_PROMPT_CHARS: usize = 1024;
+
+/// A custom style has to leave most of the prompt for the dish
+pub const MAX_STYLE_CHARS: usize = 300;
+
+static PRESETS: &[(&str, &str)] = &[
+    (
+        "natural",
+        "overhead food photography, natural light, shallow depth of field, no text, no watermark",
+    ),
+    (
+        "rustic",
+        "rustic wooden table, warm window light, food photography, shallow depth of field, \
+         no text, no watermark",
+    ),
+    (
+        "minimal",
+        "plain white background, soft even light, minimalist food photography, no text, \
+         no watermark",
+    ),
+    (
+        "bright",
+        "bright airy daylight, white marble surface, vibrant colors, overhead food photography, \
+         no text, no watermark",
+    ),
+];
+
+static BUILTIN_BRANDS: &str = "heinz, kraft, nestle, nestlé, kellogg's, kelloggs, coca-cola, \
+     coke, pepsi, hellmann's, hellmanns, tabasco, nutella, oreo, barilla, \
+     cheerios, doritos, campbell's, campbells, hershey's, hersheys, lay's, ritz, skippy, \
+     jif, velveeta, uncle ben's, pillsbury, betty crocker, trader joe's, whole foods";
+
+/// Brands that are also part of dish names, e.g. a Philadelphia cheesesteak,
+/// so they're only replaced together with the product they name
+static BRANDED_PRODUCTS: &[(&str, &str)] = &[("philadelphia cream cheese", "cream cheese")];
+
+/// A named preset, or a custom suffix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageStyle {
+    pub name: String,
+    pub suffix: String,
+}
+
+impl Default for ImageStyle {
+    fn default() -> Self {
+        ImageStyle {
+            name: PRESETS[0].0.to_string(),
+            suffix: PRESETS[0].1.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ImageStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl FromStr for ImageStyle {
+    type Err = String;
+
+    /// A preset name (natural, rustic, minimal, bright), or anything else as
+    /// a custom suffix of up to [MAX_STYLE_CHARS]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("the image style can't be empty".to_string());
+        }
+        let chars = s.chars().count();
+        if chars > MAX_STYLE_CHARS {
+            return Err(format!(
+                "the image style is {} characters, the most is {}",
+                chars, MAX_STYLE_CHARS
+            ));
+        }
+        let preset = PRESETS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s));
+        Ok(match preset {
+            Some((name, suffix)) => ImageStyle {
+                name: name.to_string(),
+                suffix: suffix.to_string(),
+            },
+            None => ImageStyle {
+                name: "custom".to_string(),
+                suffix: s.to_string(),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImagePromptProcessor {
+    style: ImageStyle,
+    brands: Vec<String>,
+    max_chars: usize,
+}
+
+impl ImagePromptProcessor {
+    pub fn new(style: ImageStyle) -> ImagePromptProcessor {
+        ImagePromptProcessor {
+            style,
+            brands: BUILTIN_BRANDS
+                .split(", ")
+                .map(|b| b.trim().to_string())
+                .collect(),
+            max_chars: CANVAS_MAX_PROMPT_CHARS,
+        }
+    }
+
+    /// The built in brands extended with a JSON list of names from a config
+    /// file, if it exists
+    pub fn with_config(style: ImageStyle, path: &Path) -> io::Result<ImagePromptProcessor> {
+        let mut processor = ImagePromptProcessor::new(style);
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(processor),
+            Err(e) => return Err(e),
+        };
+        let extra: Vec<String> = serde_json::from_str(&contents)?;
+        processor
+            .brands
+            .extend(extra.into_iter().map(|b| b.to_lowercase()));
+        Ok(processor)
+    }
+
+    pub fn style(&self) -> &ImageStyle {
+        &self.style
+    }
+
+    /// Strips brands, appends the style suffix, and trims the model's part of
+    /// the prompt so that the whole thing fits Canvas's limit
+    pub fn process(&self, prompt: &str) -> String {
+        let mut text = prompt.to_string();
+        for (product, generic) in BRANDED_PRODUCTS {
+            text = replace_word(&text, product, generic);
+        }
+        // longest first, so "uncle ben's" goes before a shorter brand inside it
+        let mut brands: Vec<&String> = self.brands.iter().collect();
+        brands.sort_by_key(|b| std::cmp::Reverse(b.len()));
+        for brand in brands {
+            text = replace_word(&text, brand, "");
+        }
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = text.trim_end_matches([',', '.', ' ']);
+
+        let separator = ". ";
+        let budget = self
+            .max_chars
+            .saturating_sub(self.style.suffix.chars().count() + separator.len());
+        let text: String = text.chars().take(budget).collect();
+        format!("{}{}{}", text.trim_end(), separator, self.style.suffix)
+    }
+}
+
+/// Replaces case-insensitive, whole-word occurrences of `word`
+fn replace_word(text: &str, word: &str, replacement: &str) -> String {
+    let lower = text.to_lowercase();
+    // lowercasing can change byte lengths; leave such text alone
+    if lower.len() != text.len() {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (idx, _) in lower.match_indices(word) {
+        let end = idx + word.len();
+        let before = lower[..idx].chars().next_back();
+        let after = lower[end..].chars().next();
+        let boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+        if idx >= last && boundary(before) && boundary(after) {
+            out.push_str(&text[last..idx]);
+            out.push_str(replacement);
+            last = end;
+        }
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processor(style: &str) -> ImagePromptProcessor {
+        ImagePromptProcessor::new(style.parse().unwrap())
+    }
+
+    #[test]
+    fn presets_by_name_and_custom_styles() {
+        assert_eq!("Rustic".parse::<ImageStyle>().unwrap().name, "rustic");
+        let custom: ImageStyle = "watercolor illustration".parse().unwrap();
+        assert_eq!(custom.name, "custom");
+        assert_eq!(custom.suffix, "watercolor illustration");
+        assert!("  ".parse::<ImageStyle>().is_err());
+        assert!("x"
+            .repeat(MAX_STYLE_CHARS + 1)
+            .parse::<ImageStyle>()
+            .is_err());
+    }
+
+    #[test]
+    fn appends_the_style_suffix() {
+        assert_eq!(
+            processor("minimal").process("A bowl of tomato soup."),
+            "A bowl of tomato soup. plain white background, soft even light, minimalist food \
+             photography, no text, no watermark"
+        );
+    }
+
+    #[test]
+    fn strips_brands_as_whole_words() {
+        let processed =
+            processor("natural").process("Toast with Nutella and a jar of Heinz ketchup");
+        assert!(
+            processed.starts_with("Toast with and a jar of ketchup. "),
+            "{}",
+            processed
+        );
+        // "oreo" inside another word is left alone
+        assert!(processor("natural")
+            .process("choreography")
+            .starts_with("choreography. "));
+    }
+
+    #[test]
+    fn keeps_dishes_named_after_a_brand() {
+        let p = processor("natural");
+        assert!(p
+            .process("A Philadelphia cheesesteak")
+            .starts_with("A Philadelphia cheesesteak. "));
+        assert!(p
+            .process("Bagels with Philadelphia cream cheese")
+            .starts_with("Bagels with cream cheese. "));
+    }
+
+    #[test]
+    fn fits_canvas_limit() {
+        let longest_style = "y".repeat(MAX_STYLE_CHARS);
+        for style in ["bright", longest_style.as_str()] {
+            let processed = processor(style).process(&"a very long prompt ".repeat(200));
+            assert!(processed.chars().count() <= CANVAS_MAX_PROMPT_CHARS);
+            assert!(processed.ends_with(&processor(style).style().suffix));
+        }
+    }
+}
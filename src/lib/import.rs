@@ -0,0 +1,96 @@
+//! Bringing recipes saved by older versions, or written by hand, into the
+//! history so that `report` and the rest see them alongside generated ones.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+#[cfg(feature = "history")]
+use crate::history::HistoryEntry;
+use crate::parse;
+use crate::recipe::Section;
+
+/// A recipe file that looks importable
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub source: PathBuf,
+    pub title: String,
+    pub text: String,
+    /// Seconds since the unix epoch, from the file's mtime
+    pub modified: u64,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Unreadable(PathBuf, io::Error),
+    /// No title, or neither ingredients nor instructions
+    NotARecipe(PathBuf),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Unreadable(path, e) => {
+                write!(f, "couldn't read {}: {}", path.display(), e)
+            }
+            ImportError::NotARecipe(path) => write!(
+                f,
+                "{} doesn't look like a recipe (no title, ingredients, or instructions)",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Reads `path` and checks that the section parser can make sense of it
+pub fn inspect(path: &Path) -> Result<Candidate, ImportError> {
+    let unreadable = |e| ImportError::Unreadable(path.to_path_buf(), e);
+    let text = fs::read_to_string(path)
+        .map_err(unreadable)?
+        .replace("\r\n", "\n");
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_err(unreadable)?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    let parsed = parse::sections(&text);
+    let has_body =
+        parsed.get(Section::Ingredients).is_some() || parsed.get(Section::Instructions).is_some();
+    let title = match parsed.title() {
+        Some(title) if has_body && !title.trim().is_empty() => title.trim().to_string(),
+        _ => return Err(ImportError::NotARecipe(path.to_path_buf())),
+    };
+    Ok(Candidate {
+        source: path.to_path_buf(),
+        title,
+        text,
+        modified,
+    })
+}
+
+/// Lowercase words without punctuation, so that `Banana Bread!` and
+/// `banana  bread` are the same recipe
+pub fn normalize_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The history entry for a recipe with the same title, if there is one
+#[cfg(feature = "history")]
+pub fn find_duplicate<'a>(title: &str, entries: &'a [HistoryEntry]) -> Option<&'a HistoryEntry> {
+    let title = normalize_title(title);
+    entries.iter().find(|e| {
+        e.event.adds_recipe()
+            && e.title
+                .as_deref()
+                .is_some_and(|t| normalize_title(t) == title)
+    })
+}
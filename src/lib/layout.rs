@@ -0,0 +1,221 @@
+//! Where the files written for the user go.  Everything lands under the
+//! `--output` root, either side by side (flat, the default) or sorted into
+//! a subdirectory per kind (nested).
+//!
+//! Files that are ours rather than the user's (history, config, caches)
+//! belong in [crate::paths] instead.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::paths;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayoutMode {
+    #[default]
+    Flat,
+    Nested,
+}
+
+impl fmt::Display for LayoutMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutMode::Flat => write!(f, "flat"),
+            LayoutMode::Nested => write!(f, "nested"),
+        }
+    }
+}
+
+impl FromStr for LayoutMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "flat" => Ok(LayoutMode::Flat),
+            "nested" => Ok(LayoutMode::Nested),
+            _ => Err(format!("unknown layout: {} (expected flat or nested)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputLayout {
+    root: PathBuf,
+    mode: LayoutMode,
+}
+
+impl OutputLayout {
+    /// `root` may start with `~`, see [paths::expand].  Relative roots are
+    /// resolved against the current directory now, so every path handed out
+    /// is absolute.
+    pub fn new(root: &str, mode: LayoutMode) -> OutputLayout {
+        OutputLayout {
+            root: paths::absolute(&paths::expand(root)),
+            mode,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn mode(&self) -> LayoutMode {
+        self.mode
+    }
+
+    fn dir(&self, nested: &str) -> PathBuf {
+        match self.mode {
+            LayoutMode::Flat => self.root.clone(),
+            LayoutMode::Nested => self.root.join(nested),
+        }
+    }
+
+    /// Recipe text, images, metadata, and change notes, named after the
+    /// recipe's stem
+    pub fn recipes_dir(&self) -> PathBuf {
+        self.dir("recipes")
+    }
+
+    /// The path for `<stem>` within [OutputLayout::recipes_dir], to which
+    /// `.txt`, `-0.png`, etc. are appended
+    pub fn recipe_stem(&self, stem: &str) -> PathBuf {
+        self.recipes_dir().join(stem)
+    }
+
+    /// Images the assistant returned in the conversation, as opposed to the
+    /// ones generated for a recipe
+    pub fn images_dir(&self) -> PathBuf {
+        self.dir("images")
+    }
+
+    /// HTML and other shareable exports
+    pub fn exports_dir(&self) -> PathBuf {
+        self.dir("exports")
+    }
+
+    /// CSV from `report` and `evaluate`
+    pub fn reports_dir(&self) -> PathBuf {
+        self.dir("reports")
+    }
+
+    /// A file within `dir`, creating the directory if needed
+    pub fn file_in(dir: PathBuf, name: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(&dir)?;
+        Ok(dir.join(name))
+    }
+
+    /// Resolves a path the user gave for an artifact: relative paths go in
+    /// `dir`, anything absolute or starting with `~` is used as is.  Creates
+    /// the parent directory if needed.
+    pub fn resolve(dir: PathBuf, path: &Path) -> io::Result<PathBuf> {
+        let path = dir.join(paths::expand(&path.to_string_lossy()));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files_under(dir: &Path) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(files_under(&path));
+            } else {
+                files.push(path);
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn every_artifact_lands_under_the_root() {
+        for (mode, expected) in [
+            (
+                LayoutMode::Flat,
+                [
+                    "2026-10-16.csv",
+                    "a.png",
+                    "leek-risotto.html",
+                    "leek-risotto.meta.json",
+                    "leek-risotto.txt",
+                    "week/menu.csv",
+                ]
+                .as_slice(),
+            ),
+            (
+                LayoutMode::Nested,
+                [
+                    "exports/leek-risotto.html",
+                    "images/a.png",
+                    "recipes/leek-risotto.meta.json",
+                    "recipes/leek-risotto.txt",
+                    "reports/2026-10-16.csv",
+                    "reports/week/menu.csv",
+                ]
+                .as_slice(),
+            ),
+        ] {
+            let root = std::env::temp_dir().join(format!("gourmand-layout-{}", ulid::Ulid::new()));
+            let layout = OutputLayout::new(&root.to_string_lossy(), mode);
+            let stem = layout.recipe_stem("leek-risotto").display().to_string();
+            fs::create_dir_all(layout.recipes_dir()).unwrap();
+            fs::write(format!("{}.txt", stem), "").unwrap();
+            fs::write(format!("{}.meta.json", stem), "{}").unwrap();
+            fs::write(
+                OutputLayout::file_in(layout.images_dir(), "a.png").unwrap(),
+                "",
+            )
+            .unwrap();
+            fs::write(
+                OutputLayout::file_in(layout.exports_dir(), "leek-risotto.html").unwrap(),
+                "",
+            )
+            .unwrap();
+            fs::write(
+                OutputLayout::resolve(layout.reports_dir(), Path::new("2026-10-16.csv")).unwrap(),
+                "",
+            )
+            .unwrap();
+            fs::write(
+                OutputLayout::resolve(layout.reports_dir(), Path::new("week/menu.csv")).unwrap(),
+                "",
+            )
+            .unwrap();
+
+            let files: Vec<PathBuf> = files_under(&root)
+                .iter()
+                .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+                .collect();
+            let expected: Vec<PathBuf> = expected.iter().map(PathBuf::from).collect();
+            assert_eq!(files, expected, "{}", mode);
+            fs::remove_dir_all(&root).unwrap();
+        }
+    }
+
+    #[test]
+    fn absolute_paths_are_used_as_given() {
+        let layout = OutputLayout::new("recipes", LayoutMode::Nested);
+        assert!(layout.root().is_absolute(), "{}", layout.root().display());
+        let elsewhere = std::env::temp_dir().join(format!("gourmand-{}.csv", ulid::Ulid::new()));
+        assert_eq!(
+            OutputLayout::resolve(layout.reports_dir(), &elsewhere).unwrap(),
+            elsewhere
+        );
+    }
+
+    #[test]
+    fn parses_the_mode() {
+        assert_eq!("Nested".parse(), Ok(LayoutMode::Nested));
+        assert_eq!("flat".parse(), Ok(LayoutMode::Flat));
+        assert!("tree".parse::<LayoutMode>().is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Size limits on what the model sends us and what we echo back to it, and
+//! on what we send to Bedrock.
+//!
+//! A misbehaving model can stuff an entire conversation into a tool argument;
+//! without limits that ends up on disk and, worse, echoed back into the context.
+//!
+//! In the other direction, a long history with a few photos in it can grow
+//! past what a Converse request may carry, and Bedrock answers that with an
+//! unhelpful validation error.  [request_bytes] estimates the size of a
+//! request before it's sent so that [crate::conversation] can refuse it
+//! with advice instead.
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, DocumentSource, ImageSource, Message, SystemContentBlock, ToolConfiguration,
+    ToolResultContentBlock,
+};
+
+use crate::session::document_to_json;
+use crate::toolspec;
+
+/// Appended to anything we cut short so that both the user and the model can tell
+pub const TRUNCATION_MARKER: &str = "\n[...truncated]";
+
+#[derive(Debug, Clone, Copy)]
+pub struct ToolInputLimits {
+    /// Max bytes of `recipe_details`
+    pub recipe_details: usize,
+    /// Max bytes of `image_prompt`
+    pub image_prompt: usize,
+    /// Max bytes of `file_stem`, after sanitization
+    pub file_stem: usize,
+}
+
+impl Default for ToolInputLimits {
+    fn default() -> Self {
+        ToolInputLimits {
+            recipe_details: 32 * 1024,
+            image_prompt: 2 * 1024,
+            file_stem: 128,
+        }
+    }
+}
+
+/// Truncates `s` to at most `max` bytes (marker included), on a char boundary.
+/// Returns whether anything was cut.
+pub fn truncate(s: &mut String, max: usize) -> bool {
+    truncate_with(s, max, TRUNCATION_MARKER)
+}
+
+/// Like [truncate] but without a marker, for things like file names
+pub fn truncate_silently(s: &mut String, max: usize) -> bool {
+    truncate_with(s, max, "")
+}
+
+fn truncate_with(s: &mut String, max: usize, marker: &str) -> bool {
+    if s.len() <= max {
+        return false;
+    }
+    let mut end = max.saturating_sub(marker.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+    if marker.len() <= max {
+        s.push_str(marker);
+    }
+    true
+}
+
+/// Largest Converse request we'll send, serialized.  Bedrock doesn't put a
+/// single number on it, but requests past this fail validation.
+pub const REQUEST_BYTES: usize = 20 * 1024 * 1024;
+
+/// Largest image Converse accepts, before base64 (3.75 MB)
+pub const IMAGE_BYTES: usize = 3_932_160;
+
+/// Share of [REQUEST_BYTES] past which we warn
+pub const WARN_FRACTION: f64 = 0.8;
+
+/// How a request's size compares to [REQUEST_BYTES]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadCheck {
+    Fits,
+    /// Over [WARN_FRACTION] of the limit, but it can still be sent
+    NearLimit,
+    TooLarge,
+}
+
+pub fn check_request(bytes: usize) -> PayloadCheck {
+    if bytes > REQUEST_BYTES {
+        PayloadCheck::TooLarge
+    } else if bytes as f64 > REQUEST_BYTES as f64 * WARN_FRACTION {
+        PayloadCheck::NearLimit
+    } else {
+        PayloadCheck::Fits
+    }
+}
+
+/// Length of `bytes` bytes once base64 encoded, as binary goes over the wire
+pub fn base64_len(bytes: usize) -> usize {
+    bytes.div_ceil(3) * 4
+}
+
+fn image_bytes(source: Option<&ImageSource>) -> usize {
+    match source {
+        Some(ImageSource::Bytes(bytes)) => base64_len(bytes.as_ref().len()),
+        _ => 0,
+    }
+}
+
+/// Approximate serialized size of message content, images included
+pub fn content_bytes(content: &[ContentBlock]) -> usize {
+    content
+        .iter()
+        .map(|c| match c {
+            ContentBlock::Text(text) => text.len(),
+            ContentBlock::Image(image) => image_bytes(image.source()),
+            ContentBlock::Document(doc) => match doc.source() {
+                Some(DocumentSource::Bytes(bytes)) => base64_len(bytes.as_ref().len()),
+                _ => 0,
+            },
+            ContentBlock::ToolUse(t) => {
+                t.tool_use_id().len()
+                    + t.name().len()
+                    + document_to_json(t.input()).to_string().len()
+            }
+            ContentBlock::ToolResult(r) => {
+                r.tool_use_id().len()
+                    + r.content()
+                        .iter()
+                        .map(|c| match c {
+                            ToolResultContentBlock::Text(text) => text.len(),
+                            ToolResultContentBlock::Json(doc) => {
+                                document_to_json(doc).to_string().len()
+                            }
+                            ToolResultContentBlock::Image(image) => image_bytes(image.source()),
+                            _ => 0,
+                        })
+                        .sum::<usize>()
+            }
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Approximate serialized size of a Converse request: the system prompt,
+/// the tool configuration and every message.  JSON punctuation isn't
+/// counted, which is small next to the content.
+pub fn request_bytes(
+    system: &[SystemContentBlock],
+    tools: Option<&ToolConfiguration>,
+    messages: &[Message],
+) -> usize {
+    let system: usize = system
+        .iter()
+        .map(|block| block.as_text().map_or(0, String::len))
+        .sum();
+    let tools = tools.map_or(0, |t| toolspec::canonical_json(t).to_string().len());
+    let messages: usize = messages.iter().map(|m| content_bytes(m.content())).sum();
+    system + tools + messages
+}
+
+/// Megabytes with one decimal, for messages
+pub fn format_mb(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_bedrockruntime::primitives::Blob;
+    use aws_sdk_bedrockruntime::types::{ConversationRole, ImageBlock, ImageFormat};
+
+    use super::*;
+
+    #[test]
+    fn tool_inputs_at_their_limits() {
+        let limits = ToolInputLimits::default();
+        for max in [limits.recipe_details, limits.image_prompt] {
+            let mut at = "a".repeat(max);
+            assert!(!truncate(&mut at, max));
+            assert_eq!(at.len(), max);
+
+            let mut over = "a".repeat(max + 1);
+            assert!(truncate(&mut over, max));
+            assert_eq!(over.len(), max);
+            assert!(over.ends_with(TRUNCATION_MARKER));
+        }
+
+        let mut stem = "a".repeat(limits.file_stem);
+        assert!(!truncate_silently(&mut stem, limits.file_stem));
+        stem.push('a');
+        assert!(truncate_silently(&mut stem, limits.file_stem));
+        assert_eq!(stem, "a".repeat(limits.file_stem));
+    }
+
+    #[test]
+    fn truncation_keeps_whole_chars() {
+        // the cut lands inside the second é
+        let mut s = "éé".to_string();
+        assert!(truncate_silently(&mut s, 3));
+        assert_eq!(s, "é");
+
+        let max = TRUNCATION_MARKER.len() + 3;
+        let mut s = "é".repeat(10);
+        assert!(truncate(&mut s, max));
+        assert_eq!(s, format!("é{}", TRUNCATION_MARKER));
+        assert!(s.len() <= max);
+
+        // no room for the marker at all
+        let mut s = "abcdef".to_string();
+        assert!(truncate(&mut s, 3));
+        assert_eq!(s, "");
+    }
+
+    #[test]
+    fn request_sizes_at_the_limits() {
+        let warn_at = (REQUEST_BYTES as f64 * WARN_FRACTION) as usize;
+        for (bytes, check) in [
+            (0, PayloadCheck::Fits),
+            (warn_at, PayloadCheck::Fits),
+            (warn_at + 1, PayloadCheck::NearLimit),
+            (REQUEST_BYTES, PayloadCheck::NearLimit),
+            (REQUEST_BYTES + 1, PayloadCheck::TooLarge),
+        ] {
+            assert_eq!(check_request(bytes), check, "{}", bytes);
+        }
+        assert_eq!(format_mb(REQUEST_BYTES), "20.0 MB");
+    }
+
+    #[test]
+    fn base64_lengths() {
+        for (bytes, encoded) in [(0, 0), (1, 4), (3, 4), (4, 8), (IMAGE_BYTES, 5_242_880)] {
+            assert_eq!(base64_len(bytes), encoded, "{}", bytes);
+        }
+    }
+
+    #[test]
+    fn requests_count_text_and_images() {
+        let image = ImageBlock::builder()
+            .format(ImageFormat::Png)
+            .source(ImageSource::Bytes(Blob::new(vec![0; 300])))
+            .build()
+            .unwrap();
+        let message = Message::builder()
+            .role(ConversationRole::User)
+            .content(ContentBlock::Text("What can I make with this?".to_string()))
+            .content(ContentBlock::Image(image))
+            .build()
+            .unwrap();
+        let system = [SystemContentBlock::Text("You are a chef.".to_string())];
+        assert_eq!(content_bytes(message.content()), 26 + 400);
+        assert_eq!(request_bytes(&system, None, &[message]), 15 + 26 + 400);
+    }
+}
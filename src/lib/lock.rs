@@ -0,0 +1,154 @@
+//! Keeping simultaneous sessions that share an output directory from
+//! clobbering each other's files.
+//!
+//! Waiting on a lock blocks the thread, so async code goes through
+//! [unblock] or the async functions here, which wait on tokio's blocking
+//! pool instead of a runtime worker.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use fs2::FileExt;
+
+const LOCK_FILE: &str = ".gourmand.lock";
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An advisory lock on a directory, held until dropped.
+///
+/// The lock file records the holder's PID and when it was taken, so that a
+/// session stuck waiting can say who it's waiting on.  The OS releases the
+/// lock if the holder dies, so a leftover lock file is never stale on its own.
+#[derive(Debug)]
+pub struct DirLock {
+    file: File,
+}
+
+impl DirLock {
+    pub fn acquire(dir: &Path) -> io::Result<DirLock> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let start = Instant::now();
+        while file.try_lock_exclusive().is_err() {
+            if start.elapsed() > LOCK_TIMEOUT {
+                let holder = fs::read_to_string(&path).unwrap_or_default();
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("{} is locked by {}", dir.display(), holder.trim()),
+                ));
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        file.set_len(0)?;
+        write!(file, "pid {} at {}", std::process::id(), now)?;
+        Ok(DirLock { file })
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// Runs `f`, which may wait on a [DirLock], on tokio's blocking pool
+pub async fn unblock<T, F>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Claims a file stem in `dir` that no other session is using by creating
+/// `<stem>.txt`, adding `-2`, `-3`, ... if needed.  Returns the claimed stem's path.
+pub async fn reserve_stem(dir: &Path, stem: &str) -> io::Result<PathBuf> {
+    let (dir, stem) = (dir.to_path_buf(), stem.to_string());
+    unblock(move || reserve_stem_blocking(&dir, &stem)).await
+}
+
+fn reserve_stem_blocking(dir: &Path, stem: &str) -> io::Result<PathBuf> {
+    let _lock = DirLock::acquire(dir)?;
+    let mut n = 1;
+    loop {
+        let candidate = match n {
+            1 => stem.to_string(),
+            n => format!("{}-{}", stem, n),
+        };
+        let path = dir.join(&candidate);
+        let created = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(format!("{}.txt", candidate)));
+        match created {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("gourmand-lock-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_sessions_get_distinct_stems() {
+        let dir = scratch_dir("stems");
+        let tasks: Vec<_> = (0..2)
+            .map(|_| {
+                let dir = dir.clone();
+                tokio::spawn(async move {
+                    let first = reserve_stem(&dir, "banana_bread").await.unwrap();
+                    let second = reserve_stem(&dir, "banana_bread").await.unwrap();
+                    vec![first, second]
+                })
+            })
+            .collect();
+        let mut stems = vec![];
+        for task in tasks {
+            stems.extend(task.await.unwrap());
+        }
+        stems.sort();
+        stems.dedup();
+        assert_eq!(stems.len(), 4);
+        let files = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "txt"))
+            .count();
+        assert_eq!(files, 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_held_lock_names_its_holder() {
+        let dir = scratch_dir("holder");
+        let _held = DirLock::acquire(&dir).unwrap();
+        let holder = fs::read_to_string(dir.join(LOCK_FILE)).unwrap();
+        assert!(holder.starts_with(&format!("pid {} at ", std::process::id())));
+        drop(_held);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,266 @@
+//! Logging to the console and, optionally, a file.
+//!
+//! Console levels are set per module with a spec in env_logger's syntax,
+//! from `--log-level` or `GOURMAND_LOG`:
+//!
+//! ```text
+//! warn,recipes=trace,aws_config=info
+//! ```
+//!
+//! A bare level applies to every module without one of its own.  The log
+//! file, if any, gets everything at debug regardless, so a quiet console
+//! still leaves a full record to attach to a bug report.
+//!
+//! Each record is written with a single call on a locked handle, so log
+//! lines from concurrent tool calls don't interleave with each other, and
+//! the line is finished before anything else reaches the terminal.
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// The environment variable read when there's no `--log-level`
+pub const ENV_VAR: &str = "GOURMAND_LOG";
+
+/// Levels by module path prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    default: LevelFilter,
+    /// Longest prefix first, so the most specific module wins
+    modules: Vec<(String, LevelFilter)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError(String);
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bad log level {:?}, expected e.g. \"info\" or \"warn,recipes=debug\"",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+impl Filter {
+    /// Every module at `level`
+    pub fn all(level: LevelFilter) -> Filter {
+        Filter {
+            default: level,
+            modules: vec![],
+        }
+    }
+
+    /// What the console shows without a spec: this program at info (debug
+    /// if `verbose`), anything else only from warn
+    pub fn standard(verbose: bool) -> Filter {
+        let ours = if verbose {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        };
+        Filter::all(LevelFilter::Warn).module("recipes", ours)
+    }
+
+    pub fn module(mut self, prefix: &str, level: LevelFilter) -> Filter {
+        self.modules.retain(|(m, _)| m != prefix);
+        self.modules.push((prefix.to_string(), level));
+        self.modules.sort_by_key(|(m, _)| Reverse(m.len()));
+        self
+    }
+
+    /// Parses `level` or `module=level` directives separated by commas,
+    /// on top of `base`
+    pub fn parse(spec: &str, base: Filter) -> Result<Filter, FilterError> {
+        let mut filter = base;
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let err = || FilterError(directive.to_string());
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let level = level.trim().parse().map_err(|_| err())?;
+                    filter = filter.module(module.trim(), level);
+                }
+                None => filter.default = directive.parse().map_err(|_| err())?,
+            }
+        }
+        Ok(filter)
+    }
+
+    /// The level for a record's target, e.g. `recipes::conversation`
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(prefix, _)| {
+                target == prefix
+                    || target
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// The most verbose level anything passes at
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+/// What the log file records
+pub const FILE_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// Writes a whole console line in one call, so that nothing else on the
+/// terminal lands inside it
+fn write_stderr(line: &[u8]) {
+    let _ = io::stderr().lock().write_all(line);
+}
+
+struct Logger {
+    console: Filter,
+    /// [write_stderr], except in tests
+    write_console: fn(&[u8]),
+    file: Option<Mutex<File>>,
+}
+
+impl Logger {
+    fn wants_file(&self, metadata: &Metadata) -> bool {
+        self.file.is_some() && metadata.level() <= FILE_LEVEL
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.console.level_for(metadata.target()) || self.wants_file(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let metadata = record.metadata();
+        if metadata.level() <= self.console.level_for(metadata.target()) {
+            let line = format!("{} - {}\n", record.level(), record.args());
+            (self.write_console)(line.as_bytes());
+        }
+        if let (true, Some(file)) = (self.wants_file(metadata), &self.file) {
+            let line = format!(
+                "{} {:5} {}: {}\n",
+                chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            if let Ok(mut file) = file.lock() {
+                let _ = file.write_all(line.as_bytes());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+        if let Some(Ok(mut file)) = self.file.as_ref().map(Mutex::lock) {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Installs the logger, appending to `file` if given.  Fails if the file
+/// can't be opened.
+pub fn init(console: Filter, file: Option<&Path>) -> io::Result<()> {
+    let file = match file {
+        Some(path) => Some(Mutex::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        )),
+        None => None,
+    };
+    let max = match file {
+        Some(_) => console.max_level().max(FILE_LEVEL),
+        None => console.max_level(),
+    };
+    let logger = Logger {
+        console,
+        write_console: write_stderr,
+        file,
+    };
+    log::set_boxed_logger(Box::new(logger)).map_err(|e| io::Error::other(e.to_string()))?;
+    log::set_max_level(max);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    static CONSOLE: Mutex<Vec<u8>> = Mutex::new(vec![]);
+
+    fn capture(bytes: &[u8]) {
+        CONSOLE.lock().unwrap().extend_from_slice(bytes);
+    }
+
+    #[test]
+    fn specs_set_levels_by_module() {
+        let filter = Filter::parse(
+            "warn,recipes=trace,aws_config=info",
+            Filter::standard(false),
+        )
+        .unwrap();
+        for (target, expected) in [
+            ("recipes", LevelFilter::Trace),
+            ("recipes::conversation", LevelFilter::Trace),
+            ("recipes_extra", LevelFilter::Warn),
+            ("aws_config::profile", LevelFilter::Info),
+            ("hyper", LevelFilter::Warn),
+        ] {
+            assert_eq!(filter.level_for(target), expected, "{}", target);
+        }
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+        assert!(Filter::parse("recipes=loud", Filter::standard(false)).is_err());
+    }
+
+    #[test]
+    fn log_lines_and_spinner_frames_dont_interleave() {
+        let logger = Logger {
+            console: Filter::all(LevelFilter::Info),
+            write_console: capture,
+            file: None,
+        };
+        thread::scope(|scope| {
+            // a spinner redrawing its frame in place, through the same stream
+            scope.spawn(|| {
+                for frame in ["|", "/", "-", "\\"].iter().cycle().take(400) {
+                    capture(format!("\r{} waiting for the model", frame).as_bytes());
+                }
+            });
+            for worker in 0..4 {
+                let logger = &logger;
+                scope.spawn(move || {
+                    for n in 0..100 {
+                        logger.log(
+                            &Record::builder()
+                                .args(format_args!("tool call {} finished step {}", worker, n))
+                                .level(log::Level::Info)
+                                .target("recipes::tools")
+                                .build(),
+                        );
+                    }
+                });
+            }
+        });
+        let console = String::from_utf8(CONSOLE.lock().unwrap().clone()).unwrap();
+        for worker in 0..4 {
+            for n in 0..100 {
+                let line = format!("INFO - tool call {} finished step {}\n", worker, n);
+                assert_eq!(console.matches(&line).count(), 1, "{:?}", line);
+            }
+        }
+        assert_eq!(console.matches("waiting for the model").count(), 400);
+    }
+}
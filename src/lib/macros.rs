@@ -0,0 +1,353 @@
+//! Named prompts the user reuses, kept in `macros.json` in the config
+//! directory as `{"weeknight": "quick {protein} dinner, kids will eat it"}`.
+//!
+//! `@weeknight` anywhere in a prompt is replaced by the macro's text, and
+//! `key=value` words right after it fill in `{key}` placeholders.  Macros
+//! may refer to other macros, but not to themselves.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MacroError {
+    Unknown(String),
+    /// The chain of macros that leads back to the first one
+    Recursive(Vec<String>),
+    MissingParam {
+        name: String,
+        param: String,
+    },
+    InvalidName(String),
+}
+
+impl fmt::Display for MacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MacroError::Unknown(name) => write!(f, "no macro named @{}", name),
+            MacroError::Recursive(chain) => write!(
+                f,
+                "macro refers to itself: {}",
+                chain
+                    .iter()
+                    .map(|n| format!("@{}", n))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ),
+            MacroError::MissingParam { name, param } => write!(
+                f,
+                "@{} needs a value for {{{}}}, e.g. @{} {}=...",
+                name, param, name, param
+            ),
+            MacroError::InvalidName(name) => write!(
+                f,
+                "invalid macro name: {} (use letters, digits, - and _)",
+                name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MacroError {}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_name_char)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Macros {
+    path: Option<PathBuf>,
+    macros: BTreeMap<String, String>,
+}
+
+impl Macros {
+    /// Loads macros from `path`, which doesn't need to exist yet
+    pub fn with_config(path: &Path) -> io::Result<Macros> {
+        let macros = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Macros {
+            path: Some(path.to_path_buf()),
+            macros,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.macros.get(name).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.macros.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Adds or replaces a macro and writes the file back
+    pub fn add(&mut self, name: &str, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if !valid_name(name) {
+            return Err(MacroError::InvalidName(name.to_string()).into());
+        }
+        // reject text that would never expand, before it's saved
+        let mut candidate = self.clone();
+        candidate.macros.insert(name.to_string(), text.to_string());
+        candidate.check_cycles(name, &mut vec![])?;
+        self.macros = candidate.macros;
+        if let Some(path) = &self.path {
+            paths::write_atomic(path, serde_json::to_string_pretty(&self.macros)?)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces every `@name [key=value ...]` in `input`.  Input without
+    /// macros comes back unchanged.
+    pub fn expand(&self, input: &str) -> Result<String, MacroError> {
+        self.expand_with(input, &mut vec![])
+    }
+
+    fn expand_with(&self, input: &str, stack: &mut Vec<String>) -> Result<String, MacroError> {
+        let mut out = String::with_capacity(input.len());
+        let mut rest = input;
+        while let Some(at) = find_reference(rest) {
+            out.push_str(&rest[..at]);
+            let after = &rest[at + 1..];
+            let name_len = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+            let name = &after[..name_len];
+            let (params, remaining) = parse_params(&after[name_len..]);
+            out.push_str(&self.expand_macro(name, &params, stack)?);
+            rest = remaining;
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Follows references from `name` without expanding anything, so that
+    /// macros with placeholders can be checked too
+    fn check_cycles(&self, name: &str, stack: &mut Vec<String>) -> Result<(), MacroError> {
+        if stack.iter().any(|n| n == name) {
+            let mut chain = stack.clone();
+            chain.push(name.to_string());
+            return Err(MacroError::Recursive(chain));
+        }
+        let Some(text) = self.get(name) else {
+            return Ok(());
+        };
+        stack.push(name.to_string());
+        for reference in references(text) {
+            self.check_cycles(reference, stack)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    fn expand_macro(
+        &self,
+        name: &str,
+        params: &[(String, String)],
+        stack: &mut Vec<String>,
+    ) -> Result<String, MacroError> {
+        if stack.iter().any(|n| n == name) {
+            let mut chain = stack.clone();
+            chain.push(name.to_string());
+            return Err(MacroError::Recursive(chain));
+        }
+        let text = self
+            .get(name)
+            .ok_or_else(|| MacroError::Unknown(name.to_string()))?;
+        let text = substitute(name, text, params)?;
+        stack.push(name.to_string());
+        let expanded = self.expand_with(&text, stack);
+        stack.pop();
+        expanded
+    }
+}
+
+/// Byte offset of the next `@` that starts a macro reference: at the start
+/// or after whitespace (so email addresses are left alone), followed by a name
+fn find_reference(s: &str) -> Option<usize> {
+    let mut prev: Option<char> = None;
+    for (idx, c) in s.char_indices() {
+        if c == '@'
+            && prev.is_none_or(char::is_whitespace)
+            && s[idx + 1..].starts_with(is_name_char)
+        {
+            return Some(idx);
+        }
+        prev = Some(c);
+    }
+    None
+}
+
+/// Names of the macros that `text` refers to
+fn references(text: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut rest = text;
+    while let Some(at) = find_reference(rest) {
+        let after = &rest[at + 1..];
+        let name_len = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+        names.push(&after[..name_len]);
+        rest = &after[name_len..];
+    }
+    names
+}
+
+/// Consumes ` key=value` words following a reference, returning them and
+/// what's left of the input.  A value ends at whitespace.
+fn parse_params(s: &str) -> (Vec<(String, String)>, &str) {
+    let mut params = vec![];
+    let mut rest = s;
+    loop {
+        let trimmed = rest.trim_start_matches([' ', '\t']);
+        if trimmed.len() == rest.len() {
+            break;
+        }
+        let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+        let word = &trimmed[..end];
+        match word.split_once('=') {
+            Some((key, value)) if valid_name(key) && !value.is_empty() => {
+                // punctuation after a value belongs to the sentence
+                let value = value.trim_end_matches([',', ';', '.', '!', '?']);
+                params.push((key.to_string(), value.to_string()));
+                rest = &trimmed[key.len() + 1 + value.len()..];
+            }
+            _ => break,
+        }
+    }
+    (params, rest)
+}
+
+/// Fills `{key}` placeholders.  Braces around anything that isn't a
+/// placeholder name are left as they are.
+fn substitute(name: &str, text: &str, params: &[(String, String)]) -> Result<String, MacroError> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        match after.find('}') {
+            Some(close) if valid_name(&after[..close]) => {
+                let param = &after[..close];
+                let value = params
+                    .iter()
+                    .find(|(k, _)| k == param)
+                    .map(|(_, v)| v)
+                    .ok_or_else(|| MacroError::MissingParam {
+                        name: name.to_string(),
+                        param: param.to_string(),
+                    })?;
+                out.push_str(value);
+                rest = &after[close + 1..];
+            }
+            _ => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn macros(entries: &[(&str, &str)]) -> Macros {
+        Macros {
+            path: None,
+            macros: entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn a_macro_that_uses_itself_is_refused() {
+        let mut m = Macros::default();
+        let e = m.add("again", "more, @again").unwrap_err();
+        assert_eq!(e.to_string(), "macro refers to itself: @again -> @again");
+        assert_eq!(m.get("again"), None);
+
+        // one that got into macros.json by hand
+        let m = macros(&[("again", "more, @again")]);
+        assert_eq!(
+            m.expand("@again"),
+            Err(MacroError::Recursive(vec!["again".into(), "again".into()]))
+        );
+    }
+
+    #[test]
+    fn macros_that_use_each_other_are_refused() {
+        let mut m = macros(&[("lunch", "@dinner leftovers")]);
+        assert!(m.add("dinner", "something for @lunch").is_err());
+        assert_eq!(m.get("dinner"), None);
+
+        let m = macros(&[("lunch", "@dinner leftovers"), ("dinner", "like @lunch")]);
+        assert_eq!(
+            m.expand("@lunch"),
+            Err(MacroError::Recursive(vec![
+                "lunch".into(),
+                "dinner".into(),
+                "lunch".into()
+            ]))
+        );
+    }
+
+    #[test]
+    fn nested_macros_expand() {
+        let m = macros(&[("kids", "kids will eat it"), ("weeknight", "quick, @kids")]);
+        assert_eq!(
+            m.expand("@weeknight tonight").unwrap(),
+            "quick, kids will eat it tonight"
+        );
+    }
+
+    #[test]
+    fn params_fill_placeholders() {
+        let m = macros(&[("weeknight", "quick {protein} dinner for {n}")]);
+        assert_eq!(
+            m.expand("@weeknight protein=tofu n=4, please").unwrap(),
+            "quick tofu dinner for 4, please"
+        );
+        assert_eq!(
+            m.expand("@weeknight protein=tofu"),
+            Err(MacroError::MissingParam {
+                name: "weeknight".into(),
+                param: "n".into()
+            })
+        );
+        // braces that aren't placeholders stay
+        let m = macros(&[("json", "answer as {\"title\": ...}")]);
+        assert_eq!(m.expand("@json").unwrap(), "answer as {\"title\": ...}");
+    }
+
+    #[test]
+    fn unknown_macros_are_errors() {
+        let m = macros(&[("weeknight", "quick dinner")]);
+        assert_eq!(
+            m.expand("@weekend brunch"),
+            Err(MacroError::Unknown("weekend".into()))
+        );
+        assert_eq!(
+            m.expand("@weekend brunch").unwrap_err().to_string(),
+            "no macro named @weekend"
+        );
+    }
+
+    #[test]
+    fn email_addresses_are_left_alone() {
+        let m = macros(&[]);
+        assert_eq!(
+            m.expand("send it to me@example.com").unwrap(),
+            "send it to me@example.com"
+        );
+        assert_eq!(m.expand("just @ home").unwrap(), "just @ home");
+    }
+}
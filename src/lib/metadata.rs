@@ -0,0 +1,153 @@
+//! How a recipe was produced, saved next to it as `<stem>.meta.json` so that
+//! a good result can be understood and its image regenerated.
+//!
+//! Every field has a default so that metadata written by older versions
+//! still loads.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::citations::Sources;
+use crate::context::Context;
+use crate::imageformat::ImageFormat;
+use crate::preferences::Preferences;
+
+pub const METADATA_VERSION: u32 = 1;
+
+/// The image model [crate::canvas] calls
+pub const IMAGE_MODEL: &str = "amazon.nova-canvas-v1:0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct InferenceParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageOutcome {
+    /// Images were turned off
+    #[default]
+    Skipped,
+    Generated,
+    /// Canvas returned no images, which is what a content filter block looks like
+    Blocked,
+    TimedOut,
+    /// The request failed for another reason, logged when it happened
+    Failed,
+}
+
+/// How the image request went, for debugging and for AWS support
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ImageGeneration {
+    pub outcome: ImageOutcome,
+    pub trace_id: Option<String>,
+    pub duration_ms: u64,
+    /// The prompt Canvas blocked, if this is the retry with a reworded one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rewritten_from: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RecipeMetadata {
+    pub version: u32,
+    pub crate_version: String,
+    pub model: String,
+    pub inference: InferenceParams,
+    pub system_prompt_sha256: String,
+    /// Number of messages in the conversation when the recipe was transmitted
+    pub turn_count: usize,
+    pub image_model: String,
+    /// The prompt as sent to the image model, after post-processing
+    pub image_prompt: String,
+    /// The prompt as the conversation model wrote it
+    pub image_prompt_original: String,
+    pub image_style: String,
+    pub image_generation: ImageGeneration,
+    /// What the images in `files` are encoded as
+    pub image_format: ImageFormat,
+    /// What the images were generated with, for `reproduce`; missing from
+    /// metadata written before seeds were chosen
+    pub image_seed: Option<u64>,
+    pub files: Vec<String>,
+    /// Shown side by side with another recipe (`--show-both`) and not the
+    /// one picked, so generated but never meant to be cooked
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub candidate: bool,
+    /// Why this recipe, from the model or else from [derive_rationale]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rationale: Option<String>,
+    /// The `.txt` of the recipe this one revises, when asked for with `tweak`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision_of: Option<String>,
+    /// The tweak that asked for the revision
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tweak: Option<String>,
+    /// The shell turn that produced the recipe, as in the audit log and
+    /// the debug log
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Appliances the instructions call for, from [crate::equipment::detect]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub equipment: Vec<String>,
+    /// The documents cited in the answers that led to the recipe, also
+    /// listed at the end of its `.txt`
+    #[serde(skip_serializing_if = "Sources::is_empty")]
+    pub sources: Sources,
+}
+
+impl RecipeMetadata {
+    /// Where the metadata for the recipe saved under `stem` lives
+    pub fn path_for(stem: &str) -> PathBuf {
+        PathBuf::from(format!("{}.meta.json", stem))
+    }
+
+    /// Finds the metadata for a recipe given its stem, its `.txt`, or the metadata file itself
+    pub fn locate(path: &Path) -> PathBuf {
+        let s = path.to_string_lossy();
+        if s.ends_with(".meta.json") {
+            path.to_path_buf()
+        } else {
+            RecipeMetadata::path_for(s.strip_suffix(".txt").unwrap_or(&s))
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<RecipeMetadata> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// A rationale for when the model didn't give one, from what the session
+/// knew: the household's diet and allergies, today's theme, the meal and
+/// the season
+pub fn derive_rationale(preferences: Option<&Preferences>, context: &Context) -> String {
+    let mut reasons = vec![];
+    if let Some(preferences) = preferences {
+        reasons.extend(preferences.diet.iter().cloned());
+        reasons.extend(preferences.allergies.iter().map(|a| format!("no {}", a)));
+    }
+    if let Some(theme) = &context.theme {
+        reasons.push(format!("{} theme", theme));
+    }
+    reasons.push(format!("{} in {}", context.meal, context.season()));
+    format!("derived from the session: {}", reasons.join(", "))
+}
+
+/// Hex encoded SHA-256, for recording which system prompt produced a recipe
+pub fn sha256_hex(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -1 +1,150 @@
+//! Recipe recommendations from a conversation with a Bedrock model.
+//!
+//! The `recipes` binary is a thin shell over this library.  Embedders start
+//! from the items re-exported here, which are the library's semver surface:
+//! a [Config] built up with [Preferences], a [ToolRegistry] of their own
+//! tools and an [ArtifactSink] for transmitted recipes, then
+//! [Conversation::connect] and [Conversation::ask] for each prompt.  None of
+//! them mention `aws_sdk_bedrockruntime` types, and structs and enums that
+//! may grow are `#[non_exhaustive]` with builder-style constructors.  See
+//! `examples/embedded.rs`.
+//!
+//! The `bedrock` feature (on by default) also makes public what works on
+//! the SDK's types directly: [Conversation::builder] and the `send` family,
+//! the tool configuration helpers in `toolspec`, sessions, recordings,
+//! image generation and the rest of what the binary is built from.  Those
+//! follow the SDK's own versioning, not this crate's.
+#![cfg_attr(
+    not(feature = "bedrock"),
+    allow(dead_code, unused_imports, private_interfaces, private_bounds)
+)]
+
+/// A module that's public with the `bedrock` feature and crate-private
+/// without it, because its API is written in terms of the SDK's types
+macro_rules! bedrock_modules {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[cfg(feature = "bedrock")]
+            pub mod $name;
+            #[cfg(not(feature = "bedrock"))]
+            mod $name;
+        )*
+    };
+}
+
+/// An item that's public with the `bedrock` feature and `pub(crate)`
+/// without it, for SDK-typed items in otherwise SDK-free modules
+macro_rules! bedrock_pub {
+    ($(#[$attr:meta])* async fn $($rest:tt)*) => {
+        #[cfg(feature = "bedrock")]
+        $(#[$attr])*
+        pub async fn $($rest)*
+        #[cfg(not(feature = "bedrock"))]
+        $(#[$attr])*
+        pub(crate) async fn $($rest)*
+    };
+    ($(#[$attr:meta])* fn $($rest:tt)*) => {
+        #[cfg(feature = "bedrock")]
+        $(#[$attr])*
+        pub fn $($rest)*
+        #[cfg(not(feature = "bedrock"))]
+        $(#[$attr])*
+        pub(crate) fn $($rest)*
+    };
+    ($(#[$attr:meta])* struct $($rest:tt)*) => {
+        #[cfg(feature = "bedrock")]
+        $(#[$attr])*
+        pub struct $($rest)*
+        #[cfg(not(feature = "bedrock"))]
+        $(#[$attr])*
+        pub(crate) struct $($rest)*
+    };
+}
+
+bedrock_modules! {
+    allowlist,
+    artifacts,
+    awsinit,
+    bench,
+    canvas,
+    citations,
+    cost,
+    doctor,
+    eval,
+    limits,
+    models,
+    moderation,
+    recap,
+    recording,
+    repair,
+    session,
+    tokens,
+    toolcache,
+    toolinput,
+    toolspec,
+    translate,
+}
+
+pub use config::Config;
+pub use conversation::{Conversation, ConversationError, Reply, Usage};
+pub use error::GourmandError;
+pub use preferences::Preferences;
+pub use recipe::Recipe;
+pub use sink::{ArtifactSink, DirSink, MemorySink};
+pub use tools::{ToolDef, ToolRegistry};
+
+pub mod audit;
+pub mod bigtext;
+pub mod config;
+pub mod constraints;
+pub mod context;
+pub mod conversation;
+pub mod dietary;
+pub mod diff;
+pub mod equipment;
+pub mod error;
+pub mod events;
+pub mod export;
+#[cfg(test)]
+mod golden;
+#[cfg(feature = "history")]
+pub mod history;
+pub mod imageformat;
+pub mod imagestyle;
+pub mod import;
+pub mod layout;
+pub mod lock;
+pub mod logging;
+pub mod macros;
+pub mod metadata;
+pub mod notify;
+pub mod oneshot;
+pub mod pacing;
+pub mod pantry;
+pub mod parse;
+pub mod paths;
+pub mod preferences;
+pub mod promptecho;
+pub mod recipe;
+#[cfg(feature = "history")]
+pub mod report;
+#[cfg(feature = "sms")]
+pub mod rpc;
+#[cfg(test)]
+mod scripted;
+pub mod selftest;
+pub mod shopping;
+pub mod shutdown;
+pub mod sink;
+pub mod specials;
 pub mod system_prompts;
+pub mod themes;
+pub mod tidy;
+pub mod toolrun;
+pub mod tools;
+pub mod topic;
+pub mod tweaks;
+pub mod typeahead;
+pub mod ui;
+pub mod units;
+pub mod webimport;
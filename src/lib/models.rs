@@ -0,0 +1,139 @@
+//! Inference settings suited to different kinds of turns: recipe ideas
+//! benefit from a higher temperature than mechanical follow-ups like
+//! rescaling a recipe.
+use std::fmt;
+use std::str::FromStr;
+
+use aws_sdk_bedrockruntime::types::InferenceConfiguration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InferenceProfilePreset {
+    /// Varied suggestions, for brainstorming recipes
+    Creative,
+    Balanced,
+    /// Predictable output, for follow-ups issued by the program itself
+    Precise,
+}
+
+impl fmt::Display for InferenceProfilePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InferenceProfilePreset::Creative => write!(f, "creative"),
+            InferenceProfilePreset::Balanced => write!(f, "balanced"),
+            InferenceProfilePreset::Precise => write!(f, "precise"),
+        }
+    }
+}
+
+impl FromStr for InferenceProfilePreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "creative" => Ok(InferenceProfilePreset::Creative),
+            "balanced" => Ok(InferenceProfilePreset::Balanced),
+            "precise" => Ok(InferenceProfilePreset::Precise),
+            _ => Err(format!(
+                "unknown preset: {} (expected creative, balanced, or precise)",
+                s
+            )),
+        }
+    }
+}
+
+impl InferenceProfilePreset {
+    pub fn temperature(&self) -> f32 {
+        match self {
+            InferenceProfilePreset::Creative => 0.9,
+            InferenceProfilePreset::Balanced => 0.5,
+            InferenceProfilePreset::Precise => 0.1,
+        }
+    }
+
+    pub fn top_p(&self) -> f32 {
+        match self {
+            InferenceProfilePreset::Creative => 0.95,
+            InferenceProfilePreset::Balanced => 0.9,
+            InferenceProfilePreset::Precise => 0.5,
+        }
+    }
+
+    /// `None` leaves it to the model's default
+    pub fn max_tokens(&self) -> Option<i32> {
+        match self {
+            InferenceProfilePreset::Creative | InferenceProfilePreset::Balanced => None,
+            InferenceProfilePreset::Precise => Some(1024),
+        }
+    }
+
+    /// The preset's settings, with `max_tokens` (e.g. from `--max-tokens`)
+    /// winning over the preset's own
+    pub fn inference(&self, max_tokens: Option<i32>) -> InferenceConfiguration {
+        InferenceConfiguration::builder()
+            .temperature(self.temperature())
+            .top_p(self.top_p())
+            .set_max_tokens(max_tokens.or(self.max_tokens()))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_bedrockruntime::types::ContentBlock;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::conversation::Conversation;
+    use crate::scripted::{Reply, Scripted};
+
+    /// The inferenceConfig of a request made with `inference`
+    async fn sent(inference: InferenceConfiguration) -> Value {
+        let backend = Scripted::new([Reply::text("Rice and beans?")]);
+        let mut conversation = Conversation::builder(backend.client(), "amazon.nova-lite-v1:0")
+            .inference(inference)
+            .build();
+        conversation
+            .send(ContentBlock::Text("dinner?".into()))
+            .await
+            .unwrap();
+        backend.last_body()["inferenceConfig"].clone()
+    }
+
+    // the SDK sends floats as f32s widened to f64, 0.8999999761581421 for 0.9
+    #[tokio::test]
+    async fn each_preset_sends_its_settings() {
+        for (preset, expected) in [
+            (
+                InferenceProfilePreset::Creative,
+                json!({"temperature": 0.9f32, "topP": 0.95f32}),
+            ),
+            (
+                InferenceProfilePreset::Balanced,
+                json!({"temperature": 0.5f32, "topP": 0.9f32}),
+            ),
+            (
+                InferenceProfilePreset::Precise,
+                json!({"temperature": 0.1f32, "topP": 0.5f32, "maxTokens": 1024}),
+            ),
+        ] {
+            assert_eq!(sent(preset.inference(None)).await, expected, "{}", preset);
+        }
+    }
+
+    #[tokio::test]
+    async fn max_tokens_wins_over_the_preset() {
+        for preset in [
+            InferenceProfilePreset::Creative,
+            InferenceProfilePreset::Precise,
+        ] {
+            let config = sent(preset.inference(Some(300))).await;
+            assert_eq!(config["maxTokens"], 300, "{}", preset);
+            assert_eq!(
+                config["temperature"],
+                json!(preset.temperature()),
+                "{}",
+                preset
+            );
+        }
+    }
+}
@@ -0,0 +1,386 @@
+//! Screening what users type before it reaches the model, for front ends
+//! that serve people outside the household.
+//!
+//! A blocked prompt never enters the conversation: the front end answers
+//! with [BLOCKED_REPLY] and records it in the audit log instead.  The
+//! moderators are [NoModeration] (the default), [KeywordModerator] with a
+//! word list, and [GuardrailModerator], which asks a Bedrock guardrail.
+//! Choose one with a [ModerationSpec] such as `keywords:~/blocked.txt` or
+//! `guardrail:abc123:2`.
+use std::fmt::{self, Debug};
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+
+use aws_sdk_bedrockruntime::types::{
+    GuardrailAction, GuardrailContentBlock, GuardrailContentSource, GuardrailTextBlock,
+};
+use aws_sdk_bedrockruntime::Client;
+use log::warn;
+
+use crate::paths;
+
+/// What the user is told instead of an answer
+pub const BLOCKED_REPLY: &str =
+    "Sorry, I can't help with that. Tell me what you'd like to cook and I'll find a recipe.";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Block { reason: String },
+}
+
+/// [InputModerator::check]'s future
+pub type Check<'a> = Pin<Box<dyn Future<Output = Verdict> + Send + 'a>>;
+
+pub trait InputModerator: Debug + Send + Sync {
+    /// Names the moderator in the audit log
+    fn name(&self) -> &str;
+
+    /// Whether `text` may be sent.  A moderator that can't tell blocks it.
+    fn check<'a>(&'a self, text: &'a str) -> Check<'a>;
+}
+
+/// Lets everything through; the default
+#[derive(Debug, Default)]
+pub struct NoModeration;
+
+impl InputModerator for NoModeration {
+    fn name(&self) -> &str {
+        "none"
+    }
+
+    fn check<'a>(&'a self, _text: &'a str) -> Check<'a> {
+        Box::pin(async { Verdict::Allow })
+    }
+}
+
+/// Blocks prompts containing any of a list of words or phrases, matched
+/// whole and ignoring case
+#[derive(Debug, Clone, Default)]
+pub struct KeywordModerator {
+    blocked: Vec<Vec<String>>,
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl KeywordModerator {
+    pub fn new<S: AsRef<str>>(blocked: &[S]) -> KeywordModerator {
+        KeywordModerator {
+            blocked: blocked
+                .iter()
+                .map(|phrase| words(phrase.as_ref()))
+                .filter(|phrase| !phrase.is_empty())
+                .collect(),
+        }
+    }
+
+    /// One word or phrase per line; blank lines and lines starting with `#`
+    /// are skipped
+    pub fn load(path: &std::path::Path) -> io::Result<KeywordModerator> {
+        let contents = fs::read_to_string(path)?;
+        let lines: Vec<&str> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .collect();
+        Ok(KeywordModerator::new(&lines))
+    }
+
+    fn first_match(&self, text: &str) -> Option<String> {
+        let text = words(text);
+        self.blocked
+            .iter()
+            .find(|phrase| text.windows(phrase.len()).any(|w| w == phrase.as_slice()))
+            .map(|phrase| phrase.join(" "))
+    }
+}
+
+impl InputModerator for KeywordModerator {
+    fn name(&self) -> &str {
+        "keywords"
+    }
+
+    fn check<'a>(&'a self, text: &'a str) -> Check<'a> {
+        let verdict = match self.first_match(text) {
+            Some(phrase) => Verdict::Block {
+                reason: format!("contains \"{}\"", phrase),
+            },
+            None => Verdict::Allow,
+        };
+        Box::pin(async move { verdict })
+    }
+}
+
+/// Asks a Bedrock guardrail with ApplyGuardrail, which is billed per
+/// request
+#[derive(Debug, Clone)]
+pub struct GuardrailModerator {
+    client: Client,
+    id: String,
+    version: String,
+}
+
+impl GuardrailModerator {
+    pub fn new(client: Client, id: impl Into<String>, version: impl Into<String>) -> Self {
+        GuardrailModerator {
+            client,
+            id: id.into(),
+            version: version.into(),
+        }
+    }
+
+    async fn apply(&self, text: &str) -> Verdict {
+        let block = match GuardrailTextBlock::builder().text(text).build() {
+            Ok(block) => block,
+            Err(e) => {
+                return Verdict::Block {
+                    reason: format!("couldn't build the guardrail request: {}", e),
+                }
+            }
+        };
+        let response = self
+            .client
+            .apply_guardrail()
+            .guardrail_identifier(&self.id)
+            .guardrail_version(&self.version)
+            .source(GuardrailContentSource::Input)
+            .content(GuardrailContentBlock::Text(block))
+            .send()
+            .await;
+        match response {
+            Ok(output) if output.action() == &GuardrailAction::GuardrailIntervened => {
+                Verdict::Block {
+                    reason: format!("guardrail {} intervened", self.id),
+                }
+            }
+            Ok(_) => Verdict::Allow,
+            Err(e) => {
+                let e = aws_sdk_bedrockruntime::Error::from(e);
+                warn!("guardrail {} failed, blocking: {}", self.id, e);
+                Verdict::Block {
+                    reason: format!("guardrail {} couldn't be reached: {}", self.id, e),
+                }
+            }
+        }
+    }
+}
+
+impl InputModerator for GuardrailModerator {
+    fn name(&self) -> &str {
+        "guardrail"
+    }
+
+    fn check<'a>(&'a self, text: &'a str) -> Check<'a> {
+        Box::pin(self.apply(text))
+    }
+}
+
+/// Which moderator to use, as given on the command line
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ModerationSpec {
+    #[default]
+    None,
+    /// `keywords:<path>`
+    Keywords(PathBuf),
+    /// `guardrail:<id>[:<version>]`, the draft version if none is given
+    Guardrail { id: String, version: String },
+}
+
+impl fmt::Display for ModerationSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModerationSpec::None => write!(f, "none"),
+            ModerationSpec::Keywords(path) => write!(f, "keywords:{}", path.display()),
+            ModerationSpec::Guardrail { id, version } => {
+                write!(f, "guardrail:{}:{}", id, version)
+            }
+        }
+    }
+}
+
+impl FromStr for ModerationSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) = s.split_once(':').unwrap_or((s, ""));
+        match (kind, rest) {
+            ("none", "") => Ok(ModerationSpec::None),
+            ("keywords", path) if !path.is_empty() => {
+                Ok(ModerationSpec::Keywords(paths::expand(path)))
+            }
+            ("guardrail", rest) if !rest.is_empty() => {
+                let (id, version) = rest.split_once(':').unwrap_or((rest, "DRAFT"));
+                Ok(ModerationSpec::Guardrail {
+                    id: id.to_string(),
+                    version: version.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "unknown moderation: {} (expected none, keywords:<path>, or guardrail:<id>[:<version>])",
+                s
+            )),
+        }
+    }
+}
+
+impl ModerationSpec {
+    /// Builds the moderator, loading a keyword list now so a bad path fails
+    /// at startup
+    pub fn build(&self, client: &Client) -> io::Result<Box<dyn InputModerator>> {
+        Ok(match self {
+            ModerationSpec::None => Box::new(NoModeration),
+            ModerationSpec::Keywords(path) => Box::new(KeywordModerator::load(path)?),
+            ModerationSpec::Guardrail { id, version } => {
+                Box::new(GuardrailModerator::new(client.clone(), id, version))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::scripted::{Reply, Scripted};
+
+    fn blocks(moderator: &KeywordModerator, text: &str) -> bool {
+        moderator.first_match(text).is_some()
+    }
+
+    #[test]
+    fn keywords_match_whole_words_and_phrases() {
+        let moderator = KeywordModerator::new(&["meth", "Pipe Bomb", "  ", "#"]);
+        let cases = [
+            ("how do I make meth at home", true),
+            ("METH, please", true),
+            ("meth.", true),
+            ("a curry with methi leaves", false),
+            ("something with methylcellulose", false),
+            ("how to build a pipe bomb", true),
+            ("how to build a pipe-bomb", true),
+            ("a pipe of bomb pop", false),
+            ("pipe the bomb, er, the meringue", false),
+            ("", false),
+        ];
+        for (text, blocked) in cases {
+            assert_eq!(blocks(&moderator, text), blocked, "{:?}", text);
+        }
+        assert_eq!(
+            moderator.first_match("one PIPE  bomb"),
+            Some("pipe bomb".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn a_keyword_blocks_with_a_reason() {
+        let moderator = KeywordModerator::new(&["meth"]);
+        assert_eq!(
+            moderator.check("make meth").await,
+            Verdict::Block {
+                reason: "contains \"meth\"".to_string()
+            }
+        );
+        assert_eq!(moderator.check("leek risotto").await, Verdict::Allow);
+        assert_eq!(NoModeration.check("make meth").await, Verdict::Allow);
+    }
+
+    #[test]
+    fn keyword_lists_skip_comments_and_blank_lines() {
+        let path = std::env::temp_dir().join(format!("gourmand-blocked-{}", ulid::Ulid::new()));
+        fs::write(&path, "# house rules\n\nmeth\n  pipe bomb  \n#risotto\n").unwrap();
+        let moderator = KeywordModerator::load(&path).unwrap();
+        assert!(blocks(&moderator, "make meth"));
+        assert!(blocks(&moderator, "a pipe bomb"));
+        assert!(!blocks(&moderator, "leek risotto"));
+        assert!(!blocks(&moderator, "house rules"));
+        fs::remove_file(&path).unwrap();
+        assert!(KeywordModerator::load(&path).is_err());
+    }
+
+    fn guardrail_reply(action: &str) -> Reply {
+        Reply::json(json!({
+            "usage": {
+                "topicPolicyUnits": 1,
+                "contentPolicyUnits": 1,
+                "wordPolicyUnits": 1,
+                "sensitiveInformationPolicyUnits": 0,
+                "sensitiveInformationPolicyFreeUnits": 0,
+                "contextualGroundingPolicyUnits": 0
+            },
+            "action": action,
+            "outputs": [],
+            "assessments": []
+        }))
+    }
+
+    #[tokio::test]
+    async fn a_guardrail_blocks_when_it_intervenes_or_cant_be_reached() {
+        let backend = Scripted::new([
+            guardrail_reply("GUARDRAIL_INTERVENED"),
+            guardrail_reply("NONE"),
+            Reply::error(403, "AccessDeniedException", "not allowed"),
+        ]);
+        let moderator = GuardrailModerator::new(backend.client(), "abc123", "2");
+        assert_eq!(
+            moderator.check("make meth").await,
+            Verdict::Block {
+                reason: "guardrail abc123 intervened".to_string()
+            }
+        );
+        assert_eq!(
+            backend.last_body(),
+            json!({"source": "INPUT", "content": [{"text": {"text": "make meth"}}]})
+        );
+        assert_eq!(moderator.check("leek risotto").await, Verdict::Allow);
+        assert!(matches!(
+            moderator.check("leek risotto").await,
+            Verdict::Block { reason } if reason.contains("couldn't be reached")
+        ));
+    }
+
+    #[test]
+    fn specs() {
+        let cases = [
+            ("none", Some(ModerationSpec::None)),
+            (
+                "guardrail:abc123:2",
+                Some(ModerationSpec::Guardrail {
+                    id: "abc123".to_string(),
+                    version: "2".to_string(),
+                }),
+            ),
+            (
+                "guardrail:abc123",
+                Some(ModerationSpec::Guardrail {
+                    id: "abc123".to_string(),
+                    version: "DRAFT".to_string(),
+                }),
+            ),
+            (
+                "keywords:/etc/blocked.txt",
+                Some(ModerationSpec::Keywords(PathBuf::from("/etc/blocked.txt"))),
+            ),
+            ("keywords", None),
+            ("guardrail:", None),
+            ("openai", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(text.parse::<ModerationSpec>().ok(), expected, "{}", text);
+        }
+        for text in ["none", "guardrail:abc123:2", "keywords:/etc/blocked.txt"] {
+            let spec: ModerationSpec = text.parse().unwrap();
+            assert_eq!(spec.to_string(), text);
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Let the user know when slow work finishes, via a desktop notification
+//! and/or a webhook POST.
+//!
+//! Notifications are best effort: failures are logged as warnings and never
+//! interrupt the conversation.
+//!
+//! How long a turn may take before the user is told it finished can also be
+//! set in `notify.json` in the config directory:
+//!
+//! ```json
+//! {"after_secs": 20}
+//! ```
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[cfg(any(feature = "desktop-notify", feature = "http"))]
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// The kinds of events a user can subscribe to
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// A transmitted recipe and its images have been written to disk
+    RecipeSaved,
+    /// Images generated again for a saved recipe have been written to disk
+    ImagesSaved,
+    /// A batch of work, e.g. an import or an evaluation, has finished
+    BatchFinished,
+    /// A prompt took longer than the configured time to answer, and the
+    /// answer is ready
+    AnswerReady,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 4] = [
+        EventKind::RecipeSaved,
+        EventKind::ImagesSaved,
+        EventKind::BatchFinished,
+        EventKind::AnswerReady,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::RecipeSaved => "recipe_saved",
+            EventKind::ImagesSaved => "images_saved",
+            EventKind::BatchFinished => "batch_finished",
+            EventKind::AnswerReady => "answer_ready",
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EventKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = EventKind::ALL.iter().map(EventKind::as_str).collect();
+                format!(
+                    "unknown event type: {} (expected one of {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// The JSON body POSTed to the webhook
+///
+/// ```json
+/// {"event": "recipe_saved", "title": "Banana Bread", "summary": null, "paths": ["./banana_bread_1234.txt"]}
+/// ```
+///
+/// `title` is the recipe's, when the event is about one recipe; `summary`
+/// says what happened, for events that aren't.  Both are always present,
+/// and null when they don't apply.
+#[derive(Serialize, Debug, Clone)]
+pub struct Payload {
+    pub event: EventKind,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub paths: Vec<String>,
+}
+
+impl Payload {
+    pub fn new(event: EventKind) -> Payload {
+        Payload {
+            event,
+            title: None,
+            summary: None,
+            paths: vec![],
+        }
+    }
+
+    /// What a desktop notification says
+    pub fn body(&self) -> String {
+        self.title
+            .clone()
+            .or_else(|| self.summary.clone())
+            .unwrap_or_else(|| self.event.to_string())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct NotifyConfig {
+    /// Ring the bell when a prompt takes longer than this to answer
+    pub after_secs: Option<u64>,
+}
+
+impl NotifyConfig {
+    /// Loads the config from `path`, or the defaults if it doesn't exist
+    pub fn load(path: &Path) -> io::Result<NotifyConfig> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(NotifyConfig::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn after(&self) -> Option<Duration> {
+        self.after_secs.map(Duration::from_secs)
+    }
+}
+
+/// Shows a desktop notification, if this build supports them
+pub fn desktop(body: &str) {
+    #[cfg(feature = "desktop-notify")]
+    if let Err(e) = notify_rust::Notification::new()
+        .summary("gourmand")
+        .body(body)
+        .show()
+    {
+        warn!("desktop notification failed: {}", e);
+    }
+    #[cfg(not(feature = "desktop-notify"))]
+    let _ = body;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    desktop: bool,
+    #[cfg(feature = "http")]
+    webhook: Option<String>,
+    /// Only these events are delivered; empty means all of them
+    events: Vec<EventKind>,
+    #[cfg(feature = "http")]
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    /// Fails if a delivery method was requested that this build doesn't support
+    pub fn new(
+        desktop: bool,
+        webhook: Option<String>,
+        events: Vec<EventKind>,
+    ) -> Result<Notifier, String> {
+        if desktop && !cfg!(feature = "desktop-notify") {
+            return Err(
+                "built without desktop notification support (feature desktop-notify)".to_string(),
+            );
+        }
+        if webhook.is_some() && !cfg!(feature = "http") {
+            return Err("built without webhook support (feature http)".to_string());
+        }
+        Ok(Notifier {
+            desktop,
+            #[cfg(feature = "http")]
+            webhook,
+            events,
+            #[cfg(feature = "http")]
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+        })
+    }
+
+    fn wants(&self, event: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    /// Whether `event` would be shown on the desktop
+    pub fn shows_desktop(&self, event: EventKind) -> bool {
+        self.desktop && self.wants(event)
+    }
+
+    pub async fn notify(&self, payload: &Payload) {
+        if !self.wants(payload.event) {
+            return;
+        }
+
+        if self.desktop {
+            desktop(&payload.body());
+        }
+
+        #[cfg(feature = "http")]
+        if let Some(url) = &self.webhook {
+            let result = self
+                .client
+                .post(url)
+                .json(payload)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            if let Err(e) = result {
+                warn!("webhook notification to {} failed: {}", url, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn payload_schema() {
+        let payload = Payload {
+            title: Some("Banana Bread".to_string()),
+            paths: vec!["./banana_bread_1234.txt".to_string()],
+            ..Payload::new(EventKind::RecipeSaved)
+        };
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            json!({
+                "event": "recipe_saved",
+                "title": "Banana Bread",
+                "summary": null,
+                "paths": ["./banana_bread_1234.txt"],
+            })
+        );
+        let payload = Payload {
+            summary: Some("imported 3 recipes".to_string()),
+            ..Payload::new(EventKind::BatchFinished)
+        };
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            json!({
+                "event": "batch_finished",
+                "title": null,
+                "summary": "imported 3 recipes",
+                "paths": [],
+            })
+        );
+    }
+
+    #[test]
+    fn event_names_round_trip() {
+        for kind in EventKind::ALL {
+            assert_eq!(kind.to_string().parse::<EventKind>(), Ok(kind));
+            assert_eq!(serde_json::to_value(kind).unwrap(), json!(kind.as_str()));
+        }
+        let err = "cooked".parse::<EventKind>().unwrap_err();
+        assert!(err.contains("answer_ready"), "{}", err);
+    }
+
+    #[test]
+    fn desktop_body_prefers_the_title() {
+        let mut payload = Payload::new(EventKind::AnswerReady);
+        assert_eq!(payload.body(), "answer_ready");
+        payload.summary = Some("Try the stew".to_string());
+        assert_eq!(payload.body(), "Try the stew");
+        payload.title = Some("Beef Stew".to_string());
+        assert_eq!(payload.body(), "Beef Stew");
+    }
+
+    #[test]
+    fn only_subscribed_events_are_delivered() {
+        let all = Notifier::default();
+        assert!(EventKind::ALL.into_iter().all(|kind| all.wants(kind)));
+        let some = Notifier {
+            events: vec![EventKind::BatchFinished],
+            ..Notifier::default()
+        };
+        assert!(some.wants(EventKind::BatchFinished));
+        assert!(!some.wants(EventKind::RecipeSaved));
+        assert!(!some.shows_desktop(EventKind::BatchFinished));
+    }
+
+    #[test]
+    fn config_defaults_without_a_file() {
+        let path = std::env::temp_dir().join(format!("notify-{}.json", std::process::id()));
+        assert_eq!(NotifyConfig::load(&path).unwrap().after(), None);
+        fs::write(&path, r#"{"after_secs": 20}"#).unwrap();
+        let config = NotifyConfig::load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(config.after(), Some(Duration::from_secs(20)));
+    }
+}
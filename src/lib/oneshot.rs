@@ -0,0 +1,493 @@
+//! A recipe from a single request, without a conversation.
+//!
+//! The shell asks questions, offers two titles, and only then transmits a
+//! recipe.  A script that wants dinner for tonight can't answer questions,
+//! so [generate] sends the constraints in one request with the
+//! `transmit_recipe` tool forced through `tool_choice`, and reads the
+//! recipe straight out of the tool input.  Nothing is sent back to the
+//! model afterwards.
+//!
+//! Some models reject `tool_choice`, and some accept it and answer in text
+//! anyway.  Either way the recipe is taken from the text if it has
+//! recognizable sections, see [crate::parse].
+//!
+//! ```no_run
+//! # #[cfg(feature = "bedrock")]
+//! # async fn f(client: aws_sdk_bedrockruntime::Client) -> Result<(), Box<dyn std::error::Error>> {
+//! use recipes::oneshot::{self, GenerateRequest};
+//!
+//! let request = GenerateRequest::new(oneshot::DEFAULT_MODEL, "vegetarian, under 30 minutes")
+//!     .save_to("recipes");
+//! let recipe = oneshot::generate(client, request).await?;
+//! println!("{}", recipe.recipe_details);
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use aws_sdk_bedrockruntime::error::BuildError;
+use aws_sdk_bedrockruntime::types::{ContentBlock, ToolConfiguration};
+use aws_sdk_bedrockruntime::Client;
+use log::{debug, warn};
+
+#[cfg(feature = "images")]
+use crate::canvas;
+use crate::conversation::{Conversation, ConversationError, ToolChoice, Usage};
+use crate::preferences::Preferences;
+use crate::recipe::Recipe;
+use crate::sink::{ArtifactSink, DirSink};
+use crate::system_prompts::{self, Preset, SystemPrompt};
+use crate::toolinput;
+use crate::tools::{ToolDef, ToolRegistry};
+
+pub const DEFAULT_MODEL: &str = "us.anthropic.claude-3-5-sonnet-20241022-v2:0";
+
+pub const TRANSMIT_TOOL: &str = "transmit_recipe";
+
+/// The tool the model hands the finished recipe to, in the shell as well as
+/// here and in [Conversation::ask]
+pub fn transmission_def() -> ToolDef {
+    let description = "
+    this tool transmits a recipe (ingredients, instructions, and shopping list) and a prompt for an
+    image generation model to produce an appetizing photo of the recipe.  The files are named after
+    the title.  It will return the actual location so that you can respond to the user.
+    ";
+    ToolDef::new(TRANSMIT_TOOL, description)
+        .required(
+            "recipe_details",
+            "The actual recipe, including ingredients, instructions, and shopping list",
+        )
+        .required(
+            "image_prompt",
+            "A prompt suitable for an image generation model to produce an appetizing photo of final dish",
+        )
+        .optional(
+            "title",
+            "The title of the recipe, which the files are named after",
+        )
+        .optional(
+            "rationale",
+            "optional.  why this recipe, in a short list drawn from what the user asked for, \
+             e.g. \"vegetarian, uses the spinach on hand, under 25 minutes\"",
+        )
+        .property(
+            "other_candidates",
+            serde_json::json!({
+                "type": "array",
+                "description": "optional.  only when asked to transmit several candidate recipes at once: \
+                    the others besides this one, each with its own title, recipe_details, and image_prompt",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "title": { "type": "string" },
+                        "recipe_details": { "type": "string" },
+                        "image_prompt": { "type": "string" },
+                        "rationale": { "type": "string" },
+                    },
+                    "required": ["recipe_details", "image_prompt"],
+                },
+            }),
+            false,
+        )
+}
+
+bedrock_pub! {
+    /// [transmission_def] on its own
+    fn transmission_tool() -> Result<ToolConfiguration, BuildError> {
+        ToolRegistry::new().tool(transmission_def()).configuration()
+    }
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct GenerateRequest {
+    pub model: String,
+    /// What the recipe should be, in the user's words, e.g. "a quick
+    /// vegetarian main with what's in season"
+    pub constraints: String,
+    pub preferences: Option<Preferences>,
+    /// Also generate a photo with Nova Canvas (feature `images`)
+    pub with_image: bool,
+    /// Where to save the recipe and its image.  Nothing is saved if unset.
+    pub artifacts: Option<Arc<dyn ArtifactSink>>,
+}
+
+impl GenerateRequest {
+    pub fn new(model: impl Into<String>, constraints: impl Into<String>) -> GenerateRequest {
+        GenerateRequest {
+            model: model.into(),
+            constraints: constraints.into(),
+            preferences: None,
+            with_image: false,
+            artifacts: None,
+        }
+    }
+
+    pub fn preferences(mut self, preferences: Option<Preferences>) -> GenerateRequest {
+        self.preferences = preferences;
+        self
+    }
+
+    pub fn with_image(mut self, with_image: bool) -> GenerateRequest {
+        self.with_image = with_image;
+        self
+    }
+
+    pub fn artifacts(mut self, sink: impl ArtifactSink + 'static) -> GenerateRequest {
+        self.artifacts = Some(Arc::new(sink));
+        self
+    }
+
+    /// Saves the recipe and its image into `dir`, see [DirSink]
+    pub fn save_to(self, dir: impl Into<PathBuf>) -> GenerateRequest {
+        self.artifacts(DirSink::new(dir))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct GeneratedRecipe {
+    pub title: Option<String>,
+    /// Title, ingredients, instructions, and shopping list
+    pub recipe_details: String,
+    /// `None` if the model answered in text instead of using the tool
+    pub image_prompt: Option<String>,
+    /// Base64 PNGs
+    pub images: Vec<String>,
+    /// Whether the recipe came from the tool rather than from the text
+    pub from_tool: bool,
+    /// Where [GenerateRequest::artifacts] saved the recipe, then its images
+    pub saved: Vec<String>,
+    /// Tokens for the request that produced the recipe, if the model said
+    pub usage: Option<Usage>,
+}
+
+impl GeneratedRecipe {
+    fn from_recipe(recipe: Recipe, from_tool: bool) -> GeneratedRecipe {
+        GeneratedRecipe {
+            title: recipe.title,
+            recipe_details: recipe.details,
+            image_prompt: recipe.image_prompt,
+            from_tool,
+            ..GeneratedRecipe::default()
+        }
+    }
+
+    fn recipe(&self) -> Recipe {
+        let mut recipe = Recipe::new(self.recipe_details.clone());
+        recipe.title = self.title.clone();
+        recipe.image_prompt = self.image_prompt.clone();
+        recipe
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OneShotError {
+    Conversation(ConversationError),
+    /// The tool configuration couldn't be built, which is a bug
+    Build(String),
+    /// The model answered, but with nothing that looks like a recipe
+    NoRecipe(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for OneShotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OneShotError::Conversation(e) => write!(f, "{}", e),
+            OneShotError::Build(msg) => write!(f, "couldn't build the request: {}", msg),
+            OneShotError::NoRecipe(why) => write!(f, "no recipe in the response: {}", why),
+            OneShotError::Io(e) => write!(f, "couldn't save the recipe: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OneShotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OneShotError::Conversation(e) => Some(e),
+            OneShotError::Build(_) | OneShotError::NoRecipe(_) => None,
+            OneShotError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<ConversationError> for OneShotError {
+    fn from(e: ConversationError) -> OneShotError {
+        OneShotError::Conversation(e)
+    }
+}
+
+impl From<BuildError> for OneShotError {
+    fn from(e: BuildError) -> OneShotError {
+        OneShotError::Build(e.to_string())
+    }
+}
+
+impl From<io::Error> for OneShotError {
+    fn from(e: io::Error) -> OneShotError {
+        OneShotError::Io(e)
+    }
+}
+
+bedrock_pub! {
+    /// One recipe for `request.constraints`, in a single request (plus one for
+    /// the image)
+    async fn generate(
+        client: Client,
+        request: GenerateRequest,
+    ) -> Result<GeneratedRecipe, OneShotError> {
+        let mut system = SystemPrompt::new(Preset::OneShot);
+        if let Some(preferences) = request.preferences.as_ref().filter(|p| !p.is_empty()) {
+            system.add(system_prompts::preferences(&preferences.render()));
+        }
+        let tools = transmission_tool()?;
+        let input = ContentBlock::Text(request.constraints.clone());
+
+        // forced tool first, then the tool on offer, then no tools at all
+        let attempts = [
+            (
+                Some(tools.clone()),
+                ToolChoice::Specific(TRANSMIT_TOOL.to_string()),
+            ),
+            (Some(tools), ToolChoice::Auto),
+            (None, ToolChoice::Auto),
+        ];
+        let mut rejected = None;
+        let mut turn = None;
+        for (tools, choice) in attempts {
+            let mut conversation =
+                Conversation::builder(client.clone(), &request.model).system(system.blocks());
+            if let Some(tools) = tools {
+                conversation = conversation.tools(tools);
+            }
+            let sent = conversation
+                .build()
+                .send_blocks_choosing(vec![input.clone()], None, choice)
+                .await;
+            match sent {
+                Ok(t) => {
+                    turn = Some(t);
+                    break;
+                }
+                Err(ConversationError::ToolsRejected(msg)) => {
+                    debug!("tools rejected: {}", msg);
+                    rejected = Some(msg);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let Some(turn) = turn else {
+            return Err(ConversationError::ToolsRejected(rejected.unwrap_or_default()).into());
+        };
+
+        let mut recipe = match from_tool_use(&turn.content) {
+            Some(recipe) => recipe,
+            None => {
+                warn!("{} answered without the tool", request.model);
+                from_text(&turn.content)?
+            }
+        };
+        recipe.usage = turn.usage.as_ref().map(|reported| {
+            let mut usage = Usage::default();
+            usage.add(reported);
+            usage
+        });
+
+        if request.with_image {
+            if let Some(prompt) = &recipe.image_prompt {
+                recipe.images = generate_images(&client, prompt).await;
+            }
+        }
+        if let Some(sink) = &request.artifacts {
+            recipe.saved = sink.save(&recipe.recipe(), &recipe.images).await?;
+        }
+        Ok(recipe)
+    }
+}
+
+fn from_tool_use(content: &[ContentBlock]) -> Option<GeneratedRecipe> {
+    let tool_use = content
+        .iter()
+        .filter_map(|c| c.as_tool_use().ok())
+        .find(|t| t.name() == TRANSMIT_TOOL)?;
+    let input = match toolinput::json(tool_use.input()) {
+        Ok(input) => input,
+        Err(e) => {
+            warn!("{} input isn't an object: {}", TRANSMIT_TOOL, e);
+            return None;
+        }
+    };
+    let recipe = Recipe::from_tool_input(&input)?;
+    Some(GeneratedRecipe::from_recipe(recipe, true))
+}
+
+fn from_text(content: &[ContentBlock]) -> Result<GeneratedRecipe, OneShotError> {
+    let text = content
+        .iter()
+        .filter_map(|c| c.as_text().ok())
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    match Recipe::from_text(&text) {
+        Some(recipe) => Ok(GeneratedRecipe::from_recipe(recipe, false)),
+        None => {
+            let start: String = text.chars().take(80).collect();
+            Err(OneShotError::NoRecipe(format!("{:?}", start)))
+        }
+    }
+}
+
+#[cfg(feature = "images")]
+async fn generate_images(client: &Client, prompt: &str) -> Vec<String> {
+    match canvas::text_to_image(client, prompt, canvas::random_seed()).await {
+        Ok(generated) if generated.images.is_empty() => {
+            warn!(
+                "image blocked by content filter (request id {})",
+                generated.request_id.as_deref().unwrap_or("none")
+            );
+            vec![]
+        }
+        Ok(generated) => generated.images,
+        Err(e) => {
+            warn!("image generation failed: {}", e);
+            vec![]
+        }
+    }
+}
+
+#[cfg(not(feature = "images"))]
+async fn generate_images(_client: &Client, _prompt: &str) -> Vec<String> {
+    warn!("built without image support (feature images)");
+    vec![]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::scripted::{Reply, Scripted};
+    use crate::sink::MemorySink;
+    use crate::toolspec;
+
+    const RECIPE: &str = "Rice and Beans\n\nIngredients:\n- 1 cup rice\n- 1 can black beans\n\n\
+                          Instructions:\n1. Cook the rice.\n2. Warm the beans.\n\n\
+                          Shopping list:\n- rice\n- black beans";
+
+    fn tools_rejected() -> Reply {
+        Reply::error(
+            400,
+            "ValidationException",
+            "This model doesn't support tool use.",
+        )
+    }
+
+    #[test]
+    fn transmission_tool_snapshot() {
+        let tools = transmission_tool().unwrap();
+        assert!(
+            toolspec::validate(&tools).is_empty(),
+            "{:?}",
+            toolspec::validate(&tools)
+        );
+        let snapshot = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/lib/testdata/tools/transmit_recipe.json");
+        if let Err(e) = toolspec::check_snapshot(&tools, &snapshot) {
+            panic!("{}", e);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_recipe_comes_from_the_forced_tool() {
+        let backend = Scripted::new([Reply::converse(
+            json!({"role": "assistant", "content": [{"toolUse": {
+                "toolUseId": "t1",
+                "name": TRANSMIT_TOOL,
+                "input": {
+                    "title": "Rice and Beans",
+                    "recipe_details": RECIPE,
+                    "image_prompt": "a bowl of rice and black beans",
+                },
+            }}]}),
+            "tool_use",
+        )]);
+        let sink = Arc::new(MemorySink::new());
+        let recipe = generate(
+            backend.client(),
+            GenerateRequest::new(DEFAULT_MODEL, "vegetarian, under 30 minutes")
+                .artifacts(sink.clone()),
+        )
+        .await
+        .unwrap();
+
+        assert!(recipe.from_tool);
+        assert_eq!(recipe.title.as_deref(), Some("Rice and Beans"));
+        assert_eq!(
+            recipe.image_prompt.as_deref(),
+            Some("a bowl of rice and black beans")
+        );
+        assert_eq!(recipe.usage.map(|u| u.input_tokens), Some(100));
+        assert_eq!(sink.recipes().len(), 1);
+        assert_eq!(recipe.saved.len(), 1);
+
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].body["toolConfig"]["toolChoice"],
+            json!({"tool": {"name": TRANSMIT_TOOL}})
+        );
+    }
+
+    #[tokio::test]
+    async fn a_text_answer_falls_back_to_parsing() {
+        let backend = Scripted::new([Reply::text(RECIPE), Reply::text("Sorry, I can't help.")]);
+        let recipe = generate(
+            backend.client(),
+            GenerateRequest::new(DEFAULT_MODEL, "vegetarian"),
+        )
+        .await
+        .unwrap();
+        assert!(!recipe.from_tool);
+        assert_eq!(recipe.image_prompt, None);
+        assert!(recipe.recipe_details.contains("black beans"));
+        assert_eq!(backend.requests().len(), 1);
+
+        let err = generate(
+            backend.client(),
+            GenerateRequest::new(DEFAULT_MODEL, "vegetarian"),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, OneShotError::NoRecipe(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn rejected_tools_fall_through_every_attempt() {
+        let backend = Scripted::new([tools_rejected(), tools_rejected(), Reply::text(RECIPE)]);
+        let recipe = generate(
+            backend.client(),
+            GenerateRequest::new(DEFAULT_MODEL, "vegetarian"),
+        )
+        .await
+        .unwrap();
+        assert!(!recipe.from_tool);
+        assert!(recipe.recipe_details.contains("black beans"));
+
+        let bodies: Vec<_> = backend.requests().into_iter().map(|r| r.body).collect();
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(
+            bodies[0]["toolConfig"]["toolChoice"],
+            json!({"tool": {"name": TRANSMIT_TOOL}})
+        );
+        assert!(bodies[1]["toolConfig"]["tools"].is_array());
+        assert!(bodies[1]["toolConfig"].get("toolChoice").is_none());
+        assert!(bodies[2].get("toolConfig").is_none());
+    }
+}
@@ -0,0 +1,222 @@
+//! Client-side request pacing, so that bursts of turns wait for the
+//! per-minute quotas instead of being throttled by Bedrock.
+//!
+//! A [Pacer] is cheap to share: wrap it in an `Arc` and hand the same one to
+//! every [crate::conversation::Conversation] that draws on the same quota.
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+/// A token bucket that refills continuously up to its capacity.  The level
+/// may go negative when actual usage is only known after the fact; it then
+/// has to refill back to zero before anything else is let through.
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    capacity: f64,
+    level: f64,
+    per_sec: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    /// A full bucket allowing `per_minute` units per minute
+    pub fn per_minute(per_minute: u32, now: Instant) -> Bucket {
+        let capacity = f64::from(per_minute.max(1));
+        Bucket {
+            capacity,
+            level: capacity,
+            per_sec: capacity / 60.0,
+            last: now,
+        }
+    }
+
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    pub fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.level = (self.level + elapsed * self.per_sec).min(self.capacity);
+        self.last = now;
+    }
+
+    /// How long until `amount` is available (zero if it already is)
+    pub fn delay_for(&mut self, amount: f64, now: Instant) -> Duration {
+        self.refill(now);
+        let missing = amount.min(self.capacity) - self.level;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.per_sec)
+        }
+    }
+
+    pub fn take(&mut self, amount: f64, now: Instant) {
+        self.refill(now);
+        self.level -= amount;
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+#[derive(Debug)]
+pub struct Pacer {
+    buckets: Mutex<Buckets>,
+}
+
+impl Pacer {
+    /// Limits that are `None` aren't enforced
+    pub fn new(requests_per_minute: Option<u32>, tokens_per_minute: Option<u32>) -> Pacer {
+        let now = Instant::now();
+        Pacer {
+            buckets: Mutex::new(Buckets {
+                requests: requests_per_minute.map(|n| Bucket::per_minute(n, now)),
+                tokens: tokens_per_minute.map(|n| Bucket::per_minute(n, now)),
+            }),
+        }
+    }
+
+    /// How long the next request has to wait, taking its slot if it doesn't
+    pub fn try_acquire(&self, now: Instant) -> Duration {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let Buckets { requests, tokens } = &mut *buckets;
+        // token usage is charged afterwards, so only wait for the debt to clear
+        let delay = [
+            requests.as_mut().map(|b| b.delay_for(1.0, now)),
+            tokens.as_mut().map(|b| b.delay_for(0.0, now)),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or(Duration::ZERO);
+        if delay.is_zero() {
+            if let Some(b) = requests.as_mut() {
+                b.take(1.0, now);
+            }
+        }
+        delay
+    }
+
+    /// Waits until a request is allowed
+    pub async fn acquire(&self) {
+        loop {
+            let delay = self.try_acquire(Instant::now());
+            if delay.is_zero() {
+                return;
+            }
+            warn!(
+                "pacing: waiting {}s to respect rate limits",
+                delay.as_secs_f64().ceil()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Charges the tokens a request actually used
+    pub fn record_tokens(&self, used: u32, now: Instant) {
+        if let Some(b) = self
+            .buckets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .tokens
+            .as_mut()
+        {
+            b.take(f64::from(used), now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(delay: Duration) -> f64 {
+        delay.as_secs_f64()
+    }
+
+    #[test]
+    fn buckets_refill_with_the_clock() {
+        let start = Instant::now();
+        let mut bucket = Bucket::per_minute(60, start);
+        assert_eq!(bucket.delay_for(60.0, start), Duration::ZERO);
+        bucket.take(60.0, start);
+        assert_eq!(bucket.delay_for(1.0, start), Duration::from_secs(1));
+        assert_eq!(
+            bucket.delay_for(1.0, start + Duration::from_millis(500)),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            bucket.delay_for(1.0, start + Duration::from_secs(1)),
+            Duration::ZERO
+        );
+
+        // never past capacity, and a clock going backwards changes nothing
+        bucket.refill(start + Duration::from_secs(600));
+        assert_eq!(bucket.level(), 60.0);
+        bucket.refill(start);
+        assert_eq!(bucket.level(), 60.0);
+        // more than fits only waits for a full bucket
+        assert_eq!(bucket.delay_for(100.0, start), Duration::ZERO);
+    }
+
+    #[test]
+    fn debt_has_to_clear() {
+        let start = Instant::now();
+        let mut bucket = Bucket::per_minute(60, start);
+        bucket.take(90.0, start);
+        assert_eq!(bucket.level(), -30.0);
+        assert_eq!(bucket.delay_for(0.0, start), Duration::from_secs(30));
+        assert_eq!(
+            bucket.delay_for(0.0, start + Duration::from_secs(30)),
+            Duration::ZERO
+        );
+        assert_eq!(Bucket::per_minute(0, start).level(), 1.0);
+    }
+
+    #[test]
+    fn requests_per_minute() {
+        let pacer = Pacer::new(Some(2), None);
+        let start = Instant::now();
+        assert_eq!(pacer.try_acquire(start), Duration::ZERO);
+        assert_eq!(pacer.try_acquire(start), Duration::ZERO);
+        // one request every 30 seconds from here on
+        let delay = secs(pacer.try_acquire(start));
+        assert!(delay > 29.0 && delay <= 30.0, "{}", delay);
+        // waiting didn't take the slot
+        let delay = secs(pacer.try_acquire(start + Duration::from_secs(15)));
+        assert!(delay > 14.0 && delay <= 15.0, "{}", delay);
+        let later = start + Duration::from_secs(30);
+        assert_eq!(pacer.try_acquire(later), Duration::ZERO);
+        assert!(!pacer.try_acquire(later).is_zero());
+    }
+
+    #[test]
+    fn tokens_are_charged_afterwards() {
+        let pacer = Pacer::new(None, Some(600));
+        let start = Instant::now();
+        assert_eq!(pacer.try_acquire(start), Duration::ZERO);
+        pacer.record_tokens(1200, start);
+        // 600 tokens of debt at 10 a second
+        let delay = secs(pacer.try_acquire(start));
+        assert!(delay > 59.0 && delay <= 60.0, "{}", delay);
+        assert_eq!(
+            pacer.try_acquire(start + Duration::from_secs(60)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn no_limits() {
+        let pacer = Pacer::new(None, None);
+        let now = Instant::now();
+        pacer.record_tokens(u32::MAX, now);
+        for _ in 0..100 {
+            assert_eq!(pacer.try_acquire(now), Duration::ZERO);
+        }
+    }
+}
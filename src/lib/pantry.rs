@@ -0,0 +1,288 @@
+//! What's already in the kitchen, so the shopping list can say what still
+//! has to be bought.
+//!
+//! The pantry is a text file, one item per line with an optional quantity:
+//! `6 eggs`, `500 g rice`, `olive oil`.  An item without a quantity is a
+//! staple, which covers any amount and is never used up.  Cooking a recipe
+//! takes what its shopping list needs from the items with quantities, and
+//! drops those that run out.
+//!
+//! Matching errs towards buying.  A shopping line is only covered by a
+//! pantry item for the same ingredient, compared with [Line::ingredient],
+//! and when both have quantities the pantry must have at least as much in
+//! units that mix.  So "garlic" covers "3 garlic cloves" but never "garlic
+//! powder", and "2 eggs" doesn't cover a line that just says "eggs".
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::paths;
+use crate::units::Line;
+
+/// In the state directory
+pub const FILE_NAME: &str = "pantry.txt";
+
+#[derive(Debug, Clone, Default)]
+pub struct Pantry {
+    items: Vec<Line>,
+}
+
+/// A line of a shopping list, checked against the pantry
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListItem {
+    /// An aisle, e.g. "Produce:"
+    Heading(String),
+    Item {
+        text: String,
+        covered: bool,
+    },
+}
+
+impl Pantry {
+    /// Blank lines and lines starting with `#` are skipped.  A missing file
+    /// is an empty pantry.
+    pub fn load(path: &Path) -> io::Result<Pantry> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Pantry::default()),
+            Err(e) => return Err(e),
+        };
+        let mut pantry = Pantry::default();
+        for line in contents.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                pantry.add(line);
+            }
+        }
+        Ok(pantry)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let lines: Vec<String> = self.items.iter().map(|i| format!("{}\n", i)).collect();
+        paths::write_atomic(path, lines.concat())
+    }
+
+    pub fn items(&self) -> &[Line] {
+        &self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn find(&self, line: &Line) -> Option<usize> {
+        let ingredient = line.ingredient();
+        self.items
+            .iter()
+            .position(|item| item.ingredient() == ingredient)
+    }
+
+    /// Adds an item, adding to the quantity of the same ingredient where the
+    /// units mix, and otherwise replacing it
+    pub fn add(&mut self, text: &str) {
+        let line = Line::parse(text);
+        match self.find(&line) {
+            Some(idx) => {
+                let existing = &mut self.items[idx];
+                match (&existing.quantity, &line.quantity) {
+                    (Some(a), Some(b)) if a.add(b).is_some() => existing.quantity = a.add(b),
+                    _ => *existing = line,
+                }
+            }
+            None => self.items.push(line),
+        }
+    }
+
+    /// Removes the item for the same ingredient as `name`, returning it
+    pub fn remove(&mut self, name: &str) -> Option<Line> {
+        let idx = self.find(&Line::parse(name))?;
+        Some(self.items.remove(idx))
+    }
+
+    /// Whether the pantry has enough for one shopping list line
+    pub fn covers(&self, text: &str) -> bool {
+        let line = Line::parse(text);
+        let Some(idx) = self.find(&line) else {
+            return false;
+        };
+        match (&self.items[idx].quantity, &line.quantity) {
+            // a staple
+            (None, _) => true,
+            // no telling how much is needed
+            (Some(_), None) => false,
+            (Some(have), Some(needed)) => have.covers(needed).unwrap_or(false),
+        }
+    }
+
+    /// Each line of a shopping list section, with whether it's covered
+    pub fn check(&self, list: &str) -> Vec<ListItem> {
+        list.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let item = line.trim_start_matches(['-', '*', '•']).trim();
+                if item == line && line.ends_with(':') {
+                    ListItem::Heading(line.to_string())
+                } else {
+                    ListItem::Item {
+                        text: item.to_string(),
+                        covered: self.covers(item),
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Takes what a shopping list needs from the items with quantities,
+    /// dropping any that run out.  Returns what changed, e.g. "eggs: 6 → 4".
+    pub fn use_up(&mut self, list: &str) -> Vec<String> {
+        let mut changes = vec![];
+        for item in self.check(list) {
+            let ListItem::Item { text, .. } = item else {
+                continue;
+            };
+            let needed = Line::parse(&text);
+            let (Some(idx), Some(used)) = (self.find(&needed), needed.quantity) else {
+                continue;
+            };
+            let Some(have) = self.items[idx].quantity else {
+                continue;
+            };
+            let Some(left) = have.sub(&used) else {
+                continue;
+            };
+            let name = self.items[idx].item.clone();
+            if left.is_zero() {
+                self.items.remove(idx);
+                changes.push(format!("{}: used up", name));
+            } else {
+                self.items[idx].quantity = Some(left);
+                changes.push(format!("{}: {} → {}", name, have, left));
+            }
+        }
+        changes
+    }
+}
+
+/// How many items of a checked list still have to be bought
+pub fn to_buy(items: &[ListItem]) -> usize {
+    items
+        .iter()
+        .filter(|i| matches!(i, ListItem::Item { covered: false, .. }))
+        .count()
+}
+
+/// A checked list as markdown.  With `all`, covered items are kept and
+/// marked ✓; otherwise they're left out, along with headings that have
+/// nothing left under them.
+pub fn render(items: &[ListItem], all: bool) -> String {
+    let mut sections: Vec<(Option<&str>, Vec<String>)> = vec![];
+    let mut current: (Option<&str>, Vec<String>) = (None, vec![]);
+    for item in items {
+        match item {
+            ListItem::Heading(heading) => {
+                sections.push(std::mem::replace(&mut current, (Some(heading), vec![])))
+            }
+            ListItem::Item { text, covered } => match (covered, all) {
+                (true, true) => current.1.push(format!("- ✓ {}", text)),
+                (true, false) => {}
+                (false, _) => current.1.push(format!("- {}", text)),
+            },
+        }
+    }
+    sections.push(current);
+    sections
+        .into_iter()
+        .filter(|(_, lines)| !lines.is_empty())
+        .map(|(heading, lines)| match heading {
+            Some(heading) => format!("{}\n{}", heading, lines.join("\n")),
+            None => lines.join("\n"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pantry(items: &[&str]) -> Pantry {
+        let mut pantry = Pantry::default();
+        for item in items {
+            pantry.add(item);
+        }
+        pantry
+    }
+
+    const LIST: &str = "- olive oil\nProduce:\n- 2 onions\n- garlic\nDairy:\n- 3 eggs\n";
+
+    #[test]
+    fn covers_only_the_same_ingredient_in_amounts_that_mix() {
+        let pantry = pantry(&["garlic", "2 eggs", "2 tomatoes", "500 g rice", "1 l milk"]);
+        for (line, covered) in [
+            ("garlic", true),
+            ("3 garlic cloves", true),
+            ("garlic powder", false),
+            ("1 tsp garlic powder", false),
+            // no telling how many
+            ("eggs", false),
+            ("1 egg", true),
+            ("2 eggs", true),
+            ("3 eggs", false),
+            ("1 tomato", true),
+            ("200 g rice", true),
+            ("2 cups rice", false),
+            ("2 cups milk", true),
+            ("2 lb milk", false),
+            ("flour", false),
+        ] {
+            assert_eq!(pantry.covers(line), covered, "{}", line);
+        }
+    }
+
+    #[test]
+    fn use_up_takes_quantities_and_leaves_staples() {
+        let mut pantry = pantry(&["olive oil", "garlic", "6 eggs", "2 tomatoes", "500 g rice"]);
+        let changes = pantry.use_up(
+            "- olive oil\n- 3 garlic cloves\nProduce:\n- 1 tomato\n- 6 eggs\n- 200 g rice\n- 2 cups flour",
+        );
+        assert_eq!(
+            changes,
+            ["tomatoes: 2 → 1", "eggs: used up", "rice: 500 g → 300 g"]
+        );
+        assert_eq!(pantry.items().len(), 4);
+        for (line, covered) in [
+            ("olive oil", true),
+            ("garlic", true),
+            ("1 egg", false),
+            ("1 tomato", true),
+            ("2 tomatoes", false),
+            ("300 g rice", true),
+            ("400 g rice", false),
+        ] {
+            assert_eq!(pantry.covers(line), covered, "{}", line);
+        }
+    }
+
+    #[test]
+    fn render_drops_covered_items_and_their_empty_headings() {
+        let items = pantry(&["olive oil", "garlic", "6 eggs"]).check(LIST);
+        assert_eq!(to_buy(&items), 1);
+        assert_eq!(render(&items, false), "Produce:\n- 2 onions");
+    }
+
+    #[test]
+    fn render_all_marks_covered_items() {
+        let items = pantry(&["olive oil", "6 eggs"]).check(LIST);
+        assert_eq!(
+            render(&items, true),
+            "- ✓ olive oil\n\nProduce:\n- 2 onions\n- garlic\n\nDairy:\n- ✓ 3 eggs"
+        );
+    }
+
+    #[test]
+    fn render_without_headings() {
+        let items = Pantry::default().check("- salt\n- pepper");
+        assert_eq!(render(&items, false), "- salt\n- pepper");
+        assert_eq!(render(&[], true), "");
+    }
+}
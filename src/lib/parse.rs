@@ -0,0 +1,555 @@
+//! Finding the sections of a recipe in free-form model output.
+//!
+//! Sections are found by their headings (`Ingredients`, `## Instructions`,
+//! `**Shopping List:**`, ...) in any order.  If there are no headings at all we
+//! fall back to the blank-line separated layout the system prompt asks for:
+//! title, ingredients, instructions, shopping list.
+use std::ops::Range;
+
+use crate::recipe::Section;
+
+/// Byte ranges of each recipe section within the original text
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedRecipe<'a> {
+    text: &'a str,
+    pub title: Option<Range<usize>>,
+    pub ingredients: Option<Range<usize>>,
+    pub instructions: Option<Range<usize>>,
+    pub shopping_list: Option<Range<usize>>,
+    pub notes: Option<Range<usize>>,
+    pub sources: Option<Range<usize>>,
+}
+
+impl<'a> ParsedRecipe<'a> {
+    pub fn title(&self) -> Option<&'a str> {
+        self.title.clone().map(|span| clean_title(&self.text[span]))
+    }
+
+    /// The body of a section, without its heading
+    pub fn get(&self, section: Section) -> Option<&'a str> {
+        self.span(section).map(|span| &self.text[span])
+    }
+
+    pub fn span(&self, section: Section) -> Option<Range<usize>> {
+        match section {
+            Section::Ingredients => self.ingredients.clone(),
+            Section::Instructions => self.instructions.clone(),
+            Section::ShoppingList => self.shopping_list.clone(),
+            Section::Notes => self.notes.clone(),
+            Section::Sources => self.sources.clone(),
+        }
+    }
+
+    fn span_mut(&mut self, section: Section) -> &mut Option<Range<usize>> {
+        match section {
+            Section::Ingredients => &mut self.ingredients,
+            Section::Instructions => &mut self.instructions,
+            Section::ShoppingList => &mut self.shopping_list,
+            Section::Notes => &mut self.notes,
+            Section::Sources => &mut self.sources,
+        }
+    }
+}
+
+/// Splits a recipe into its sections
+pub fn sections(text: &str) -> ParsedRecipe<'_> {
+    let mut parsed = ParsedRecipe {
+        text,
+        ..ParsedRecipe::default()
+    };
+
+    // (line start, line end, heading) for every line
+    let mut lines = vec![];
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let end = offset + line.len();
+        lines.push((offset, end, Section::from_heading(line)));
+        offset = end;
+    }
+
+    let headings: Vec<(usize, Section)> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, (_, _, heading))| heading.map(|h| (idx, h)))
+        .collect();
+
+    if headings.is_empty() {
+        return by_paragraphs(parsed);
+    }
+
+    for (n, (idx, section)) in headings.iter().enumerate() {
+        let start = lines[*idx].1;
+        let end = headings
+            .get(n + 1)
+            .map_or(text.len(), |(next, _)| lines[*next].0);
+        let slot = parsed.span_mut(*section);
+        if slot.is_none() {
+            *slot = trim_span(text, start..end);
+        }
+    }
+
+    // the title is the first thing before the first heading
+    let first_heading = lines[headings[0].0].0;
+    parsed.title = first_line_span(text, 0..first_heading);
+    parsed
+}
+
+fn by_paragraphs(mut parsed: ParsedRecipe<'_>) -> ParsedRecipe<'_> {
+    let text = parsed.text;
+    let mut paragraphs = vec![];
+    let mut start = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        match (blank, start) {
+            (false, None) => start = Some(offset),
+            (true, Some(s)) => {
+                paragraphs.push(s..offset);
+                start = None;
+            }
+            _ => (),
+        }
+        offset += line.len();
+    }
+    if let Some(s) = start {
+        paragraphs.push(s..text.len());
+    }
+
+    parsed.title = paragraphs
+        .first()
+        .and_then(|p| first_line_span(text, p.clone()));
+    if paragraphs.len() >= 4 {
+        let last = paragraphs.len() - 1;
+        parsed.ingredients = trim_span(text, paragraphs[1].clone());
+        parsed.instructions = trim_span(text, paragraphs[2].start..paragraphs[last - 1].end);
+        parsed.shopping_list = trim_span(text, paragraphs[last].clone());
+    }
+    parsed
+}
+
+fn trim_span(text: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let slice = &text[span.clone()];
+    let trimmed = slice.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let start = span.start + (slice.len() - slice.trim_start().len());
+    Some(start..start + trimmed.len())
+}
+
+fn first_line_span(text: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let mut offset = span.start;
+    for line in text[span].split_inclusive('\n') {
+        if let Some(found) = trim_span(text, offset..offset + line.len()) {
+            return Some(found);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn clean_title(line: &str) -> &str {
+    let line = line.trim().trim_start_matches('#').trim_matches('*').trim();
+    // Nova bolds just the label, as in `**Title:** Lentil Soup`
+    line.strip_prefix("Title:")
+        .unwrap_or(line)
+        .trim_matches(|c: char| c == '*' || c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Section; 5] = [
+        Section::Ingredients,
+        Section::Instructions,
+        Section::ShoppingList,
+        Section::Notes,
+        Section::Sources,
+    ];
+
+    /// A section with its first and last lines
+    type Expected = (Section, &'static str, &'static str);
+
+    /// Recipes as Claude and Nova models have sent them, with the title and
+    /// the first and last lines of each section we expect to find
+    const CORPUS: &[(&str, &str, Option<&str>, &[Expected])] = &[
+        (
+            "claude, plain headings",
+            "Lemon Garlic Chicken
+
+Ingredients:
+- 4 chicken thighs
+- 1 lemon
+- 3 cloves garlic
+
+Instructions:
+1. Zest and juice the lemon.
+2. Roast for 35 minutes.
+
+Shopping List:
+- chicken thighs
+- lemon
+",
+            Some("Lemon Garlic Chicken"),
+            &[
+                (
+                    Section::Ingredients,
+                    "- 4 chicken thighs",
+                    "- 3 cloves garlic",
+                ),
+                (
+                    Section::Instructions,
+                    "1. Zest and juice the lemon.",
+                    "2. Roast for 35 minutes.",
+                ),
+                (Section::ShoppingList, "- chicken thighs", "- lemon"),
+            ],
+        ),
+        (
+            "claude, markdown headings with notes",
+            "# Mushroom Risotto
+
+## Ingredients
+- 1 1/2 cups arborio rice
+- 8 oz cremini mushrooms
+
+## Instructions
+1. Warm the stock.
+2. Toast the rice, then add stock a ladle at a time.
+
+## Shopping List
+- arborio rice
+
+## Notes
+Keep the stock at a simmer.",
+            Some("Mushroom Risotto"),
+            &[
+                (
+                    Section::Ingredients,
+                    "- 1 1/2 cups arborio rice",
+                    "- 8 oz cremini mushrooms",
+                ),
+                (
+                    Section::Instructions,
+                    "1. Warm the stock.",
+                    "2. Toast the rice, then add stock a ladle at a time.",
+                ),
+                (Section::ShoppingList, "- arborio rice", "- arborio rice"),
+                (
+                    Section::Notes,
+                    "Keep the stock at a simmer.",
+                    "Keep the stock at a simmer.",
+                ),
+            ],
+        ),
+        (
+            "claude, bold headings and sub-headings",
+            "**Black Bean Tacos**
+
+**Ingredients:**
+*For the filling:*
+- 2 cans black beans
+*For the slaw:*
+- 1/4 red cabbage
+
+**Instructions:**
+1. Warm the beans with cumin.
+2. Toss the cabbage with lime.
+
+**Shopping List:**
+- red cabbage
+",
+            Some("Black Bean Tacos"),
+            &[
+                (
+                    Section::Ingredients,
+                    "*For the filling:*",
+                    "- 1/4 red cabbage",
+                ),
+                (
+                    Section::Instructions,
+                    "1. Warm the beans with cumin.",
+                    "2. Toss the cabbage with lime.",
+                ),
+                (Section::ShoppingList, "- red cabbage", "- red cabbage"),
+            ],
+        ),
+        (
+            "claude, serving size and method",
+            "Sheet Pan Gnocchi
+
+Ingredients (serves 4):
+- 1 lb shelf-stable gnocchi
+- 1 pint cherry tomatoes
+
+Method:
+Toss everything with olive oil.
+
+Roast at 425°F for 20 minutes.
+
+Grocery List:
+- gnocchi
+- cherry tomatoes",
+            Some("Sheet Pan Gnocchi"),
+            &[
+                (
+                    Section::Ingredients,
+                    "- 1 lb shelf-stable gnocchi",
+                    "- 1 pint cherry tomatoes",
+                ),
+                (
+                    Section::Instructions,
+                    "Toss everything with olive oil.",
+                    "Roast at 425°F for 20 minutes.",
+                ),
+                (Section::ShoppingList, "- gnocchi", "- cherry tomatoes"),
+            ],
+        ),
+        (
+            "claude, shopping list first",
+            "Overnight Oats
+
+Shopping List:
+- rolled oats
+- chia seeds
+
+Ingredients:
+- 1/2 cup rolled oats
+- 1 tbsp chia seeds
+
+Directions:
+- Stir everything together.
+- Refrigerate overnight.",
+            Some("Overnight Oats"),
+            &[
+                (
+                    Section::Ingredients,
+                    "- 1/2 cup rolled oats",
+                    "- 1 tbsp chia seeds",
+                ),
+                (
+                    Section::Instructions,
+                    "- Stir everything together.",
+                    "- Refrigerate overnight.",
+                ),
+                (Section::ShoppingList, "- rolled oats", "- chia seeds"),
+            ],
+        ),
+        (
+            "claude, cited sources",
+            "## Shakshuka
+
+### Ingredients
+- 6 eggs
+- 1 can crushed tomatoes
+
+### Instructions
+1. Simmer the tomatoes with spices for 10 minutes.
+2. Crack in the eggs and cover.
+
+### Sources
+- [1] Shakshuka, Serious Eats",
+            Some("Shakshuka"),
+            &[
+                (Section::Ingredients, "- 6 eggs", "- 1 can crushed tomatoes"),
+                (
+                    Section::Instructions,
+                    "1. Simmer the tomatoes with spices for 10 minutes.",
+                    "2. Crack in the eggs and cover.",
+                ),
+                (
+                    Section::Sources,
+                    "- [1] Shakshuka, Serious Eats",
+                    "- [1] Shakshuka, Serious Eats",
+                ),
+            ],
+        ),
+        (
+            "nova, bold title label",
+            "**Title:** Vegetable Stir-Fry
+
+**Ingredients:**
+1. 2 cups broccoli florets
+2. 1 red bell pepper
+
+**Instructions:**
+1. **Prep:** Slice the vegetables.
+2. **Cook:** Stir-fry over high heat for 5 minutes.
+
+**Shopping List:**
+- broccoli
+- red bell pepper",
+            Some("Vegetable Stir-Fry"),
+            &[
+                (
+                    Section::Ingredients,
+                    "1. 2 cups broccoli florets",
+                    "2. 1 red bell pepper",
+                ),
+                (
+                    Section::Instructions,
+                    "1. **Prep:** Slice the vegetables.",
+                    "2. **Cook:** Stir-fry over high heat for 5 minutes.",
+                ),
+                (Section::ShoppingList, "- broccoli", "- red bell pepper"),
+            ],
+        ),
+        (
+            "nova, plain title label",
+            "Title: Spiced Lentil Soup
+
+Ingredients:
+- 1 cup red lentils
+- 1 tsp cumin
+
+Steps:
+Step 1: Sweat the onion.
+Step 2: Add lentils and simmer for 25 minutes.
+
+Dietary Notes:
+Vegan and gluten-free.",
+            Some("Spiced Lentil Soup"),
+            &[
+                (Section::Ingredients, "- 1 cup red lentils", "- 1 tsp cumin"),
+                (
+                    Section::Instructions,
+                    "Step 1: Sweat the onion.",
+                    "Step 2: Add lentils and simmer for 25 minutes.",
+                ),
+                (
+                    Section::Notes,
+                    "Vegan and gluten-free.",
+                    "Vegan and gluten-free.",
+                ),
+            ],
+        ),
+        (
+            "nova, markdown headings with colons",
+            "### Chickpea Curry
+
+#### Ingredients:
+- 2 cans chickpeas
+- 1 can coconut milk
+
+#### Preparation:
+1. Fry the spices.
+2. Simmer 15 mins.
+
+#### Shopping List:
+- coconut milk",
+            Some("Chickpea Curry"),
+            &[
+                (
+                    Section::Ingredients,
+                    "- 2 cans chickpeas",
+                    "- 1 can coconut milk",
+                ),
+                (
+                    Section::Instructions,
+                    "1. Fry the spices.",
+                    "2. Simmer 15 mins.",
+                ),
+                (Section::ShoppingList, "- coconut milk", "- coconut milk"),
+            ],
+        ),
+        (
+            "claude, in spanish for --language",
+            "Arroz con Pollo
+
+Ingredientes:
+- 2 tazas de arroz
+- 4 muslos de pollo
+
+Preparación:
+1. Dorar el pollo.
+2. Cocer el arroz 20 minutos.
+
+Lista de la compra:
+- pollo",
+            Some("Arroz con Pollo"),
+            &[
+                (
+                    Section::Ingredients,
+                    "- 2 tazas de arroz",
+                    "- 4 muslos de pollo",
+                ),
+                (
+                    Section::Instructions,
+                    "1. Dorar el pollo.",
+                    "2. Cocer el arroz 20 minutos.",
+                ),
+                (Section::ShoppingList, "- pollo", "- pollo"),
+            ],
+        ),
+        (
+            "no headings, as the system prompt asks",
+            "Pasta Primavera
+
+- 8 oz penne
+- 1 zucchini
+
+1. Boil the pasta.
+2. Saute the zucchini.
+
+3. Toss together.
+
+- penne
+- zucchini
+",
+            Some("Pasta Primavera"),
+            &[
+                (Section::Ingredients, "- 8 oz penne", "- 1 zucchini"),
+                (
+                    Section::Instructions,
+                    "1. Boil the pasta.",
+                    "3. Toss together.",
+                ),
+                (Section::ShoppingList, "- penne", "- zucchini"),
+            ],
+        ),
+        (
+            "no headings, too short to split",
+            "Cinnamon Toast
+
+Butter the toast and sprinkle with cinnamon sugar.",
+            Some("Cinnamon Toast"),
+            &[],
+        ),
+        (
+            "a question rather than a recipe",
+            "What's in your pantry? I can suggest something with what you have.",
+            Some("What's in your pantry? I can suggest something with what you have."),
+            &[],
+        ),
+    ];
+
+    #[test]
+    fn corpus() {
+        for (name, text, title, expected) in CORPUS {
+            let parsed = sections(text);
+            assert_eq!(parsed.title(), *title, "{}", name);
+            for section in ALL {
+                let body = parsed.get(section);
+                match expected.iter().find(|(s, _, _)| *s == section) {
+                    Some((_, first, last)) => {
+                        let body = body.unwrap_or_else(|| panic!("{}: no {:?}", name, section));
+                        assert_eq!(body.lines().next(), Some(*first), "{}: {:?}", name, section);
+                        assert_eq!(body.lines().last(), Some(*last), "{}: {:?}", name, section);
+                    }
+                    None => assert_eq!(body, None, "{}: {:?}", name, section),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spans_point_into_the_text() {
+        let text = CORPUS[0].1;
+        let parsed = sections(text);
+        let span = parsed.span(Section::Instructions).unwrap();
+        assert!(text[..span.start].ends_with("Instructions:\n"));
+        assert_eq!(&text[span], parsed.get(Section::Instructions).unwrap());
+        assert_eq!(sections(""), ParsedRecipe::default());
+    }
+}
@@ -0,0 +1,264 @@
+//! Where gourmand keeps the files it persists between runs.
+//!
+//! Config, state, and cache directories are resolved per platform:
+//!
+//! * Linux/BSD: `$XDG_CONFIG_HOME`, `$XDG_STATE_HOME`, `$XDG_CACHE_HOME`
+//!   (falling back to `~/.config`, `~/.local/state`, `~/.cache`)
+//! * macOS: `~/Library/Application Support` and `~/Library/Caches`
+//! * Windows: `%APPDATA%` and `%LOCALAPPDATA%`
+//!
+//! Each of those gets a `gourmand` subdirectory.  A `--state-dir` override
+//! collapses all three under a single root, which is handy for tests and for
+//! running several independent households on one machine.
+//!
+//! Anything that persists data should ask this module for a path rather than
+//! inventing its own.
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use base64::Engine;
+
+const APP_DIR: &str = "gourmand";
+
+#[derive(Debug, Clone)]
+pub struct Paths {
+    config: PathBuf,
+    state: PathBuf,
+    cache: PathBuf,
+}
+
+impl Paths {
+    /// Resolves the directories, honoring `state_dir` as an override for all of them.
+    pub fn resolve(state_dir: Option<&Path>) -> Paths {
+        match state_dir {
+            Some(root) => Paths {
+                config: root.join("config"),
+                state: root.join("state"),
+                cache: root.join("cache"),
+            },
+            None => Paths {
+                config: platform_config_dir().join(APP_DIR),
+                state: platform_state_dir().join(APP_DIR),
+                cache: platform_cache_dir().join(APP_DIR),
+            },
+        }
+    }
+
+    /// User-editable configuration
+    pub fn config_dir(&self) -> &Path {
+        &self.config
+    }
+
+    /// Data we'd be sad to lose: history, preferences, pantry, etc.
+    pub fn state_dir(&self) -> &Path {
+        &self.state
+    }
+
+    /// Data that can be regenerated at will
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache
+    }
+
+    /// A file within the config directory, creating the directory if needed.
+    pub fn config_file(&self, name: &str) -> io::Result<PathBuf> {
+        ensure_file_in(&self.config, name)
+    }
+
+    /// A file within the state directory, creating the directory if needed.
+    pub fn state_file(&self, name: &str) -> io::Result<PathBuf> {
+        ensure_file_in(&self.state, name)
+    }
+
+    /// A file within the cache directory, creating the directory if needed.
+    pub fn cache_file(&self, name: &str) -> io::Result<PathBuf> {
+        ensure_file_in(&self.cache, name)
+    }
+
+    /// The shell's readline history
+    pub fn readline_history(&self) -> io::Result<PathBuf> {
+        self.state_file("readline_history.txt")
+    }
+}
+
+fn ensure_file_in(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    Ok(dir.join(name))
+}
+
+/// Writes `contents` to a temp file next to `path` and renames it into
+/// place, so a crash mid-write, or a sync service copying the file at the
+/// wrong moment, never sees half a file.  Creates the parent directory if
+/// needed.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    // hidden, and unique so two writers, even in one process, don't share
+    // a temp file
+    let tmp = dir.join(format!(".{}.{}.tmp", name, ulid::Ulid::new()));
+    let written = fs::write(&tmp, contents).and_then(|()| fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+/// Decodes a base64 image from Bedrock into `path`, atomically like
+/// [write_atomic]
+pub fn write_base64(path: &Path, data: &str) -> io::Result<()> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(path, bytes)
+}
+
+/// Expands a leading `~` (`~/recipes`, or `~\recipes` on Windows) or
+/// `%USERPROFILE%` to the home directory.  Anything else is returned as is.
+pub fn expand(path: &str) -> PathBuf {
+    for prefix in ["~", "%USERPROFILE%"] {
+        let Some(rest) = path.strip_prefix(prefix) else {
+            continue;
+        };
+        if rest.is_empty() {
+            return home_dir();
+        }
+        if let Some(rest) = rest.strip_prefix(['/', '\\']) {
+            return home_dir().join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
+
+/// Makes `path` absolute against the current directory, dropping `.`
+/// components.  Unlike `fs::canonicalize` the path doesn't need to exist,
+/// and symlinks are left alone.
+pub fn absolute(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .unwrap_or_else(|_| PathBuf::from("."))
+            .join(path)
+    };
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+/// `path` for showing to the user, with the home directory abbreviated to `~`
+pub fn display(path: &Path) -> String {
+    let home = home_dir();
+    match path.strip_prefix(&home) {
+        Ok(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Ok(rest) => Path::new("~").join(rest).display().to_string(),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+fn home_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn platform_config_dir() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(|| home_dir().join(".config"))
+}
+
+// dirs::state_dir() is only defined on Linux; elsewhere local data is the
+// closest equivalent.
+fn platform_state_dir() -> PathBuf {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .unwrap_or_else(|| home_dir().join(".local").join("state"))
+}
+
+fn platform_cache_dir() -> PathBuf {
+    dirs::cache_dir().unwrap_or_else(|| home_dir().join(".cache"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_dir_holds_all_three() {
+        let root = Path::new("households").join("smith");
+        let paths = Paths::resolve(Some(&root));
+        for dir in [paths.config_dir(), paths.state_dir(), paths.cache_dir()] {
+            assert!(dir.starts_with(&root), "{}", dir.display());
+        }
+        assert_ne!(paths.config_dir(), paths.state_dir());
+        assert_ne!(paths.state_dir(), paths.cache_dir());
+    }
+
+    #[test]
+    fn failed_writes_leave_no_temp_file() {
+        let dir = std::env::temp_dir().join(format!("gourmand-paths-{}", ulid::Ulid::new()));
+        // renaming a file over a directory that isn't empty fails everywhere
+        let taken = dir.join("risotto.txt");
+        fs::create_dir_all(taken.join("inside")).unwrap();
+        assert!(write_atomic(&taken, "Leek Risotto").is_err());
+        write_atomic(&dir.join("soup.txt"), "Leek Soup").unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["risotto.txt", "soup.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn expand_home_in_every_spelling() {
+        let home = home_dir();
+        for (path, expanded) in [
+            ("~", home.clone()),
+            ("~/recipes", home.join("recipes")),
+            ("~\\recipes", home.join("recipes")),
+            ("%USERPROFILE%", home.clone()),
+            ("%USERPROFILE%\\recipes", home.join("recipes")),
+            ("%USERPROFILE%/recipes", home.join("recipes")),
+            // someone else's home, and names that only start like it
+            ("~alice/recipes", PathBuf::from("~alice/recipes")),
+            ("~recipes", PathBuf::from("~recipes")),
+            ("recipes/~", PathBuf::from("recipes/~")),
+            (
+                "%USERPROFILE%recipes",
+                PathBuf::from("%USERPROFILE%recipes"),
+            ),
+        ] {
+            assert_eq!(expand(path), expanded, "{}", path);
+        }
+        assert_eq!(
+            display(&expand("~/recipes")),
+            format!("~{}recipes", std::path::MAIN_SEPARATOR)
+        );
+        assert_eq!(display(&home), "~");
+    }
+}
+
+#[cfg(all(test, windows))]
+mod windows_tests {
+    use super::*;
+
+    #[test]
+    fn backslashes_are_separators() {
+        let home = home_dir();
+        assert_eq!(
+            expand("~\\recipes\\weeknight"),
+            home.join("recipes").join("weeknight")
+        );
+        assert_eq!(expand("%USERPROFILE%\\recipes"), expand("~/recipes"));
+        assert_eq!(display(&home.join("recipes")), "~\\recipes");
+    }
+
+    #[test]
+    fn absolute_keeps_the_drive() {
+        assert_eq!(
+            absolute(Path::new("C:\\recipes\\.\\weeknight")),
+            PathBuf::from("C:\\recipes\\weeknight")
+        );
+        assert!(absolute(Path::new("recipes")).is_absolute());
+    }
+}
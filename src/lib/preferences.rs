@@ -0,0 +1,369 @@
+//! What the household has told us about itself, kept in `preferences.json`
+//! in the state directory so that a new session can skip the interview:
+//!
+//! ```json
+//! {
+//!   "household_size": 4,
+//!   "diet": ["vegetarian"],
+//!   "allergies": ["peanuts"],
+//!   "dislikes": ["mushrooms"],
+//!   "units": "metric",
+//!   "locale": "en_AU",
+//!   "meal_cutoffs": {"lunch_from": 12, "dinner_from": 17},
+//!   "strictness": {"vegetarian": "strict", "low carb": "prefer"}
+//! }
+//! ```
+//!
+//! Every field is optional.  Without a `strictness` entry, allergies are
+//! strict, diets are preferred and dislikes are avoided; see
+//! [crate::dietary] for what each tier does to a transmitted recipe.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::context::MealCutoffs;
+use crate::equipment;
+use crate::paths;
+
+/// Items beyond this many are summarized as "and N more", except allergies,
+/// which are always listed in full
+const MAX_LISTED: usize = 8;
+
+/// Longer items are cut off, so a stray paragraph can't bloat every intro
+const MAX_ITEM_CHARS: usize = 40;
+
+/// How firmly a diet, allergy or dislike applies
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Strictness {
+    /// Never broken: a recipe that breaks it is sent back to the model
+    Strict,
+    /// Followed where practical: a break is noted in the saved recipe
+    Prefer,
+    /// Kept to a minimum: a break is noted in the saved recipe
+    Avoid,
+}
+
+impl fmt::Display for Strictness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strictness::Strict => write!(f, "strict"),
+            Strictness::Prefer => write!(f, "prefer"),
+            Strictness::Avoid => write!(f, "avoid"),
+        }
+    }
+}
+
+impl FromStr for Strictness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "strict" => Ok(Strictness::Strict),
+            "prefer" => Ok(Strictness::Prefer),
+            "avoid" => Ok(Strictness::Avoid),
+            _ => Err(format!(
+                "unknown strictness: {} (expected strict, prefer, or avoid)",
+                s
+            )),
+        }
+    }
+}
+
+impl Strictness {
+    /// How the model is told about it, after the restriction
+    fn describe(&self) -> &'static str {
+        match self {
+            Strictness::Strict => "strict",
+            Strictness::Prefer => "where practical",
+            Strictness::Avoid => "keep to a minimum",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct Preferences {
+    pub household_size: Option<u32>,
+    /// e.g. vegetarian, low carb, halal
+    pub diet: Vec<String>,
+    pub allergies: Vec<String>,
+    pub dislikes: Vec<String>,
+    /// metric or imperial
+    pub units: Option<String>,
+    /// Appliances beyond an oven and stovetop, e.g. air_fryer, see
+    /// [crate::equipment]
+    pub equipment: Vec<String>,
+    /// Anything else, in the user's words
+    pub notes: Option<String>,
+    /// e.g. en_AU, for the hemisphere and so the season, ahead of $LANG
+    pub locale: Option<String>,
+    /// When the household has lunch and dinner, if not at the usual hours
+    pub meal_cutoffs: Option<MealCutoffs>,
+    /// By lowercase restriction, for those that aren't the default for
+    /// their list
+    pub strictness: BTreeMap<String, Strictness>,
+}
+
+impl Preferences {
+    /// No preferences, to build up with the methods below, e.g.
+    /// `Preferences::new().household_size(2).allergy("peanuts")`
+    pub fn new() -> Preferences {
+        Preferences::default()
+    }
+
+    pub fn household_size(mut self, size: u32) -> Preferences {
+        self.household_size = Some(size);
+        self
+    }
+
+    pub fn diet(mut self, diet: impl Into<String>) -> Preferences {
+        self.diet.push(diet.into());
+        self
+    }
+
+    pub fn allergy(mut self, allergy: impl Into<String>) -> Preferences {
+        self.allergies.push(allergy.into());
+        self
+    }
+
+    pub fn dislike(mut self, dislike: impl Into<String>) -> Preferences {
+        self.dislikes.push(dislike.into());
+        self
+    }
+
+    /// metric or imperial
+    pub fn units(mut self, units: impl Into<String>) -> Preferences {
+        self.units = Some(units.into());
+        self
+    }
+
+    pub fn equipment(mut self, appliance: impl Into<String>) -> Preferences {
+        self.equipment.push(appliance.into());
+        self
+    }
+
+    pub fn notes(mut self, notes: impl Into<String>) -> Preferences {
+        self.notes = Some(notes.into());
+        self
+    }
+
+    pub fn locale(mut self, locale: impl Into<String>) -> Preferences {
+        self.locale = Some(locale.into());
+        self
+    }
+
+    pub fn meal_cutoffs(mut self, cutoffs: MealCutoffs) -> Preferences {
+        self.meal_cutoffs = Some(cutoffs);
+        self
+    }
+
+    /// Like [Preferences::set_strictness]
+    pub fn restriction(mut self, restriction: &str, strictness: Strictness) -> Preferences {
+        self.set_strictness(restriction, strictness);
+        self
+    }
+
+    /// Loads preferences from `path`, or `None` if there aren't any yet
+    pub fn load(path: &Path) -> io::Result<Option<Preferences>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        paths::write_atomic(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// How firmly `restriction` applies: as set, or by which list it's in
+    pub fn strictness(&self, restriction: &str) -> Strictness {
+        let key = restriction.trim().to_lowercase();
+        if let Some(strictness) = self.strictness.get(&key) {
+            return *strictness;
+        }
+        let listed = |items: &[String]| items.iter().any(|i| i.trim().to_lowercase() == key);
+        if listed(&self.allergies) {
+            Strictness::Strict
+        } else if listed(&self.dislikes) {
+            Strictness::Avoid
+        } else {
+            Strictness::Prefer
+        }
+    }
+
+    /// Every diet, allergy and dislike with how firmly it applies
+    pub fn restrictions(&self) -> Vec<(String, Strictness)> {
+        let mut restrictions: Vec<(String, Strictness)> = vec![];
+        for item in self
+            .diet
+            .iter()
+            .chain(&self.allergies)
+            .chain(&self.dislikes)
+        {
+            let item = item.trim();
+            if item.is_empty()
+                || restrictions
+                    .iter()
+                    .any(|(r, _)| r.eq_ignore_ascii_case(item))
+            {
+                continue;
+            }
+            restrictions.push((item.to_string(), self.strictness(item)));
+        }
+        restrictions
+    }
+
+    /// Sets how firmly `restriction` applies, adding it to the diet if it
+    /// isn't in any list yet, e.g. `prefs set vegetarian strict`
+    pub fn set_strictness(&mut self, restriction: &str, strictness: Strictness) {
+        let restriction = restriction.trim();
+        let key = restriction.to_lowercase();
+        let known = self
+            .diet
+            .iter()
+            .chain(&self.allergies)
+            .chain(&self.dislikes)
+            .any(|i| i.trim().to_lowercase() == key);
+        if !known {
+            self.diet.push(restriction.to_string());
+        }
+        self.strictness.remove(&key);
+        if self.strictness(&key) != strictness {
+            self.strictness.insert(key, strictness);
+        }
+    }
+
+    /// `items` with the strictness after any that isn't the list's default
+    fn labeled(&self, items: &[String], default: Strictness) -> Vec<String> {
+        items
+            .iter()
+            .map(|item| match self.strictness(item) {
+                s if s == default => clip(item),
+                s => format!("{} ({})", clip(item), s.describe()),
+            })
+            .collect()
+    }
+
+    /// Whether there's nothing to tell the model.  The locale and meal
+    /// times only go into the session's context.
+    pub fn is_empty(&self) -> bool {
+        self.render().is_empty()
+    }
+
+    /// One paragraph about the household for the model, e.g. "The user is
+    /// a household of 4.  Diet: vegetarian.  Allergies: peanuts."
+    pub fn render(&self) -> String {
+        let mut sentences = vec![];
+        match self.household_size {
+            Some(1) => sentences.push("The user cooks for one.".to_string()),
+            Some(n) => sentences.push(format!("The user is a household of {}.", n)),
+            None => (),
+        }
+        let diet = self.labeled(&self.diet, Strictness::Prefer);
+        if let Some(list) = list(diet, MAX_LISTED) {
+            sentences.push(format!("Diet: {}.", list));
+        }
+        let allergies = self.labeled(&self.allergies, Strictness::Strict);
+        if let Some(list) = list(allergies, usize::MAX) {
+            sentences.push(format!("Allergies (never include these): {}.", list));
+        }
+        let dislikes = self.labeled(&self.dislikes, Strictness::Avoid);
+        if let Some(list) = list(dislikes, MAX_LISTED) {
+            sentences.push(format!("Dislikes: {}.", list));
+        }
+        if let Some(units) = &self.units {
+            sentences.push(format!("Use {} units.", clip(units)));
+        }
+        let equipment = self
+            .equipment
+            .iter()
+            .map(|item| clip(&equipment::label(item)))
+            .collect();
+        if let Some(list) = list(equipment, MAX_LISTED) {
+            sentences.push(format!(
+                "Kitchen equipment beyond an oven and stovetop (don't call for other \
+                 appliances): {}.",
+                list
+            ));
+        }
+        if let Some(notes) = &self.notes {
+            sentences.push(format!("Notes: {}", clip(notes)));
+        }
+        sentences.join("  ")
+    }
+}
+
+fn clip(item: &str) -> String {
+    let item = item.trim();
+    match item.char_indices().nth(MAX_ITEM_CHARS) {
+        Some((idx, _)) => format!("{}...", &item[..idx]),
+        None => item.to_string(),
+    }
+}
+
+/// `items`, already clipped, joined with commas, or `None` if there are none
+fn list(items: Vec<String>, max: usize) -> Option<String> {
+    let mut items: Vec<String> = items.into_iter().filter(|item| !item.is_empty()).collect();
+    items.dedup();
+    if items.is_empty() {
+        return None;
+    }
+    let more = items.len().saturating_sub(max);
+    items.truncate(max);
+    let mut text = items.join(", ");
+    if more > 0 {
+        text.push_str(&format!(", and {} more", more));
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system_prompts;
+    use crate::tokens::Estimator;
+
+    /// What the warm-start intro may cost in tokens, however much the
+    /// household has saved
+    const INTRO_CEILING: usize = 1_000;
+
+    #[test]
+    fn long_preferences_keep_the_intro_small() {
+        let long = |what: &str, n: usize| {
+            format!(
+                "{} number {} that the household wrote out at considerable length",
+                what, n
+            )
+        };
+        let prefs = Preferences {
+            household_size: Some(6),
+            diet: (0..20).map(|n| long("diet", n)).collect(),
+            allergies: (0..50).map(|n| long("allergy", n)).collect(),
+            dislikes: (0..50).map(|n| long("dislike", n)).collect(),
+            notes: Some("We cook on weeknights after football practice. ".repeat(40)),
+            ..Preferences::default()
+        };
+        let rendered = prefs.render();
+        // every allergy is listed, however many there are
+        for n in 0..50 {
+            assert!(
+                rendered.contains(&format!("allergy number {} ", n)),
+                "{}",
+                n
+            );
+        }
+        assert!(rendered.contains("and 42 more"), "{}", rendered);
+
+        let intro = system_prompts::warm_start_intro(&rendered);
+        let tokens = Estimator::default().tokens(intro.len());
+        assert!(tokens < INTRO_CEILING, "{} tokens:\n{}", tokens, intro);
+    }
+}
@@ -0,0 +1,152 @@
+//! Finding the image prompt in what the assistant says to the user.
+//!
+//! The system prompt tells the model not to show the image prompt, but it
+//! still does now and then ("Here's the prompt I used for the photo: ..."),
+//! either word for word or lightly reworded.  [strip] removes the
+//! paragraphs that are mostly the prompt's words, and only those, so that
+//! a recipe that happens to share a few ingredients with it stays intact.
+use std::collections::BTreeSet;
+
+/// Share of a paragraph's words that must come from the prompt.  Lead-ins
+/// like "Here's the prompt I used:" keep a verbatim echo around 0.8, and a
+/// paraphrase around 0.6; a recipe paragraph is usually under 0.3.
+pub const PARAGRAPH_THRESHOLD: f64 = 0.55;
+
+/// Share of the prompt's words the paragraph must repeat, so a short
+/// sentence that mentions the dish isn't taken for the prompt
+pub const PROMPT_THRESHOLD: f64 = 0.5;
+
+fn words(text: &str) -> BTreeSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `paragraph` is the image prompt, verbatim or paraphrased
+pub fn is_echo(paragraph: &str, image_prompt: &str) -> bool {
+    let (paragraph, prompt) = (words(paragraph), words(image_prompt));
+    if paragraph.is_empty() || prompt.is_empty() {
+        return false;
+    }
+    let shared = paragraph.intersection(&prompt).count() as f64;
+    shared / paragraph.len() as f64 >= PARAGRAPH_THRESHOLD
+        && shared / prompt.len() as f64 >= PROMPT_THRESHOLD
+}
+
+/// Whether `paragraph` only introduces what follows, e.g. "Here's the
+/// image prompt I used:"
+fn is_lead_in(paragraph: &str) -> bool {
+    let lower = paragraph.trim().to_lowercase();
+    lower.ends_with(':') && (lower.contains("prompt") || lower.contains("image"))
+}
+
+/// `text` without the paragraphs that echo `image_prompt` (and a lead-in
+/// right before one), and how many paragraphs were removed
+pub fn strip(text: &str, image_prompt: &str) -> (String, usize) {
+    let paragraphs: Vec<&str> = text.split("\n\n").collect();
+    let mut keep = vec![true; paragraphs.len()];
+    for (idx, paragraph) in paragraphs.iter().enumerate() {
+        if is_echo(paragraph, image_prompt) {
+            keep[idx] = false;
+            if idx > 0 && is_lead_in(paragraphs[idx - 1]) {
+                keep[idx - 1] = false;
+            }
+        }
+    }
+    let removed = keep.iter().filter(|k| !**k).count();
+    if removed == 0 {
+        return (text.to_string(), 0);
+    }
+    let kept = paragraphs
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(p, _)| *p)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    (kept, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROMPT: &str = "A rustic bowl of creamy leek risotto topped with shaved parmesan \
+        and fresh thyme, on a weathered wooden table, soft natural window light, overhead shot";
+
+    #[test]
+    fn echoes_are_told_from_the_recipe() {
+        for (paragraph, echo) in [
+            // verbatim, and near-duplicates
+            (PROMPT, true),
+            (
+                "A rustic bowl of creamy leek risotto topped with shaved parmesan and fresh \
+                 thyme on a wooden table, soft window light, shot from overhead.",
+                true,
+            ),
+            (
+                "\"A rustic bowl of creamy leek risotto with shaved parmesan and thyme, \
+                 weathered wooden table, natural window light, overhead.\"",
+                true,
+            ),
+            // paraphrased
+            (
+                "The photo shows creamy leek risotto in a rustic bowl with parmesan shavings \
+                 and thyme, shot overhead on a wooden table in soft window light.",
+                true,
+            ),
+            (
+                "I pictured it as a rustic bowl of creamy risotto with parmesan and thyme, \
+                 overhead, on weathered wood in natural light.",
+                true,
+            ),
+            // the recipe, which shares the ingredients
+            (
+                "Ingredients:\n- 2 leeks, sliced\n- 1 cup arborio rice\n- 4 cups stock\n\
+                 - 1/2 cup parmesan\n- a few sprigs of thyme",
+                false,
+            ),
+            (
+                "Stir in the parmesan and thyme off the heat, and serve the risotto in warm \
+                 bowls.",
+                false,
+            ),
+            // too short to be the prompt, even if every word is in it
+            ("Enjoy the leek risotto!", false),
+            ("Creamy leek risotto.", false),
+            ("", false),
+        ] {
+            assert_eq!(is_echo(paragraph, PROMPT), echo, "{:?}", paragraph);
+        }
+    }
+
+    #[test]
+    fn strips_the_echo_and_its_lead_in() {
+        let text = format!(
+            "Here's a cozy risotto for tonight.\n\n\
+             Here's the image prompt I used:\n\n{}\n\n\
+             Stir in the parmesan and thyme off the heat.",
+            PROMPT
+        );
+        assert_eq!(
+            strip(&text, PROMPT),
+            (
+                "Here's a cozy risotto for tonight.\n\n\
+                 Stir in the parmesan and thyme off the heat."
+                    .to_string(),
+                2
+            )
+        );
+    }
+
+    #[test]
+    fn leaves_text_without_an_echo_alone() {
+        let text = "Ingredients:\n- 2 leeks\n- parmesan\n\nEnjoy the leek risotto!";
+        assert_eq!(strip(text, PROMPT), (text.to_string(), 0));
+        // a lead-in alone isn't removed
+        let text = "The image is on its way:\n\nEnjoy!";
+        assert_eq!(strip(text, PROMPT), (text.to_string(), 0));
+    }
+}
@@ -0,0 +1,376 @@
+//! Earlier turns of a long session, kept out of the requests.
+//!
+//! With `--live-turns`, only the most recent turns are sent with each
+//! request.  Older ones move into a [Recap], and the model can look them up
+//! with the `conversation_recap` tool instead of paying for them on every
+//! turn.  Each archived turn is boiled down locally to what the user said,
+//! the start of the answer, and the titles of any recipes transmitted,
+//! which is usually what's needed back: a preference stated early on, or a
+//! recipe to build on.  The binary can also add model-written summaries
+//! (`--summarize-history`), which are listed first.
+//!
+//! The archived messages themselves are kept too, so a saved session still
+//! has the whole conversation.
+use aws_sdk_bedrockruntime::types::{ContentBlock, ConversationRole, Message};
+use aws_smithy_types::Document;
+
+use crate::recipe;
+
+pub const TOOL_NAME: &str = "conversation_recap";
+
+/// How much of each message is kept in an entry
+const USER_CHARS: usize = 600;
+const ASSISTANT_CHARS: usize = 300;
+
+/// Roughly how much of the recap one lookup returns, newest turns first
+const LOOKUP_CHARS: usize = 6000;
+
+/// One archived turn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecapEntry {
+    /// Counting from 1 at the start of the session
+    pub turn: usize,
+    pub user: String,
+    pub assistant: String,
+    /// Titles of the recipes transmitted during the turn
+    pub recipes: Vec<String>,
+}
+
+impl RecapEntry {
+    /// Whether any word here starts like one of `keys`, see [key]
+    fn matches(&self, keys: &[String]) -> bool {
+        let text = format!(
+            "{} {} {}",
+            self.user,
+            self.assistant,
+            self.recipes.join(" ")
+        )
+        .to_lowercase();
+        text.split(|c: char| !c.is_alphanumeric())
+            .any(|word| keys.iter().any(|key| word.starts_with(key.as_str())))
+    }
+
+    fn render(&self) -> String {
+        let mut lines = vec![format!("turn {}: the user said: {}", self.turn, self.user)];
+        if !self.assistant.is_empty() {
+            lines.push(format!("  you answered: {}", self.assistant));
+        }
+        if !self.recipes.is_empty() {
+            lines.push(format!(
+                "  recipes transmitted: {}",
+                self.recipes.join("; ")
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Recap {
+    messages: Vec<Message>,
+    entries: Vec<RecapEntry>,
+    summaries: Vec<String>,
+}
+
+impl Recap {
+    /// Adds messages cut from the start of the live history.  They must
+    /// start a turn, as from [crate::conversation::Conversation::split_off_turns].
+    pub fn archive(&mut self, messages: Vec<Message>) {
+        for msg in &messages {
+            let texts = msg.content().iter().filter_map(|c| c.as_text().ok());
+            if msg.role() == &ConversationRole::User {
+                let said = texts.map(String::as_str).collect::<Vec<_>>().join(" ");
+                // tool results come back as user messages too, but without text
+                if !said.trim().is_empty() {
+                    self.entries.push(RecapEntry {
+                        turn: self.entries.len() + 1,
+                        user: clip(&said, USER_CHARS),
+                        assistant: String::new(),
+                        recipes: vec![],
+                    });
+                }
+                continue;
+            }
+            if self.entries.is_empty() {
+                self.entries.push(RecapEntry {
+                    turn: 1,
+                    user: String::new(),
+                    assistant: String::new(),
+                    recipes: vec![],
+                });
+            }
+            let Some(entry) = self.entries.last_mut() else {
+                continue;
+            };
+            for text in texts {
+                if entry.assistant.chars().count() < ASSISTANT_CHARS {
+                    let joined = format!("{} {}", entry.assistant, text);
+                    entry.assistant = clip(&joined, ASSISTANT_CHARS);
+                }
+            }
+            entry
+                .recipes
+                .extend(msg.content().iter().filter_map(transmitted_title));
+        }
+        self.messages.extend(messages);
+    }
+
+    /// The model's summary of a batch of archived turns
+    pub fn add_summary(&mut self, summary: String) {
+        self.summaries.push(summary);
+    }
+
+    /// Every archived message, oldest first
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn turns(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Recap::default();
+    }
+
+    /// What the tool answers: the summaries, then the archived turns that
+    /// mention any word of `query`, in any form (all of them without one),
+    /// as many of the newest as fit
+    pub fn lookup(&self, query: Option<&str>) -> String {
+        if self.entries.is_empty() {
+            return "no earlier turns have been archived; the whole conversation is in your context"
+                .to_string();
+        }
+        let keys: Vec<String> = query
+            .unwrap_or_default()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.len() > 2)
+            .map(key)
+            .collect();
+        let matching: Vec<&RecapEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| keys.is_empty() || entry.matches(&keys))
+            .collect();
+
+        let mut out = vec![];
+        let mut used = 0;
+        for summary in &self.summaries {
+            used += summary.len();
+            out.push(format!("summary: {}", summary));
+        }
+        let mut shown = vec![];
+        for entry in matching.iter().rev() {
+            let rendered = entry.render();
+            if used + rendered.len() > LOOKUP_CHARS && !shown.is_empty() {
+                break;
+            }
+            used += rendered.len();
+            shown.push(rendered);
+        }
+        let left_out = matching.len() - shown.len();
+        shown.reverse();
+        if matching.is_empty() {
+            out.push(format!(
+                "none of the {} earlier turns mention {}",
+                self.entries.len(),
+                query.unwrap_or_default()
+            ));
+        }
+        out.extend(shown);
+        if left_out > 0 {
+            out.push(format!(
+                "({} older matching turns left out, look up something more specific to see them)",
+                left_out
+            ));
+        }
+        out.join("\n\n")
+    }
+}
+
+/// Letters a word's forms usually share, so a lookup for allergies finds
+/// "allergic" and one for sesame finds "sesame-free"
+const KEY_CHARS: usize = 6;
+
+/// The start of `word` that other forms of it begin with too
+fn key(word: &str) -> String {
+    word.to_lowercase().chars().take(KEY_CHARS).collect()
+}
+
+/// The conversation in `messages` as plain text, for a model to summarize
+pub fn transcript(messages: &[Message]) -> String {
+    let mut lines = vec![];
+    for msg in messages {
+        let speaker = match msg.role() {
+            ConversationRole::User => "user",
+            _ => "assistant",
+        };
+        for content in msg.content() {
+            if let Ok(text) = content.as_text() {
+                lines.push(format!("{}: {}", speaker, text.trim()));
+            } else if let Some(title) = transmitted_title(content) {
+                lines.push(format!("assistant transmitted a recipe: {}", title));
+            }
+        }
+    }
+    lines.join("\n\n")
+}
+
+/// The title of the recipe in a `transmit_recipe` tool use
+fn transmitted_title(content: &ContentBlock) -> Option<String> {
+    let tool_use = content.as_tool_use().ok()?;
+    if tool_use.name() != crate::oneshot::TRANSMIT_TOOL {
+        return None;
+    }
+    let Document::Object(input) = tool_use.input() else {
+        return None;
+    };
+    match input.get("recipe_details")? {
+        Document::String(details) => recipe::title(details),
+        _ => None,
+    }
+}
+
+fn clip(text: &str, max: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(max) {
+        Some((idx, _)) => format!("{}...", &text[..idx]),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_bedrockruntime::types::{ToolResultBlock, ToolResultContentBlock, ToolUseBlock};
+
+    use super::*;
+
+    fn message(role: ConversationRole, text: &str) -> Message {
+        Message::builder()
+            .role(role)
+            .content(ContentBlock::Text(text.to_string()))
+            .build()
+            .unwrap()
+    }
+
+    /// 30 turns, the first stating an allergy and the rest long enough
+    /// that they don't all fit in one lookup
+    fn long_session() -> Recap {
+        let mut recap = Recap::default();
+        recap.archive(vec![
+            message(
+                ConversationRole::User,
+                "Before anything else: my daughter is allergic to sesame.",
+            ),
+            message(ConversationRole::Assistant, "Noted, no sesame."),
+        ]);
+        for turn in 2..=30 {
+            recap.archive(vec![
+                message(
+                    ConversationRole::User,
+                    &format!("idea {} please, {}", turn, "something quick ".repeat(20)),
+                ),
+                message(
+                    ConversationRole::Assistant,
+                    &format!("Here's idea {}: {}", turn, "a tasty dish ".repeat(30)),
+                ),
+            ]);
+        }
+        recap
+    }
+
+    #[test]
+    fn a_preference_from_30_turns_ago_can_be_looked_up() {
+        let recap = long_session();
+        assert_eq!(recap.turns(), 30);
+        assert_eq!(recap.len(), 60);
+
+        let everything = recap.lookup(None);
+        assert!(!everything.contains("sesame"), "{}", everything);
+        assert!(everything.contains("turn 30: the user said: idea 30 please"));
+        assert!(everything.ends_with(
+            "older matching turns left out, look up something more specific to see them)"
+        ));
+
+        assert_eq!(
+            recap.lookup(Some("any allergies?")),
+            recap.lookup(Some("allergic")),
+            "a word matches in any form"
+        );
+        assert_eq!(
+            recap.lookup(Some("Allergies")),
+            "turn 1: the user said: Before anything else: my daughter is allergic to sesame.\n  \
+             you answered: Noted, no sesame."
+        );
+        assert_eq!(
+            recap.lookup(Some("shellfish")),
+            "none of the 30 earlier turns mention shellfish"
+        );
+    }
+
+    #[test]
+    fn summaries_come_first() {
+        let mut recap = long_session();
+        recap.add_summary("The user's daughter can't have sesame.".to_string());
+        assert!(recap
+            .lookup(Some("sesame"))
+            .starts_with("summary: The user's daughter can't have sesame.\n\nturn 1: "));
+    }
+
+    #[test]
+    fn tool_results_and_transmitted_recipes_stay_with_their_turn() {
+        let transmit = ContentBlock::ToolUse(
+            ToolUseBlock::builder()
+                .tool_use_id("tooluse_1")
+                .name(crate::oneshot::TRANSMIT_TOOL)
+                .input(Document::Object(
+                    [(
+                        "recipe_details".to_string(),
+                        Document::String("Leek Risotto\n\nIngredients:\n- leeks".to_string()),
+                    )]
+                    .into(),
+                ))
+                .build()
+                .unwrap(),
+        );
+        let result = ContentBlock::ToolResult(
+            ToolResultBlock::builder()
+                .tool_use_id("tooluse_1")
+                .content(ToolResultContentBlock::Text(
+                    "{\"saved\": true}".to_string(),
+                ))
+                .build()
+                .unwrap(),
+        );
+        let mut recap = Recap::default();
+        recap.archive(vec![
+            message(ConversationRole::User, "something with leeks"),
+            Message::builder()
+                .role(ConversationRole::Assistant)
+                .content(transmit)
+                .build()
+                .unwrap(),
+            Message::builder()
+                .role(ConversationRole::User)
+                .content(result)
+                .build()
+                .unwrap(),
+            message(ConversationRole::Assistant, "Enjoy!"),
+        ]);
+        assert_eq!(recap.turns(), 1);
+        assert_eq!(
+            recap.lookup(Some("risotto")),
+            "turn 1: the user said: something with leeks\n  you answered: Enjoy!\n  \
+             recipes transmitted: Leek Risotto"
+        );
+        assert_eq!(Recap::default().turns(), 0);
+    }
+}
@@ -0,0 +1,622 @@
+//! Picking apart the recipe text the model transmits.
+//!
+//! The model is asked to send the title, ingredients, instructions, and
+//! shopping list separated by blank lines, but in practice the headings,
+//! numbering, and bullets vary from model to model, so everything here is
+//! deliberately forgiving.  Finding the sections themselves is up to [crate::parse].
+
+/// The headings we know how to recognize
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Ingredients,
+    Instructions,
+    ShoppingList,
+    /// Tips from the model, or our own dietary notes
+    Notes,
+    /// The documents an answer cited, see [crate::citations]
+    Sources,
+}
+
+impl Section {
+    /// Recognizes a heading line such as `Instructions:`, `## Method`,
+    /// `**Shopping List**`, or `Ingredients (serves 4):`
+    pub fn from_heading(line: &str) -> Option<Section> {
+        let line = line.split('(').next().unwrap_or(line);
+        let heading = line
+            .trim()
+            .trim_start_matches('#')
+            .trim_matches(|c: char| c == '*' || c == '_' || c == ':' || c.is_whitespace())
+            .to_lowercase();
+        // and in Spanish, Portuguese and German, for --language and translations
+        match heading.as_str() {
+            "ingredients" | "ingredientes" | "zutaten" => Some(Section::Ingredients),
+            "instructions" | "directions" | "method" | "steps" | "preparation" => {
+                Some(Section::Instructions)
+            }
+            "instrucciones" | "preparación" | "elaboración" | "pasos" => {
+                Some(Section::Instructions)
+            }
+            "modo de preparo" | "preparo" | "preparação" | "passos" => {
+                Some(Section::Instructions)
+            }
+            "zubereitung" | "anleitung" | "schritte" => Some(Section::Instructions),
+            "shopping list" | "shopping" | "grocery list" => Some(Section::ShoppingList),
+            "lista de compras" | "lista de la compra" | "einkaufsliste" => {
+                Some(Section::ShoppingList)
+            }
+            "notes" | "dietary notes" | "notas" | "notizen" | "hinweise" => Some(Section::Notes),
+            "sources" | "fuentes" | "fontes" | "quellen" => Some(Section::Sources),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the body of `section`, without its heading.  See [crate::parse].
+pub fn section(recipe: &str, section: Section) -> Option<String> {
+    crate::parse::sections(recipe)
+        .get(section)
+        .map(str::to_string)
+}
+
+/// The recipe's title, with any markdown decoration removed
+pub fn title(recipe: &str) -> Option<String> {
+    crate::parse::sections(recipe).title().map(str::to_string)
+}
+
+/// Splits instructions into individual steps.
+///
+/// Numbered lists (`1.`, `1)`, `Step 1:`) and bullet lists (`-`, `*`, `•`) give
+/// one step per item, with wrapped continuation lines folded into the item
+/// they belong to.  Plain paragraphs give one step per paragraph, or one step
+/// per sentence if there's only a single paragraph.
+pub fn parse_steps(instructions: &str) -> Vec<String> {
+    let lines: Vec<&str> = instructions
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    if lines.iter().any(|l| strip_list_marker(l).is_some()) {
+        let mut steps: Vec<String> = vec![];
+        for line in lines {
+            match strip_list_marker(line) {
+                Some(item) => steps.push(item.to_string()),
+                None => match steps.last_mut() {
+                    Some(step) => {
+                        step.push(' ');
+                        step.push_str(line);
+                    }
+                    None => steps.push(line.to_string()),
+                },
+            }
+        }
+        return steps;
+    }
+
+    let paragraphs: Vec<String> = instructions
+        .split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .collect();
+    if paragraphs.len() != 1 {
+        return paragraphs;
+    }
+    split_sentences(&paragraphs[0])
+}
+
+/// Durations mentioned in a step, e.g. `["10 minutes", "1-2 hours"]`, for announcing timers
+pub fn extract_timers(step: &str) -> Vec<String> {
+    let words: Vec<&str> = step.split_whitespace().collect();
+    let mut timers = vec![];
+    for pair in words.windows(2) {
+        let amount = pair[0].trim_start_matches('(');
+        let unit = pair[1]
+            .trim_end_matches(|c: char| !c.is_alphabetic())
+            .to_lowercase();
+        let is_amount = !amount.is_empty()
+            && amount.chars().any(|c| c.is_ascii_digit())
+            && amount
+                .chars()
+                .all(|c| c.is_ascii_digit() || "-–/.".contains(c));
+        let is_unit = matches!(
+            unit.as_str(),
+            "sec"
+                | "secs"
+                | "second"
+                | "seconds"
+                | "min"
+                | "mins"
+                | "minute"
+                | "minutes"
+                | "hr"
+                | "hrs"
+                | "hour"
+                | "hours"
+        );
+        if is_amount && is_unit {
+            timers.push(format!("{} {}", amount, unit));
+        }
+    }
+    timers
+}
+
+/// The titles of the numbered options in an answer, as in `Option 1: Lemon
+/// Chicken` or `**Option 2** - Mushroom Risotto: creamy and quick`, in
+/// order.  A description after the title is left off.  Numbering has to
+/// start at 1 and count up, so "option 3" in passing isn't taken.
+pub fn offered_options(answer: &str) -> Vec<String> {
+    let mut options: Vec<String> = vec![];
+    for line in answer.lines() {
+        let line = line.replace("**", "").replace("__", "");
+        let line = line.trim().trim_start_matches(['#', '-', '*', '•']).trim();
+        let Some(rest) = line
+            .get(.."option ".len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case("option "))
+            .map(|_| &line["option ".len()..])
+        else {
+            continue;
+        };
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        if rest[..digits].parse::<usize>().ok() != Some(options.len() + 1) {
+            continue;
+        }
+        let rest = rest[digits..].trim_start_matches([':', '.', ')', '-', '–', '—', ' ']);
+        let title = [" - ", " – ", " — ", ":"]
+            .iter()
+            .filter_map(|sep| rest.find(sep))
+            .min()
+            .map_or(rest, |end| &rest[..end])
+            .trim()
+            .trim_end_matches(['.', '!']);
+        if !title.is_empty() {
+            options.push(title.to_string());
+        }
+    }
+    options
+}
+
+pub(crate) fn strip_list_marker(line: &str) -> Option<&str> {
+    for bullet in ["- ", "* ", "• ", "+ "] {
+        if let Some(rest) = line.strip_prefix(bullet) {
+            return Some(rest.trim());
+        }
+    }
+
+    let lower = line.to_lowercase();
+    let line = if lower.starts_with("step ") {
+        &line[5..]
+    } else {
+        line
+    };
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    let rest = &line[digits..];
+    ['.', ')', ':']
+        .iter()
+        .find_map(|sep| rest.strip_prefix(*sep))
+        .map(str::trim)
+}
+
+fn split_sentences(paragraph: &str) -> Vec<String> {
+    let mut sentences = vec![];
+    let mut current = String::new();
+    for word in paragraph.split_whitespace() {
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+        if word.ends_with('.') || word.ends_with('!') || word.ends_with('?') {
+            sentences.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        sentences.push(current);
+    }
+    sentences
+}
+
+/// Folds a file stem down to ASCII so paths stay portable, transliterating
+/// common accented Latin letters (`jalapeño` → `jalapeno`, `süß` → `suss`)
+/// and dropping anything else that isn't ASCII.
+pub fn ascii_stem(stem: &str) -> String {
+    let mut out = String::with_capacity(stem.len());
+    for c in stem.chars() {
+        if c.is_ascii() {
+            out.push(c);
+            continue;
+        }
+        let folded = match c {
+            'à' | 'á' | 'â' | 'ã' | 'å' | 'ā' => "a",
+            'ä' | 'æ' => "ae",
+            'ç' | 'č' | 'ć' => "c",
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' => "e",
+            'ì' | 'í' | 'î' | 'ï' => "i",
+            'ñ' | 'ń' => "n",
+            'ò' | 'ó' | 'ô' | 'õ' | 'ø' => "o",
+            'ö' | 'œ' => "oe",
+            'ù' | 'ú' | 'û' => "u",
+            'ü' => "ue",
+            'ý' | 'ÿ' => "y",
+            'ß' => "ss",
+            'ł' => "l",
+            'š' | 'ś' => "s",
+            'ž' | 'ź' | 'ż' => "z",
+            'À' | 'Á' | 'Â' | 'Ã' | 'Å' => "A",
+            'Ä' => "Ae",
+            'Ç' => "C",
+            'È' | 'É' | 'Ê' | 'Ë' => "E",
+            'Ì' | 'Í' | 'Î' | 'Ï' => "I",
+            'Ñ' => "N",
+            'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ø' => "O",
+            'Ö' => "Oe",
+            'Ù' | 'Ú' | 'Û' => "U",
+            'Ü' => "Ue",
+            _ => "",
+        };
+        out.push_str(folded);
+    }
+    out
+}
+
+/// Stem used when nothing is left of the one asked for
+pub const FALLBACK_STEM: &str = "recipe";
+
+/// Makes a file stem safe on every platform we run on: characters NTFS
+/// doesn't allow (`<>:"/\|?*` and control characters) become `_`, leading
+/// dots (so `..` can't climb out of the directory, nor a name hide) and
+/// trailing dots and spaces are dropped, and device names like `con` or
+/// `com1` get a `_` appended.  A stem with nothing left becomes
+/// [FALLBACK_STEM].
+pub fn portable_stem(stem: &str) -> String {
+    let replaced: String = stem
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let mut stem = replaced
+        .trim_start_matches('.')
+        .trim_end_matches(['.', ' '])
+        .to_string();
+    if stem.trim().is_empty() {
+        return FALLBACK_STEM.to_string();
+    }
+    let device = stem.split('.').next().unwrap_or("").to_uppercase();
+    let reserved = matches!(device.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (device.len() == 4
+            && (device.starts_with("COM") || device.starts_with("LPT"))
+            && device.ends_with(|c: char| ('1'..='9').contains(&c)));
+    if reserved {
+        stem.push('_');
+    }
+    stem
+}
+
+/// Longest slug [slugify] will produce, before any suffix is added
+pub const MAX_SLUG_LEN: usize = 48;
+
+/// Turns a title into a lowercase, underscore separated ASCII file stem,
+/// e.g. `Crème Brûlée (Easy!)` → `creme_brulee_easy`.  Long titles are cut
+/// at a word boundary.  Returns `None` if nothing usable is left, such as
+/// for a title that's all emoji or punctuation.
+pub fn slugify(title: &str) -> Option<String> {
+    let folded = ascii_stem(title).to_lowercase();
+    let words: Vec<&str> = folded
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut slug = String::new();
+    for word in words {
+        let extra = if slug.is_empty() { 0 } else { 1 };
+        if slug.len() + extra + word.len() > MAX_SLUG_LEN {
+            if slug.is_empty() {
+                slug.push_str(&word[..MAX_SLUG_LEN]);
+            }
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('_');
+        }
+        slug.push_str(word);
+    }
+    (!slug.is_empty()).then_some(slug)
+}
+
+/// Replaces the body of `section`, keeping its heading.  Returns `None` if the section isn't there.
+pub fn replace_section(recipe: &str, section: Section, body: &str) -> Option<String> {
+    let span = crate::parse::sections(recipe).span(section)?;
+    Some(format!(
+        "{}{}{}",
+        &recipe[..span.start],
+        body,
+        &recipe[span.end..]
+    ))
+}
+
+/// Minutes on the first line mentioning `label`, like "Prep time: 10 minutes"
+/// or "Cook: 1 hour 5 min" for `prep` and `cook`
+pub fn labeled_minutes(recipe: &str, label: &str) -> Option<u32> {
+    let line = recipe
+        .lines()
+        .find(|line| line.to_lowercase().contains(label))?
+        .to_lowercase();
+    let mut minutes = 0;
+    let mut found = false;
+    let words: Vec<&str> = line
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    for pair in words.windows(2) {
+        let Ok(n) = pair[0].parse::<u32>() else {
+            continue;
+        };
+        if pair[1].starts_with('h') {
+            minutes += n * 60;
+            found = true;
+        } else if pair[1].starts_with("min") {
+            minutes += n;
+            found = true;
+        }
+    }
+    found.then_some(minutes)
+}
+
+/// Words in an ingredient that say nothing about which ingredient it is
+const INGREDIENT_FILLER: &[&str] = &[
+    "large", "small", "medium", "fresh", "dried", "ground", "chopped", "minced", "sliced", "diced",
+    "whole", "extra", "virgin", "boneless", "skinless", "cup", "cups", "tbsp", "tsp", "can", "and",
+    "for", "the", "plus", "optional", "taste",
+];
+
+/// The ingredient lines (as written, without bullets) that `step` seems to
+/// use, so their amounts can be shown alongside it.  An ingredient matches
+/// if any meaningful word of it starts a word in the step.
+pub fn ingredients_for_step(ingredients: &str, step: &str) -> Vec<String> {
+    let step = step.to_lowercase();
+    let step_words: Vec<&str> = step
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    ingredients
+        .lines()
+        .map(|line| line.trim().trim_start_matches(['-', '*', '•']).trim())
+        .filter(|line| !line.is_empty())
+        .filter(|line| {
+            let key = crate::units::Line::parse(line).key();
+            key.split(|c: char| !c.is_alphanumeric())
+                .filter(|w| w.len() >= 3 && !INGREDIENT_FILLER.contains(w))
+                .any(|w| step_words.iter().any(|s| s.starts_with(w)))
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// A transmitted recipe, as [crate::Conversation::ask] hands it back and an
+/// [crate::ArtifactSink] saves it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Recipe {
+    pub title: Option<String>,
+    /// Title, ingredients, instructions, and shopping list
+    pub details: String,
+    /// For an image generation model to produce a photo of the dish
+    pub image_prompt: Option<String>,
+    /// Why this recipe, e.g. "vegetarian, uses the spinach on hand"
+    pub rationale: Option<String>,
+}
+
+impl Recipe {
+    /// A recipe with the whole text in `details`, titled from its first line
+    pub fn new(details: impl Into<String>) -> Recipe {
+        let details = details.into();
+        Recipe {
+            title: title(&details),
+            details,
+            ..Recipe::default()
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Recipe {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn image_prompt(mut self, prompt: impl Into<String>) -> Recipe {
+        self.image_prompt = Some(prompt.into());
+        self
+    }
+
+    pub fn rationale(mut self, rationale: impl Into<String>) -> Recipe {
+        self.rationale = Some(rationale.into());
+        self
+    }
+
+    /// The file stem it's saved under, see [slugify]
+    pub fn stem(&self) -> String {
+        self.title
+            .as_deref()
+            .and_then(slugify)
+            .unwrap_or(FALLBACK_STEM.to_string())
+    }
+
+    /// From the input to `transmit_recipe`, already unwrapped into an
+    /// object.  `None` without any `recipe_details`.
+    pub fn from_tool_input(input: &serde_json::Value) -> Option<Recipe> {
+        let get = |key: &str| {
+            input
+                .get(key)
+                .and_then(|value| value.as_str())
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        };
+        let mut recipe = Recipe::new(get("recipe_details")?);
+        if let Some(title) = get("title") {
+            recipe.title = Some(title);
+        }
+        recipe.image_prompt = get("image_prompt");
+        recipe.rationale = get("rationale");
+        Some(recipe)
+    }
+
+    /// From an answer in text, for models that write the recipe out instead
+    /// of using the tool.  `None` unless it has an ingredients section.
+    pub fn from_text(text: &str) -> Option<Recipe> {
+        section(text, Section::Ingredients)?;
+        Some(Recipe::new(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_in_spanish_and_german() {
+        for (heading, section) in [
+            ("## Ingredientes", Section::Ingredients),
+            ("**Preparación:**", Section::Instructions),
+            ("Lista de la compra", Section::ShoppingList),
+            ("Zutaten (für 4 Personen):", Section::Ingredients),
+            ("### Zubereitung", Section::Instructions),
+            ("Einkaufsliste:", Section::ShoppingList),
+            ("Hinweise", Section::Notes),
+        ] {
+            assert_eq!(Section::from_heading(heading), Some(section), "{}", heading);
+        }
+        assert_eq!(Section::from_heading("Guten Appetit"), None);
+    }
+
+    #[test]
+    fn steps_from_each_layout() {
+        for (instructions, steps) in [
+            (
+                "1. Boil the water.\n   Salt it well.\n2) Add the pasta.",
+                vec!["Boil the water. Salt it well.", "Add the pasta."],
+            ),
+            (
+                "Step 1: Preheat the oven.\nStep 2: Bake.",
+                vec!["Preheat the oven.", "Bake."],
+            ),
+            (
+                "• Chop\n- Fry\n* Season\n+ Serve",
+                vec!["Chop", "Fry", "Season", "Serve"],
+            ),
+            (
+                "Before you start:\n1. Chop the onion.",
+                vec!["Before you start:", "Chop the onion."],
+            ),
+            (
+                "Chop the onion.\n\nFry it\nuntil golden.",
+                vec!["Chop the onion.", "Fry it until golden."],
+            ),
+            (
+                "Chop the onion. Fry it! Done? Serve warm",
+                vec!["Chop the onion.", "Fry it!", "Done?", "Serve warm"],
+            ),
+            ("Bake at 350 degrees.", vec!["Bake at 350 degrees."]),
+            ("", vec![]),
+        ] {
+            assert_eq!(parse_steps(instructions), steps, "{:?}", instructions);
+        }
+    }
+
+    #[test]
+    fn timers_in_a_step() {
+        for (step, timers) in [
+            (
+                "Simmer for 10 minutes, then rest (5 mins).",
+                vec!["10 minutes", "5 mins"],
+            ),
+            ("Braise 1-2 hours.", vec!["1-2 hours"]),
+            ("Blend for 30 sec", vec!["30 sec"]),
+            ("Add 2 cups of stock.", vec![]),
+            ("Cook a few minutes.", vec![]),
+        ] {
+            assert_eq!(extract_timers(step), timers, "{:?}", step);
+        }
+    }
+
+    #[test]
+    fn localized_ingredients_match_their_steps() {
+        let ingredients = "- 2 huevos\n- 1,5 tazas de arroz\n- 200 g de queso";
+        assert_eq!(
+            ingredients_for_step(ingredients, "Cocer el arroz 20 minutos."),
+            ["1,5 tazas de arroz"]
+        );
+    }
+
+    #[test]
+    fn stems_stay_in_their_directory() {
+        for (stem, portable) in [
+            ("../../etc/passwd", "_.._etc_passwd"),
+            ("..", FALLBACK_STEM),
+            (".hidden", "hidden"),
+            ("  ", FALLBACK_STEM),
+            ("", FALLBACK_STEM),
+            ("lpt1.txt", "lpt1.txt_"),
+            ("pasta al limone. ", "pasta al limone"),
+        ] {
+            assert_eq!(portable_stem(stem), portable, "{:?}", stem);
+        }
+    }
+
+    #[test]
+    fn slugs_from_titles() {
+        let long = "Slow Roasted Tomato and Garlic Soup with Crusty Sourdough Croutons";
+        let word = "Rindfleischetikettierungsueberwachungsaufgabenuebertragungsgesetz";
+        for (title, slug) in [
+            ("Crème Brûlée", Some("creme_brulee")),
+            ("Crème Brûlée (Easy!)", Some("creme_brulee_easy")),
+            ("Smørrebrød mit Käse", Some("smorrebrod_mit_kaese")),
+            ("🍰🍓", None),
+            ("🍕 Pizza Night 🎉", Some("pizza_night")),
+            (
+                long,
+                Some("slow_roasted_tomato_and_garlic_soup_with_crusty"),
+            ),
+            ("!!!", None),
+            ("", None),
+        ] {
+            assert_eq!(slugify(title).as_deref(), slug, "{:?}", title);
+        }
+        let cut = slugify(&format!("{} Stew", word)).unwrap();
+        assert_eq!(cut.len(), MAX_SLUG_LEN);
+        assert!(word.to_lowercase().starts_with(&cut), "{}", cut);
+    }
+
+    #[test]
+    fn recipes_from_tool_input_and_text() {
+        let input = serde_json::json!({
+            "recipe_details": "Rice and Beans\n\nIngredients:\n- rice\n- black beans",
+            "image_prompt": "a bowl of rice and beans",
+            "rationale": " ",
+        });
+        let recipe = Recipe::from_tool_input(&input).unwrap();
+        assert_eq!(recipe.title.as_deref(), Some("Rice and Beans"));
+        assert_eq!(recipe.rationale, None);
+        assert_eq!(recipe.stem(), "rice_and_beans");
+        assert_eq!(
+            Recipe::from_tool_input(&serde_json::json!({"title": "Pasta"})),
+            None
+        );
+
+        let titled = serde_json::json!({"title": "Weeknight Rice", "recipe_details": "rice"});
+        assert_eq!(
+            Recipe::from_tool_input(&titled).unwrap().stem(),
+            "weeknight_rice"
+        );
+
+        assert_eq!(Recipe::from_text("How about rice and beans?"), None);
+        assert_eq!(
+            Recipe::from_text(input["recipe_details"].as_str().unwrap()),
+            Some(Recipe::new(input["recipe_details"].as_str().unwrap()))
+        );
+        assert_eq!(Recipe::new("🍚").stem(), FALLBACK_STEM);
+    }
+}
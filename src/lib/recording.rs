@@ -0,0 +1,276 @@
+//! Recording a session's Converse requests and responses, and replaying
+//! them without the network.
+//!
+//! A recording is a directory with one `NNNN.json` per request, in order,
+//! plus `index.json` listing them.  Replay hands back the recorded
+//! responses in the same order and stops with [ReplayError::Diverged] as
+//! soon as a request doesn't look like the one that was recorded, which is
+//! what makes a recording useful for catching changes to the tool loop.
+//!
+//! Only the shape of each request is compared (model, number of messages,
+//! and a hash of the tool configuration), not its text, so rewording a
+//! prompt doesn't invalidate every recording.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use aws_sdk_bedrockruntime::types::ToolConfiguration;
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::sha256_hex;
+use crate::session::StoredMessage;
+use crate::toolspec;
+
+const INDEX: &str = "index.json";
+
+/// What's compared between a recorded request and a replayed one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RequestShape {
+    pub model: String,
+    pub message_count: usize,
+    pub tools_sha256: String,
+}
+
+/// One request and the response it got
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Exchange {
+    pub request: RequestShape,
+    /// The newest message in the request, for people reading the recording
+    pub last_message: Option<StoredMessage>,
+    /// The response's message, or `None` for a response without one, which
+    /// replays as [crate::conversation::ConversationError::NoOutput]
+    #[serde(default)]
+    pub response: Option<StoredMessage>,
+    pub stop_reason: String,
+    pub input_tokens: i32,
+    pub output_tokens: i32,
+    /// Bedrock's x-amzn-RequestId, for AWS support
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// The caller's id for the user action behind the request, see
+    /// [crate::conversation::Conversation::set_correlation_id]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Index {
+    exchanges: Vec<String>,
+}
+
+/// A stable hash of the tools offered to the model
+pub fn tools_sha256(tools: Option<&ToolConfiguration>) -> String {
+    let json = tools.map_or(serde_json::Value::Null, toolspec::canonical_json);
+    sha256_hex(&json.to_string())
+}
+
+/// Writes each exchange as it happens, so a crash keeps what came before
+#[derive(Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+    count: AtomicUsize,
+}
+
+impl Recorder {
+    /// Starts a recording in `dir`, which must not already hold one
+    pub fn create(dir: &Path) -> io::Result<Recorder> {
+        fs::create_dir_all(dir)?;
+        if dir.join(INDEX).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already holds a recording", dir.display()),
+            ));
+        }
+        Ok(Recorder {
+            dir: dir.to_path_buf(),
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn record(&self, exchange: &Exchange) -> io::Result<()> {
+        let n = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        let name = format!("{:04}.json", n);
+        fs::write(
+            self.dir.join(&name),
+            serde_json::to_string_pretty(exchange)?,
+        )?;
+        let index = Index {
+            exchanges: (1..=n).map(|i| format!("{:04}.json", i)).collect(),
+        };
+        fs::write(self.dir.join(INDEX), serde_json::to_string_pretty(&index)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// More requests were made than were recorded
+    Exhausted(usize),
+    Diverged {
+        /// 1-based, matching the file name
+        exchange: usize,
+        recorded: RequestShape,
+        actual: RequestShape,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Exhausted(n) => {
+                write!(f, "the recording has only {} exchanges", n)
+            }
+            ReplayError::Diverged {
+                exchange,
+                recorded,
+                actual,
+            } => {
+                write!(f, "request {} differs from the recording:", exchange)?;
+                if recorded.model != actual.model {
+                    write!(f, " model {} vs {}", recorded.model, actual.model)?;
+                }
+                if recorded.message_count != actual.message_count {
+                    write!(
+                        f,
+                        " {} messages vs {}",
+                        recorded.message_count, actual.message_count
+                    )?;
+                }
+                if recorded.tools_sha256 != actual.tools_sha256 {
+                    write!(f, " tool configuration changed")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+#[derive(Debug)]
+pub struct Replayer {
+    exchanges: Vec<Exchange>,
+    next: AtomicUsize,
+}
+
+impl Replayer {
+    pub fn open(dir: &Path) -> io::Result<Replayer> {
+        let index: Index = serde_json::from_str(&fs::read_to_string(dir.join(INDEX))?)?;
+        let exchanges = index
+            .exchanges
+            .iter()
+            .map(|name| {
+                let contents = fs::read_to_string(dir.join(name))?;
+                Ok(serde_json::from_str(&contents)?)
+            })
+            .collect::<io::Result<Vec<Exchange>>>()?;
+        Ok(Replayer {
+            exchanges,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.exchanges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exchanges.is_empty()
+    }
+
+    /// The recorded response for `request`, if it matches what was recorded
+    pub fn next(&self, request: &RequestShape) -> Result<&Exchange, ReplayError> {
+        let idx = self.next.fetch_add(1, Ordering::SeqCst);
+        let exchange = self
+            .exchanges
+            .get(idx)
+            .ok_or(ReplayError::Exhausted(self.exchanges.len()))?;
+        if &exchange.request != request {
+            return Err(ReplayError::Diverged {
+                exchange: idx + 1,
+                recorded: exchange.request.clone(),
+                actual: request.clone(),
+            });
+        }
+        Ok(exchange)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{ToolDef, ToolRegistry};
+
+    /// A conversation asking for dinner: the model checks the pantry,
+    /// transmits a recipe, then says so
+    fn pantry_recording() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lib/testdata/recordings/pantry")
+    }
+
+    fn shape(message_count: usize) -> RequestShape {
+        let tools = ToolRegistry::new()
+            .tool(ToolDef::new("check_pantry", "what's at home"))
+            .tool(ToolDef::new("transmit_recipe", "saves a recipe"));
+        RequestShape {
+            model: "us.anthropic.claude-3-5-sonnet-20241022-v2:0".to_string(),
+            message_count,
+            tools_sha256: tools_sha256(Some(&tools.configuration().unwrap())),
+        }
+    }
+
+    #[test]
+    fn replays_in_order() {
+        let replayer = Replayer::open(&pantry_recording()).unwrap();
+        assert_eq!(replayer.len(), 3);
+        for (n, stop_reason) in [(1, "tool_use"), (3, "tool_use"), (5, "end_turn")] {
+            let exchange = replayer.next(&shape(n)).unwrap();
+            assert_eq!(exchange.stop_reason, stop_reason);
+        }
+        assert_eq!(
+            replayer.next(&shape(7)).unwrap_err(),
+            ReplayError::Exhausted(3)
+        );
+    }
+
+    #[test]
+    fn divergence_is_caught() {
+        let replayer = Replayer::open(&pantry_recording()).unwrap();
+        replayer.next(&shape(1)).unwrap();
+        let mut actual = shape(3);
+        actual.tools_sha256 = tools_sha256(None);
+        let err = replayer.next(&actual).unwrap_err();
+        assert_eq!(
+            err,
+            ReplayError::Diverged {
+                exchange: 2,
+                recorded: shape(3),
+                actual,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "request 2 differs from the recording: tool configuration changed"
+        );
+    }
+
+    #[test]
+    fn recordings_read_back() {
+        let dir = std::env::temp_dir().join(format!("gourmand-recording-{}", ulid::Ulid::new()));
+        let recorded = Replayer::open(&pantry_recording()).unwrap();
+        let recorder = Recorder::create(&dir).unwrap();
+        for exchange in &recorded.exchanges {
+            recorder.record(exchange).unwrap();
+        }
+        let err = Recorder::create(&dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        let replayer = Replayer::open(&dir).unwrap();
+        assert_eq!(replayer.len(), recorded.len());
+        for (ours, theirs) in replayer.exchanges.iter().zip(&recorded.exchanges) {
+            assert_eq!(ours.request, theirs.request);
+            assert_eq!(ours.response, theirs.response);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+//! Detecting and repairing message histories that Bedrock would reject.
+//!
+//! If the process dies (or a tool panics) after the assistant asked for a
+//! tool but before the result was sent, the history ends up with a
+//! `tool_use` that has no matching `tool_result`.  Every later request then
+//! fails with a validation error, which makes the session unusable.
+use std::fmt;
+use std::str::FromStr;
+
+use aws_sdk_bedrockruntime::error::BuildError;
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ConversationRole, Message, ToolResultBlock, ToolResultContentBlock,
+    ToolResultStatus,
+};
+use log::error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepairMode {
+    /// Refuse to send a broken history
+    Strict,
+    /// Answer dangling tool uses with an "interrupted" error result
+    #[default]
+    Synthesize,
+    /// Drop the dangling assistant message and the user message before it
+    Drop,
+}
+
+impl fmt::Display for RepairMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepairMode::Strict => write!(f, "strict"),
+            RepairMode::Synthesize => write!(f, "synthesize"),
+            RepairMode::Drop => write!(f, "drop"),
+        }
+    }
+}
+
+impl FromStr for RepairMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(RepairMode::Strict),
+            "synthesize" => Ok(RepairMode::Synthesize),
+            "drop" => Ok(RepairMode::Drop),
+            _ => Err(format!(
+                "unknown repair mode: {} (expected strict, synthesize, or drop)",
+                s
+            )),
+        }
+    }
+}
+
+/// An assistant message whose tool uses aren't all answered by the next message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingToolUse {
+    /// Index of the assistant message
+    pub index: usize,
+    pub tool_use_ids: Vec<String>,
+}
+
+impl fmt::Display for DanglingToolUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "message {} has tool uses without results: {}",
+            self.index,
+            self.tool_use_ids.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for DanglingToolUse {}
+
+/// Finds the first assistant message with unanswered tool uses
+pub fn find_dangling(messages: &[Message]) -> Option<DanglingToolUse> {
+    messages.iter().enumerate().find_map(|(index, msg)| {
+        if msg.role() != &ConversationRole::Assistant {
+            return None;
+        }
+        let answered: Vec<&str> = messages
+            .get(index + 1)
+            .filter(|next| next.role() == &ConversationRole::User)
+            .map(|next| {
+                next.content()
+                    .iter()
+                    .filter_map(|c| c.as_tool_result().ok())
+                    .map(|r| r.tool_use_id())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let tool_use_ids: Vec<String> = msg
+            .content()
+            .iter()
+            .filter_map(|c| c.as_tool_use().ok())
+            .map(|t| t.tool_use_id())
+            .filter(|id| !answered.contains(id))
+            .map(str::to_string)
+            .collect();
+        (!tool_use_ids.is_empty()).then_some(DanglingToolUse {
+            index,
+            tool_use_ids,
+        })
+    })
+}
+
+/// Repairs `messages` in place, returning what was repaired.  In strict mode
+/// nothing is changed and the first problem is returned as an error, as it
+/// is if the results can't be built.
+pub fn repair(
+    messages: &mut Vec<Message>,
+    mode: RepairMode,
+) -> Result<Vec<DanglingToolUse>, DanglingToolUse> {
+    let mut repaired = vec![];
+    while let Some(dangling) = find_dangling(messages) {
+        match mode {
+            RepairMode::Strict => return Err(dangling),
+            RepairMode::Synthesize => {
+                if let Err(e) = synthesize_results(messages, &dangling) {
+                    error!("couldn't synthesize tool results: {}", e);
+                    return Err(dangling);
+                }
+            }
+            RepairMode::Drop => {
+                // the user message before it has to go too, to keep the roles alternating
+                let start = dangling.index.saturating_sub(1);
+                messages.drain(start..=dangling.index);
+            }
+        }
+        repaired.push(dangling);
+    }
+    Ok(repaired)
+}
+
+/// Adds error results to the following user message, or a new one if there isn't one
+fn synthesize_results(
+    messages: &mut Vec<Message>,
+    dangling: &DanglingToolUse,
+) -> Result<(), BuildError> {
+    let results = dangling.tool_use_ids.iter().map(|id| {
+        ToolResultBlock::builder()
+            .tool_use_id(id)
+            .content(ToolResultContentBlock::Text("interrupted".to_string()))
+            .status(ToolResultStatus::Error)
+            .build()
+            .map(ContentBlock::ToolResult)
+    });
+    let next = dangling.index + 1;
+    let mut content: Vec<ContentBlock> = results.collect::<Result<_, _>>()?;
+    if messages
+        .get(next)
+        .is_some_and(|m| m.role() == &ConversationRole::User)
+    {
+        // tool results have to come first in the user's message
+        content.extend(messages[next].content().iter().cloned());
+        messages[next] = rebuild(ConversationRole::User, content)?;
+    } else {
+        messages.insert(next, rebuild(ConversationRole::User, content)?);
+    }
+    Ok(())
+}
+
+fn rebuild(role: ConversationRole, content: Vec<ContentBlock>) -> Result<Message, BuildError> {
+    Message::builder()
+        .role(role)
+        .set_content(Some(content))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_bedrockruntime::types::ToolUseBlock;
+
+    use super::*;
+    use crate::session::json_to_document;
+
+    fn text(text: &str) -> ContentBlock {
+        ContentBlock::Text(text.to_string())
+    }
+
+    fn tool_use(id: &str) -> ContentBlock {
+        ContentBlock::ToolUse(
+            ToolUseBlock::builder()
+                .tool_use_id(id)
+                .name("check_pantry")
+                .input(json_to_document(&serde_json::json!({})))
+                .build()
+                .unwrap(),
+        )
+    }
+
+    fn tool_result(id: &str) -> ContentBlock {
+        ContentBlock::ToolResult(
+            ToolResultBlock::builder()
+                .tool_use_id(id)
+                .content(ToolResultContentBlock::Text("rice, beans".to_string()))
+                .build()
+                .unwrap(),
+        )
+    }
+
+    fn user(content: Vec<ContentBlock>) -> Message {
+        rebuild(ConversationRole::User, content).unwrap()
+    }
+
+    fn assistant(content: Vec<ContentBlock>) -> Message {
+        rebuild(ConversationRole::Assistant, content).unwrap()
+    }
+
+    /// (tool use id, whether it's an error) for each result in `message`
+    fn results(message: &Message) -> Vec<(&str, bool)> {
+        message
+            .content()
+            .iter()
+            .filter_map(|c| c.as_tool_result().ok())
+            .map(|r| {
+                (
+                    r.tool_use_id(),
+                    r.status() == Some(&ToolResultStatus::Error),
+                )
+            })
+            .collect()
+    }
+
+    /// The session died while the tools were running
+    fn died_mid_tool() -> Vec<Message> {
+        vec![
+            user(vec![text("What's for dinner?")]),
+            assistant(vec![
+                text("Let me look in the pantry."),
+                tool_use("t1"),
+                tool_use("t2"),
+            ]),
+        ]
+    }
+
+    #[test]
+    fn answered_histories_are_left_alone() {
+        let mut messages = vec![
+            user(vec![text("What's for dinner?")]),
+            assistant(vec![tool_use("t1")]),
+            user(vec![tool_result("t1")]),
+            assistant(vec![text("Rice and beans!")]),
+        ];
+        assert_eq!(find_dangling(&messages), None);
+        for mode in [RepairMode::Strict, RepairMode::Synthesize, RepairMode::Drop] {
+            assert_eq!(repair(&mut messages, mode), Ok(vec![]));
+            assert_eq!(messages.len(), 4);
+        }
+    }
+
+    #[test]
+    fn strict_refuses() {
+        let mut messages = died_mid_tool();
+        let dangling = DanglingToolUse {
+            index: 1,
+            tool_use_ids: vec!["t1".to_string(), "t2".to_string()],
+        };
+        assert_eq!(find_dangling(&messages), Some(dangling.clone()));
+        assert_eq!(repair(&mut messages, RepairMode::Strict), Err(dangling));
+        assert_eq!(messages, died_mid_tool());
+    }
+
+    #[test]
+    fn synthesize_answers_with_errors() {
+        let mut messages = died_mid_tool();
+        let repaired = repair(&mut messages, RepairMode::Synthesize).unwrap();
+        assert_eq!(repaired.len(), 1);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2].role(), &ConversationRole::User);
+        assert_eq!(results(&messages[2]), [("t1", true), ("t2", true)]);
+        assert_eq!(find_dangling(&messages), None);
+    }
+
+    #[test]
+    fn synthesized_results_go_first() {
+        // one tool answered before the crash, and the user typed on afterwards
+        let mut messages = vec![
+            user(vec![text("What's for dinner?")]),
+            assistant(vec![tool_use("t1"), tool_use("t2")]),
+            user(vec![tool_result("t1"), text("Vegetarian, please.")]),
+            assistant(vec![text("Noted!")]),
+        ];
+        let repaired = repair(&mut messages, RepairMode::Synthesize).unwrap();
+        assert_eq!(repaired[0].tool_use_ids, ["t2"]);
+        assert_eq!(messages.len(), 4);
+        assert_eq!(results(&messages[2]), [("t2", true), ("t1", false)]);
+        assert_eq!(
+            messages[2].content().last().and_then(|c| c.as_text().ok()),
+            Some(&"Vegetarian, please.".to_string())
+        );
+    }
+
+    #[test]
+    fn drop_removes_the_exchange() {
+        let mut messages = died_mid_tool();
+        assert_eq!(repair(&mut messages, RepairMode::Drop).unwrap().len(), 1);
+        assert!(messages.is_empty());
+
+        // resumed after the crash with a new question
+        let mut messages = died_mid_tool();
+        messages.push(user(vec![text("Something quick, then.")]));
+        repair(&mut messages, RepairMode::Drop).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].role(), &ConversationRole::User);
+        assert_eq!(find_dangling(&messages), None);
+    }
+
+    #[test]
+    fn every_dangling_message_is_repaired() {
+        let mut messages = died_mid_tool();
+        messages.push(user(vec![text("Hello?")]));
+        messages.push(assistant(vec![tool_use("t3")]));
+        let repaired = repair(&mut messages, RepairMode::Synthesize).unwrap();
+        let ids: Vec<_> = repaired.iter().map(|d| d.tool_use_ids.clone()).collect();
+        assert_eq!(ids, [vec!["t1", "t2"], vec!["t3"]]);
+        assert_eq!(messages.len(), 5);
+        assert_eq!(find_dangling(&messages), None);
+    }
+
+    #[test]
+    fn modes_round_trip() {
+        for mode in [RepairMode::Strict, RepairMode::Synthesize, RepairMode::Drop] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+        assert!("lenient".parse::<RepairMode>().is_err());
+    }
+}
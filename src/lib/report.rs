@@ -0,0 +1,533 @@
+//! What we actually eat: a breakdown of the recipe history, computed
+//! entirely offline from history.jsonl and the saved recipe files.
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::time::Duration;
+
+use chrono::DateTime;
+
+use crate::equipment;
+use crate::history::{self, Event, HistoryEntry};
+use crate::metadata::RecipeMetadata;
+use crate::recipe::{self, Section};
+use crate::units::Line;
+
+/// How many of the latest recipes the report says why it recommended
+pub const RECENT_REASONS: usize = 5;
+
+/// Keywords by protein, checked against the ingredients.  The first match wins.
+static PROTEINS: &[(&str, &str)] = &[
+    ("chicken", "chicken, turkey, duck"),
+    ("beef", "beef, steak, ground chuck, veal"),
+    ("pork", "pork, bacon, ham, sausage, prosciutto, chorizo"),
+    ("lamb", "lamb"),
+    (
+        "seafood",
+        "fish, salmon, tuna, cod, tilapia, halibut, shrimp, prawn, scallop, crab, mussel",
+    ),
+    ("tofu", "tofu, tempeh, seitan"),
+    ("legumes", "beans, lentil, chickpea, black bean, edamame"),
+    ("eggs", "egg"),
+];
+
+/// Keywords by cuisine, checked against the title and ingredients
+static CUISINES: &[(&str, &str)] = &[
+    (
+        "italian",
+        "pasta, spaghetti, penne, risotto, parmesan, pesto, lasagna, gnocchi",
+    ),
+    (
+        "mexican",
+        "tortilla, taco, salsa, enchilada, quesadilla, jalapeno, burrito",
+    ),
+    (
+        "chinese",
+        "soy sauce, stir fry, stir-fry, hoisin, bok choy, fried rice",
+    ),
+    ("japanese", "miso, teriyaki, mirin, sushi, ramen, udon"),
+    ("indian", "curry, garam masala, turmeric, naan, dal, tikka"),
+    (
+        "thai",
+        "fish sauce, lemongrass, coconut milk, pad thai, thai",
+    ),
+    (
+        "mediterranean",
+        "feta, hummus, tahini, olives, pita, tzatziki",
+    ),
+];
+
+/// Keywords by technique, checked against the instructions.  Every match counts.
+static TECHNIQUES: &[(&str, &str)] = &[
+    ("braised", "braise, braising"),
+    ("roasted", "roast"),
+    ("grilled", "grill, char"),
+    ("stir-fried", "stir fry, stir-fry, wok"),
+    ("seared", "sear, pan-fry, pan fry"),
+    ("baked", "bake, oven"),
+    ("simmered", "simmer, stew"),
+    ("steamed", "steam"),
+    ("deep-fried", "deep fry, deep-fry"),
+    ("slow-cooked", "slow cooker, slow cook"),
+    ("raw", "no-cook, marinate, dress the salad"),
+];
+
+/// What a recipe is like, for asking for more like it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub protein: &'static str,
+    pub cuisine: &'static str,
+    pub techniques: Vec<&'static str>,
+}
+
+impl Profile {
+    /// Classifies a recipe's text the same way the report does
+    pub fn of(text: &str) -> Profile {
+        let ingredients = recipe::section(text, Section::Ingredients).unwrap_or_default();
+        let instructions = recipe::section(text, Section::Instructions).unwrap_or_default();
+        let haystack = format!(
+            "{}\n{}",
+            recipe::title(text).unwrap_or_default(),
+            ingredients
+        );
+        let lower = instructions.to_lowercase();
+        Profile {
+            protein: classify(&ingredients, PROTEINS).unwrap_or("vegetarian"),
+            cuisine: classify(&haystack, CUISINES).unwrap_or("other"),
+            techniques: TECHNIQUES
+                .iter()
+                .filter(|(_, words)| words.split(", ").any(|w| lower.contains(w)))
+                .map(|(name, _)| *name)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} protein", self.protein)?;
+        if self.cuisine != "other" {
+            write!(f, ", {} cuisine", self.cuisine)?;
+        }
+        if !self.techniques.is_empty() {
+            write!(f, ", {}", self.techniques.join(" and "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a lookback like `90d`, `12w`, or `6m` (30 day months)
+pub fn parse_since(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, days_per) = match s.chars().last() {
+        Some('d') => (&s[..s.len() - 1], 1),
+        Some('w') => (&s[..s.len() - 1], 7),
+        Some('m') => (&s[..s.len() - 1], 30),
+        _ => (s, 1),
+    };
+    let n: u64 = number
+        .parse()
+        .map_err(|_| format!("can't parse {} (expected e.g. 90d, 12w, or 6m)", s))?;
+    Ok(Duration::from_secs(n * days_per * 24 * 60 * 60))
+}
+
+fn classify(text: &str, table: &[(&'static str, &str)]) -> Option<&'static str> {
+    let text = text.to_lowercase();
+    table
+        .iter()
+        .find(|(_, words)| words.split(", ").any(|w| text.contains(w)))
+        .map(|(name, _)| *name)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub generated: usize,
+    pub cooked: usize,
+    /// Generated side by side with the recipe that was picked, and left
+    /// out of everything else
+    pub unpicked: usize,
+    /// ISO week (`2026-W41`) to recipes generated that week
+    pub per_week: BTreeMap<String, usize>,
+    pub top_ingredients: Vec<(String, usize)>,
+    pub proteins: Vec<(String, usize)>,
+    pub cuisines: Vec<(String, usize)>,
+    /// Appliances by how many recipes call for them
+    pub equipment: Vec<(String, usize)>,
+    pub avg_prep_minutes: Option<f64>,
+    pub avg_cook_minutes: Option<f64>,
+    /// Month (`2026-10`) to (average rating, number of ratings)
+    pub ratings: BTreeMap<String, (f64, usize)>,
+    /// Titles that came up more than once, with how often
+    pub repeats: Vec<(String, usize)>,
+    /// Favorite titles with how often they were cooked, most cooked first.
+    /// Favorites are listed whenever they were pinned, whatever `since` is.
+    pub favorites: Vec<(String, usize)>,
+    /// The latest titles with why they were recommended, newest first, for
+    /// recipes whose metadata records it
+    pub reasons: Vec<(String, String)>,
+}
+
+fn sorted_counts(counts: BTreeMap<String, usize>, limit: usize) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(limit);
+    counts
+}
+
+fn average(values: &[u32]) -> Option<f64> {
+    (!values.is_empty())
+        .then(|| values.iter().map(|&v| f64::from(v)).sum::<f64>() / values.len() as f64)
+}
+
+impl Report {
+    /// Builds the report from entries at or after `since` (seconds since the
+    /// epoch).  `read` returns a recipe's text given its path, or `None` if
+    /// it's gone; entries without text still count where they can.
+    /// `metadata` returns its `.meta.json`, which tells candidates that
+    /// weren't chosen (see [RecipeMetadata::candidate]) and why the recipe
+    /// was recommended.
+    pub fn build(
+        entries: &[HistoryEntry],
+        since: u64,
+        read: impl Fn(&str) -> Option<String>,
+        metadata: impl Fn(&str) -> Option<RecipeMetadata>,
+    ) -> Report {
+        let mut report = Report::default();
+        let mut ingredients: BTreeMap<String, usize> = BTreeMap::new();
+        let mut proteins: BTreeMap<String, usize> = BTreeMap::new();
+        let mut cuisines: BTreeMap<String, usize> = BTreeMap::new();
+        let mut appliances: BTreeMap<String, usize> = BTreeMap::new();
+        let mut titles: BTreeMap<String, usize> = BTreeMap::new();
+        let mut ratings: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        let mut prep = vec![];
+        let mut cook = vec![];
+        let archived = history::archived(entries);
+        let favorites = history::favorites(entries);
+        let mut favorite_titles: BTreeMap<&str, String> = BTreeMap::new();
+        let mut favorite_cooked: BTreeMap<&str, usize> = BTreeMap::new();
+        for entry in entries {
+            if favorites.contains(entry.path.as_str()) {
+                if let Some(title) = &entry.title {
+                    favorite_titles.insert(&entry.path, title.clone());
+                }
+                if entry.event == Event::Cooked {
+                    *favorite_cooked.entry(&entry.path).or_default() += 1;
+                }
+            }
+        }
+
+        for entry in entries.iter().filter(|e| e.timestamp >= since) {
+            let when = DateTime::from_timestamp(entry.timestamp as i64, 0).unwrap_or_default();
+            if entry.event == Event::Cooked {
+                report.cooked += 1;
+                if let Some(rating) = entry.rating {
+                    let month = when.format("%Y-%m").to_string();
+                    ratings.entry(month).or_default().push(rating);
+                }
+                continue;
+            }
+            if !entry.event.adds_recipe() {
+                continue;
+            }
+            let meta = metadata(&entry.path);
+            if meta.as_ref().is_some_and(|m| m.candidate) {
+                report.unpicked += 1;
+                continue;
+            }
+
+            report.generated += 1;
+            *report
+                .per_week
+                .entry(when.format("%G-W%V").to_string())
+                .or_default() += 1;
+
+            let text = read(&entry.path);
+            let title = entry
+                .title
+                .clone()
+                .or_else(|| text.as_deref().and_then(recipe::title));
+            // archived recipes were put away on purpose, they aren't repeats
+            if let (Some(title), false) = (&title, archived.contains(entry.path.as_str())) {
+                *titles.entry(title.trim().to_lowercase()).or_default() += 1;
+            }
+            if let (Some(title), Some(rationale)) = (&title, meta.and_then(|m| m.rationale)) {
+                report.reasons.push((title.clone(), rationale));
+            }
+            let Some(text) = text else {
+                continue;
+            };
+
+            let ingredient_text = recipe::section(&text, Section::Ingredients).unwrap_or_default();
+            for line in ingredient_text.lines() {
+                let line = line.trim().trim_start_matches(['-', '*', '•']).trim();
+                if !line.is_empty() {
+                    *ingredients.entry(Line::parse(line).key()).or_default() += 1;
+                }
+            }
+            let protein = classify(&ingredient_text, PROTEINS).unwrap_or("vegetarian");
+            *proteins.entry(protein.to_string()).or_default() += 1;
+            let haystack = format!("{}\n{}", title.unwrap_or_default(), ingredient_text);
+            let cuisine = classify(&haystack, CUISINES).unwrap_or("other");
+            *cuisines.entry(cuisine.to_string()).or_default() += 1;
+            for appliance in equipment::detect(&text) {
+                *appliances.entry(equipment::label(appliance)).or_default() += 1;
+            }
+
+            prep.extend(recipe::labeled_minutes(&text, "prep"));
+            cook.extend(recipe::labeled_minutes(&text, "cook"));
+        }
+
+        report.top_ingredients = sorted_counts(ingredients, 10);
+        report.proteins = sorted_counts(proteins, usize::MAX);
+        report.cuisines = sorted_counts(cuisines, usize::MAX);
+        report.equipment = sorted_counts(appliances, usize::MAX);
+        report.repeats = sorted_counts(titles, usize::MAX)
+            .into_iter()
+            .filter(|(_, n)| *n > 1)
+            .collect();
+        report.favorites = favorites
+            .iter()
+            .map(|path| {
+                let title = favorite_titles
+                    .get(path)
+                    .cloned()
+                    .unwrap_or_else(|| path.to_string());
+                (title, favorite_cooked.get(path).copied().unwrap_or(0))
+            })
+            .collect();
+        report
+            .favorites
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        report.reasons.reverse();
+        report.reasons.truncate(RECENT_REASONS);
+        report.avg_prep_minutes = average(&prep);
+        report.avg_cook_minutes = average(&cook);
+        report.ratings = ratings
+            .into_iter()
+            .map(|(month, r)| {
+                let avg = r.iter().map(|&v| f64::from(v)).sum::<f64>() / r.len() as f64;
+                (month, (avg, r.len()))
+            })
+            .collect();
+        report
+    }
+
+    /// Rows of (section, key, value), shared by the table and the CSV
+    fn rows(&self) -> Vec<(&'static str, String, String)> {
+        let mut rows = vec![
+            (
+                "totals",
+                "generated".to_string(),
+                self.generated.to_string(),
+            ),
+            ("totals", "cooked".to_string(), self.cooked.to_string()),
+        ];
+        if self.unpicked > 0 {
+            rows.push((
+                "totals",
+                "unpicked candidates".to_string(),
+                self.unpicked.to_string(),
+            ));
+        }
+        for (minutes, label) in [
+            (self.avg_prep_minutes, "avg prep minutes"),
+            (self.avg_cook_minutes, "avg cook minutes"),
+        ] {
+            if let Some(m) = minutes {
+                rows.push(("totals", label.to_string(), format!("{:.0}", m)));
+            }
+        }
+        rows.extend(
+            self.favorites
+                .iter()
+                .map(|(k, v)| ("favorites", k.clone(), format!("cooked {}", v))),
+        );
+        rows.extend(
+            self.per_week
+                .iter()
+                .map(|(k, v)| ("per week", k.clone(), v.to_string())),
+        );
+        rows.extend(
+            self.top_ingredients
+                .iter()
+                .map(|(k, v)| ("top ingredients", k.clone(), v.to_string())),
+        );
+        rows.extend(
+            self.proteins
+                .iter()
+                .map(|(k, v)| ("protein", k.clone(), v.to_string())),
+        );
+        rows.extend(
+            self.cuisines
+                .iter()
+                .map(|(k, v)| ("cuisine", k.clone(), v.to_string())),
+        );
+        rows.extend(
+            self.equipment
+                .iter()
+                .map(|(k, v)| ("equipment", k.clone(), v.to_string())),
+        );
+        rows.extend(
+            self.ratings
+                .iter()
+                .map(|(k, (avg, n))| ("rating", k.clone(), format!("{:.1} ({} rated)", avg, n))),
+        );
+        rows.extend(
+            self.repeats
+                .iter()
+                .map(|(k, v)| ("repeated", k.clone(), v.to_string())),
+        );
+        rows.extend(
+            self.reasons
+                .iter()
+                .map(|(k, v)| ("why", k.clone(), v.clone())),
+        );
+        rows
+    }
+
+    /// A plain console table, one block per section
+    pub fn render(&self) -> String {
+        let rows = self.rows();
+        let width = rows
+            .iter()
+            .map(|(_, k, _)| k.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut out = String::new();
+        let mut current = "";
+        for (section, key, value) in &rows {
+            if *section != current {
+                if !current.is_empty() {
+                    out.push('\n');
+                }
+                let _ = writeln!(out, "{}", section);
+                current = section;
+            }
+            let _ = writeln!(out, "  {:width$}  {}", key, value, width = width);
+        }
+        out
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("section,key,value\n");
+        for (section, key, value) in self.rows() {
+            let _ = writeln!(out, "{},{},{}", section, csv_field(&key), csv_field(&value));
+        }
+        out
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::golden;
+
+    /// 2026-09-01, after the risotto and before everything else
+    const SINCE: u64 = 1_788_220_800;
+
+    /// The report on `testdata/report`: a history with a favorite, a repeat,
+    /// an unpicked candidate, an entry without a title or id, a recipe
+    /// whose file is gone, and metadata with and without a rationale
+    fn fixture() -> Report {
+        let dir = golden::path("report");
+        let entries: Vec<HistoryEntry> = fs::read_to_string(dir.join("history.jsonl"))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        Report::build(
+            &entries,
+            SINCE,
+            |path| fs::read_to_string(dir.join(path)).ok(),
+            |path| RecipeMetadata::load(&RecipeMetadata::locate(&dir.join(path))).ok(),
+        )
+    }
+
+    #[test]
+    fn renders_the_fixture_history() {
+        golden::check(&fixture().render(), "report/report.txt");
+    }
+
+    #[test]
+    fn writes_the_fixture_history_as_csv() {
+        golden::check(&fixture().to_csv(), "report/report.csv");
+    }
+
+    #[test]
+    fn metadata_from_before_rationales_is_left_out_of_why() {
+        let dir = golden::path("report/recipes");
+        let tacos = RecipeMetadata::load(&dir.join("beef-tacos.meta.json")).unwrap();
+        assert_eq!(tacos.rationale, None);
+        assert!(!tacos.candidate);
+        let report = fixture();
+        let titles: Vec<&str> = report
+            .reasons
+            .iter()
+            .map(|(title, _)| title.as_str())
+            .collect();
+        assert_eq!(titles, ["lemon garlic chicken", "Lemon Garlic Chicken"]);
+    }
+
+    #[test]
+    fn profiles_the_fixture_recipes() {
+        let cases = [
+            ("beef-tacos", "beef", "mexican", vec!["grilled"]),
+            ("chana-masala", "legumes", "indian", vec!["simmered"]),
+            (
+                "lemon-garlic-chicken",
+                "chicken",
+                "other",
+                vec!["roasted", "baked"],
+            ),
+            (
+                "lemon-garlic-chicken-2",
+                "chicken",
+                "other",
+                vec!["seared", "simmered"],
+            ),
+            (
+                "mushroom-risotto",
+                "vegetarian",
+                "italian",
+                vec!["simmered"],
+            ),
+            ("tofu-stir-fry", "tofu", "chinese", vec!["stir-fried"]),
+        ];
+        let dir = golden::path("report/recipes");
+        for (stem, protein, cuisine, techniques) in cases {
+            let text = fs::read_to_string(dir.join(format!("{}.txt", stem))).unwrap();
+            let expected = Profile {
+                protein,
+                cuisine,
+                techniques,
+            };
+            assert_eq!(Profile::of(&text), expected, "{}", stem);
+        }
+    }
+
+    #[test]
+    fn profiles_read_as_a_phrase() {
+        let dir = golden::path("report/recipes");
+        let profile = |stem: &str| {
+            Profile::of(&fs::read_to_string(dir.join(format!("{}.txt", stem))).unwrap()).to_string()
+        };
+        assert_eq!(
+            profile("beef-tacos"),
+            "beef protein, mexican cuisine, grilled"
+        );
+        assert_eq!(
+            profile("lemon-garlic-chicken"),
+            "chicken protein, roasted and baked"
+        );
+        assert_eq!(Profile::of("Toast").to_string(), "vegetarian protein");
+    }
+}
@@ -0,0 +1,125 @@
+//! The protocol `recipes serve` speaks: JSON-RPC 2.0, one JSON object per
+//! line, over a Unix socket.
+//!
+//! ```text
+//! > {"jsonrpc": "2.0", "id": 1, "method": "new_session"}
+//! < {"jsonrpc": "2.0", "id": 1, "result": {"session_id": "..."}}
+//! > {"jsonrpc": "2.0", "id": 2, "method": "send", "params": {"session_id": "...", "prompt": "a quick dinner"}}
+//! < {"jsonrpc": "2.0", "method": "event", "params": {"id": 2, "session_id": "...", "event": {"type": "text_emitted", "data": "..."}}}
+//! < {"jsonrpc": "2.0", "id": 2, "result": {"recipe": "/home/me/recipes/..."}}
+//! ```
+//!
+//! A `send` streams its [TurnEvent]s as `event` notifications tagged with
+//! the request's id, then answers.  The other methods are `get_recipe` and
+//! `close_session`, both with a `session_id`.  Everything here is parsing
+//! and formatting; the sessions themselves live in the binary.
+use serde_json::{json, Value};
+
+use crate::events::TurnEvent;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// No session with that id, or it timed out
+pub const UNKNOWN_SESSION: i64 = -32001;
+/// The session is already answering a `send`
+pub const BUSY: i64 = -32002;
+/// The turn or the method failed
+pub const FAILED: i64 = -32003;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+    NewSession,
+    Send { session_id: String, prompt: String },
+    GetRecipe { session_id: String },
+    CloseSession { session_id: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    /// Echoed in the response, `null` if the request didn't have one
+    pub id: Value,
+    pub call: Call,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+fn string_param(params: &Value, name: &str) -> Result<String, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("missing string param {}", name)))
+}
+
+/// Parses one line.  On failure, also returns the id, if there was one to
+/// answer with.
+pub fn parse(line: &str) -> Result<Request, (Value, RpcError)> {
+    let value: Value = serde_json::from_str(line)
+        .map_err(|e| (Value::Null, RpcError::new(PARSE_ERROR, e.to_string())))?;
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    let fail = |e: RpcError| (id.clone(), e);
+    let Some(method) = value.get("method").and_then(Value::as_str) else {
+        return Err(fail(RpcError::new(INVALID_REQUEST, "missing method")));
+    };
+    let params = value.get("params").cloned().unwrap_or(Value::Null);
+    let call = match method {
+        "new_session" => Call::NewSession,
+        "send" => Call::Send {
+            session_id: string_param(&params, "session_id").map_err(fail)?,
+            prompt: string_param(&params, "prompt").map_err(fail)?,
+        },
+        "get_recipe" => Call::GetRecipe {
+            session_id: string_param(&params, "session_id").map_err(fail)?,
+        },
+        "close_session" => Call::CloseSession {
+            session_id: string_param(&params, "session_id").map_err(fail)?,
+        },
+        other => {
+            return Err(fail(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method {}", other),
+            )))
+        }
+    };
+    Ok(Request { id, call })
+}
+
+/// A success response, as one line without the newline
+pub fn result(id: &Value, result: Value) -> String {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string()
+}
+
+/// An error response, as one line without the newline
+pub fn error(id: &Value, error: &RpcError) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+    .to_string()
+}
+
+/// An event from the `send` with `id`, as one line without the newline
+pub fn event(id: &Value, session_id: &str, event: &TurnEvent) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "event",
+        "params": { "id": id, "session_id": session_id, "event": event },
+    })
+    .to_string()
+}
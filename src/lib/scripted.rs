@@ -0,0 +1,194 @@
+//! A Bedrock runtime client for tests that answers from a script instead
+//! of the network and keeps every request it was sent.
+//!
+//! It sits where the SDK's HTTP client would, so a [Conversation] under
+//! test builds and serializes its requests exactly as it would for
+//! Bedrock, and tests assert on the JSON bodies: the tool configuration,
+//! the inference settings, the messages.
+//!
+//! [Conversation]: crate::conversation::Conversation
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aws_sdk_bedrockruntime::config::{BehaviorVersion, Credentials, Region};
+use aws_sdk_bedrockruntime::Client;
+use aws_smithy_runtime_api::client::http::{
+    HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector,
+};
+use aws_smithy_runtime_api::client::orchestrator::{HttpRequest, HttpResponse};
+use aws_smithy_runtime_api::client::runtime_components::RuntimeComponents;
+use aws_smithy_runtime_api::http::StatusCode;
+use aws_smithy_types::body::SdkBody;
+use aws_smithy_types::retry::RetryConfig;
+use serde_json::{json, Value};
+
+/// One scripted response
+#[derive(Debug, Clone)]
+pub struct Reply {
+    status: u16,
+    body: Value,
+    request_id: Option<String>,
+    delay: Option<Duration>,
+}
+
+impl Reply {
+    /// A Converse response with `content` from the assistant
+    pub fn message(content: Value) -> Reply {
+        Reply::converse(json!({"role": "assistant", "content": content}), "end_turn")
+    }
+
+    /// An answer in plain text
+    pub fn text(text: &str) -> Reply {
+        Reply::message(json!([{"text": text}]))
+    }
+
+    /// A Converse response with `message` as its output
+    pub fn converse(message: Value, stop_reason: &str) -> Reply {
+        Reply {
+            status: 200,
+            body: json!({
+                "output": {"message": message},
+                "stopReason": stop_reason,
+                "usage": {"inputTokens": 100, "outputTokens": 20, "totalTokens": 120},
+                "metrics": {"latencyMs": 250}
+            }),
+            request_id: None,
+            delay: None,
+        }
+    }
+
+    /// A 200 response with `body` as it is, for operations other than
+    /// Converse
+    pub fn json(body: Value) -> Reply {
+        Reply {
+            status: 200,
+            body,
+            request_id: None,
+            delay: None,
+        }
+    }
+
+    /// The same reply, sent only after `delay`
+    pub fn after(self, delay: Duration) -> Reply {
+        Reply {
+            delay: Some(delay),
+            ..self
+        }
+    }
+
+    /// The same reply, with `id` as Bedrock's x-amzn-RequestId
+    pub fn request_id(self, id: &str) -> Reply {
+        Reply {
+            request_id: Some(id.to_string()),
+            ..self
+        }
+    }
+
+    /// An error response, e.g. 403 and `AccessDeniedException`
+    pub fn error(status: u16, kind: &str, message: &str) -> Reply {
+        Reply {
+            status,
+            body: json!({"__type": kind, "message": message}),
+            request_id: None,
+            delay: None,
+        }
+    }
+}
+
+/// A request the client was sent
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    replies: VecDeque<Reply>,
+    requests: Vec<Request>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Scripted {
+    script: Arc<Mutex<Script>>,
+}
+
+impl Scripted {
+    /// A backend that answers with `replies` in order, and with a 500 once
+    /// they run out
+    pub fn new(replies: impl IntoIterator<Item = Reply>) -> Scripted {
+        Scripted {
+            script: Arc::new(Mutex::new(Script {
+                replies: replies.into_iter().collect(),
+                requests: vec![],
+            })),
+        }
+    }
+
+    /// A runtime client that sends its requests here, without retries
+    pub fn client(&self) -> Client {
+        Client::from_conf(
+            aws_sdk_bedrockruntime::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(Region::new("us-east-1"))
+                .credentials_provider(Credentials::new("AKIDTEST", "secret", None, None, "test"))
+                .retry_config(RetryConfig::disabled())
+                .http_client(self.clone())
+                .build(),
+        )
+    }
+
+    /// Every request sent so far, oldest first
+    pub fn requests(&self) -> Vec<Request> {
+        self.script.lock().unwrap().requests.clone()
+    }
+
+    /// The body of the most recent request
+    pub fn last_body(&self) -> Value {
+        self.requests()
+            .last()
+            .map(|r| r.body.clone())
+            .expect("no request was sent")
+    }
+}
+
+impl HttpConnector for Scripted {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let body = request
+            .body()
+            .bytes()
+            .and_then(|bytes| serde_json::from_slice(bytes).ok())
+            .unwrap_or(Value::Null);
+        let reply = {
+            let mut script = self.script.lock().unwrap();
+            script.requests.push(Request { body });
+            script.replies.pop_front().unwrap_or_else(|| {
+                Reply::error(500, "InternalServerException", "the script ran out")
+            })
+        };
+        HttpConnectorFuture::new(async move {
+            if let Some(delay) = reply.delay {
+                tokio::time::sleep(delay).await;
+            }
+            let status = StatusCode::try_from(reply.status).expect("a valid status");
+            let mut response = HttpResponse::new(status, SdkBody::from(reply.body.to_string()));
+            response
+                .headers_mut()
+                .insert("content-type", "application/json");
+            if let Some(id) = reply.request_id {
+                response.headers_mut().insert("x-amzn-RequestId", id);
+            }
+            Ok(response)
+        })
+    }
+}
+
+impl HttpClient for Scripted {
+    fn http_connector(
+        &self,
+        _settings: &HttpConnectorSettings,
+        _components: &RuntimeComponents,
+    ) -> SharedHttpConnector {
+        SharedHttpConnector::new(self.clone())
+    }
+}
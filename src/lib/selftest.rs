@@ -0,0 +1,295 @@
+//! Checks for `recipes selftest`: probes of the environment (locale,
+//! terminal, filesystem) and of the local parsing and formatting code
+//! against fixed inputs.  Nothing here talks to AWS.
+//!
+//! Each check returns a [Check] like the ones in [crate::doctor], so the
+//! output is the same checklist and can be pasted into a bug report.
+use std::fs;
+use std::path::Path;
+
+use crate::doctor::{Check, Status};
+use crate::metadata::RecipeMetadata;
+use crate::paths;
+use crate::recipe::{self, Section};
+use crate::system_prompts::{self, Preset};
+use crate::ui;
+use crate::units::{self, Quantity};
+
+const FIXTURE: &str = "\
+Lemony Spinach Pasta
+
+Ingredients:
+- 8 oz spaghetti
+- 2 cups spinach
+- 1 1/2 tbsp olive oil
+
+Instructions:
+1. Boil the spaghetti for 9 minutes.
+2. Toss with the spinach and olive oil.
+
+Shopping List:
+- spaghetti
+- spinach
+";
+
+/// Languages that write decimals with a comma, by locale prefix
+const DECIMAL_COMMA: &[&str] = &[
+    "de", "fr", "es", "it", "pt", "nl", "ru", "pl", "sv", "da", "nb", "fi", "cs", "tr",
+];
+
+fn locale_var(names: &[&str]) -> Option<(String, String)> {
+    names.iter().find_map(|name| {
+        let value = std::env::var(name).ok().filter(|v| !v.is_empty())?;
+        Some((name.to_string(), value))
+    })
+}
+
+/// Whether the numeric locale writes "1,5", which the model may copy into
+/// quantities
+pub fn probe_locale() -> Check {
+    let name = "numeric locale";
+    let Some((var, locale)) = locale_var(&["LC_ALL", "LC_NUMERIC", "LANG"]) else {
+        return Check::new(name, Status::Pass, "not set, decimals use a point");
+    };
+    let language = locale.split(['_', '.', '-']).next().unwrap_or("");
+    if DECIMAL_COMMA.contains(&language) {
+        Check::new(
+            name,
+            Status::Warn,
+            format!(
+                "{}={} writes decimals with a comma; quantities like \"1,5 cups\" won't add up",
+                var, locale
+            ),
+        )
+    } else {
+        Check::new(name, Status::Pass, format!("{}={}", var, locale))
+    }
+}
+
+/// Whether the terminal is expected to show non-ASCII text
+pub fn probe_unicode() -> Check {
+    let name = "unicode terminal";
+    match locale_var(&["LC_ALL", "LC_CTYPE", "LANG"]) {
+        Some((var, locale)) if locale.to_lowercase().replace('-', "").contains("utf8") => {
+            Check::new(name, Status::Pass, format!("{}={}", var, locale))
+        }
+        Some((var, locale)) => Check::new(
+            name,
+            Status::Warn,
+            format!(
+                "{}={} isn't UTF-8, accented titles may not display",
+                var, locale
+            ),
+        ),
+        None if cfg!(windows) => Check::new(name, Status::Pass, "windows console"),
+        None => Check::new(
+            name,
+            Status::Warn,
+            "no locale set, accented titles may not display",
+        ),
+    }
+}
+
+/// Whether `dir` is on a case-insensitive filesystem, where stems that
+/// differ only in case would overwrite each other
+pub fn probe_case_sensitivity(dir: &Path) -> Check {
+    let name = "filesystem case";
+    let upper = dir.join(".Selftest-Probe");
+    let lower = dir.join(".selftest-probe");
+    let result = fs::create_dir_all(dir).and_then(|_| fs::write(&upper, b"probe"));
+    if let Err(e) = result {
+        return Check::new(name, Status::Fail, format!("{}: {}", dir.display(), e));
+    }
+    let insensitive = lower.exists();
+    let _ = fs::remove_file(&upper);
+    if insensitive {
+        Check::new(
+            name,
+            Status::Warn,
+            format!(
+                "{} is case-insensitive; stems are lowercased, but files copied in by hand may collide",
+                dir.display()
+            ),
+        )
+    } else {
+        Check::new(
+            name,
+            Status::Pass,
+            format!("{} is case-sensitive", dir.display()),
+        )
+    }
+}
+
+/// A failure message if `got` isn't `want`
+fn case<T: PartialEq + std::fmt::Debug>(what: &str, got: T, want: T) -> Option<String> {
+    (got != want).then(|| format!("{}: got {:?}, expected {:?}", what, got, want))
+}
+
+fn compare(name: &str, cases: Vec<Option<String>>) -> Check {
+    let total = cases.len();
+    let failures: Vec<String> = cases.into_iter().flatten().collect();
+    if failures.is_empty() {
+        Check::new(name, Status::Pass, format!("{} cases", total))
+    } else {
+        Check::new(name, Status::Fail, failures.join("; "))
+    }
+}
+
+/// Titles, sections and steps of a fixture recipe
+pub fn check_sections() -> Check {
+    let ingredients = recipe::section(FIXTURE, Section::Ingredients).unwrap_or_default();
+    let instructions = recipe::section(FIXTURE, Section::Instructions).unwrap_or_default();
+    let shopping = recipe::section(FIXTURE, Section::ShoppingList).unwrap_or_default();
+    compare(
+        "section parser",
+        vec![
+            case(
+                "title",
+                recipe::title(FIXTURE),
+                Some("Lemony Spinach Pasta".to_string()),
+            ),
+            case("ingredients", ingredients.lines().count(), 3),
+            case("steps", recipe::parse_steps(&instructions).len(), 2),
+            case("shopping list", shopping.lines().count(), 2),
+        ],
+    )
+}
+
+/// Parsing and adding quantities
+pub fn check_quantities() -> Check {
+    let amount = |text: &str| Quantity::parse(text).map(|(q, _)| q.low);
+    let consolidated = units::consolidate(&["1 cup flour", "1/2 cup flour", "2 eggs"]);
+    compare(
+        "quantity math",
+        vec![
+            case("decimal", amount("1.5 cups rice"), Some(1.5)),
+            case("fraction", amount("3/4 tsp salt"), Some(0.75)),
+            case("mixed number", amount("1 1/2 tbsp oil"), Some(1.5)),
+            case("consolidated lines", consolidated.len() as f64, 2.0),
+        ],
+    )
+}
+
+/// File stems from titles the model has actually sent
+pub fn check_sanitizer() -> Check {
+    compare(
+        "stem sanitizer",
+        vec![
+            case(
+                "accents",
+                recipe::slugify("Crème Brûlée (Easy!)"),
+                Some("creme_brulee_easy".to_string()),
+            ),
+            case("emoji only", recipe::slugify("🍝🍝"), None),
+            case(
+                "reserved name",
+                recipe::portable_stem("con"),
+                "con_".to_string(),
+            ),
+            case(
+                "separators",
+                recipe::portable_stem("a/b:c"),
+                "a_b_c".to_string(),
+            ),
+        ],
+    )
+}
+
+/// `~` expansion and abbreviation, and finding metadata from a recipe path
+pub fn check_paths() -> Check {
+    let home = Path::new("~").join("recipes").display().to_string();
+    let expanded = paths::expand(&home);
+    compare(
+        "path round trip",
+        vec![
+            case("home", paths::display(&expanded), home),
+            case(
+                "metadata",
+                RecipeMetadata::locate(Path::new("out/pasta_1234.txt")),
+                RecipeMetadata::path_for("out/pasta_1234"),
+            ),
+        ],
+    )
+}
+
+/// Wrapping the fixture at the terminal's width, or 40 columns without one
+pub fn check_rendering() -> Check {
+    let name = "wrapping";
+    let width = ui::output_width(None).unwrap_or(40).max(20);
+    let wrapped = ui::wrap(FIXTURE, width);
+    let too_wide: Vec<&str> = wrapped
+        .lines()
+        .filter(|l| l.chars().count() > width)
+        .collect();
+    if too_wide.is_empty() {
+        Check::new(name, Status::Pass, format!("{} columns", width))
+    } else {
+        Check::new(
+            name,
+            Status::Fail,
+            format!("lines over {} columns: {:?}", width, too_wide),
+        )
+    }
+}
+
+/// The family preset against the prompt it was split from, so existing
+/// users keep the same system prompt.  Reports where they first differ.
+pub fn check_system_prompts() -> Check {
+    let first_difference = |got: &str, want: &str| {
+        (got != want).then(|| {
+            got.bytes()
+                .zip(want.bytes())
+                .position(|(a, b)| a != b)
+                .unwrap_or(got.len().min(want.len()))
+        })
+    };
+    compare(
+        "system prompt",
+        vec![case(
+            "family, byte",
+            first_difference(&Preset::Family.text(), system_prompts::SYS_PROMPT2),
+            None,
+        )],
+    )
+}
+
+/// Every check, with the filesystem probe in `output_dir`
+pub fn run(output_dir: &Path) -> Vec<Check> {
+    vec![
+        probe_locale(),
+        probe_unicode(),
+        probe_case_sensitivity(output_dir),
+        check_sections(),
+        check_quantities(),
+        check_sanitizer(),
+        check_paths(),
+        check_rendering(),
+        check_system_prompts(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Everything [run] does but the probes, whose answers depend on the
+    /// machine
+    #[test]
+    fn every_pure_check_passes() {
+        let dir = std::env::temp_dir().join(format!("gourmand-selftest-{}", ulid::Ulid::new()));
+        let probes = [
+            probe_locale().name,
+            probe_unicode().name,
+            probe_case_sensitivity(&dir).name,
+        ];
+        let checks: Vec<Check> = run(&dir)
+            .into_iter()
+            .filter(|check| !probes.contains(&check.name))
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(checks.len(), 6);
+        for check in checks {
+            assert_eq!(check.status, Status::Pass, "{:?}", check);
+        }
+    }
+}
@@ -0,0 +1,827 @@
+//! Saving and loading conversations.
+//!
+//! Bedrock's `Message` type isn't serializable, so sessions are stored in a
+//! format of our own, a [SessionFile], and converted at the edges.  Autosave,
+//! `save`, `--resume`, the save on SIGHUP, and `serve` all use it.
+//!
+//! The file has a version.  Files from before it was versioned read as 0,
+//! and loading one written by an older build runs each of the [MIGRATIONS]
+//! from its version up, so old sessions keep loading.  Images the assistant
+//! returned aren't inlined: they're written once to [IMAGES_DIR] next to the
+//! session file, named by their hash, and the session refers to them by
+//! relative path.
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ConversationRole, ImageBlock, ImageFormat, ImageSource, Message, ToolResultBlock,
+    ToolResultContentBlock, ToolResultStatus, ToolUseBlock,
+};
+use aws_smithy_types::{Blob, Document, Number};
+use base64::Engine;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::citations::CitedText;
+use crate::cost::SessionCost;
+use crate::paths;
+
+/// The version of the session format written by this build.  Files from
+/// before it was versioned read as 0.  1 added the settings, usage, and
+/// recipes, and JSON content in tool results, and moved images out of the
+/// file.
+pub const SESSION_VERSION: u32 = 1;
+
+/// Where images are kept, next to the session files
+pub const IMAGES_DIR: &str = "images";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// An image in [IMAGES_DIR]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ImageRef {
+    /// png, jpeg, gif, or webp
+    pub format: String,
+    /// Relative to the session file's directory
+    pub path: String,
+    /// Held from conversion until [SessionFile::save] writes them, and read
+    /// back by [SessionFile::load]
+    #[serde(skip)]
+    pub bytes: Option<Vec<u8>>,
+}
+
+impl ImageRef {
+    fn new(format: &str, bytes: Vec<u8>) -> ImageRef {
+        let hash: String = Sha256::digest(&bytes)
+            .iter()
+            .take(8)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        ImageRef {
+            format: format.to_string(),
+            path: format!("{}/{}.{}", IMAGES_DIR, hash, format),
+            bytes: Some(bytes),
+        }
+    }
+
+    fn to_bedrock(&self) -> Option<ImageBlock> {
+        let Some(bytes) = &self.bytes else {
+            warn!("image {} wasn't loaded, leaving it out", self.path);
+            return None;
+        };
+        ImageBlock::builder()
+            .format(ImageFormat::from(self.format.as_str()))
+            .source(ImageSource::Bytes(Blob::new(bytes.clone())))
+            .build()
+            .ok()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Content {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        tool_use_id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        /// success or error, when the result said
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<String>,
+        content: Vec<ResultContent>,
+    },
+    /// An image the assistant returned
+    Image(ImageRef),
+    /// Text the assistant drew from an attached document, with what it
+    /// cited.  Sent back to the model as plain text.
+    Citations(CitedText),
+}
+
+/// What a tool result can hold
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResultContent {
+    Text { text: String },
+    Json { json: serde_json::Value },
+    Image(ImageRef),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredMessage {
+    pub role: Role,
+    pub content: Vec<Content>,
+}
+
+impl StoredMessage {
+    fn images(&self) -> Vec<&ImageRef> {
+        let mut images = vec![];
+        for content in &self.content {
+            match content {
+                Content::Image(image) => images.push(image),
+                Content::ToolResult { content, .. } => {
+                    images.extend(content.iter().filter_map(|r| match r {
+                        ResultContent::Image(image) => Some(image),
+                        _ => None,
+                    }))
+                }
+                _ => (),
+            }
+        }
+        images
+    }
+
+    fn images_mut(&mut self) -> Vec<&mut ImageRef> {
+        let mut images = vec![];
+        for content in &mut self.content {
+            match content {
+                Content::Image(image) => images.push(image),
+                Content::ToolResult { content, .. } => {
+                    images.extend(content.iter_mut().filter_map(|r| match r {
+                        ResultContent::Image(image) => Some(image),
+                        _ => None,
+                    }))
+                }
+                _ => (),
+            }
+        }
+        images
+    }
+}
+
+/// Settings the session was run with, for reference when it's resumed
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub system_prompt_sha256: String,
+    pub tools: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub live_turns: Option<usize>,
+}
+
+/// A recipe transmitted during the session
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecipeRef {
+    pub title: String,
+    /// The recipe's `.txt`
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionFile {
+    pub version: u32,
+    /// Seconds since the unix epoch
+    #[serde(default)]
+    pub created: u64,
+    pub model: String,
+    #[serde(default)]
+    pub settings: Settings,
+    pub messages: Vec<StoredMessage>,
+    #[serde(default)]
+    pub usage: SessionCost,
+    #[serde(default)]
+    pub recipes: Vec<RecipeRef>,
+}
+
+/// Seconds since the unix epoch, for [SessionFile::created]
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl SessionFile {
+    pub fn from_messages(model: &str, messages: &[Message]) -> SessionFile {
+        SessionFile {
+            version: SESSION_VERSION,
+            created: now(),
+            model: model.to_string(),
+            settings: Settings::default(),
+            messages: messages.iter().map(to_stored_message).collect(),
+            usage: SessionCost::default(),
+            recipes: vec![],
+        }
+    }
+
+    pub fn to_messages(&self) -> Vec<Message> {
+        self.messages
+            .iter()
+            .filter_map(to_bedrock_message)
+            .collect()
+    }
+
+    /// Loads a session written by this or an older build, reading its
+    /// images.  An image that's gone missing is left out with a warning.
+    pub fn load(path: &Path) -> io::Result<SessionFile> {
+        let mut value: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        migrate(&mut value, path)?;
+        let mut session: SessionFile = serde_json::from_value(value)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for image in session
+            .messages
+            .iter_mut()
+            .flat_map(StoredMessage::images_mut)
+        {
+            match fs::read(dir.join(&image.path)) {
+                Ok(bytes) => image.bytes = Some(bytes),
+                Err(e) => warn!("couldn't read {}: {}", dir.join(&image.path).display(), e),
+            }
+        }
+        Ok(session)
+    }
+
+    /// Writes atomically, so a crash mid-write can't corrupt an existing
+    /// session.  Images not yet in [IMAGES_DIR] are written first.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for image in self.messages.iter().flat_map(StoredMessage::images) {
+            let image_path = dir.join(&image.path);
+            if let (Some(bytes), false) = (&image.bytes, image_path.exists()) {
+                paths::write_atomic(&image_path, bytes)?;
+            }
+        }
+        paths::write_atomic(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Upgrades a session file at `path` from one version to the next
+type Migration = fn(&mut serde_json::Value, &Path) -> io::Result<()>;
+
+/// The migration from each version to the next, indexed by the version it
+/// upgrades from
+pub const MIGRATIONS: &[Migration] = &[v0_to_v1];
+
+/// Brings a session file up to [SESSION_VERSION]
+pub fn migrate(value: &mut serde_json::Value, path: &Path) -> io::Result<()> {
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    if version > SESSION_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} is a version {} session, this build reads up to version {}",
+                path.display(),
+                version,
+                SESSION_VERSION
+            ),
+        ));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(value, path)?;
+    }
+    Ok(())
+}
+
+/// Tool results became a list of blocks, keeping their status, and inline
+/// base64 images moved out to [IMAGES_DIR]
+fn v0_to_v1(value: &mut serde_json::Value, path: &Path) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+    let Some(session) = value.as_object_mut() else {
+        return Err(invalid("a session must be a JSON object"));
+    };
+    let messages = session
+        .get_mut("messages")
+        .and_then(|m| m.as_array_mut())
+        .ok_or_else(|| invalid("a session must have messages"))?;
+    for block in messages
+        .iter_mut()
+        .filter_map(|m| m.get_mut("content").and_then(|c| c.as_array_mut()))
+        .flatten()
+    {
+        let Some(block) = block.as_object_mut() else {
+            continue;
+        };
+        match block.get("type").and_then(|t| t.as_str()) {
+            Some("tool_result") => {
+                let text = block.remove("text").unwrap_or_default();
+                block.insert(
+                    "content".to_string(),
+                    serde_json::json!([{ "type": "text", "text": text }]),
+                );
+            }
+            Some("image") => {
+                let data = block
+                    .remove("data")
+                    .and_then(|d| d.as_str().map(str::to_string))
+                    .unwrap_or_default();
+                let format = block
+                    .get("format")
+                    .and_then(|f| f.as_str())
+                    .unwrap_or("png")
+                    .to_string();
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let image = ImageRef::new(&format, bytes);
+                let image_path = dir.join(&image.path);
+                if !image_path.exists() {
+                    paths::write_atomic(&image_path, image.bytes.unwrap_or_default())?;
+                }
+                block.insert("path".to_string(), image.path.into());
+            }
+            _ => (),
+        }
+    }
+    let created = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    session.insert("created".to_string(), created.into());
+    session.insert("version".to_string(), 1.into());
+    Ok(())
+}
+
+/// The most recently modified `.json` session in `dir`
+pub fn most_recent(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let mut newest: Option<(std::time::SystemTime, PathBuf)> = None;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let modified = fs::metadata(&path)?.modified()?;
+        if newest.as_ref().is_none_or(|(t, _)| modified > *t) {
+            newest = Some((modified, path));
+        }
+    }
+    Ok(newest.map(|(_, path)| path))
+}
+
+fn image_ref(image: &ImageBlock) -> Option<ImageRef> {
+    match image.source() {
+        Some(ImageSource::Bytes(bytes)) => Some(ImageRef::new(
+            image.format().as_str(),
+            bytes.as_ref().to_vec(),
+        )),
+        _ => {
+            warn!("not saving image without bytes: {:?}", image.format());
+            None
+        }
+    }
+}
+
+pub(crate) fn to_stored_message(msg: &Message) -> StoredMessage {
+    let role = match msg.role() {
+        ConversationRole::Assistant => Role::Assistant,
+        _ => Role::User,
+    };
+    let content = msg
+        .content()
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text(text) => Some(Content::Text { text: text.clone() }),
+            ContentBlock::ToolUse(tool_use) => Some(Content::ToolUse {
+                tool_use_id: tool_use.tool_use_id().to_string(),
+                name: tool_use.name().to_string(),
+                input: document_to_json(tool_use.input()),
+            }),
+            ContentBlock::ToolResult(result) => Some(Content::ToolResult {
+                tool_use_id: result.tool_use_id().to_string(),
+                status: result.status().map(|s| s.as_str().to_string()),
+                content: result
+                    .content()
+                    .iter()
+                    .filter_map(|c| match c {
+                        ToolResultContentBlock::Text(text) => {
+                            Some(ResultContent::Text { text: text.clone() })
+                        }
+                        ToolResultContentBlock::Json(json) => Some(ResultContent::Json {
+                            json: document_to_json(json),
+                        }),
+                        ToolResultContentBlock::Image(image) => {
+                            image_ref(image).map(ResultContent::Image)
+                        }
+                        other => {
+                            warn!("not saving unsupported tool result content: {:?}", other);
+                            None
+                        }
+                    })
+                    .collect(),
+            }),
+            ContentBlock::Image(image) => image_ref(image).map(Content::Image),
+            ContentBlock::CitationsContent(cited) => Some(Content::Citations(cited.into())),
+            other => {
+                warn!("not saving unsupported content: {:?}", other);
+                None
+            }
+        })
+        .collect();
+    StoredMessage { role, content }
+}
+
+pub(crate) fn to_bedrock_message(msg: &StoredMessage) -> Option<Message> {
+    let role = match msg.role {
+        Role::User => ConversationRole::User,
+        Role::Assistant => ConversationRole::Assistant,
+    };
+    let content = msg
+        .content
+        .iter()
+        .filter_map(|c| match c {
+            Content::Text { text } => Some(ContentBlock::Text(text.clone())),
+            Content::ToolUse {
+                tool_use_id,
+                name,
+                input,
+            } => ToolUseBlock::builder()
+                .tool_use_id(tool_use_id)
+                .name(name)
+                .input(json_to_document(input))
+                .build()
+                .ok()
+                .map(ContentBlock::ToolUse),
+            Content::ToolResult {
+                tool_use_id,
+                status,
+                content,
+            } => ToolResultBlock::builder()
+                .tool_use_id(tool_use_id)
+                .set_status(status.as_deref().map(ToolResultStatus::from))
+                .set_content(Some(
+                    content
+                        .iter()
+                        .filter_map(|r| match r {
+                            ResultContent::Text { text } => {
+                                Some(ToolResultContentBlock::Text(text.clone()))
+                            }
+                            ResultContent::Json { json } => {
+                                Some(ToolResultContentBlock::Json(json_to_document(json)))
+                            }
+                            ResultContent::Image(image) => {
+                                image.to_bedrock().map(ToolResultContentBlock::Image)
+                            }
+                        })
+                        .collect(),
+                ))
+                .build()
+                .ok()
+                .map(ContentBlock::ToolResult),
+            Content::Image(image) => image.to_bedrock().map(ContentBlock::Image),
+            Content::Citations(cited) => Some(ContentBlock::Text(cited.text())),
+        })
+        .collect::<Vec<_>>();
+    Message::builder()
+        .role(role)
+        .set_content(Some(content))
+        .build()
+        .ok()
+}
+
+pub fn document_to_json(doc: &Document) -> serde_json::Value {
+    match doc {
+        Document::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), document_to_json(v)))
+                .collect(),
+        ),
+        Document::Array(items) => {
+            serde_json::Value::Array(items.iter().map(document_to_json).collect())
+        }
+        Document::Number(Number::PosInt(n)) => serde_json::Value::from(*n),
+        Document::Number(Number::NegInt(n)) => serde_json::Value::from(*n),
+        Document::Number(Number::Float(n)) => serde_json::Value::from(*n),
+        Document::String(s) => serde_json::Value::String(s.clone()),
+        Document::Bool(b) => serde_json::Value::Bool(*b),
+        Document::Null => serde_json::Value::Null,
+    }
+}
+
+pub fn json_to_document(value: &serde_json::Value) -> Document {
+    match value {
+        serde_json::Value::Object(map) => Document::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), json_to_document(v)))
+                .collect::<HashMap<_, _>>(),
+        ),
+        serde_json::Value::Array(items) => {
+            Document::Array(items.iter().map(json_to_document).collect())
+        }
+        serde_json::Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                Document::Number(Number::PosInt(u))
+            } else if let Some(i) = n.as_i64() {
+                Document::Number(Number::NegInt(i))
+            } else {
+                Document::Number(Number::Float(n.as_f64().unwrap_or_default()))
+            }
+        }
+        serde_json::Value::String(s) => Document::String(s.clone()),
+        serde_json::Value::Bool(b) => Document::Bool(*b),
+        serde_json::Value::Null => Document::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ulid::Ulid;
+
+    use super::*;
+
+    fn tool_result(id: &str, status: Option<ToolResultStatus>) -> Message {
+        Message::builder()
+            .role(ConversationRole::User)
+            .content(ContentBlock::ToolResult(
+                ToolResultBlock::builder()
+                    .tool_use_id(id)
+                    .set_status(status)
+                    .content(ToolResultContentBlock::Text("result".to_string()))
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap()
+    }
+
+    fn statuses(messages: &[Message]) -> Vec<Option<ToolResultStatus>> {
+        messages
+            .iter()
+            .flat_map(Message::content)
+            .filter_map(|c| c.as_tool_result().ok())
+            .map(|r| r.status().cloned())
+            .collect()
+    }
+
+    #[test]
+    fn tool_result_status_survives_a_save() {
+        let messages = vec![
+            tool_result("a", Some(ToolResultStatus::Error)),
+            tool_result("b", Some(ToolResultStatus::Success)),
+            tool_result("c", None),
+        ];
+        let dir = std::env::temp_dir().join(format!("gourmand-session-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.json");
+        SessionFile::from_messages("model", &messages)
+            .save(&path)
+            .unwrap();
+        let loaded = SessionFile::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(statuses(&loaded.to_messages()), statuses(&messages));
+    }
+
+    #[test]
+    fn cited_answers_resume_as_text() {
+        let stored: StoredMessage = serde_json::from_value(serde_json::json!({
+            "role": "assistant",
+            "content": [
+                {"type": "text", "text": "From your notes: "},
+                {
+                    "type": "citations",
+                    "content": [{"text": "knead for ten minutes."}],
+                    "citations": [{
+                        "title": "Bread Basics",
+                        "sourceContent": [{"text": "Knead 10 min"}]
+                    }]
+                }
+            ]
+        }))
+        .unwrap();
+        let message = to_bedrock_message(&stored).unwrap();
+        let text: Vec<&str> = message
+            .content()
+            .iter()
+            .filter_map(|c| c.as_text().ok())
+            .map(String::as_str)
+            .collect();
+        assert_eq!(text, ["From your notes: ", "knead for ten minutes."]);
+    }
+
+    #[test]
+    fn unversioned_tool_results_keep_their_status() {
+        let dir = std::env::temp_dir().join(format!("gourmand-session-v0-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.json");
+        let v0 = serde_json::json!({
+            "model": "model",
+            "messages": [{
+                "role": "user",
+                "content": [{
+                    "type": "tool_result",
+                    "tool_use_id": "a",
+                    "status": "error",
+                    "text": "no such recipe"
+                }]
+            }]
+        });
+        fs::write(&path, v0.to_string()).unwrap();
+        let loaded = SessionFile::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            statuses(&loaded.to_messages()),
+            vec![Some(ToolResultStatus::Error)]
+        );
+    }
+
+    fn risotto_png() -> Vec<u8> {
+        fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/lib/testdata/images/risotto.png"))
+            .unwrap()
+    }
+
+    fn image(bytes: &[u8]) -> ImageBlock {
+        ImageBlock::builder()
+            .format(ImageFormat::Png)
+            .source(ImageSource::Bytes(Blob::new(bytes.to_vec())))
+            .build()
+            .unwrap()
+    }
+
+    fn message(role: ConversationRole, content: Vec<ContentBlock>) -> Message {
+        Message::builder()
+            .role(role)
+            .set_content(Some(content))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn every_kind_of_block_survives_a_save() {
+        let png = risotto_png();
+        let result = |id: &str, status, content| {
+            ContentBlock::ToolResult(
+                ToolResultBlock::builder()
+                    .tool_use_id(id)
+                    .status(status)
+                    .content(content)
+                    .build()
+                    .unwrap(),
+            )
+        };
+        let messages = vec![
+            message(
+                ConversationRole::User,
+                vec![ContentBlock::Text("something with leeks".to_string())],
+            ),
+            message(
+                ConversationRole::Assistant,
+                vec![
+                    ContentBlock::Text("Let me check what's in season.".to_string()),
+                    ContentBlock::ToolUse(
+                        ToolUseBlock::builder()
+                            .tool_use_id("a")
+                            .name("seasonal_produce")
+                            .input(json_to_document(&serde_json::json!({"month": 10})))
+                            .build()
+                            .unwrap(),
+                    ),
+                ],
+            ),
+            message(
+                ConversationRole::User,
+                vec![
+                    result(
+                        "a",
+                        ToolResultStatus::Success,
+                        ToolResultContentBlock::Json(json_to_document(
+                            &serde_json::json!({"in_season": ["leeks", "squash"]}),
+                        )),
+                    ),
+                    result(
+                        "b",
+                        ToolResultStatus::Error,
+                        ToolResultContentBlock::Text("no such recipe".to_string()),
+                    ),
+                    result(
+                        "c",
+                        ToolResultStatus::Success,
+                        ToolResultContentBlock::Image(image(&png)),
+                    ),
+                ],
+            ),
+            message(
+                ConversationRole::Assistant,
+                vec![
+                    ContentBlock::Image(image(&png)),
+                    ContentBlock::Text("Here's the risotto.".to_string()),
+                ],
+            ),
+        ];
+        let dir = std::env::temp_dir().join(format!("gourmand-session-{}", Ulid::new()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.json");
+        SessionFile::from_messages("model", &messages)
+            .save(&path)
+            .unwrap();
+
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let image_path = written["messages"][3]["content"][0]["path"]
+            .as_str()
+            .unwrap();
+        assert!(
+            image_path.starts_with("images/") && image_path.ends_with(".png"),
+            "{}",
+            image_path
+        );
+        assert_eq!(
+            written["messages"][2]["content"][2]["content"][0]["path"], image_path,
+            "the same image is stored once"
+        );
+        assert_eq!(fs::read(dir.join(image_path)).unwrap(), png);
+
+        let loaded = SessionFile::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.version, SESSION_VERSION);
+        assert_eq!(loaded.to_messages(), messages);
+    }
+
+    #[test]
+    fn unversioned_sessions_move_their_images_out() {
+        let png = risotto_png();
+        let dir = std::env::temp_dir().join(format!("gourmand-session-v0-{}", Ulid::new()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.json");
+        let v0 = serde_json::json!({
+            "model": "model",
+            "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "show me"}]},
+                {"role": "assistant", "content": [{
+                    "type": "image",
+                    "format": "png",
+                    "data": base64::engine::general_purpose::STANDARD.encode(&png)
+                }]}
+            ]
+        });
+        fs::write(&path, v0.to_string()).unwrap();
+
+        let loaded = SessionFile::load(&path).unwrap();
+        assert_eq!(loaded.version, SESSION_VERSION);
+        assert!(loaded.created > 0);
+        let Content::Image(image) = &loaded.messages[1].content[0] else {
+            panic!("not an image: {:?}", loaded.messages[1]);
+        };
+        assert!(image.path.starts_with("images/"), "{}", image.path);
+        assert_eq!(fs::read(dir.join(&image.path)).unwrap(), png);
+        assert_eq!(image.bytes.as_deref(), Some(png.as_slice()));
+
+        // saved again, it's a current session that loads the same
+        loaded.save(&path).unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["version"], SESSION_VERSION);
+        assert!(written["messages"][1]["content"][0].get("data").is_none());
+        assert_eq!(SessionFile::load(&path).unwrap(), loaded);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn documents_round_trip() {
+        let fixture = serde_json::json!({
+            "recipe_details": "Crème brûlée\n\nIngredients:\n- 4 egg yolks",
+            "servings": 4,
+            "adjust_minutes": -5,
+            "scale": 1.5,
+            "whole_float": 2.0,
+            "largest": u64::MAX,
+            "smallest": i64::MIN,
+            "vegetarian": true,
+            "rationale": null,
+            "other_candidates": [
+                {"title": "Flan", "tags": ["custard", "gluten-free"]},
+                [],
+                {}
+            ]
+        });
+        let doc = json_to_document(&fixture);
+        assert_eq!(document_to_json(&doc), fixture);
+
+        let Document::Object(map) = &doc else {
+            panic!("not an object: {:?}", doc);
+        };
+        for (key, number) in [
+            ("servings", Number::PosInt(4)),
+            ("adjust_minutes", Number::NegInt(-5)),
+            ("scale", Number::Float(1.5)),
+            ("whole_float", Number::Float(2.0)),
+            ("largest", Number::PosInt(u64::MAX)),
+            ("smallest", Number::NegInt(i64::MIN)),
+        ] {
+            assert_eq!(map[key], Document::Number(number), "{}", key);
+        }
+        assert_eq!(map["rationale"], Document::Null);
+    }
+
+    #[test]
+    fn non_finite_floats_become_null() {
+        let doc = Document::Array(vec![
+            Document::Number(Number::Float(f64::NAN)),
+            Document::Number(Number::Float(f64::INFINITY)),
+        ]);
+        assert_eq!(document_to_json(&doc), serde_json::json!([null, null]));
+    }
+}
@@ -0,0 +1,243 @@
+//! Grouping the shopping list by store aisle, so the user doesn't zig-zag
+//! across the store.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::units;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Aisle {
+    Produce,
+    Bakery,
+    Meat,
+    Dairy,
+    Frozen,
+    Pantry,
+    Other,
+}
+
+impl fmt::Display for Aisle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Aisle::Produce => "Produce",
+            Aisle::Bakery => "Bakery",
+            Aisle::Meat => "Meat",
+            Aisle::Dairy => "Dairy",
+            Aisle::Frozen => "Frozen",
+            Aisle::Pantry => "Pantry",
+            Aisle::Other => "Other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+static BUILTIN_KEYWORDS: &[(Aisle, &str)] = &[
+    (Aisle::Frozen, "frozen, ice cream"),
+    (
+        Aisle::Bakery,
+        "bread, baguette, buns, rolls, tortillas, pita, bagels, croissant",
+    ),
+    (
+        Aisle::Meat,
+        "chicken, beef, pork, turkey, bacon, sausage, ham, lamb, steak, salmon, shrimp, fish, \
+         tuna, cod, tilapia",
+    ),
+    (
+        Aisle::Dairy,
+        "milk, butter, cheese, parmesan, mozzarella, cheddar, feta, ricotta, yogurt, cream, \
+         eggs, sour cream",
+    ),
+    (
+        Aisle::Produce,
+        "apple, banana, lemon, lime, orange, onion, garlic, potato, tomato, lettuce, spinach, \
+         kale, carrot, celery, pepper, zucchini, broccoli, cucumber, avocado, cilantro, \
+         parsley, basil, ginger, mushroom, berries, strawberries, scallions, peas, corn, \
+         green beans, squash",
+    ),
+    (
+        Aisle::Pantry,
+        "flour, sugar, salt, oil, vinegar, rice, pasta, spaghetti, penne, macaroni, noodles, \
+         beans, broth, stock, sauce, spice, cumin, paprika, oregano, honey, peanut butter, \
+         canned, soy sauce, black pepper, oats, baking powder, baking soda, vanilla, chocolate, \
+         cinnamon, nutmeg, chili powder, garlic powder, onion powder, ground ginger, \
+         chicken broth, chicken stock, beef broth, beef stock, fish sauce",
+    ),
+];
+
+/// Maps shopping list items to aisles by keyword.  The longest matching
+/// keyword wins, so "frozen peas" is frozen, "peanut butter" is pantry and
+/// "chicken broth" isn't meat.
+#[derive(Debug, Clone)]
+pub struct AisleClassifier {
+    keywords: Vec<(String, Aisle)>,
+}
+
+impl Default for AisleClassifier {
+    fn default() -> Self {
+        let keywords = BUILTIN_KEYWORDS
+            .iter()
+            .flat_map(|(aisle, words)| words.split(", ").map(|w| (w.trim().to_string(), *aisle)))
+            .collect();
+        AisleClassifier { keywords }
+    }
+}
+
+impl AisleClassifier {
+    /// The built in table extended with `{"aisle": ["keyword", ...]}` from a
+    /// JSON config file, if it exists.  User keywords win ties.
+    pub fn with_config(path: &Path) -> io::Result<AisleClassifier> {
+        let mut classifier = AisleClassifier::default();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(classifier),
+            Err(e) => return Err(e),
+        };
+        let extra: BTreeMap<Aisle, Vec<String>> = serde_json::from_str(&contents)?;
+        let mut user: Vec<(String, Aisle)> = extra
+            .into_iter()
+            .flat_map(|(aisle, words)| words.into_iter().map(move |w| (w.to_lowercase(), aisle)))
+            .collect();
+        user.append(&mut classifier.keywords);
+        classifier.keywords = user;
+        Ok(classifier)
+    }
+
+    pub fn classify(&self, item: &str) -> Aisle {
+        let item = item.to_lowercase();
+        let mut best: Option<(usize, Aisle)> = None;
+        for (keyword, aisle) in &self.keywords {
+            if contains_word(&item, keyword) && best.is_none_or(|(len, _)| keyword.len() > len) {
+                best = Some((keyword.len(), *aisle));
+            }
+        }
+        best.map_or(Aisle::Other, |(_, aisle)| aisle)
+    }
+
+    /// Groups items by aisle, keeping their original order within each aisle
+    pub fn group<'a>(&self, items: &[&'a str]) -> BTreeMap<Aisle, Vec<&'a str>> {
+        let mut groups: BTreeMap<Aisle, Vec<&str>> = BTreeMap::new();
+        for item in items {
+            groups.entry(self.classify(item)).or_default().push(item);
+        }
+        groups
+    }
+
+    /// Rewrites a shopping list as markdown, one heading per aisle, combining
+    /// repeated ingredients
+    pub fn render_grouped(&self, shopping_list: &str) -> String {
+        let items: Vec<&str> = shopping_list
+            .lines()
+            .map(|line| line.trim().trim_start_matches(['-', '*', '•']).trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        let items = units::consolidate(&items);
+        let items: Vec<&str> = items.iter().map(String::as_str).collect();
+        self.group(&items)
+            .into_iter()
+            .map(|(aisle, items)| {
+                let items: Vec<String> = items.iter().map(|i| format!("- {}", i)).collect();
+                format!("{}:\n{}", aisle, items.join("\n"))
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Whether `keyword` appears in `text` on word boundaries, allowing a plural "s"
+fn contains_word(text: &str, keyword: &str) -> bool {
+    text.match_indices(keyword).any(|(idx, _)| {
+        let before = text[..idx].chars().next_back();
+        let after = text[idx + keyword.len()..]
+            .trim_start_matches(['s'])
+            .chars()
+            .next();
+        before.is_none_or(|c| !c.is_alphanumeric()) && after.is_none_or(|c| !c.is_alphanumeric())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_ambiguous_items() {
+        let classifier = AisleClassifier::default();
+        for (item, aisle) in [
+            ("frozen peas", Aisle::Frozen),
+            ("peas", Aisle::Produce),
+            ("parmesan", Aisle::Dairy),
+            ("1/2 cup grated Parmesan", Aisle::Dairy),
+            ("peanut butter", Aisle::Pantry),
+            ("butter", Aisle::Dairy),
+            ("ground cumin", Aisle::Pantry),
+            ("ground cinnamon", Aisle::Pantry),
+            ("ground ginger", Aisle::Pantry),
+            ("fresh ginger", Aisle::Produce),
+            ("ground beef", Aisle::Meat),
+            ("chicken thighs", Aisle::Meat),
+            ("chicken broth", Aisle::Pantry),
+            ("low-sodium chicken stock", Aisle::Pantry),
+            ("garlic powder", Aisle::Pantry),
+            ("2 cloves garlic", Aisle::Produce),
+            ("black pepper", Aisle::Pantry),
+            ("red bell peppers", Aisle::Produce),
+            ("sour cream", Aisle::Dairy),
+            ("ice cream", Aisle::Frozen),
+            ("flour tortillas", Aisle::Bakery),
+            ("paper towels", Aisle::Other),
+        ] {
+            assert_eq!(classifier.classify(item), aisle, "{}", item);
+        }
+    }
+
+    #[test]
+    fn keywords_match_whole_words() {
+        let classifier = AisleClassifier::default();
+        // "ham" in "graham", "oil" in "foil"
+        assert_eq!(classifier.classify("graham crackers"), Aisle::Other);
+        assert_eq!(classifier.classify("aluminum foil"), Aisle::Other);
+        assert_eq!(classifier.classify("lemons"), Aisle::Produce);
+    }
+
+    #[test]
+    fn groups_keep_the_original_order() {
+        let classifier = AisleClassifier::default();
+        let groups = classifier.group(&["milk", "onion", "eggs", "basil"]);
+        assert_eq!(groups[&Aisle::Produce], vec!["onion", "basil"]);
+        assert_eq!(groups[&Aisle::Dairy], vec!["milk", "eggs"]);
+    }
+
+    #[test]
+    fn renders_a_heading_per_aisle() {
+        let classifier = AisleClassifier::default();
+        let rendered = classifier.render_grouped("- spinach\n- 8 oz spaghetti\n- milk\n");
+        assert_eq!(
+            rendered,
+            "Produce:\n- spinach\n\nDairy:\n- milk\n\nPantry:\n- 8 oz spaghetti"
+        );
+    }
+
+    #[test]
+    fn config_keywords_win_ties() {
+        let path = std::env::temp_dir().join(format!("aisles-{}.json", std::process::id()));
+        fs::write(&path, r#"{"frozen": ["peas"], "pantry": ["matcha"]}"#).unwrap();
+        let classifier = AisleClassifier::with_config(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(classifier.classify("peas"), Aisle::Frozen);
+        assert_eq!(classifier.classify("matcha powder"), Aisle::Pantry);
+        assert_eq!(classifier.classify("milk"), Aisle::Dairy);
+    }
+
+    #[test]
+    fn missing_config_is_the_builtin_table() {
+        let classifier =
+            AisleClassifier::with_config(Path::new("/nonexistent/aisles.json")).unwrap();
+        assert_eq!(classifier.classify("parmesan"), Aisle::Dairy);
+    }
+}
@@ -0,0 +1,312 @@
+//! Keeping the session when the terminal goes away.
+//!
+//! Closing the terminal window or dropping an SSH connection sends SIGHUP,
+//! and `kill` or a system shutdown sends SIGTERM; either used to end the
+//! shell with the session unsaved.  The binary keeps a [Snapshot] of the
+//! session as of the last finished turn, which [Hangup::save] saves.
+//! A turn still running is abandoned, so the saved history never ends in
+//! the middle of a tool round.
+//!
+//! While a shell runs it also holds a [Running] marker in the state
+//! directory.  A clean exit removes it.  The marker is locked like
+//! [crate::lock::DirLock], so one left behind by a process that's gone can
+//! be told apart from one for a shell that's still open, and the next
+//! launch can offer to resume its session ([unclean]).
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use fs2::FileExt;
+#[cfg(unix)]
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::session::SessionFile;
+
+/// Where the markers go, inside the state directory
+pub const RUNNING_DIR: &str = "running";
+
+/// What a running shell leaves in its marker
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub session_id: String,
+    /// Where the session is saved on SIGHUP or SIGTERM, and by autosave
+    pub session: PathBuf,
+    pub pid: u32,
+    /// The signal that ended it, if one did
+    #[serde(default)]
+    pub signal: Option<String>,
+}
+
+/// A marker held by this process until [Running::finish]
+#[derive(Debug)]
+pub struct Running {
+    file: File,
+    path: PathBuf,
+    marker: Mutex<Marker>,
+}
+
+impl Running {
+    pub fn start(dir: &Path, marker: Marker) -> io::Result<Running> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.json", marker.session_id));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        file.try_lock_exclusive()?;
+        let running = Running {
+            file,
+            path,
+            marker: Mutex::new(marker),
+        };
+        running.write()?;
+        Ok(running)
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let marker = self.marker.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut file = &self.file;
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(serde_json::to_string_pretty(&*marker)?.as_bytes())?;
+        file.sync_all()
+    }
+
+    /// Records the signal that's ending the process
+    pub fn note_signal(&self, signal: &str) -> io::Result<()> {
+        self.marker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .signal = Some(signal.to_string());
+        self.write()
+    }
+
+    /// Removes the marker on a clean exit
+    pub fn finish(&self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// Markers left by shells that didn't exit cleanly, which are removed as
+/// they're returned.  Markers of shells still running are left alone.
+pub fn unclean(dir: &Path) -> io::Result<Vec<Marker>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut markers = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        if file.try_lock_exclusive().is_err() {
+            continue;
+        }
+        let marker = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Marker>(&contents).ok());
+        fs::remove_file(&path)?;
+        let _ = FileExt::unlock(&file);
+        markers.extend(marker);
+    }
+    Ok(markers)
+}
+
+/// The session as of the last finished turn, shared with the signal handler
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot(Arc<Mutex<Option<SessionFile>>>);
+
+impl Snapshot {
+    pub fn update(&self, session: SessionFile) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(session);
+    }
+
+    /// Saves the latest session to `path`, returning false if there's
+    /// nothing to save yet
+    pub fn save(&self, path: &Path) -> io::Result<bool> {
+        let session = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        match session.as_ref() {
+            Some(session) if !session.messages.is_empty() => {
+                session.save(path)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+/// SIGHUP and SIGTERM, caught from when [Hangup::listen] returns
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct Hangup {
+    hup: tokio::signal::unix::Signal,
+    term: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl Hangup {
+    pub fn listen() -> io::Result<Hangup> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        Ok(Hangup {
+            hup: signal(SignalKind::hangup())?,
+            term: signal(SignalKind::terminate())?,
+        })
+    }
+
+    /// Waits for either signal, returning its name and the exit status a
+    /// shell would report for it
+    pub async fn recv(&mut self) -> (&'static str, i32) {
+        tokio::select! {
+            _ = self.hup.recv() => ("SIGHUP", 128 + 1),
+            _ = self.term.recv() => ("SIGTERM", 128 + 15),
+        }
+    }
+
+    /// Waits for either signal, then saves `snapshot` to `path` and notes
+    /// the signal in the `running` marker.  Returns the exit status to end
+    /// the process with.
+    pub async fn save(
+        mut self,
+        snapshot: &Snapshot,
+        path: &Path,
+        running: Option<&Running>,
+    ) -> i32 {
+        let (signal, status) = self.recv().await;
+        match snapshot.save(path) {
+            Ok(true) => info!("{}: saved the session to {}", signal, path.display()),
+            Ok(false) => info!("{}: nothing to save", signal),
+            Err(e) => error!(
+                "{}: couldn't save the session to {}: {}",
+                signal,
+                path.display(),
+                e
+            ),
+        }
+        if let Some(running) = running {
+            if let Err(e) = running.note_signal(signal) {
+                warn!("couldn't update the running marker: {}", e);
+            }
+        }
+        status
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::repair::{self, RepairMode};
+
+    /// Set for the copy of the test binary that plays the shell
+    const CHILD_DIR: &str = "GOURMAND_SHUTDOWN_CHILD_DIR";
+
+    /// A session whose last turn asked for a tool that never answered
+    fn interrupted_session() -> SessionFile {
+        serde_json::from_value(serde_json::json!({
+            "version": crate::session::SESSION_VERSION,
+            "model": "us.anthropic.claude-3-5-sonnet-20241022-v2:0",
+            "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "what's for dinner?"}]},
+                {"role": "assistant", "content": [
+                    {"type": "text", "text": "Let me check what's in season."},
+                    {
+                        "type": "tool_use",
+                        "tool_use_id": "tooluse_produce",
+                        "name": "seasonal_produce",
+                        "input": {}
+                    }
+                ]}
+            ]
+        }))
+        .unwrap()
+    }
+
+    /// Not a test on its own: what the child process runs, see
+    /// [sigterm_saves_a_child_shells_session]
+    #[test]
+    fn child_shell() {
+        let Some(dir) = std::env::var_os(CHILD_DIR).map(PathBuf::from) else {
+            return;
+        };
+        let snapshot = Snapshot::default();
+        snapshot.update(interrupted_session());
+        let running = Running::start(
+            &dir.join(RUNNING_DIR),
+            Marker {
+                session_id: "child".into(),
+                session: dir.join("child.json"),
+                pid: std::process::id(),
+                signal: None,
+            },
+        )
+        .unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let status = runtime.block_on(async {
+            let hangup = Hangup::listen().unwrap();
+            fs::write(dir.join("listening"), "").unwrap();
+            hangup
+                .save(&snapshot, &dir.join("child.json"), Some(&running))
+                .await
+        });
+        std::process::exit(status);
+    }
+
+    #[test]
+    fn sigterm_saves_a_child_shells_session() {
+        let dir = std::env::temp_dir().join(format!("gourmand-shutdown-{}", ulid::Ulid::new()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "shutdown::tests::child_shell", "--nocapture"])
+            .env(CHILD_DIR, &dir)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let started = Instant::now();
+        while !dir.join("listening").exists() {
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "the child never started listening"
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        // its marker is locked while it runs
+        assert_eq!(unclean(&dir.join(RUNNING_DIR)).unwrap(), []);
+
+        let kill = Command::new("kill")
+            .args(["-TERM", &child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(kill.success());
+        let status = child.wait().unwrap();
+        assert_eq!(status.code(), Some(128 + 15));
+
+        let markers = unclean(&dir.join(RUNNING_DIR)).unwrap();
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].signal.as_deref(), Some("SIGTERM"));
+        assert_eq!(markers[0].pid, child.id());
+
+        let saved = SessionFile::load(&markers[0].session).unwrap();
+        assert_eq!(saved, interrupted_session());
+        let mut messages = saved.to_messages();
+        let repaired = repair::repair(&mut messages, RepairMode::Synthesize).unwrap();
+        assert_eq!(repaired[0].tool_use_ids, ["tooluse_produce"]);
+        assert_eq!(repair::find_dangling(&messages), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
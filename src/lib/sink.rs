@@ -0,0 +1,161 @@
+//! Where transmitted recipes go.
+//!
+//! [crate::Conversation::ask] and [crate::oneshot::generate] hand each
+//! recipe the model transmits to an [ArtifactSink], if they were given one.
+//! [DirSink] writes the files the shell does; [MemorySink] keeps them for a
+//! caller that stores recipes its own way, and for tests.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+
+use crate::lock;
+use crate::paths;
+use crate::recipe::Recipe;
+
+pub trait ArtifactSink: fmt::Debug + Send + Sync {
+    /// Saves `recipe` and its images (base64 PNGs), returning where they
+    /// went, e.g. paths or keys, which the model is told so it can tell the
+    /// user
+    fn save<'a>(
+        &'a self,
+        recipe: &'a Recipe,
+        images: &'a [String],
+    ) -> BoxFuture<'a, io::Result<Vec<String>>>;
+}
+
+/// So a caller can keep a handle on the sink it gave away, e.g. to read a
+/// [MemorySink] back
+impl<S: ArtifactSink + ?Sized> ArtifactSink for Arc<S> {
+    fn save<'a>(
+        &'a self,
+        recipe: &'a Recipe,
+        images: &'a [String],
+    ) -> BoxFuture<'a, io::Result<Vec<String>>> {
+        (**self).save(recipe, images)
+    }
+}
+
+/// `<stem>.txt` and `<stem>-N.png` in a directory, the same layout the
+/// shell uses
+#[derive(Debug, Clone)]
+pub struct DirSink {
+    dir: PathBuf,
+}
+
+impl DirSink {
+    pub fn new(dir: impl Into<PathBuf>) -> DirSink {
+        DirSink { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl ArtifactSink for DirSink {
+    fn save<'a>(
+        &'a self,
+        recipe: &'a Recipe,
+        images: &'a [String],
+    ) -> BoxFuture<'a, io::Result<Vec<String>>> {
+        Box::pin(async move {
+            fs::create_dir_all(&self.dir)?;
+            let stem = lock::reserve_stem(&self.dir, &recipe.stem())
+                .await?
+                .display()
+                .to_string();
+
+            let txt_path = format!("{}.txt", stem);
+            fs::write(&txt_path, &recipe.details)?;
+            let mut files = vec![txt_path];
+            for (idx, image) in images.iter().enumerate() {
+                let path = format!("{}-{}.png", stem, idx);
+                paths::write_base64(Path::new(&path), image)?;
+                files.push(path);
+            }
+            Ok(files)
+        })
+    }
+}
+
+/// Keeps every recipe, with its images, in memory
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    saved: Mutex<Vec<(Recipe, Vec<String>)>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    /// The recipes saved so far, oldest first
+    pub fn recipes(&self) -> Vec<Recipe> {
+        self.saved
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(recipe, _)| recipe.clone())
+            .collect()
+    }
+
+    /// The images saved with the `idx`th recipe
+    pub fn images(&self, idx: usize) -> Vec<String> {
+        self.saved
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(idx)
+            .map(|(_, images)| images.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl ArtifactSink for MemorySink {
+    /// Answers with `memory:<stem>`
+    fn save<'a>(
+        &'a self,
+        recipe: &'a Recipe,
+        images: &'a [String],
+    ) -> BoxFuture<'a, io::Result<Vec<String>>> {
+        self.saved
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((recipe.clone(), images.to_vec()));
+        let location = format!("memory:{}", recipe.stem());
+        Box::pin(async move { Ok(vec![location]) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn directories_keep_titles_apart() {
+        let dir = std::env::temp_dir().join(format!("gourmand-sink-{}", ulid::Ulid::new()));
+        let sink = DirSink::new(&dir);
+        let recipe = Recipe::new("Rice and Beans\n\nIngredients:\n- rice");
+        let first = sink.save(&recipe, &[]).await.unwrap();
+        let second = sink.save(&recipe, &[]).await.unwrap();
+        assert_eq!(first.len(), 1);
+        assert_ne!(first, second);
+        assert!(first[0].ends_with("rice_and_beans.txt"), "{:?}", first);
+        assert_eq!(fs::read_to_string(&second[0]).unwrap(), recipe.details);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn memory() {
+        let sink = MemorySink::new();
+        let recipe = Recipe::new("Pasta").rationale("quick");
+        let images = ["aW1hZ2U=".to_string()];
+        assert_eq!(sink.save(&recipe, &images).await.unwrap(), ["memory:pasta"]);
+        assert_eq!(sink.recipes(), [recipe]);
+        assert_eq!(sink.images(0), images);
+        assert!(sink.images(1).is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+//! The user's grocery store weekly specials, read from a CSV of
+//! `item, price, unit` on disk or over HTTP.
+use std::fmt;
+#[cfg(feature = "http")]
+use std::time::Duration;
+
+/// Don't download more than this
+#[cfg(feature = "http")]
+const MAX_SPECIALS_BYTES: u64 = 256 * 1024;
+#[cfg(feature = "http")]
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecialItem {
+    pub item: String,
+    pub price: Option<f64>,
+    pub unit: Option<String>,
+}
+
+impl fmt::Display for SpecialItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.item)?;
+        if let Some(price) = self.price {
+            write!(f, " {:.2}", price)?;
+        }
+        if let Some(unit) = &self.unit {
+            write!(f, "/{}", unit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads specials from a local path or an http(s) URL
+pub async fn load(source: &str) -> Result<Vec<SpecialItem>, String> {
+    let text = if source.starts_with("http://") || source.starts_with("https://") {
+        fetch(source).await?
+    } else {
+        std::fs::read_to_string(source).map_err(|e| format!("{}: {}", source, e))?
+    };
+    Ok(parse_csv(&text))
+}
+
+#[cfg(not(feature = "http"))]
+async fn fetch(url: &str) -> Result<String, String> {
+    Err(format!(
+        "built without http support (feature http), can't fetch {}",
+        url
+    ))
+}
+
+#[cfg(feature = "http")]
+async fn fetch(url: &str) -> Result<String, String> {
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?;
+    if response.content_length().unwrap_or(0) > MAX_SPECIALS_BYTES {
+        return Err(format!(
+            "{} is larger than {} bytes",
+            url, MAX_SPECIALS_BYTES
+        ));
+    }
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    if bytes.len() as u64 > MAX_SPECIALS_BYTES {
+        return Err(format!(
+            "{} is larger than {} bytes",
+            url, MAX_SPECIALS_BYTES
+        ));
+    }
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// Parses `item, price, unit` rows, skipping a header row and blank lines.
+/// Tolerates a byte order mark, quoted fields, and currency symbols in prices.
+pub fn parse_csv(text: &str) -> Vec<SpecialItem> {
+    let text = text.trim_start_matches('\u{feff}');
+    let mut items = vec![];
+    for line in text.lines() {
+        let fields = split_csv_line(line);
+        let Some(item) = fields.first().map(|f| f.trim()).filter(|f| !f.is_empty()) else {
+            continue;
+        };
+        if item.eq_ignore_ascii_case("item") {
+            continue; // header
+        }
+        let price = fields.get(1).and_then(|p| parse_price(p));
+        let unit = fields
+            .get(2)
+            .map(|u| u.trim().to_string())
+            .filter(|u| !u.is_empty());
+        items.push(SpecialItem {
+            item: item.to_string(),
+            price,
+            unit,
+        });
+    }
+    items
+}
+
+fn parse_price(field: &str) -> Option<f64> {
+    let cleaned: String = field
+        .trim()
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .collect();
+    // treat a lone comma as a decimal separator, e.g. "1,99 €"
+    let cleaned = if cleaned.contains('.') {
+        cleaned.replace(',', "")
+    } else {
+        cleaned.replace(',', ".")
+    };
+    cleaned.parse().ok()
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => in_quotes = !in_quotes,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn special(item: &str, price: Option<f64>, unit: Option<&str>) -> SpecialItem {
+        SpecialItem {
+            item: item.to_string(),
+            price,
+            unit: unit.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn reads_a_spreadsheet_export() {
+        let csv = "\u{feff}Item,Price,Unit\r\n\
+                   \"Tomatoes, vine-ripened\",$2.49,lb\r\n\
+                   \"Chicken \"\"family pack\"\"\",4.99 €,kg\r\n\
+                   \r\n\
+                   Butter,\"1,99 €\",\r\n\
+                   Olive oil,£7,bottle\r\n\
+                   Saffron,\"$1,299.00\", oz \r\n\
+                   Bread,,loaf\r\n\
+                   Eggs,free,dozen\r\n";
+        assert_eq!(
+            parse_csv(csv),
+            [
+                special("Tomatoes, vine-ripened", Some(2.49), Some("lb")),
+                special("Chicken \"family pack\"", Some(4.99), Some("kg")),
+                special("Butter", Some(1.99), None),
+                special("Olive oil", Some(7.0), Some("bottle")),
+                special("Saffron", Some(1299.0), Some("oz")),
+                special("Bread", None, Some("loaf")),
+                special("Eggs", None, Some("dozen")),
+            ]
+        );
+    }
+
+    #[test]
+    fn items_are_shown_with_price_and_unit() {
+        for (item, shown) in [
+            (special("Butter", Some(1.99), None), "Butter 1.99"),
+            (
+                special("Tomatoes", Some(2.5), Some("lb")),
+                "Tomatoes 2.50/lb",
+            ),
+            (special("Bread", None, Some("loaf")), "Bread/loaf"),
+        ] {
+            assert_eq!(item.to_string(), shown);
+        }
+    }
+
+    #[tokio::test]
+    async fn loads_a_file_with_a_byte_order_mark() {
+        let path =
+            std::env::temp_dir().join(format!("gourmand-specials-{}.csv", ulid::Ulid::new()));
+        std::fs::write(&path, "\u{feff}item,price,unit\nLeeks,$1.29,bunch\n").unwrap();
+        let items = load(&path.display().to_string()).await.unwrap();
+        assert_eq!(items, [special("Leeks", Some(1.29), Some("bunch"))]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(load(&path.display().to_string()).await.is_err());
+    }
+}
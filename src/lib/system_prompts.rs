@@ -1,7 +1,282 @@
+//! The system prompt, assembled from fragments: who the model is, what it
+//! won't talk about, how it interviews the user, how it uses the tools, and
+//! how it shows the recipe, followed by whatever the session adds (language,
+//! context, preferences).  A [Preset] is the fragments for one kind of
+//! session, and a feature that needs to say something to the model adds a
+//! fragment to the [SystemPrompt] rather than editing a preset's text.
+//!
+//! Fragments carry their own whitespace and are concatenated as they are, so
+//! [Preset::Family] reads exactly like [SYS_PROMPT2] did before it was split.
+//!
 //! TODO - when this is done as an SMS agent, we'll need a tool to fetch preferenes from the database
 //! TODO - and change the text message to include a link for modifying preferences/config.
 //! TODO - some of this system prompt is guardrail in nature.  add actual guardrails.
+use aws_sdk_bedrockruntime::types::SystemContentBlock;
 
+/// What a fragment is for.  Fragments are assembled in this order, and in
+/// the order they were added within a kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FragmentKind {
+    /// Who the model is and what it recommends
+    Persona,
+    /// What it won't discuss
+    Guardrail,
+    /// How it narrows down what the user wants
+    Constraints,
+    /// When and what to send to the tools
+    ToolBehavior,
+    /// What the user sees
+    OutputFormat,
+    /// Added by the session: language, context, preferences, etc.
+    Session,
+}
+
+/// A piece of the system prompt.  The name identifies it, so adding a
+/// fragment with a name that's already there replaces it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub kind: FragmentKind,
+    pub name: &'static str,
+    pub text: String,
+}
+
+impl Fragment {
+    pub fn new(kind: FragmentKind, name: &'static str, text: impl Into<String>) -> Fragment {
+        Fragment {
+            kind,
+            name,
+            text: text.into(),
+        }
+    }
+
+    /// Something the session adds after the preset, e.g. the meal context
+    pub fn session(name: &'static str, text: impl Into<String>) -> Fragment {
+        Fragment::new(FragmentKind::Session, name, text)
+    }
+}
+
+/// Shared by every preset but [Preset::Minimal]
+static PERSONA: &str = "
+    You recommend recipes for busy families.  They are simple with relatively few ingredients,
+    with less than 10 minutes of prep and 20 minutes of cooking.";
+static TOPIC: &str = "  If the user tries to change 
+    the topic, politely remind them that all you can discuss is recipes.  
+    
+    ";
+static INTERVIEW: &str = "Before recommending a recipe, you will ask the user some basic questions about their preference, 
+    for example if they're looking looking for side dishes, a main course, or dessert, and if they 
+    have have dietary preferences like vegan or low carb.  You should always summarize their preferences 
+    back to them and then give them a choice of two recipes by title, and ask them which one they want 
+    before recommending the recipe or if they're unhappy with both and want you to recommend another two.  
+    
+    ";
+static TRANSMIT: &str = "After the user picks a recipe but before you show it to them, you must transmit the recipe (title, ingredients,
+    instructions, and shopping list with two newlines between each section) and a prompt suitable for 
+    an image generation model to produce an appetizing photorealistic picture of the final dish.  Also 
+    transmit a short rationale listing which of the user's stated 
+    constraints and preferences the recipe meets.  Don't say anything when you use the tool.  ";
+static DISPLAY: &str = "But once the tooling 
+    returns, you must display the title, ingredients, instructions, and shopping list to the user.  You should also 
+    tell the user where the files were saved: quote the display_path from the tool result exactly as given.  
+    Do not display the image prompt to the user.
+";
+
+/// Without tools, the recipe is shown inline with recognizable headings so that
+/// it can be saved locally.
+static INLINE: &str = "Once the user picks a recipe, display it in full: the title on its own line, followed by sections
+    headed exactly \"Ingredients:\", \"Instructions:\", and \"Shopping List:\", with two newlines between
+    each section.  The recipe will be saved for the user automatically.
+";
+
+/// No questions, since nobody is there to answer them
+static ONESHOT: &str = "
+
+    The user's message describes what they want.  Don't ask questions or offer choices: pick the
+    single recipe that fits best and transmit it with the tool (title, ingredients, instructions,
+    and shopping list with two newlines between each section), with a prompt suitable for an image
+    generation model to produce an appetizing photorealistic picture of the final dish.
+";
+
+/// Terse, for slow connections
+static MINIMAL_PERSONA: &str = "
+    You recommend simple recipes for busy families: few ingredients, under 10 minutes of prep and
+    20 minutes of cooking.";
+static MINIMAL_TOPIC: &str = "  Only discuss recipes.  Be terse: no preamble or pleasantries.
+
+    ";
+static MINIMAL_INTERVIEW: &str =
+    "Ask at most one short question about the user's preferences, then offer two recipe titles.
+    ";
+static MINIMAL_TRANSMIT: &str = "Once the user picks one, you must transmit the recipe (title, ingredients, numbered instructions,
+    and shopping list with two newlines between each section) and a short image prompt
+    using the tool, then show the user only the ingredients and numbered steps.
+";
+
+/// For `--show-both`
+static SHOW_BOTH: &str = "
+    Instead of offering two recipes by title, transmit both of them, in a single use of the tool
+    with the second one in other_candidates, and then show the user both in full.  Ask which one
+    they'd like to go with.  Once they pick, don't transmit it again; the files are already saved.
+";
+
+/// The fragments for one kind of session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// The interview, then the recipe through the tool: [SYS_PROMPT2]
+    Family,
+    /// For models or regions that reject tool configurations
+    NoTools,
+    /// A compact prompt for slow connections: terse answers, no chatter
+    Minimal,
+    /// For [crate::oneshot]: a single recipe, no questions
+    OneShot,
+}
+
+impl Preset {
+    pub fn fragments(self) -> Vec<Fragment> {
+        use FragmentKind::*;
+        let fragments = match self {
+            Preset::Family => vec![
+                (Persona, "persona", PERSONA),
+                (Guardrail, "topic", TOPIC),
+                (Constraints, "interview", INTERVIEW),
+                (ToolBehavior, "transmit", TRANSMIT),
+                (OutputFormat, "display", DISPLAY),
+            ],
+            Preset::NoTools => vec![
+                (Persona, "persona", PERSONA),
+                (Guardrail, "topic", TOPIC),
+                (Constraints, "interview", INTERVIEW),
+                (OutputFormat, "inline", INLINE),
+            ],
+            Preset::Minimal => vec![
+                (Persona, "persona", MINIMAL_PERSONA),
+                (Guardrail, "topic", MINIMAL_TOPIC),
+                (Constraints, "interview", MINIMAL_INTERVIEW),
+                (ToolBehavior, "transmit", MINIMAL_TRANSMIT),
+            ],
+            Preset::OneShot => vec![
+                (Persona, "persona", PERSONA),
+                (ToolBehavior, "transmit", ONESHOT),
+            ],
+        };
+        fragments
+            .into_iter()
+            .map(|(kind, name, text)| Fragment::new(kind, name, text))
+            .collect()
+    }
+
+    /// The preset on its own, without anything from the session
+    pub fn text(self) -> String {
+        SystemPrompt::new(self).text()
+    }
+}
+
+/// A preset plus the session's fragments, assembled into what's sent
+#[derive(Debug, Clone)]
+pub struct SystemPrompt {
+    preset: Preset,
+    fragments: Vec<Fragment>,
+}
+
+impl SystemPrompt {
+    pub fn new(preset: Preset) -> SystemPrompt {
+        SystemPrompt {
+            preset,
+            fragments: preset.fragments(),
+        }
+    }
+
+    pub fn preset(&self) -> Preset {
+        self.preset
+    }
+
+    /// Adds `fragment`, replacing one with the same name
+    pub fn add(&mut self, fragment: Fragment) {
+        match self.fragments.iter_mut().find(|f| f.name == fragment.name) {
+            Some(existing) => *existing = fragment,
+            None => self.fragments.push(fragment),
+        }
+    }
+
+    pub fn with(mut self, fragment: Fragment) -> SystemPrompt {
+        self.add(fragment);
+        self
+    }
+
+    /// Switches to `preset`, keeping the session's fragments, e.g. when the
+    /// model turns out to reject tools
+    pub fn set_preset(&mut self, preset: Preset) {
+        let session = self
+            .fragments
+            .drain(..)
+            .filter(|f| f.kind == FragmentKind::Session);
+        let mut fragments = preset.fragments();
+        fragments.extend(session);
+        self.preset = preset;
+        self.fragments = fragments;
+    }
+
+    /// In the order they're assembled
+    pub fn fragments(&self) -> Vec<&Fragment> {
+        let mut fragments: Vec<&Fragment> = self.fragments.iter().collect();
+        fragments.sort_by_key(|f| f.kind);
+        fragments
+    }
+
+    pub fn text(&self) -> String {
+        self.fragments().iter().map(|f| f.text.as_str()).collect()
+    }
+
+    bedrock_pub! {
+        /// For the request.  One text block, so the model sees the same
+        /// prompt as when it was a single string.
+        fn blocks(&self) -> Vec<SystemContentBlock> {
+            vec![SystemContentBlock::Text(self.text())]
+        }
+    }
+}
+
+/// Added to the prompt when the user wants to converse in a language other than English
+pub fn language(language: &str) -> Fragment {
+    Fragment::session(
+        "language",
+        format!(
+            "
+    Always converse with the user in {language}, and write the transmitted recipe (title,
+    ingredients, instructions, and shopping list) in {language} as well.
+"
+        ),
+    )
+}
+
+/// Added to the prompt for `--show-both`
+pub fn show_both() -> Fragment {
+    Fragment::session("show-both", SHOW_BOTH)
+}
+
+/// The household's saved preferences, as [crate::preferences::Preferences::render] gives them
+pub fn preferences(rendered: &str) -> Fragment {
+    Fragment::session("preferences", format!("\n    {}\n", rendered))
+}
+
+/// The first turn when the household's preferences are already known, so
+/// the model confirms them instead of asking again
+pub fn warm_start_intro(preferences: &str) -> String {
+    format!(
+        "
+    Here is what you already know about me: {preferences}
+
+    Introduce yourself, confirm these preferences back to me in one sentence, and then immediately
+    offer two recipe options that fit them.  Don't ask about my preferences unless something above
+    is unclear.
+"
+    )
+}
+
+/// The family prompt as a single string, from before it was split into
+/// fragments.  [Preset::Family] assembles to exactly this, which `recipes
+/// selftest` checks.
 pub static SYS_PROMPT2: &str = "
     You recommend recipes for busy families.  They are simple with relatively few ingredients,
     with less than 10 minutes of prep and 20 minutes of cooking.  If the user tries to change 
@@ -14,14 +289,16 @@ pub static SYS_PROMPT2: &str = "
     before recommending the recipe or if they're unhappy with both and want you to recommend another two.  
     
     After the user picks a recipe but before you show it to them, you must transmit the recipe (title, ingredients,
-    instructions, and shopping list with two newlines between each section), a prompt suitable suitable for 
-    an image generation model to produce an appetizing photorealistic picture of the final dish, and a 
-    filename for saving the recipe details.  Don't say anything when you use the tool.  But once the tooling 
+    instructions, and shopping list with two newlines between each section) and a prompt suitable for 
+    an image generation model to produce an appetizing photorealistic picture of the final dish.  Also 
+    transmit a short rationale listing which of the user's stated 
+    constraints and preferences the recipe meets.  Don't say anything when you use the tool.  But once the tooling 
     returns, you must display the title, ingredients, instructions, and shopping list to the user.  You should also 
-    tell the user where the files were saved (this will come back from the tooling).  Do not display the image 
-    prompt to the user.
+    tell the user where the files were saved: quote the display_path from the tool result exactly as given.  
+    Do not display the image prompt to the user.
 ";
 
+/// The first family prompt, kept for `eval --prompts family-v1`
 pub static SYS_PROMPT1: &str = "
     You recommend recipes for busy families.  They are simple, usually with fewer than 6 ingredients.
     These recipes should take less than 10 minutes of prep and 20 minutes of cooking.  
@@ -39,13 +316,82 @@ pub static SYS_PROMPT1: &str = "
     before recommending the recipe or if they're unhappy with both and want you to recommend another two.  
 
     The final recipe will include ingredients, instructions, and a shopping list with two newlines between
-    each section.  Before you show them the final recipe, you must silently transmit the recipe, a prompt 
-    suitable for an image generation model to produce a photorealistic picture of the final dish, and a 
-    filename for saving the recipe details.  
+    each section.  Before you show them the final recipe, you must silently transmit the recipe and a prompt 
+    suitable for an image generation model to produce a photorealistic picture of the final dish.  
     
     Once the tooling returns, you must display the ingredients, instructions, and shopping list to the user.  
-    You should also tell the user where the files were saved (this will come back from the tooling).  Do not 
-    display the image prompt to the user.
+    You should also tell the user where the files were saved: quote the display_path from the tool result
+    exactly as given.  Do not display the image prompt to the user.
 
     If the user tries to change the topic, politely remind them that all you can discuss is recipes.  
 ";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn family_assembles_to_sys_prompt2() {
+        assert_eq!(Preset::Family.text(), SYS_PROMPT2);
+        assert_eq!(
+            SystemPrompt::new(Preset::Family).blocks(),
+            vec![SystemContentBlock::Text(SYS_PROMPT2.to_string())]
+        );
+    }
+
+    #[test]
+    fn presets_share_fragments() {
+        for preset in [Preset::NoTools, Preset::OneShot] {
+            assert!(preset.text().starts_with(PERSONA), "{:?}", preset);
+        }
+        assert!(Preset::NoTools.text().contains(INTERVIEW));
+        assert!(!Preset::NoTools.text().contains(TRANSMIT));
+        assert!(!Preset::Minimal.text().contains(PERSONA));
+    }
+
+    #[test]
+    fn fragments_are_ordered_by_kind() {
+        let prompt = SystemPrompt::new(Preset::OneShot)
+            .with(Fragment::session("language", "[language]"))
+            .with(Fragment::new(
+                FragmentKind::Guardrail,
+                "allergies",
+                "[allergies]",
+            ))
+            .with(Fragment::new(FragmentKind::Persona, "units", "[units]"));
+        let names: Vec<&str> = prompt.fragments().iter().map(|f| f.name).collect();
+        assert_eq!(
+            names,
+            ["persona", "units", "allergies", "transmit", "language"]
+        );
+        assert_eq!(
+            prompt.text(),
+            format!("{}[units][allergies]{}[language]", PERSONA, ONESHOT)
+        );
+    }
+
+    #[test]
+    fn a_fragment_replaces_its_namesake() {
+        let prompt = SystemPrompt::new(Preset::Family)
+            .with(language("French"))
+            .with(language("German"))
+            .with(Fragment::new(FragmentKind::Guardrail, "topic", "[topic]"));
+        let text = prompt.text();
+        assert!(text.contains("in German") && !text.contains("French"));
+        assert!(text.contains("[topic]") && !text.contains(TOPIC));
+        assert_eq!(prompt.fragments().len(), 6);
+    }
+
+    #[test]
+    fn switching_presets_keeps_the_session() {
+        let mut prompt = SystemPrompt::new(Preset::Family)
+            .with(show_both())
+            .with(preferences("No nuts."));
+        prompt.set_preset(Preset::NoTools);
+        assert_eq!(prompt.preset(), Preset::NoTools);
+        assert_eq!(
+            prompt.text(),
+            format!("{}{}\n    No nuts.\n", Preset::NoTools.text(), SHOW_BOTH)
+        );
+    }
+}
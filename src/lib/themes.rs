@@ -0,0 +1,367 @@
+//! Weekly themes like Meatless Monday or Taco Tuesday, kept in
+//! `themes.json` in the config directory:
+//!
+//! ```json
+//! {"monday": "meatless", "tuesday": "tacos"}
+//! ```
+//!
+//! Today's theme goes into the system prompt through [crate::context], and
+//! transmitted recipes are checked against it with [check].  The checks
+//! are simple keyword matches, so a miss is only ever a warning.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::Weekday;
+
+use crate::paths;
+use crate::recipe::{self, Section};
+
+static WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Lowercase, as in `themes.json`
+pub fn day_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "monday",
+        Weekday::Tue => "tuesday",
+        Weekday::Wed => "wednesday",
+        Weekday::Thu => "thursday",
+        Weekday::Fri => "friday",
+        Weekday::Sat => "saturday",
+        Weekday::Sun => "sunday",
+    }
+}
+
+/// Parses `monday`, `Mon`, `tue`, ...
+pub fn parse_weekday(s: &str) -> Option<Weekday> {
+    let lower = s.trim().to_lowercase();
+    if lower.len() < 3 {
+        return None;
+    }
+    WEEKDAYS
+        .into_iter()
+        .find(|day| day_name(*day).starts_with(&lower))
+}
+
+const MEAT: &[&str] = &[
+    "chicken",
+    "beef",
+    "pork",
+    "bacon",
+    "ham",
+    "sausage",
+    "lamb",
+    "turkey",
+    "steak",
+    "veal",
+    "chorizo",
+    "prosciutto",
+    "salami",
+    "pepperoni",
+    "duck",
+    "anchov",
+    "fish",
+    "salmon",
+    "tuna",
+    "shrimp",
+    "prawn",
+];
+
+const ANIMAL: &[&str] = &[
+    "milk", "butter", "cheese", "cream", "yogurt", "egg", "honey", "parmesan", "feta",
+];
+
+/// Words that start like an animal product but aren't one
+const NOT_ANIMAL: &[&str] = &["eggplant", "butternut", "creamer"];
+
+/// Plant-based stand-ins: "coconut milk", "peanut butter"
+const PLANT_BASED: &[&str] = &[
+    "coconut", "almond", "oat", "soy", "peanut", "cashew", "rice", "vegan", "plant",
+];
+
+/// Whether a word of `text` starts with `keyword`, so "anchov" finds
+/// "anchovies" but "ham" doesn't find "graham"
+fn mentions(text: &str, keyword: &str) -> bool {
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    words.iter().enumerate().any(|(idx, word)| {
+        word.starts_with(keyword)
+            && !NOT_ANIMAL.contains(word)
+            && !(idx > 0 && PLANT_BASED.contains(&words[idx - 1]))
+    })
+}
+
+/// Keywords in a theme, and what they mean for the ingredients
+enum Rule {
+    /// At least one of these must appear
+    Requires(&'static [&'static str]),
+    /// None of these may appear
+    Excludes(&'static [&'static str]),
+}
+
+static RULES: &[(&[&str], Rule)] = &[
+    (&["meatless", "vegetarian", "veggie"], Rule::Excludes(MEAT)),
+    (&["vegan", "plant"], Rule::Excludes(MEAT)),
+    (&["vegan", "plant"], Rule::Excludes(ANIMAL)),
+    (&["taco"], Rule::Requires(&["tortilla", "taco"])),
+    (
+        &["pizza"],
+        Rule::Requires(&["pizza", "dough", "crust", "flatbread"]),
+    ),
+    (
+        &["pasta", "noodle"],
+        Rule::Requires(&[
+            "pasta",
+            "noodle",
+            "spaghetti",
+            "penne",
+            "linguine",
+            "fettuccine",
+            "macaroni",
+            "orzo",
+            "lasagna",
+            "gnocchi",
+            "udon",
+            "ramen",
+        ]),
+    ),
+    (
+        &["fish", "seafood"],
+        Rule::Requires(&[
+            "fish", "salmon", "tuna", "cod", "tilapia", "halibut", "shrimp", "prawn", "scallop",
+            "crab", "mussel", "clam",
+        ]),
+    ),
+    (&["soup"], Rule::Requires(&["broth", "stock", "soup"])),
+];
+
+/// Why `recipe` doesn't fit `theme`, or `None` if it does as far as the
+/// keyword checks can tell (or there are no checks for the theme)
+pub fn check(theme: &str, recipe: &str) -> Option<String> {
+    let theme = theme.to_lowercase();
+    let ingredients = recipe::section(recipe, Section::Ingredients)
+        .unwrap_or_else(|| recipe.to_string())
+        .to_lowercase();
+    let title = recipe::title(recipe).unwrap_or_default().to_lowercase();
+    for (triggers, rule) in RULES {
+        if !triggers.iter().any(|t| theme.contains(t)) {
+            continue;
+        }
+        match rule {
+            Rule::Requires(words) => {
+                let found = words
+                    .iter()
+                    .any(|w| mentions(&ingredients, w) || mentions(&title, w));
+                if !found {
+                    return Some(format!(
+                        "the theme is {} but the recipe has none of: {}",
+                        theme,
+                        words.join(", ")
+                    ));
+                }
+            }
+            Rule::Excludes(words) => {
+                let found: Vec<&str> = words
+                    .iter()
+                    .filter(|w| mentions(&ingredients, w))
+                    .copied()
+                    .collect();
+                if !found.is_empty() {
+                    return Some(format!(
+                        "the theme is {} but the recipe has {}",
+                        theme,
+                        found.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Themes {
+    path: Option<PathBuf>,
+    /// By lowercase weekday name
+    themes: BTreeMap<String, String>,
+}
+
+impl Themes {
+    /// Loads themes from `path`, which doesn't need to exist yet
+    pub fn with_config(path: &Path) -> io::Result<Themes> {
+        let themes: BTreeMap<String, String> = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        // accept "Monday" or "mon" as keys, but keep them one way
+        let themes = themes
+            .into_iter()
+            .filter_map(|(day, theme)| Some((day_name(parse_weekday(&day)?).to_string(), theme)))
+            .collect();
+        Ok(Themes {
+            path: Some(path.to_path_buf()),
+            themes,
+        })
+    }
+
+    pub fn get(&self, day: Weekday) -> Option<&str> {
+        self.themes.get(day_name(day)).map(String::as_str)
+    }
+
+    /// The whole week from Monday, days without a theme included
+    pub fn schedule(&self) -> Vec<(&'static str, Option<&str>)> {
+        WEEKDAYS
+            .into_iter()
+            .map(|day| (day_name(day), self.get(day)))
+            .collect()
+    }
+
+    /// Sets or, with an empty theme, clears the theme for `day` and writes
+    /// the file back
+    pub fn set(&mut self, day: &str, theme: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(day) = parse_weekday(day) else {
+            return Err(format!("not a day of the week: {}", day).into());
+        };
+        let theme = theme.trim();
+        if theme.is_empty() {
+            self.themes.remove(day_name(day));
+        } else {
+            self.themes
+                .insert(day_name(day).to_string(), theme.to_string());
+        }
+        if let Some(path) = &self.path {
+            paths::write_atomic(path, serde_json::to_string_pretty(&self.themes)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(title: &str, ingredients: &str) -> String {
+        format!(
+            "{}\n\nIngredients:\n{}\n\nInstructions:\n1. Cook it.",
+            title, ingredients
+        )
+    }
+
+    #[test]
+    fn recipes_are_checked_against_the_theme() {
+        for (theme, title, ingredients, fits) in [
+            (
+                "meatless",
+                "Eggplant Parmesan",
+                "- 1 eggplant\n- parmesan",
+                true,
+            ),
+            ("Meatless Monday", "Bolognese", "- 1 lb ground beef", false),
+            // stand-ins and words that only look like meat
+            ("meatless", "Graham Crackers", "- graham flour", true),
+            (
+                "vegan",
+                "Curry",
+                "- coconut milk\n- peanut butter\n- rice",
+                true,
+            ),
+            (
+                "vegan",
+                "Butternut Soup",
+                "- 1 butternut squash\n- stock",
+                true,
+            ),
+            ("vegan", "Omelette", "- 3 eggs\n- butter", false),
+            ("vegan", "Caesar", "- romaine\n- anchovies", false),
+            ("Taco Tuesday", "Fish Tacos", "- 1 lb cod\n- cabbage", true),
+            ("tacos", "Burrito Bowl", "- 8 corn tortillas\n- beans", true),
+            ("tacos", "Burrito Bowl", "- rice\n- beans", false),
+            (
+                "pasta night",
+                "Cacio e Pepe",
+                "- 1 lb spaghetti\n- pecorino",
+                true,
+            ),
+            ("pasta night", "Risotto", "- arborio rice", false),
+            ("seafood friday", "Paella", "- shrimp\n- mussels", true),
+            ("soup", "Minestrone", "- 4 cups vegetable broth", true),
+            // no checks for this one
+            (
+                "comfort food",
+                "Mac and Cheese",
+                "- macaroni\n- cheese",
+                true,
+            ),
+        ] {
+            let result = check(theme, &recipe(title, ingredients));
+            assert_eq!(result.is_none(), fits, "{}: {} {:?}", theme, title, result);
+        }
+    }
+
+    #[test]
+    fn misses_say_why() {
+        assert_eq!(
+            check(
+                "Meatless Monday",
+                &recipe("Surf and Turf", "- steak\n- shrimp")
+            ),
+            Some("the theme is meatless monday but the recipe has steak, shrimp".to_string())
+        );
+        assert_eq!(
+            check("soup", &recipe("Salad", "- lettuce")),
+            Some("the theme is soup but the recipe has none of: broth, stock, soup".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_weekdays() {
+        for (s, day) in [
+            ("monday", Some(Weekday::Mon)),
+            ("Tue", Some(Weekday::Tue)),
+            (" SATURDAY ", Some(Weekday::Sat)),
+            ("thurs", Some(Weekday::Thu)),
+            ("t", None),
+            ("someday", None),
+        ] {
+            assert_eq!(parse_weekday(s), day, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn set_writes_the_schedule_back() {
+        let path = std::env::temp_dir().join(format!("gourmand-themes-{}.json", ulid::Ulid::new()));
+        fs::write(&path, r#"{"Mon": "meatless", "someday": "ignored"}"#).unwrap();
+        let mut themes = Themes::with_config(&path).unwrap();
+        assert_eq!(themes.get(Weekday::Mon), Some("meatless"));
+        themes.set("tue", "tacos").unwrap();
+        themes.set("monday", " ").unwrap();
+        assert!(themes.set("caturday", "fish").is_err());
+
+        let themes = Themes::with_config(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            themes.schedule(),
+            [
+                ("monday", None),
+                ("tuesday", Some("tacos")),
+                ("wednesday", None),
+                ("thursday", None),
+                ("friday", None),
+                ("saturday", None),
+                ("sunday", None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Finding what's left over in the recipes directory: images from a
+//! session that crashed before the recipe was written, metadata for
+//! recipes that were deleted, empty files, and recipes saved before
+//! metadata existed.
+//!
+//! Files are grouped by the stem they're named after (`<stem>.txt`,
+//! `<stem>-0.png` or `.jpg` or `.webp`, `<stem>.meta.json`, `<stem>.CHANGES.md`,
+//! `<stem>.<lang>.md`).  Anything else in the directory, such as exports
+//! and the assistant's own images in a flat layout, is left alone.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::imageformat;
+use crate::metadata::{self, RecipeMetadata};
+use crate::recipe;
+
+/// Where `--fix` moves orphans, inside the recipes directory
+pub const ORPHANED_DIR: &str = "_orphaned";
+
+/// Files younger than this are skipped: a running session reserves a
+/// recipe's `.txt` before it generates the images and writes the text
+pub const MIN_AGE: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Text,
+    Image,
+    Metadata,
+    Changes,
+    Translation,
+}
+
+/// The files named after one stem
+#[derive(Debug, Clone, Default)]
+pub struct Group {
+    pub files: Vec<(Kind, PathBuf)>,
+}
+
+impl Group {
+    pub fn get(&self, kind: Kind) -> Option<&Path> {
+        self.files
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, path)| path.as_path())
+    }
+
+    pub fn images(&self) -> impl Iterator<Item = &Path> {
+        self.files
+            .iter()
+            .filter(|(k, _)| *k == Kind::Image)
+            .map(|(_, path)| path.as_path())
+    }
+}
+
+/// Which stem a file in the recipes directory belongs to, and as what
+fn classify(name: &str) -> Option<(&str, Kind)> {
+    let (stem, kind) = if let Some(stem) = name.strip_suffix(".meta.json") {
+        (stem, Kind::Metadata)
+    } else if let Some(stem) = name.strip_suffix(".CHANGES.md") {
+        (stem, Kind::Changes)
+    } else if let Some(stem) = name.strip_suffix(".txt") {
+        (stem, Kind::Text)
+    } else if let Some(rest) = imageformat::EXTENSIONS
+        .iter()
+        .find_map(|ext| name.strip_suffix(&format!(".{}", ext)))
+    {
+        let (stem, idx) = rest.rsplit_once('-')?;
+        if idx.is_empty() || !idx.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        (stem, Kind::Image)
+    } else {
+        let rest = name.strip_suffix(".md")?;
+        let (stem, lang) = rest.rsplit_once('.')?;
+        crate::translate::language_code(lang).ok()?;
+        (stem, Kind::Translation)
+    };
+    // the assistant's images are named <session id>-<turn>-<n>, all digits
+    let ours = !stem.is_empty() && !stem.chars().all(|c| c.is_ascii_digit() || c == '-');
+    ours.then_some((stem, kind))
+}
+
+/// Every recipe file in `dir` by stem, not looking in subdirectories
+pub fn group(dir: &Path) -> io::Result<BTreeMap<String, Group>> {
+    let mut groups: BTreeMap<String, Group> = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some((stem, kind)) = classify(&name) {
+            let group = groups.entry(stem.to_string()).or_default();
+            group.files.push((kind, entry.path()));
+            group.files.sort();
+        }
+    }
+    Ok(groups)
+}
+
+/// What [scan] found, each list sorted by path
+#[derive(Debug, Clone, Default)]
+pub struct Scan {
+    /// Images, metadata, change notes, and translations whose recipe is gone
+    pub orphans: Vec<PathBuf>,
+    /// Zero-byte files, usually a `.txt` reserved by a session that crashed
+    pub empty: Vec<PathBuf>,
+    /// Recipes without a `.meta.json`
+    pub missing_metadata: Vec<PathBuf>,
+    /// Files left alone because a session may still be writing them
+    pub too_new: usize,
+}
+
+impl Scan {
+    pub fn is_clean(&self) -> bool {
+        self.orphans.is_empty() && self.empty.is_empty() && self.missing_metadata.is_empty()
+    }
+}
+
+fn is_recent(path: &Path, now: SystemTime) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() < MIN_AGE)
+}
+
+pub fn scan(dir: &Path) -> io::Result<Scan> {
+    scan_at(dir, SystemTime::now())
+}
+
+/// [scan] as of `now`, which decides what's too new to touch
+pub fn scan_at(dir: &Path, now: SystemTime) -> io::Result<Scan> {
+    let mut scan = Scan::default();
+    for group in group(dir)?.values() {
+        let text = group.get(Kind::Text);
+        for (kind, path) in &group.files {
+            if is_recent(path, now) {
+                scan.too_new += 1;
+                continue;
+            }
+            if fs::metadata(path)?.len() == 0 {
+                scan.empty.push(path.clone());
+            } else if text.is_none() {
+                scan.orphans.push(path.clone());
+            } else if *kind == Kind::Text && group.get(Kind::Metadata).is_none() {
+                scan.missing_metadata.push(path.clone());
+            }
+        }
+    }
+    scan.orphans.sort();
+    scan.empty.sort();
+    scan.missing_metadata.sort();
+    Ok(scan)
+}
+
+/// Moves `path` into [ORPHANED_DIR] next to it, returning where it went
+pub fn move_orphan(path: &Path) -> io::Result<PathBuf> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no parent directory"))?
+        .join(ORPHANED_DIR);
+    fs::create_dir_all(&dir)?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let to = dir.join(name);
+    fs::rename(path, &to)?;
+    Ok(to)
+}
+
+/// Writes metadata for a recipe saved without any, listing its files.
+/// Returns false, writing nothing, if the text doesn't parse as a recipe.
+pub fn restore_metadata(txt: &Path) -> io::Result<bool> {
+    let text = fs::read_to_string(txt)?;
+    if recipe::title(&text).is_none() {
+        return Ok(false);
+    }
+    let txt_name = txt.to_string_lossy();
+    let stem = txt_name.strip_suffix(".txt").unwrap_or(&txt_name);
+    let dir = txt.parent().unwrap_or(Path::new("."));
+    let name = Path::new(stem)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let files = group(dir)?
+        .remove(&name)
+        .map(|group| {
+            group
+                .files
+                .iter()
+                .filter(|(kind, _)| matches!(kind, Kind::Text | Kind::Image))
+                .map(|(_, path)| path.display().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let meta = RecipeMetadata {
+        version: metadata::METADATA_VERSION,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        files,
+        ..RecipeMetadata::default()
+    };
+    meta.save(&RecipeMetadata::path_for(stem))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden;
+
+    /// A copy of `testdata/tidy/recipes` to scan and fix: a recipe with all
+    /// its files, images and a translation left by a crash or a deletion, an
+    /// empty recipe, recipes without metadata, and files that aren't ours
+    fn messy_tree() -> PathBuf {
+        fn copy(from: &Path, to: &Path) {
+            fs::create_dir_all(to).unwrap();
+            for entry in fs::read_dir(from).unwrap() {
+                let entry = entry.unwrap();
+                let to = to.join(entry.file_name());
+                if entry.file_type().unwrap().is_dir() {
+                    copy(&entry.path(), &to);
+                } else {
+                    fs::copy(entry.path(), to).unwrap();
+                }
+            }
+        }
+        let dir = std::env::temp_dir().join(format!("gourmand-tidy-{}", ulid::Ulid::new()));
+        copy(&golden::path("tidy/recipes"), &dir);
+        dir
+    }
+
+    /// An hour from now, when nothing in a fresh copy is too new any more
+    fn later() -> SystemTime {
+        SystemTime::now() + Duration::from_secs(60 * 60)
+    }
+
+    fn names(dir: &Path, paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|p| p.strip_prefix(dir).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn groups_files_by_stem() {
+        let dir = messy_tree();
+        let groups = group(&dir).unwrap();
+        assert_eq!(
+            groups.keys().collect::<Vec<_>>(),
+            [
+                "crashed-stew",
+                "deleted-curry",
+                "empty-soup",
+                "leek-risotto",
+                "old-pancakes",
+                "scratch"
+            ],
+            "the assistant's images, exports, and subdirectories aren't recipes"
+        );
+        let kinds: Vec<Kind> = groups["leek-risotto"]
+            .files
+            .iter()
+            .map(|(k, _)| *k)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                Kind::Text,
+                Kind::Image,
+                Kind::Metadata,
+                Kind::Changes,
+                Kind::Translation
+            ]
+        );
+        assert_eq!(groups["crashed-stew"].images().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scans_the_messy_tree() {
+        let dir = messy_tree();
+        let scan = scan_at(&dir, later()).unwrap();
+        assert_eq!(
+            names(&dir, &scan.orphans),
+            [
+                "crashed-stew-0.png",
+                "crashed-stew-1.jpg",
+                "deleted-curry.es.md",
+                "deleted-curry.meta.json"
+            ]
+        );
+        assert_eq!(names(&dir, &scan.empty), ["empty-soup.txt"]);
+        assert_eq!(
+            names(&dir, &scan.missing_metadata),
+            ["old-pancakes.txt", "scratch.txt"]
+        );
+        assert_eq!(scan.too_new, 0);
+        assert!(!scan.is_clean());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn new_files_are_left_alone() {
+        let dir = messy_tree();
+        let scan = scan(&dir).unwrap();
+        assert!(scan.is_clean(), "{:?}", scan);
+        assert_eq!(scan.too_new, 14);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fixing_leaves_only_the_empty_file() {
+        let dir = messy_tree();
+        let scan = scan_at(&dir, later()).unwrap();
+        for path in &scan.orphans {
+            let to = move_orphan(path).unwrap();
+            assert_eq!(to, dir.join(ORPHANED_DIR).join(path.file_name().unwrap()));
+            assert!(to.exists() && !path.exists());
+        }
+        let restored: Vec<bool> = scan
+            .missing_metadata
+            .iter()
+            .map(|path| restore_metadata(path).unwrap())
+            .collect();
+        assert_eq!(restored, [true, false], "scratch.txt isn't a recipe");
+
+        let meta = RecipeMetadata::load(&dir.join("old-pancakes.meta.json")).unwrap();
+        assert_eq!(
+            names(
+                &dir,
+                &meta.files.iter().map(PathBuf::from).collect::<Vec<_>>()
+            ),
+            ["old-pancakes.txt", "old-pancakes-0.png"]
+        );
+        assert_eq!(meta.version, metadata::METADATA_VERSION);
+
+        let rescan = scan_at(&dir, later()).unwrap();
+        assert!(rescan.orphans.is_empty(), "{:?}", rescan.orphans);
+        assert_eq!(names(&dir, &rescan.empty), ["empty-soup.txt"]);
+        assert_eq!(names(&dir, &rescan.missing_metadata), ["scratch.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
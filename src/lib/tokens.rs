@@ -0,0 +1,223 @@
+//! How much of the model's context window a conversation takes up.
+//!
+//! Tokens are estimated locally at about four characters each, which is
+//! close for English prose and off for recipes full of numbers and units.
+//! [Estimator] corrects itself with the input token counts Bedrock
+//! reports after each request.
+use aws_sdk_bedrockruntime::types::ContentBlock;
+
+pub const CHARS_PER_TOKEN: f64 = 4.0;
+
+/// Context windows in tokens, matched as prefixes of the model id without
+/// its cross-region prefix, like [crate::cost]'s prices
+static WINDOWS: &[(&str, usize)] = &[
+    ("amazon.nova-micro", 128_000),
+    ("amazon.nova-lite", 300_000),
+    ("amazon.nova-pro", 300_000),
+    ("anthropic.claude", 200_000),
+    ("meta.llama3-1", 128_000),
+    ("meta.llama3-3", 128_000),
+    ("mistral.mistral-large-2407", 128_000),
+    ("mistral.mistral-large", 32_000),
+];
+
+/// Above this share of the window the meter turns yellow
+pub const WARN_PERCENT: usize = 70;
+
+/// Above this it turns red, and it's time to `compact`
+pub const CRITICAL_PERCENT: usize = 90;
+
+fn without_region(model: &str) -> &str {
+    ["us.", "eu.", "apac."]
+        .iter()
+        .find_map(|region| model.strip_prefix(region))
+        .unwrap_or(model)
+}
+
+/// The context window of `model`, or `None` if it isn't in the table
+pub fn context_window(model: &str) -> Option<usize> {
+    let model = without_region(model);
+    WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+}
+
+/// `claude-3-5-sonnet` for `us.anthropic.claude-3-5-sonnet-20241022-v2:0`:
+/// no region, vendor, release date, or version
+pub fn short_model_name(model: &str) -> String {
+    let model = without_region(model);
+    let model = model.split_once('.').map_or(model, |(_, name)| name);
+    model
+        .split('-')
+        .take_while(|part| {
+            let date = part.len() >= 6 && part.chars().all(|c| c.is_ascii_digit());
+            let version =
+                part.starts_with('v') && part[1..].starts_with(|c: char| c.is_ascii_digit());
+            !date && !version
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Characters in content blocks, as far as they count toward the context
+pub fn content_chars(content: &[ContentBlock]) -> usize {
+    content
+        .iter()
+        .map(|c| match c {
+            ContentBlock::Text(text) => text.len(),
+            ContentBlock::ToolUse(t) => format!("{:?}", t.input()).len(),
+            ContentBlock::ToolResult(r) => r
+                .content()
+                .iter()
+                .map(|c| c.as_text().map_or(0, String::len))
+                .sum(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Turns characters into tokens, scaled by how far off the plain estimate
+/// was for recent requests
+#[derive(Debug, Clone)]
+pub struct Estimator {
+    /// Actual tokens per estimated token
+    ratio: f64,
+}
+
+impl Default for Estimator {
+    fn default() -> Estimator {
+        Estimator { ratio: 1.0 }
+    }
+}
+
+impl Estimator {
+    pub fn tokens(&self, chars: usize) -> usize {
+        (chars as f64 / CHARS_PER_TOKEN * self.ratio).round() as usize
+    }
+
+    /// Learns from a request of `chars` characters that Bedrock billed as
+    /// `input_tokens`.  Moves halfway toward the observed ratio each time,
+    /// so one odd request doesn't swing the meter.
+    pub fn correct(&mut self, chars: usize, input_tokens: i32) {
+        let plain = chars as f64 / CHARS_PER_TOKEN;
+        if plain < 1.0 || input_tokens <= 0 {
+            return;
+        }
+        let observed = (f64::from(input_tokens) / plain).clamp(0.25, 4.0);
+        self.ratio = (self.ratio + observed) / 2.0;
+    }
+}
+
+/// Percent of the window `tokens` takes, rounded up so a nearly empty
+/// conversation doesn't read as 0%
+pub fn percent(tokens: usize, window: usize) -> usize {
+    if window == 0 {
+        return 100;
+    }
+    (tokens * 100).div_ceil(window)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::golden;
+
+    #[derive(Deserialize)]
+    struct Usage {
+        model: String,
+        requests: Vec<Request>,
+    }
+
+    /// How big a request was by [crate::conversation::Conversation::request_chars],
+    /// and what Bedrock billed for it
+    #[derive(Deserialize)]
+    struct Request {
+        request_chars: usize,
+        input_tokens: i32,
+    }
+
+    /// How far off an estimate may be once the estimator has seen a request
+    const TOLERANCE: f64 = 0.10;
+
+    fn error(estimate: usize, actual: i32) -> f64 {
+        (estimate as f64 - f64::from(actual)).abs() / f64::from(actual)
+    }
+
+    #[test]
+    fn estimates_come_within_tolerance_of_recorded_usage() {
+        let contents = fs::read_to_string(golden::path("usage/recipe_session.json")).unwrap();
+        let usage: Usage = serde_json::from_str(&contents).unwrap();
+        let window = context_window(&usage.model).unwrap();
+        let mut estimator = Estimator::default();
+        for (i, request) in usage.requests.iter().enumerate() {
+            let estimate = estimator.tokens(request.request_chars);
+            let error = error(estimate, request.input_tokens);
+            if i == 0 {
+                // recipes run over four characters a token
+                assert!(
+                    error > TOLERANCE,
+                    "uncorrected estimate off by {:.3}",
+                    error
+                );
+            } else {
+                assert!(
+                    error <= TOLERANCE,
+                    "request {}: estimated {} for {} tokens",
+                    i + 1,
+                    estimate,
+                    request.input_tokens
+                );
+            }
+            // the meter doesn't jump a band because of the error
+            let actual = percent(request.input_tokens as usize, window);
+            assert!(
+                percent(estimate, window).abs_diff(actual) <= 1,
+                "request {}",
+                i + 1
+            );
+            estimator.correct(request.request_chars, request.input_tokens);
+        }
+    }
+
+    #[test]
+    fn nonsense_usage_is_ignored() {
+        let mut estimator = Estimator::default();
+        estimator.correct(4000, 0);
+        estimator.correct(2, 500);
+        assert_eq!(estimator.tokens(4000), 1000);
+        // an outlier moves the estimate, but only so far
+        estimator.correct(4000, 1_000_000);
+        assert_eq!(estimator.tokens(4000), 2500);
+    }
+
+    #[test]
+    fn short_names_and_windows() {
+        for (model, short, window) in [
+            (
+                "us.anthropic.claude-3-5-sonnet-20241022-v2:0",
+                "claude-3-5-sonnet",
+                Some(200_000),
+            ),
+            ("amazon.nova-lite-v1:0", "nova-lite", Some(300_000)),
+            (
+                "mistral.mistral-large-2407-v1:0",
+                "mistral-large-2407",
+                Some(128_000),
+            ),
+            (
+                "mistral.mistral-large-2402-v1:0",
+                "mistral-large-2402",
+                Some(32_000),
+            ),
+            ("cohere.command-r-v1:0", "command-r", None),
+        ] {
+            assert_eq!(short_model_name(model), short, "{}", model);
+            assert_eq!(context_window(model), window, "{}", model);
+        }
+    }
+}
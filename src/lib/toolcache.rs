@@ -0,0 +1,210 @@
+//! Remembering tool results for the length of a prompt, so that a tool with
+//! side effects runs at most once per request for it.
+//!
+//! A retried turn can carry a tool use we've already answered, and a model
+//! confused by an error sometimes asks again with the same input under a
+//! new id.  Running `transmit_recipe` twice means duplicate files and
+//! paying for the image twice, so [ToolCache::lookup] hands back the first
+//! result instead.  The cache is cleared for every new prompt from the
+//! user, since asking for the same recipe again later is deliberate.
+use std::collections::HashMap;
+
+use aws_sdk_bedrockruntime::types::{ToolResultBlock, ToolResultContentBlock, ToolUseBlock};
+
+use crate::session::document_to_json;
+
+/// A result we already have for a tool use
+#[derive(Debug, Clone)]
+pub enum Cached {
+    /// The very same tool use, seen again
+    SameId(ToolResultBlock),
+    /// Another tool use with identical input.  The result is addressed to
+    /// the new id, with a note for the model.
+    SameInput {
+        original_id: String,
+        result: ToolResultBlock,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct ToolCache {
+    by_id: HashMap<String, ToolResultBlock>,
+    /// (tool name, input as canonical JSON) to tool use id
+    by_input: HashMap<(String, String), String>,
+}
+
+fn input_key(tool_use: &ToolUseBlock) -> (String, String) {
+    // serde_json's maps are sorted, so equal input gives equal text
+    let input = document_to_json(tool_use.input()).to_string();
+    (tool_use.name().to_string(), input)
+}
+
+impl ToolCache {
+    pub fn lookup(&self, tool_use: &ToolUseBlock) -> Option<Cached> {
+        if let Some(result) = self.by_id.get(tool_use.tool_use_id()) {
+            return Some(Cached::SameId(result.clone()));
+        }
+        let original_id = self.by_input.get(&input_key(tool_use))?;
+        let original = self.by_id.get(original_id)?;
+        let note = format!(
+            "this is the result of the identical call {}, which already ran; it was not run again",
+            original_id
+        );
+        let result = ToolResultBlock::builder()
+            .tool_use_id(tool_use.tool_use_id())
+            .set_content(Some(original.content().to_vec()))
+            .content(ToolResultContentBlock::Text(note))
+            .set_status(original.status().cloned())
+            .build()
+            .ok()?;
+        Some(Cached::SameInput {
+            original_id: original_id.clone(),
+            result,
+        })
+    }
+
+    pub fn insert(&mut self, tool_use: &ToolUseBlock, result: &ToolResultBlock) {
+        let id = tool_use.tool_use_id().to_string();
+        self.by_input
+            .entry(input_key(tool_use))
+            .or_insert_with(|| id.clone());
+        self.by_id.insert(id, result.clone());
+    }
+
+    /// Forgets everything, at the start of a new prompt
+    pub fn clear(&mut self) {
+        self.by_id.clear();
+        self.by_input.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_id.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_bedrockruntime::types::ToolResultStatus;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::session::json_to_document;
+
+    fn tool_use(id: &str, name: &str, input: Value) -> ToolUseBlock {
+        ToolUseBlock::builder()
+            .tool_use_id(id)
+            .name(name)
+            .input(json_to_document(&input))
+            .build()
+            .unwrap()
+    }
+
+    fn result(id: &str, text: &str) -> ToolResultBlock {
+        ToolResultBlock::builder()
+            .tool_use_id(id)
+            .content(ToolResultContentBlock::Text(text.to_string()))
+            .status(ToolResultStatus::Success)
+            .build()
+            .unwrap()
+    }
+
+    fn texts(result: &ToolResultBlock) -> Vec<&str> {
+        result
+            .content()
+            .iter()
+            .filter_map(|c| c.as_text().ok())
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn transmitted() -> ToolCache {
+        let mut cache = ToolCache::default();
+        let first = tool_use(
+            "tooluse_1",
+            "transmit_recipe",
+            json!({"file_stem": "leek-risotto", "recipe_details": "Leek Risotto"}),
+        );
+        cache.insert(&first, &result("tooluse_1", "saved leek-risotto.txt"));
+        cache
+    }
+
+    #[test]
+    fn the_same_id_gets_the_same_result() {
+        let cache = transmitted();
+        let again = tool_use(
+            "tooluse_1",
+            "transmit_recipe",
+            json!({"file_stem": "leek-risotto", "recipe_details": "Leek Risotto"}),
+        );
+        let Some(Cached::SameId(result)) = cache.lookup(&again) else {
+            panic!("not cached by id");
+        };
+        assert_eq!(result.tool_use_id(), "tooluse_1");
+        assert_eq!(texts(&result), ["saved leek-risotto.txt"]);
+    }
+
+    #[test]
+    fn the_same_input_under_a_new_id_gets_the_result_with_a_note() {
+        let cache = transmitted();
+        // key order doesn't matter
+        let again = tool_use(
+            "tooluse_2",
+            "transmit_recipe",
+            json!({"recipe_details": "Leek Risotto", "file_stem": "leek-risotto"}),
+        );
+        let Some(Cached::SameInput {
+            original_id,
+            result,
+        }) = cache.lookup(&again)
+        else {
+            panic!("not cached by input");
+        };
+        assert_eq!(original_id, "tooluse_1");
+        assert_eq!(result.tool_use_id(), "tooluse_2", "addressed to the new id");
+        assert_eq!(result.status(), Some(&ToolResultStatus::Success));
+        assert_eq!(
+            texts(&result),
+            [
+                "saved leek-risotto.txt",
+                "this is the result of the identical call tooluse_1, which already ran; it was not run again"
+            ]
+        );
+    }
+
+    #[test]
+    fn different_input_or_another_tool_runs_again() {
+        let cache = transmitted();
+        for (name, input) in [
+            (
+                "transmit_recipe",
+                json!({"file_stem": "leek-risotto", "recipe_details": "Leek Risotto, revised"}),
+            ),
+            ("transmit_recipe", json!({"file_stem": "leek-risotto"})),
+            (
+                "save_preferences",
+                json!({"file_stem": "leek-risotto", "recipe_details": "Leek Risotto"}),
+            ),
+        ] {
+            let other = tool_use("tooluse_2", name, input.clone());
+            assert!(cache.lookup(&other).is_none(), "{} {}", name, input);
+        }
+    }
+
+    #[test]
+    fn a_new_prompt_starts_empty() {
+        let mut cache = transmitted();
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+        let again = tool_use(
+            "tooluse_1",
+            "transmit_recipe",
+            json!({"file_stem": "leek-risotto", "recipe_details": "Leek Risotto"}),
+        );
+        assert!(cache.lookup(&again).is_none());
+    }
+}
@@ -0,0 +1,123 @@
+//! Reading tool input the way models actually send it.
+//!
+//! The input should be a JSON object, but some models (Nova Lite, now and
+//! then) send a string containing the JSON instead, occasionally encoded
+//! twice.  Those are unwrapped here so the handlers only see objects.
+use std::collections::HashMap;
+use std::fmt;
+
+use aws_smithy_types::Document;
+
+use crate::session::{document_to_json, json_to_document};
+
+/// How the input arrived, for keeping track of which models misbehave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputShape {
+    Object,
+    /// A string holding a JSON object
+    Stringified,
+    /// A string holding a string holding a JSON object
+    DoublyEncoded,
+}
+
+impl fmt::Display for InputShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputShape::Object => write!(f, "object"),
+            InputShape::Stringified => write!(f, "stringified json"),
+            InputShape::DoublyEncoded => write!(f, "doubly encoded json"),
+        }
+    }
+}
+
+/// The input as an object, and how it arrived.  Returns an error message
+/// suitable for the model if it's neither an object nor JSON for one.
+pub fn object(input: &Document) -> Result<(HashMap<String, Document>, InputShape), String> {
+    let text = match input {
+        Document::Object(map) => return Ok((map.clone(), InputShape::Object)),
+        Document::String(text) => text,
+        _ => return Err(resend()),
+    };
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(serde_json::Value::Object(map)) => Ok((object_from_json(map), InputShape::Stringified)),
+        Ok(serde_json::Value::String(inner)) => {
+            match serde_json::from_str::<serde_json::Value>(&inner) {
+                Ok(serde_json::Value::Object(map)) => {
+                    Ok((object_from_json(map), InputShape::DoublyEncoded))
+                }
+                _ => Err(resend()),
+            }
+        }
+        _ => Err(resend()),
+    }
+}
+
+/// Like [object], as JSON for handlers that don't know the SDK's types
+pub fn json(input: &Document) -> Result<serde_json::Value, String> {
+    let (object, _) = object(input)?;
+    Ok(serde_json::Value::Object(
+        object
+            .iter()
+            .map(|(k, v)| (k.clone(), document_to_json(v)))
+            .collect(),
+    ))
+}
+
+fn object_from_json(map: serde_json::Map<String, serde_json::Value>) -> HashMap<String, Document> {
+    map.iter()
+        .map(|(k, v)| (k.clone(), json_to_document(v)))
+        .collect()
+}
+
+fn resend() -> String {
+    "the tool input wasn't a JSON object; please call the tool again with its arguments as an object"
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn recipe() -> serde_json::Value {
+        json!({
+            "recipe_details": "Rice and Beans\n\nIngredients:\n- rice",
+            "image_prompt": "a bowl of \"rice\" and beans",
+            "servings": 2,
+        })
+    }
+
+    #[test]
+    fn inputs_as_models_send_them() {
+        let stringified = recipe().to_string();
+        let doubly = serde_json::Value::String(stringified.clone()).to_string();
+        for (input, shape) in [
+            (json_to_document(&recipe()), InputShape::Object),
+            (Document::String(stringified), InputShape::Stringified),
+            (Document::String(doubly), InputShape::DoublyEncoded),
+        ] {
+            let (map, got) = object(&input).unwrap();
+            assert_eq!(got, shape);
+            assert_eq!(map["servings"], json_to_document(&json!(2)), "{}", shape);
+            assert_eq!(json(&input).unwrap(), recipe(), "{}", shape);
+        }
+    }
+
+    #[test]
+    fn anything_else_asks_for_a_resend() {
+        for input in [
+            Document::Null,
+            Document::Array(vec![json_to_document(&recipe())]),
+            Document::String("".to_string()),
+            Document::String("Rice and Beans".to_string()),
+            Document::String("[1, 2]".to_string()),
+            Document::String("\"Rice and Beans\"".to_string()),
+            Document::String("\"[1, 2]\"".to_string()),
+            Document::String(r#"{"recipe_details": "Rice"#.to_string()),
+        ] {
+            assert_eq!(object(&input), Err(resend()), "{:?}", input);
+            assert_eq!(json(&input), Err(resend()), "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Running the tools the model asked for in one message at the same time.
+//!
+//! Each handler gets shared access to the session and a [Changes] sender
+//! for whatever it needs to change, e.g. caching something it fetched.
+//! [run_concurrently] waits for all of them, then hands back the results in
+//! the order the tools were asked for, and the changes in the order they
+//! were sent, for the caller to apply once nothing borrows the session.
+use std::future::Future;
+
+use futures::future::join_all;
+use log::error;
+use tokio::sync::mpsc;
+
+/// Where a handler sends the changes it wants made to the session
+#[derive(Debug, Clone)]
+pub struct Changes<M>(mpsc::UnboundedSender<M>);
+
+impl<M> Changes<M> {
+    pub fn send(&self, change: M) {
+        // the receiver is only dropped after every handler has finished
+        if self.0.send(change).is_err() {
+            error!("a tool's change to the session was lost");
+        }
+    }
+}
+
+/// Runs `handler` on every call concurrently.  Returns the results in the
+/// order of `calls`, however long each took, and the changes the handlers
+/// sent.
+pub async fn run_concurrently<'a, C, R, M, F, Fut>(calls: &'a [C], handler: F) -> (Vec<R>, Vec<M>)
+where
+    F: Fn(&'a C, Changes<M>) -> Fut,
+    Fut: Future<Output = R>,
+{
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let results = join_all(
+        calls
+            .iter()
+            .map(|call| handler(call, Changes(sender.clone()))),
+    )
+    .await;
+    drop(sender);
+    let mut changes = vec![];
+    while let Ok(change) = receiver.try_recv() {
+        changes.push(change);
+    }
+    (results, changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// A tool that takes `millis` to answer, and says so
+    async fn slow_tool(millis: u64, changes: Changes<String>) -> u64 {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        changes.send(format!("slept {}", millis));
+        millis
+    }
+
+    #[tokio::test]
+    async fn slow_tools_take_as_long_as_the_slowest() {
+        let start = Instant::now();
+        let (results, _) =
+            run_concurrently(&[300, 300], |millis, changes| slow_tool(*millis, changes)).await;
+        let elapsed = start.elapsed();
+        assert_eq!(results, [300, 300]);
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(550), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn results_keep_the_order_asked_and_changes_the_order_sent() {
+        let (results, changes) = run_concurrently(&[120, 10, 60], |millis, changes| {
+            slow_tool(*millis, changes)
+        })
+        .await;
+        assert_eq!(results, [120, 10, 60]);
+        assert_eq!(changes, ["slept 10", "slept 60", "slept 120"]);
+    }
+
+    #[tokio::test]
+    async fn no_calls() {
+        let (results, changes): (Vec<u64>, Vec<String>) =
+            run_concurrently(&[], |millis: &u64, changes| slow_tool(*millis, changes)).await;
+        assert!(results.is_empty());
+        assert!(changes.is_empty());
+    }
+}
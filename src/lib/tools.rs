@@ -0,0 +1,226 @@
+//! Tools offered to the model, with the code that answers them.
+//!
+//! A [ToolDef] is a name, a description and a JSON schema for its input,
+//! built up an argument at a time, plus an optional handler.
+//! [crate::Conversation::ask] runs the handler when the model uses the tool
+//! and sends back what it returns.  The shell registers its tools without
+//! handlers, since answering them takes the session, and dispatches them
+//! itself.
+use std::fmt;
+use std::sync::Arc;
+
+use aws_sdk_bedrockruntime::error::BuildError;
+use aws_sdk_bedrockruntime::types::{Tool, ToolConfiguration, ToolInputSchema, ToolSpecification};
+
+use crate::session::json_to_document;
+
+/// What a handler answers with: the text for the model, or a message sent
+/// back as an error result so the model can try again or give up
+pub type ToolResult = Result<String, String>;
+
+type Handler = dyn Fn(&serde_json::Value) -> ToolResult + Send + Sync;
+
+#[derive(Clone)]
+pub struct ToolDef {
+    pub name: String,
+    pub description: String,
+    /// The input schema, always a JSON object with `properties` and
+    /// `required`
+    pub schema: serde_json::Value,
+    handler: Option<Arc<Handler>>,
+}
+
+impl ToolDef {
+    /// A tool without arguments or a handler yet
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> ToolDef {
+        ToolDef {
+            name: name.into(),
+            description: description.into(),
+            schema: serde_json::json!({
+                "type": "object",
+                "properties": {},
+                "required": [],
+            }),
+            handler: None,
+        }
+    }
+
+    /// A string argument the model must give
+    pub fn required(self, name: &str, description: &str) -> ToolDef {
+        self.property(name, string(description), true)
+    }
+
+    /// A string argument the model may leave out
+    pub fn optional(self, name: &str, description: &str) -> ToolDef {
+        self.property(name, string(description), false)
+    }
+
+    /// An argument with a schema of its own, for arrays, integers and the
+    /// like
+    pub fn property(mut self, name: &str, schema: serde_json::Value, required: bool) -> ToolDef {
+        self.schema["properties"][name] = schema;
+        if required {
+            if let Some(names) = self.schema["required"].as_array_mut() {
+                names.push(name.into());
+            }
+        }
+        self
+    }
+
+    /// Answers the tool with `handler`, which gets the input as an object
+    pub fn handler(
+        mut self,
+        handler: impl Fn(&serde_json::Value) -> ToolResult + Send + Sync + 'static,
+    ) -> ToolDef {
+        self.handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Runs the handler, or `None` without one
+    pub fn call(&self, input: &serde_json::Value) -> Option<ToolResult> {
+        self.handler.as_ref().map(|handler| handler(input))
+    }
+
+    fn spec(&self) -> Result<Tool, BuildError> {
+        let spec = ToolSpecification::builder()
+            .name(&self.name)
+            .description(&self.description)
+            .input_schema(ToolInputSchema::Json(json_to_document(&self.schema)))
+            .build()?;
+        Ok(Tool::ToolSpec(spec))
+    }
+}
+
+fn string(description: &str) -> serde_json::Value {
+    serde_json::json!({ "type": "string", "description": description })
+}
+
+impl fmt::Debug for ToolDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToolDef")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("schema", &self.schema)
+            .field("handler", &self.handler.is_some())
+            .finish()
+    }
+}
+
+/// The tools on offer, in the order they were added
+#[derive(Debug, Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<ToolDef>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> ToolRegistry {
+        ToolRegistry::default()
+    }
+
+    /// Adds `tool`, replacing one with the same name
+    pub fn tool(mut self, tool: ToolDef) -> ToolRegistry {
+        self.register(tool);
+        self
+    }
+
+    /// Like [ToolRegistry::tool], in place
+    pub fn register(&mut self, tool: ToolDef) {
+        self.tools.retain(|t| t.name != tool.name);
+        self.tools.push(tool);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ToolDef> {
+        self.tools.iter().find(|t| t.name == name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.tools.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Runs `name`'s handler, or `None` if there's no such tool or it has
+    /// no handler
+    pub fn call(&self, name: &str, input: &serde_json::Value) -> Option<ToolResult> {
+        self.get(name)?.call(input)
+    }
+
+    bedrock_pub! {
+        /// The tool configuration the model is sent
+        fn configuration(&self) -> Result<ToolConfiguration, BuildError> {
+            let tools = self
+                .tools
+                .iter()
+                .map(ToolDef::spec)
+                .collect::<Result<Vec<_>, _>>()?;
+            ToolConfiguration::builder().set_tools(Some(tools)).build()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::toolspec::{self, Arg};
+
+    #[test]
+    fn same_configuration_as_toolspec() {
+        let registry = ToolRegistry::new().tool(
+            ToolDef::new("seasonal_produce", "lists what's in season")
+                .required("region", "where the user lives")
+                .optional("month", "defaults to this month"),
+        );
+        let expected = toolspec::tool(
+            "seasonal_produce",
+            "lists what's in season",
+            &[
+                Arg::required("region", "where the user lives"),
+                Arg::optional("month", "defaults to this month"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            toolspec::canonical_json(&registry.configuration().unwrap()),
+            toolspec::canonical_json(&expected)
+        );
+    }
+
+    #[test]
+    fn handlers() {
+        let mut registry = ToolRegistry::new()
+            .tool(ToolDef::new("pantry", "what's at home").handler(|input| {
+                match input["category"].as_str() {
+                    Some("spices") => Ok("cumin, paprika".to_string()),
+                    Some(other) => Err(format!("no category {}", other)),
+                    None => Ok("rice, beans, cumin, paprika".to_string()),
+                }
+            }))
+            .tool(ToolDef::new("ask_user", "asks the user").property(
+                "choices",
+                json!({"type": "array", "items": {"type": "string"}}),
+                true,
+            ));
+        assert_eq!(
+            registry.call("pantry", &json!({"category": "spices"})),
+            Some(Ok("cumin, paprika".to_string()))
+        );
+        assert!(matches!(
+            registry.call("pantry", &json!({"category": "dairy"})),
+            Some(Err(_))
+        ));
+        assert_eq!(registry.call("ask_user", &json!({})), None);
+        assert_eq!(registry.call("weather", &json!({})), None);
+        assert_eq!(
+            registry.get("ask_user").unwrap().schema["required"],
+            json!(["choices"])
+        );
+
+        registry.register(ToolDef::new("pantry", "replaced"));
+        assert_eq!(registry.names(), ["ask_user", "pantry"]);
+        assert_eq!(registry.call("pantry", &json!({})), None);
+    }
+}
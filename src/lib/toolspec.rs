@@ -0,0 +1,361 @@
+//! The tool configuration as Bedrock sees it.
+//!
+//! Tool specs are built through helpers that hide the JSON schema, so a
+//! tweak to an argument's description quietly changes what the model is
+//! sent.  [canonical_json] renders the configuration the same way every
+//! time (keys sorted, tools in order) so it can be diffed between
+//! versions, and [validate] checks it against Bedrock's limits before a
+//! request fails on them.
+use std::fs;
+use std::path::Path;
+
+use aws_sdk_bedrockruntime::error::BuildError;
+use aws_sdk_bedrockruntime::types::{Tool, ToolConfiguration, ToolInputSchema, ToolSpecification};
+
+use crate::session::{document_to_json, json_to_document};
+
+/// Bedrock's limit on tool names, which must also match `[a-zA-Z0-9_-]+`
+pub const MAX_NAME_CHARS: usize = 64;
+
+/// Longer descriptions are accepted by some models, but they cost tokens
+/// on every request and usually mean the description is doing the system
+/// prompt's job
+pub const MAX_DESCRIPTION_CHARS: usize = 1024;
+
+/// Per tool, across the whole input schema
+pub const MAX_PROPERTIES: usize = 32;
+
+/// `{"tools": [{"name", "description", "input_schema"}, ...]}`, with
+/// object keys sorted.  Anything other than a tool spec is rendered as
+/// its kind only.
+pub fn canonical_json(tools: &ToolConfiguration) -> serde_json::Value {
+    let specs: Vec<serde_json::Value> = tools
+        .tools()
+        .iter()
+        .map(|tool| match tool {
+            Tool::ToolSpec(spec) => {
+                let schema = match spec.input_schema() {
+                    Some(ToolInputSchema::Json(schema)) => document_to_json(schema),
+                    _ => serde_json::Value::Null,
+                };
+                serde_json::json!({
+                    "name": spec.name(),
+                    "description": spec.description(),
+                    "input_schema": schema,
+                })
+            }
+            other => serde_json::json!({ "unsupported": format!("{:?}", other) }),
+        })
+        .collect();
+    serde_json::json!({ "tools": specs })
+}
+
+/// One string argument to a tool made with [tool]
+#[derive(Debug, Clone, Copy)]
+pub struct Arg<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub required: bool,
+}
+
+impl<'a> Arg<'a> {
+    pub fn required(name: &'a str, description: &'a str) -> Arg<'a> {
+        Arg {
+            name,
+            description,
+            required: true,
+        }
+    }
+
+    pub fn optional(name: &'a str, description: &'a str) -> Arg<'a> {
+        Arg {
+            name,
+            description,
+            required: false,
+        }
+    }
+}
+
+/// A configuration with a single tool taking the string `args`.
+/// Arguments of other types can be added with [with_property].
+pub fn tool(name: &str, description: &str, args: &[Arg]) -> Result<ToolConfiguration, BuildError> {
+    let properties: serde_json::Map<String, serde_json::Value> = args
+        .iter()
+        .map(|arg| {
+            (
+                arg.name.to_string(),
+                serde_json::json!({ "type": "string", "description": arg.description }),
+            )
+        })
+        .collect();
+    let required: Vec<&str> = args
+        .iter()
+        .filter(|arg| arg.required)
+        .map(|arg| arg.name)
+        .collect();
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    });
+    let spec = ToolSpecification::builder()
+        .name(name)
+        .description(description)
+        .input_schema(ToolInputSchema::Json(json_to_document(&schema)))
+        .build()?;
+    ToolConfiguration::builder()
+        .tools(Tool::ToolSpec(spec))
+        .build()
+}
+
+/// The names of the tool specs in `tools`
+pub fn names(tools: &ToolConfiguration) -> Vec<String> {
+    tools
+        .tools()
+        .iter()
+        .filter_map(|tool| match tool {
+            Tool::ToolSpec(spec) => Some(spec.name().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `tools` with only the specs whose name `keep` accepts, or `None` if that
+/// leaves nothing: some models behave differently given an empty list of
+/// tools than given none
+pub fn retain(
+    tools: &ToolConfiguration,
+    keep: impl Fn(&str) -> bool,
+) -> Result<Option<ToolConfiguration>, BuildError> {
+    let specs: Vec<Tool> = tools
+        .tools()
+        .iter()
+        .filter(|tool| match tool {
+            Tool::ToolSpec(spec) => keep(spec.name()),
+            _ => true,
+        })
+        .cloned()
+        .collect();
+    if !specs.iter().any(Tool::is_tool_spec) {
+        return Ok(None);
+    }
+    ToolConfiguration::builder()
+        .set_tools(Some(specs))
+        .set_tool_choice(tools.tool_choice().cloned())
+        .build()
+        .map(Some)
+}
+
+/// `tools` with an optional property added to the input schema of `tool`,
+/// for arguments [tool] can't express, like arrays
+pub fn with_property(
+    tools: ToolConfiguration,
+    tool: &str,
+    property: &str,
+    schema: serde_json::Value,
+) -> Result<ToolConfiguration, BuildError> {
+    let specs = tools
+        .tools()
+        .iter()
+        .map(|t| match t {
+            Tool::ToolSpec(spec) if spec.name() == tool => {
+                let Some(ToolInputSchema::Json(input)) = spec.input_schema() else {
+                    return Ok(t.clone());
+                };
+                let mut input = document_to_json(input);
+                if let Some(properties) =
+                    input.get_mut("properties").and_then(|p| p.as_object_mut())
+                {
+                    properties.insert(property.to_string(), schema.clone());
+                }
+                let spec = ToolSpecification::builder()
+                    .name(spec.name())
+                    .set_description(spec.description().map(str::to_string))
+                    .input_schema(ToolInputSchema::Json(json_to_document(&input)))
+                    .build()?;
+                Ok(Tool::ToolSpec(spec))
+            }
+            other => Ok(other.clone()),
+        })
+        .collect::<Result<_, BuildError>>()?;
+    ToolConfiguration::builder()
+        .set_tools(Some(specs))
+        .set_tool_choice(tools.tool_choice().cloned())
+        .build()
+}
+
+/// Problems that would make Bedrock reject the configuration, or that are
+/// likely mistakes, one message per problem
+pub fn validate(tools: &ToolConfiguration) -> Vec<String> {
+    let mut problems = vec![];
+    let mut names: Vec<&str> = vec![];
+    for tool in tools.tools() {
+        let Tool::ToolSpec(spec) = tool else {
+            continue;
+        };
+        let name = spec.name();
+        if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+            problems.push(format!(
+                "{}: names must be 1 to {} characters",
+                name, MAX_NAME_CHARS
+            ));
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            problems.push(format!(
+                "{}: names may only use letters, digits, _ and -",
+                name
+            ));
+        }
+        if names.contains(&name) {
+            problems.push(format!("{}: defined more than once", name));
+        }
+        names.push(name);
+
+        match spec.description().map(|d| d.trim().chars().count()) {
+            None | Some(0) => problems.push(format!("{}: no description", name)),
+            Some(n) if n > MAX_DESCRIPTION_CHARS => problems.push(format!(
+                "{}: description is {} characters, over {}",
+                name, n, MAX_DESCRIPTION_CHARS
+            )),
+            Some(_) => (),
+        }
+
+        let Some(ToolInputSchema::Json(schema)) = spec.input_schema() else {
+            problems.push(format!("{}: no JSON input schema", name));
+            continue;
+        };
+        let schema = document_to_json(schema);
+        if schema.get("type").and_then(|t| t.as_str()) != Some("object") {
+            problems.push(format!("{}: the input schema must be an object", name));
+        }
+        let properties = count_properties(&schema);
+        if properties > MAX_PROPERTIES {
+            problems.push(format!(
+                "{}: {} properties, over {}",
+                name, properties, MAX_PROPERTIES
+            ));
+        }
+        let declared = schema
+            .get("properties")
+            .and_then(|p| p.as_object())
+            .map(|p| p.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        for required in schema
+            .get("required")
+            .and_then(|r| r.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r.as_str())
+        {
+            if !declared.iter().any(|d| d == required) {
+                problems.push(format!(
+                    "{}: {} is required but not a property",
+                    name, required
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Set to make [check_snapshot] write snapshots instead of comparing
+pub const UPDATE_SNAPSHOTS: &str = "UPDATE_SNAPSHOTS";
+
+/// Compares [canonical_json] of `tools` with the snapshot in `path`, so a
+/// test notices when a tweak changes what the model is sent.  With
+/// [UPDATE_SNAPSHOTS] set the snapshot is rewritten instead, for review in
+/// the diff.
+pub fn check_snapshot(tools: &ToolConfiguration, path: &Path) -> Result<(), String> {
+    let actual = canonical_json(tools);
+    let pretty = serde_json::to_string_pretty(&actual).map_err(|e| e.to_string())? + "\n";
+    let io_error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    if std::env::var_os(UPDATE_SNAPSHOTS).is_some() {
+        return fs::write(path, pretty).map_err(io_error);
+    }
+    let snapshot = fs::read_to_string(path).map_err(io_error)?;
+    let expected: serde_json::Value =
+        serde_json::from_str(&snapshot).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if expected == actual {
+        return Ok(());
+    }
+    Err(format!(
+        "the tool configuration no longer matches {}; if that's intended, rerun with {}=1 \
+         and review the diff.  It's now:\n{}",
+        path.display(),
+        UPDATE_SNAPSHOTS,
+        pretty
+    ))
+}
+
+/// Properties at every level of the schema
+fn count_properties(schema: &serde_json::Value) -> usize {
+    let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) else {
+        return 0;
+    };
+    properties.len() + properties.values().map(count_properties).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn string_arguments() {
+        let tools = tool(
+            "seasonal_produce",
+            "lists what's in season",
+            &[
+                Arg::required("region", "where the user lives"),
+                Arg::optional("month", "defaults to this month"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            canonical_json(&tools),
+            json!({"tools": [{
+                "name": "seasonal_produce",
+                "description": "lists what's in season",
+                "input_schema": {
+                    "type": "object",
+                    "properties": {
+                        "region": {"type": "string", "description": "where the user lives"},
+                        "month": {"type": "string", "description": "defaults to this month"},
+                    },
+                    "required": ["region"],
+                },
+            }]})
+        );
+        assert!(validate(&tools).is_empty(), "{:?}", validate(&tools));
+    }
+
+    #[test]
+    fn snapshots_catch_changes() {
+        let path = std::env::temp_dir().join(format!("gourmand-tools-{}.json", ulid::Ulid::new()));
+        let tools = tool("weekly_specials", "what's on sale", &[]).unwrap();
+        fs::write(
+            &path,
+            serde_json::to_string_pretty(&canonical_json(&tools)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(check_snapshot(&tools, &path), Ok(()));
+
+        let reworded = tool("weekly_specials", "what's on sale this week", &[]).unwrap();
+        let err = check_snapshot(&reworded, &path).unwrap_err();
+        assert!(err.contains("this week"), "{}", err);
+        fs::remove_file(&path).unwrap();
+        assert!(check_snapshot(&tools, &path).is_err());
+    }
+
+    #[test]
+    fn no_arguments() {
+        let tools = tool("weekly_specials", "what's on sale", &[]).unwrap();
+        let schema = &canonical_json(&tools)["tools"][0]["input_schema"];
+        assert_eq!(schema["properties"], json!({}));
+        assert_eq!(schema["required"], json!([]));
+    }
+}
@@ -0,0 +1,293 @@
+//! Translating a saved recipe, e.g. for a babysitter who reads Portuguese.
+//!
+//! The translation runs as its own short conversation with the Precise
+//! preset, so it never enters the user's history and doesn't need to be
+//! compacted away.  The result is written next to the original as
+//! `<stem>.<lang>.md` with a little front matter saying where it came from.
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use aws_sdk_bedrockruntime::types::{ContentBlock, StopReason};
+use aws_sdk_bedrockruntime::Client;
+
+use crate::conversation::{Conversation, ConversationError};
+use crate::models::InferenceProfilePreset;
+use crate::recipe::{self, Section};
+
+const TRANSLATE_PROMPT: &str = "
+    You translate recipes.  Translate the recipe you're given into the requested language
+    faithfully: keep the title, the section headings, every ingredient, every step and every
+    shopping list item, in the same order and with the same numbering and quantities.  Don't
+    add, drop, merge or rewrite anything, and don't convert units.  Reply with the translated
+    recipe only.
+";
+
+/// Enough for a long recipe in a wordier language
+const MAX_TOKENS: i32 = 4096;
+
+#[derive(Debug)]
+pub enum TranslateError {
+    /// Not a language code like `pt` or `pt-BR`
+    BadLanguage(String),
+    Conversation(ConversationError),
+    /// The model stopped early or answered with nothing
+    Incomplete(String),
+}
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranslateError::BadLanguage(lang) => {
+                write!(f, "not a language code: {} (e.g. pt, es, pt-BR)", lang)
+            }
+            TranslateError::Conversation(e) => write!(f, "{}", e),
+            TranslateError::Incomplete(reason) => {
+                write!(f, "the translation is incomplete: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TranslateError {}
+
+impl From<ConversationError> for TranslateError {
+    fn from(e: ConversationError) -> TranslateError {
+        TranslateError::Conversation(e)
+    }
+}
+
+/// `lang` lowercased if it looks like a language code, which also makes it
+/// safe to put in a file name
+pub fn language_code(lang: &str) -> Result<String, TranslateError> {
+    let code = lang.trim().to_lowercase();
+    let valid = (2..=8).contains(&code.len())
+        && code.starts_with(|c: char| c.is_ascii_alphabetic())
+        && code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if valid {
+        Ok(code)
+    } else {
+        Err(TranslateError::BadLanguage(lang.to_string()))
+    }
+}
+
+/// `<stem>.<lang>.md` next to the recipe's `.txt`
+pub fn output_path(recipe: &Path, lang: &str) -> PathBuf {
+    let stem = recipe.to_string_lossy();
+    let stem = stem.strip_suffix(".txt").unwrap_or(&stem);
+    PathBuf::from(format!("{}.{}.md", stem, lang))
+}
+
+/// Asks `model` for a translation of `text` into `lang`
+pub async fn translate(
+    client: &Client,
+    model: &str,
+    text: &str,
+    lang: &str,
+) -> Result<String, TranslateError> {
+    let mut conversation = Conversation::builder(client.clone(), model)
+        .system_prompt(TRANSLATE_PROMPT)
+        .inference(InferenceProfilePreset::Precise.inference(Some(MAX_TOKENS)))
+        .build();
+    let request = format!("Translate this recipe into {}:\n\n{}", lang, text);
+    let turn = conversation.send(ContentBlock::Text(request)).await?;
+    if turn.stop_reason != StopReason::EndTurn {
+        return Err(TranslateError::Incomplete(turn.stop_reason.to_string()));
+    }
+    let translated = turn
+        .content
+        .iter()
+        .filter_map(|c| c.as_text().ok())
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if translated.trim().is_empty() {
+        return Err(TranslateError::Incomplete("no text".to_string()));
+    }
+    Ok(translated.trim().to_string())
+}
+
+fn counts(text: &str) -> (usize, usize) {
+    let ingredients = recipe::section(text, Section::Ingredients)
+        .map_or(0, |s| s.lines().filter(|l| !l.trim().is_empty()).count());
+    let steps =
+        recipe::section(text, Section::Instructions).map_or(0, |s| recipe::parse_steps(&s).len());
+    (ingredients, steps)
+}
+
+/// How the translation's structure differs from the original's, one
+/// message per difference.  Headings in another language may not be
+/// recognized at all, which shows up here as well.
+pub fn compare(original: &str, translated: &str) -> Vec<String> {
+    let (ingredients, steps) = counts(original);
+    let (translated_ingredients, translated_steps) = counts(translated);
+    let mut problems = vec![];
+    if ingredients != translated_ingredients {
+        problems.push(format!(
+            "{} ingredients in the original, {} in the translation",
+            ingredients, translated_ingredients
+        ));
+    }
+    if steps != translated_steps {
+        problems.push(format!(
+            "{} steps in the original, {} in the translation",
+            steps, translated_steps
+        ));
+    }
+    problems
+}
+
+/// The file contents: front matter, then the translation
+pub fn render(translated: &str, lang: &str, original: &Path) -> String {
+    let title = recipe::title(translated).unwrap_or_default();
+    let source = original
+        .file_name()
+        .map_or(original.to_string_lossy(), |name| name.to_string_lossy());
+    format!(
+        "---\ntitle: {}\nlanguage: {}\ntranslated_from: {}\n---\n\n{}\n",
+        serde_json::Value::String(title),
+        lang,
+        serde_json::Value::String(source.to_string()),
+        translated
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripted::{Reply, Scripted};
+    use serde_json::json;
+
+    const ORIGINAL: &str = "Leek Risotto
+
+Ingredients:
+- 1 tbsp olive oil
+- 2 leeks, sliced
+- 300 g arborio rice
+- 1 l vegetable stock
+
+Instructions:
+1. Soften the leeks in the oil.
+2. Stir in the rice until it's glossy.
+3. Add the stock a ladle at a time, stirring, for about 20 minutes.
+";
+
+    const TRANSLATED: &str = "Risoto de Alho-Poró
+
+Ingredientes:
+- 1 colher de sopa de azeite
+- 2 alhos-porós fatiados
+- 300 g de arroz arbório
+- 1 l de caldo de legumes
+
+Modo de preparo:
+1. Refogue o alho-poró no azeite.
+2. Junte o arroz e mexa até ficar brilhante.
+3. Acrescente o caldo uma concha por vez, mexendo, por cerca de 20 minutos.
+";
+
+    #[test]
+    fn a_faithful_translation_has_no_problems() {
+        assert_eq!(compare(ORIGINAL, TRANSLATED), Vec::<String>::new());
+    }
+
+    #[test]
+    fn dropped_ingredients_and_merged_steps_are_reported() {
+        let translated = TRANSLATED
+            .replace("- 1 colher de sopa de azeite\n", "")
+            .replace(
+                "2. Junte o arroz e mexa até ficar brilhante.\n3. Acrescente",
+                "2. Junte o arroz e acrescente",
+            );
+        assert_eq!(
+            compare(ORIGINAL, &translated),
+            vec![
+                "4 ingredients in the original, 3 in the translation",
+                "3 steps in the original, 2 in the translation",
+            ]
+        );
+    }
+
+    #[test]
+    fn unrecognized_headings_are_reported_too() {
+        let translated = TRANSLATED
+            .replace("Ingredientes:", "Zutaten-Liste:")
+            .replace("Modo de preparo:", "Verfahren:");
+        assert_eq!(
+            compare(ORIGINAL, &translated),
+            vec![
+                "4 ingredients in the original, 0 in the translation",
+                "3 steps in the original, 0 in the translation",
+            ]
+        );
+    }
+
+    #[test]
+    fn language_codes() {
+        let cases = [
+            ("pt", Some("pt")),
+            (" PT-br ", Some("pt-br")),
+            ("zh-hant", Some("zh-hant")),
+            ("p", None),
+            ("portuguese-brazil", None),
+            ("../pt", None),
+            ("pt br", None),
+            ("1pt", None),
+        ];
+        for (lang, expected) in cases {
+            assert_eq!(language_code(lang).ok().as_deref(), expected, "{:?}", lang);
+        }
+    }
+
+    #[test]
+    fn translations_are_written_next_to_the_recipe() {
+        assert_eq!(
+            output_path(Path::new("/recipes/leek-risotto.txt"), "pt"),
+            PathBuf::from("/recipes/leek-risotto.pt.md")
+        );
+        assert_eq!(
+            render(TRANSLATED, "pt", Path::new("/recipes/leek-risotto.txt")),
+            format!(
+                "---\ntitle: \"Risoto de Alho-Poró\"\nlanguage: pt\n\
+                 translated_from: \"leek-risotto.txt\"\n---\n\n{}\n",
+                TRANSLATED
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn asks_for_a_precise_translation() {
+        let backend = Scripted::new([Reply::text(&format!("\n{}\n", TRANSLATED))]);
+        let model = "us.anthropic.claude-3-5-sonnet-20241022-v2:0";
+        let translated = translate(&backend.client(), model, ORIGINAL, "pt")
+            .await
+            .unwrap();
+        assert_eq!(translated, TRANSLATED.trim());
+
+        let body = backend.last_body();
+        assert_eq!(
+            body["inferenceConfig"],
+            json!({"maxTokens": MAX_TOKENS, "temperature": 0.1f32, "topP": 0.5f32})
+        );
+        let request = body["messages"][0]["content"][0]["text"].as_str().unwrap();
+        assert_eq!(
+            request,
+            format!("Translate this recipe into pt:\n\n{}", ORIGINAL)
+        );
+    }
+
+    #[tokio::test]
+    async fn cut_off_or_empty_translations_are_incomplete() {
+        let model = "us.anthropic.claude-3-5-sonnet-20241022-v2:0";
+        let cut_off = Reply::converse(
+            json!({"role": "assistant", "content": [{"text": "Risoto de Alho-Poró"}]}),
+            "max_tokens",
+        );
+        for (reply, reason) in [(cut_off, "max_tokens"), (Reply::text("  "), "no text")] {
+            let backend = Scripted::new([reply]);
+            match translate(&backend.client(), model, ORIGINAL, "pt").await {
+                Err(TranslateError::Incomplete(r)) => assert_eq!(r, reason),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! Curated follow-ups for the changes asked for most often, so `tweak
+//! vegetarian` or `tweak halve` sends a carefully worded instruction
+//! instead of whatever gets typed in a hurry.
+//!
+//! More can be added in `tweaks.json` in the config directory as
+//! `{"kid-friendly": "Revise the last recipe so ..."}`, replacing a
+//! built-in of the same name.  `{}` in a tweak's text is filled in with the
+//! words after its name, as in `tweak add bacon`.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The built-in tweaks, by name
+static BUILTIN: &[(&str, &str)] = &[
+    (
+        "vegetarian",
+        "Revise the last recipe to be vegetarian: replace any meat, poultry, fish, \
+         or meat-based stock with something that does the same job in the dish \
+         (beans, lentils, tofu, mushrooms, eggs, or cheese), and adjust the \
+         seasoning and cooking times to suit.",
+    ),
+    (
+        "vegan",
+        "Revise the last recipe to be vegan: replace every animal product, \
+         including dairy, eggs, honey, and fish sauce, with a plant-based \
+         ingredient that does the same job, and adjust the method where the \
+         substitute behaves differently.",
+    ),
+    (
+        "gluten-free",
+        "Revise the last recipe to be gluten-free: replace wheat, barley, rye, \
+         regular soy sauce, and anything thickened or coated with flour, and \
+         call out ingredients that need a gluten-free label.",
+    ),
+    (
+        "dairy-free",
+        "Revise the last recipe to be dairy-free: replace milk, cream, butter, \
+         cheese, and yogurt with alternatives that keep the texture, and say \
+         where the flavor will differ.",
+    ),
+    (
+        "halve",
+        "Revise the last recipe to serve half as many people: halve every \
+         quantity, round to amounts that are easy to measure, and adjust pan \
+         sizes and cooking times where a smaller batch cooks differently.",
+    ),
+    (
+        "double",
+        "Revise the last recipe to serve twice as many people: double every \
+         quantity, and adjust pan sizes, batches, and cooking times where a \
+         larger batch cooks differently.",
+    ),
+    (
+        "spicier",
+        "Revise the last recipe to be noticeably spicier, adding heat in a way \
+         that suits its cuisine rather than just more chili flakes, and say \
+         how to dial it back at the table.",
+    ),
+    (
+        "milder",
+        "Revise the last recipe to be mild enough for children and anyone who \
+         avoids heat, keeping the flavor with aromatics and herbs instead.",
+    ),
+    (
+        "oven",
+        "Revise the last recipe to cook in the oven instead of on the stovetop, \
+         with the oven temperature, the dish to use, and times for each stage.",
+    ),
+    (
+        "stovetop",
+        "Revise the last recipe to cook entirely on the stovetop instead of in \
+         the oven, with the pan to use, the heat level, and times for each stage.",
+    ),
+    (
+        "equipment",
+        "Revise the last recipe for a kitchen with no {}: adapt the steps that \
+         need it to the oven, the stovetop, or an appliance the user has, with \
+         the new temperatures and times.",
+    ),
+    (
+        "quicker",
+        "Revise the last recipe so it's on the table in 30 minutes or less, \
+         using shortcuts that don't cost much flavor, and give the new total time.",
+    ),
+    (
+        "add",
+        "Revise the last recipe to include {}, working it into the ingredients \
+         and steps where it fits best rather than adding it as a garnish.",
+    ),
+    (
+        "without",
+        "Revise the last recipe without {}, replacing it with something that \
+         does the same job in the dish.",
+    ),
+];
+
+/// Built-ins whose words name what comes out of the recipe, not what goes in
+const REMOVES: &[&str] = &["without", "equipment"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TweakError {
+    Unknown(String),
+    /// The tweak's text has `{}` and nothing was given to fill it in
+    MissingWords(String),
+}
+
+impl fmt::Display for TweakError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TweakError::Unknown(name) => {
+                write!(f, "no tweak named {} (tweak list shows them all)", name)
+            }
+            TweakError::MissingWords(name) => {
+                write!(
+                    f,
+                    "tweak {} needs to know what, e.g. tweak {} bacon",
+                    name, name
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TweakError {}
+
+/// A tweak ready to send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub instruction: String,
+    /// What the tweak would put in the recipe, to check against dietary
+    /// restrictions: the words given for a built-in, all of a custom tweak.
+    /// Empty if it only takes things out.
+    pub introduces: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tweaks {
+    custom: BTreeMap<String, String>,
+}
+
+impl Tweaks {
+    /// Loads custom tweaks from `path`, which doesn't need to exist
+    pub fn with_config(path: &Path) -> io::Result<Tweaks> {
+        let custom = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Tweaks { custom })
+    }
+
+    /// Every tweak as (name, text, whether it's custom), by name
+    pub fn list(&self) -> Vec<(&str, &str, bool)> {
+        let mut all: BTreeMap<&str, (&str, bool)> = BUILTIN
+            .iter()
+            .map(|(name, text)| (*name, (*text, false)))
+            .collect();
+        for (name, text) in &self.custom {
+            all.insert(name, (text, true));
+        }
+        all.into_iter()
+            .map(|(name, (text, custom))| (name, text, custom))
+            .collect()
+    }
+
+    /// The instruction for `name`, with `words` filling in its `{}`, or
+    /// added at the end if it has none
+    pub fn expand(&self, name: &str, words: &str) -> Result<Expansion, TweakError> {
+        let name = name.to_lowercase();
+        let (text, custom) = match self.custom.get(&name) {
+            Some(text) => (text.as_str(), true),
+            None => BUILTIN
+                .iter()
+                .find(|(builtin, _)| *builtin == name)
+                .map(|(_, text)| (*text, false))
+                .ok_or_else(|| TweakError::Unknown(name.clone()))?,
+        };
+        let words = words.trim();
+        let instruction = if text.contains("{}") {
+            if words.is_empty() {
+                return Err(TweakError::MissingWords(name));
+            }
+            text.replace("{}", words)
+        } else if words.is_empty() {
+            text.to_string()
+        } else {
+            format!("{} ({})", text, words)
+        };
+        let introduces = if custom {
+            instruction.clone()
+        } else if REMOVES.contains(&name.as_str()) {
+            String::new()
+        } else {
+            words.to_string()
+        };
+        Ok(Expansion {
+            instruction,
+            introduces,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dietary;
+    use crate::preferences::Preferences;
+
+    fn expand(name: &str, words: &str) -> Expansion {
+        Tweaks::default().expand(name, words).unwrap()
+    }
+
+    #[test]
+    fn builtins_expand_to_their_text() {
+        assert_eq!(
+            expand("Halve", ""),
+            Expansion {
+                instruction: "Revise the last recipe to serve half as many people: halve every \
+                    quantity, round to amounts that are easy to measure, and adjust pan sizes \
+                    and cooking times where a smaller batch cooks differently."
+                    .to_string(),
+                introduces: String::new(),
+            }
+        );
+        assert_eq!(
+            expand("spicier", "but nothing smoky").instruction,
+            "Revise the last recipe to be noticeably spicier, adding heat in a way that suits \
+             its cuisine rather than just more chili flakes, and say how to dial it back at \
+             the table. (but nothing smoky)"
+        );
+        for (name, text) in BUILTIN {
+            assert!(text.starts_with("Revise the last recipe"), "{}", name);
+            assert!(!text.contains("  "), "{} is wrapped badly", name);
+        }
+    }
+
+    #[test]
+    fn words_fill_in_the_blank() {
+        let cases = [
+            (
+                "add",
+                " bacon ",
+                "Revise the last recipe to include bacon, working it into the ingredients \
+                 and steps where it fits best rather than adding it as a garnish.",
+                "bacon",
+            ),
+            (
+                "without",
+                "mushrooms",
+                "Revise the last recipe without mushrooms, replacing it with something that \
+                 does the same job in the dish.",
+                "",
+            ),
+            (
+                "equipment",
+                "air fryer",
+                "Revise the last recipe for a kitchen with no air fryer: adapt the steps \
+                 that need it to the oven, the stovetop, or an appliance the user has, with \
+                 the new temperatures and times.",
+                "",
+            ),
+        ];
+        for (name, words, instruction, introduces) in cases {
+            assert_eq!(
+                expand(name, words),
+                Expansion {
+                    instruction: instruction.to_string(),
+                    introduces: introduces.to_string(),
+                },
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_names_and_missing_words() {
+        let tweaks = Tweaks::default();
+        assert_eq!(
+            tweaks.expand("keto", ""),
+            Err(TweakError::Unknown("keto".to_string()))
+        );
+        assert_eq!(
+            tweaks.expand("add", "  "),
+            Err(TweakError::MissingWords("add".to_string()))
+        );
+    }
+
+    #[test]
+    fn custom_tweaks_replace_builtins() {
+        let path = std::env::temp_dir().join(format!("gourmand-tweaks-{}.json", ulid::Ulid::new()));
+        fs::write(
+            &path,
+            r#"{"halve": "Revise the last recipe for one.", "kid-friendly": "Revise the last recipe so a {} year old will eat it."}"#,
+        )
+        .unwrap();
+        let tweaks = Tweaks::with_config(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            tweaks.expand("halve", "").unwrap().instruction,
+            "Revise the last recipe for one."
+        );
+        let kid_friendly = tweaks.expand("kid-friendly", "5").unwrap();
+        assert_eq!(
+            kid_friendly.instruction,
+            "Revise the last recipe so a 5 year old will eat it."
+        );
+        assert_eq!(kid_friendly.introduces, kid_friendly.instruction);
+        let listed: Vec<(&str, bool)> = tweaks
+            .list()
+            .into_iter()
+            .map(|(name, _, custom)| (name, custom))
+            .filter(|(name, _)| ["halve", "kid-friendly", "oven"].contains(name))
+            .collect();
+        assert_eq!(
+            listed,
+            [("halve", true), ("kid-friendly", true), ("oven", false)]
+        );
+        assert!(Tweaks::with_config(&path).unwrap().custom.is_empty());
+    }
+
+    #[test]
+    fn what_a_tweak_introduces_is_checked_against_the_diet() {
+        let vegetarian = Preferences {
+            diet: vec!["vegetarian".to_string()],
+            ..Preferences::default()
+        };
+        let check = |name: &str, words: &str| {
+            let expansion = expand(name, words);
+            dietary::check_lines(&vegetarian, &[&expansion.introduces], None).len()
+        };
+        assert_eq!(check("add", "bacon"), 1);
+        assert_eq!(check("add", "chickpeas"), 0);
+        assert_eq!(check("without", "bacon"), 0, "taking bacon out is fine");
+    }
+}
@@ -0,0 +1,255 @@
+//! Lines typed at the shell while a turn is running.
+//!
+//! The shell doesn't read its next line until the running command returns,
+//! so a `say` typed during a slow turn used to wait in the terminal, echoed
+//! in the middle of the answer, and then run straight after it as if the
+//! user had seen the reply.  [TypeAhead] reads those lines itself while the
+//! turn runs, so they're queued or refused as they're typed.
+//!
+//! The terminal stays in line mode, so a line only arrives once Enter is
+//! pressed, already edited.  Anything that asks a question mid-turn must
+//! [TypeAhead::pause] it first, or the answer would be taken for a queued
+//! line.
+//!
+//! The reader doesn't print: what happened to each line goes to the
+//! caller's callback as a [Typed], for the binary to show.
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use log::debug;
+
+/// How long the reader waits for input before checking whether to stop
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+pub struct TypeAhead {
+    stop: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    reader: JoinHandle<Vec<String>>,
+}
+
+/// What became of a line typed during a turn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Typed {
+    /// Kept for afterwards; `pending` lines are now waiting
+    Queued { pending: usize },
+    /// Not queueing, so the line was dropped
+    Refused(String),
+}
+
+/// Where typed lines come from
+#[derive(Debug, Clone, Default)]
+pub enum Source {
+    /// Keys pressed at the terminal, if stdin is one
+    #[default]
+    Terminal,
+    /// Whole lines, as sent to the channel, e.g. by a test.  Lines nobody
+    /// read during one turn are left for the next.
+    Lines(Arc<Mutex<Receiver<String>>>),
+}
+
+/// Keeps the reader away from the terminal until dropped
+#[derive(Debug)]
+pub struct Paused(Arc<AtomicBool>);
+
+impl Drop for Paused {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+impl TypeAhead {
+    /// Starts reading from `source`, or `None` when it's the terminal and
+    /// stdin isn't one.  With `queue` each line is kept for
+    /// [TypeAhead::finish]; without it, it's refused.  `on_line` hears about
+    /// each line as it's typed, on the reader's thread.
+    pub fn start(
+        source: &Source,
+        queue: bool,
+        on_line: impl Fn(Typed) + Send + 'static,
+    ) -> Option<TypeAhead> {
+        if matches!(source, Source::Terminal) && !io::stdin().is_terminal() {
+            return None;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let paused = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let stop = stop.clone();
+            let paused = paused.clone();
+            let source = source.clone();
+            thread::spawn(move || {
+                let mut lines = LineReader::new(source);
+                read_lines(&stop, &paused, queue, on_line, || lines.next())
+            })
+        };
+        Some(TypeAhead {
+            stop,
+            paused,
+            reader,
+        })
+    }
+
+    pub fn pause(&self) -> Paused {
+        self.paused.store(true, Ordering::Relaxed);
+        Paused(self.paused.clone())
+    }
+
+    /// Stops reading and returns the queued lines, oldest first
+    pub fn finish(self) -> Vec<String> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.reader.join().unwrap_or_default()
+    }
+}
+
+/// Turns a [Source] into lines, one poll at a time
+struct LineReader {
+    source: Source,
+    /// Typed at the terminal so far, without the Enter
+    line: String,
+}
+
+impl LineReader {
+    fn new(source: Source) -> LineReader {
+        LineReader {
+            source,
+            line: String::new(),
+        }
+    }
+
+    /// A finished line, or `None` if none arrived within [POLL_INTERVAL]
+    fn next(&mut self) -> io::Result<Option<String>> {
+        let lines = match &self.source {
+            Source::Terminal => return self.next_from_terminal(),
+            Source::Lines(lines) => lines,
+        };
+        let lines = lines.lock().unwrap_or_else(|e| e.into_inner());
+        match lines.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => Ok(Some(line)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "nothing more will be typed",
+            )),
+        }
+    }
+
+    fn next_from_terminal(&mut self) -> io::Result<Option<String>> {
+        if !event::poll(POLL_INTERVAL)? {
+            return Ok(None);
+        }
+        let Ok(Event::Key(key)) = event::read() else {
+            return Ok(None);
+        };
+        if key.kind != KeyEventKind::Press {
+            return Ok(None);
+        }
+        match key.code {
+            KeyCode::Char(c) => {
+                self.line.push(c);
+                Ok(None)
+            }
+            KeyCode::Enter => Ok(Some(std::mem::take(&mut self.line))),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn read_lines(
+    stop: &AtomicBool,
+    paused: &AtomicBool,
+    queue: bool,
+    on_line: impl Fn(Typed),
+    mut next_line: impl FnMut() -> io::Result<Option<String>>,
+) -> Vec<String> {
+    let mut queued = vec![];
+    while !stop.load(Ordering::Relaxed) {
+        if paused.load(Ordering::Relaxed) {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        let typed = match next_line() {
+            Ok(Some(typed)) => typed,
+            Ok(None) => continue,
+            Err(e) => {
+                debug!("stopped reading typed lines: {}", e);
+                break;
+            }
+        };
+        let typed = typed.trim();
+        if typed.is_empty() {
+            continue;
+        }
+        if queue {
+            queued.push(typed.to_string());
+            on_line(Typed::Queued {
+                pending: queued.len(),
+            });
+        } else {
+            on_line(Typed::Refused(typed.to_string()));
+        }
+    }
+    queued
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    /// A reader over lines sent to the returned sender, and what it told
+    /// its callback
+    fn reading(queue: bool) -> (TypeAhead, mpsc::Sender<String>, Receiver<Typed>) {
+        let (typing, lines) = mpsc::channel();
+        let (told, heard) = mpsc::channel();
+        let source = Source::Lines(Arc::new(Mutex::new(lines)));
+        let on_line = move |typed| told.send(typed).unwrap();
+        let typeahead = TypeAhead::start(&source, queue, on_line).unwrap();
+        (typeahead, typing, heard)
+    }
+
+    #[test]
+    fn queued_lines_come_back_in_order() {
+        let (typeahead, typing, heard) = reading(true);
+        for line in ["say and a salad?", "   ", " say dessert? "] {
+            typing.send(line.to_string()).unwrap();
+        }
+        let wait = Duration::from_secs(5);
+        assert_eq!(heard.recv_timeout(wait), Ok(Typed::Queued { pending: 1 }));
+        assert_eq!(heard.recv_timeout(wait), Ok(Typed::Queued { pending: 2 }));
+        assert_eq!(typeahead.finish(), ["say and a salad?", "say dessert?"]);
+    }
+
+    #[test]
+    fn lines_are_refused_without_a_queue() {
+        let (typeahead, typing, heard) = reading(false);
+        typing.send("say and a salad?".to_string()).unwrap();
+        assert_eq!(
+            heard.recv_timeout(Duration::from_secs(5)),
+            Ok(Typed::Refused("say and a salad?".to_string()))
+        );
+        assert_eq!(typeahead.finish(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn a_paused_reader_leaves_lines_alone() {
+        let (typeahead, typing, heard) = reading(true);
+        let paused = typeahead.pause();
+        // long enough for a reader that wasn't paused to get it
+        thread::sleep(POLL_INTERVAL * 2);
+        typing.send("y".to_string()).unwrap();
+        thread::sleep(POLL_INTERVAL * 4);
+        assert!(heard.try_recv().is_err(), "the answer was taken as typed");
+        drop(paused);
+        assert_eq!(
+            heard.recv_timeout(Duration::from_secs(5)),
+            Ok(Typed::Queued { pending: 1 })
+        );
+        assert_eq!(typeahead.finish(), ["y"]);
+    }
+}
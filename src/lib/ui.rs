@@ -0,0 +1,270 @@
+//! Formatting assistant output for the terminal
+use std::fmt;
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+/// Width to wrap output at, or `None` to leave it alone.
+///
+/// `requested` (e.g. from `--width`) wins; otherwise the terminal's width is
+/// used.  Output that isn't going to a terminal is never wrapped.
+pub fn output_width(requested: Option<usize>) -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    requested
+        .or_else(|| terminal_size::terminal_size().map(|(terminal_size::Width(w), _)| w as usize))
+}
+
+/// Word-wraps markdown-ish text to `width` columns.
+///
+/// List items keep their indentation on continuation lines.  Code fences and
+/// table rows are left untouched, as are words longer than the width.
+pub fn wrap(text: &str, width: usize) -> String {
+    let mut out = vec![];
+    let mut in_fence = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_fence = !in_fence;
+            out.push(line.to_string());
+        } else if in_fence || trimmed.starts_with('|') || line.chars().count() <= width {
+            out.push(line.to_string());
+        } else {
+            wrap_line(line, width, &mut out);
+        }
+    }
+    let mut wrapped = out.join("\n");
+    if text.ends_with('\n') {
+        wrapped.push('\n');
+    }
+    wrapped
+}
+
+fn wrap_line(line: &str, width: usize, out: &mut Vec<String>) {
+    let prefix_len = list_prefix_len(line);
+    let (prefix, body) = line.split_at(prefix_len);
+    let hanging = " ".repeat(prefix.chars().count());
+
+    let mut current = prefix.to_string();
+    let mut current_len = current.chars().count();
+    let mut empty = true;
+    for word in body.split_whitespace() {
+        let word_len = word.chars().count();
+        if !empty && current_len + 1 + word_len > width {
+            out.push(std::mem::replace(&mut current, hanging.clone()));
+            current_len = hanging.len();
+            empty = true;
+        }
+        if !empty {
+            current.push(' ');
+            current_len += 1;
+        }
+        current.push_str(word);
+        current_len += word_len;
+        empty = false;
+    }
+    out.push(current);
+}
+
+/// Byte length of the leading indentation plus any list marker, e.g. `"  - "` or `"12. "`
+fn list_prefix_len(line: &str) -> usize {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    for bullet in ["- ", "* ", "+ ", "• "] {
+        if rest.starts_with(bullet) {
+            return indent + bullet.len();
+        }
+    }
+    let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && (rest[digits..].starts_with(". ") || rest[digits..].starts_with(") ")) {
+        return indent + digits + 2;
+    }
+    indent
+}
+
+/// When to show assistant output through a pager
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PagerMode {
+    /// Only when the text is taller than the terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl fmt::Display for PagerMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PagerMode::Auto => write!(f, "auto"),
+            PagerMode::Always => write!(f, "always"),
+            PagerMode::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl FromStr for PagerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(PagerMode::Auto),
+            "always" => Ok(PagerMode::Always),
+            "never" => Ok(PagerMode::Never),
+            _ => Err(format!(
+                "unknown pager mode: {} (expected auto, always, or never)",
+                s
+            )),
+        }
+    }
+}
+
+/// The escape sequence that shows an image in the terminal, for terminals
+/// known to support one: iTerm2's (also WezTerm's), or kitty's graphics
+/// protocol, which only takes PNG.  `None` anywhere else, including when
+/// stdout isn't a terminal.
+pub fn inline_image(bytes: &[u8], png: bool) -> Option<String> {
+    use base64::Engine;
+
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+    let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+    if term_program == "iTerm.app" || term_program == "WezTerm" {
+        return Some(format!(
+            "\x1b]1337;File=inline=1;size={}:{}\x07",
+            bytes.len(),
+            data
+        ));
+    }
+    let kitty = std::env::var("TERM").is_ok_and(|t| t == "xterm-kitty")
+        || std::env::var_os("KITTY_WINDOW_ID").is_some();
+    if kitty && png {
+        // sent in chunks of at most 4096 bytes, m=1 on all but the last
+        let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+        let mut out = String::new();
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let more = u8::from(idx + 1 < chunks.len());
+            let control = if idx == 0 {
+                format!("a=T,f=100,m={}", more)
+            } else {
+                format!("m={}", more)
+            };
+            out.push_str(&format!(
+                "\x1b_G{};{}\x1b\\",
+                control,
+                String::from_utf8_lossy(chunk)
+            ));
+        }
+        return Some(out);
+    }
+    None
+}
+
+/// Whether `lines` lines of output should go through the pager.  Never when
+/// stdout isn't a terminal.
+pub fn should_page(mode: PagerMode, lines: usize) -> bool {
+    if !std::io::stdout().is_terminal() {
+        return false;
+    }
+    match mode {
+        PagerMode::Never => false,
+        PagerMode::Always => true,
+        PagerMode::Auto => terminal_size::terminal_size()
+            .is_some_and(|(_, terminal_size::Height(h))| lines >= h as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"Here is a quick weeknight dinner that uses the rice and black beans you already have in the pantry.
+
+Ingredients:
+- 1 cup long-grain rice, rinsed until the water runs clear
+  - or brown rice, which takes about twice as long
+12. Simmer covered for 18 minutes, then let it rest off the heat for another 5 minutes.
+
+```
+rice: 1 cup | water: 2 cups | salt: 1 tsp | simmer: 18 minutes
+```
+| Ingredient | Amount | Notes about where to find it in the store |
+See https://example.com/a-very-long-link-to-a-recipe-that-does-not-fit-on-one-line-at-all-anywhere
+"#;
+
+    #[test]
+    fn wraps_at_40_80_and_120() {
+        for (width, expected) in [
+            (
+                40,
+                r#"Here is a quick weeknight dinner that
+uses the rice and black beans you
+already have in the pantry.
+
+Ingredients:
+- 1 cup long-grain rice, rinsed until
+  the water runs clear
+  - or brown rice, which takes about
+    twice as long
+12. Simmer covered for 18 minutes, then
+    let it rest off the heat for another
+    5 minutes.
+
+```
+rice: 1 cup | water: 2 cups | salt: 1 tsp | simmer: 18 minutes
+```
+| Ingredient | Amount | Notes about where to find it in the store |
+See
+https://example.com/a-very-long-link-to-a-recipe-that-does-not-fit-on-one-line-at-all-anywhere
+"#,
+            ),
+            (
+                80,
+                r#"Here is a quick weeknight dinner that uses the rice and black beans you already
+have in the pantry.
+
+Ingredients:
+- 1 cup long-grain rice, rinsed until the water runs clear
+  - or brown rice, which takes about twice as long
+12. Simmer covered for 18 minutes, then let it rest off the heat for another 5
+    minutes.
+
+```
+rice: 1 cup | water: 2 cups | salt: 1 tsp | simmer: 18 minutes
+```
+| Ingredient | Amount | Notes about where to find it in the store |
+See
+https://example.com/a-very-long-link-to-a-recipe-that-does-not-fit-on-one-line-at-all-anywhere
+"#,
+            ),
+            (
+                120,
+                r#"Here is a quick weeknight dinner that uses the rice and black beans you already have in the pantry.
+
+Ingredients:
+- 1 cup long-grain rice, rinsed until the water runs clear
+  - or brown rice, which takes about twice as long
+12. Simmer covered for 18 minutes, then let it rest off the heat for another 5 minutes.
+
+```
+rice: 1 cup | water: 2 cups | salt: 1 tsp | simmer: 18 minutes
+```
+| Ingredient | Amount | Notes about where to find it in the store |
+See https://example.com/a-very-long-link-to-a-recipe-that-does-not-fit-on-one-line-at-all-anywhere
+"#,
+            ),
+        ] {
+            let wrapped = wrap(SAMPLE, width);
+            assert_eq!(wrapped, expected, "{}", width);
+            // already fits, so wrapping again changes nothing
+            assert_eq!(wrap(&wrapped, width), wrapped, "{}", width);
+        }
+    }
+
+    #[test]
+    fn counts_chars_not_bytes() {
+        assert_eq!(wrap("crème brûlée crème", 18), "crème brûlée crème");
+        assert_eq!(wrap("crème brûlée crème", 17), "crème brûlée\ncrème");
+    }
+}
@@ -0,0 +1,694 @@
+//! Ingredient quantities: parsing "1 1/2 cups", adding compatible amounts,
+//! and formatting them back the way a cook would write them.
+//!
+//! Quantities in different dimensions (a cup and a pound), or that can't be
+//! parsed at all, are never combined; callers keep both lines instead.
+//!
+//! Recipes asked for in Spanish or German (`--language`) are read too:
+//! decimal commas ("1,5 litros"), and those languages' names for the units.
+//! Amounts are always written back with a decimal point and English unit
+//! abbreviations.
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Volume,
+    Mass,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Tsp,
+    Tbsp,
+    Cup,
+    Ml,
+    L,
+    G,
+    Kg,
+    Oz,
+    Lb,
+}
+
+impl Unit {
+    pub fn parse(word: &str) -> Option<Unit> {
+        let word = word.trim_end_matches('.').to_lowercase();
+        let unit = match word.as_str() {
+            "tsp" | "tsps" | "teaspoon" | "teaspoons" => Unit::Tsp,
+            "tbsp" | "tbsps" | "tbs" | "tablespoon" | "tablespoons" => Unit::Tbsp,
+            "cup" | "cups" | "c" => Unit::Cup,
+            "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => Unit::Ml,
+            "l" | "liter" | "liters" | "litre" | "litres" => Unit::L,
+            "g" | "gram" | "grams" => Unit::G,
+            "kg" | "kilogram" | "kilograms" => Unit::Kg,
+            // Spanish
+            "cucharadita" | "cucharaditas" => Unit::Tsp,
+            "cucharada" | "cucharadas" => Unit::Tbsp,
+            "taza" | "tazas" => Unit::Cup,
+            "mililitro" | "mililitros" => Unit::Ml,
+            "litro" | "litros" => Unit::L,
+            "gramo" | "gramos" => Unit::G,
+            "kilo" | "kilos" | "kilogramo" | "kilogramos" => Unit::Kg,
+            // German
+            "tl" | "teelöffel" => Unit::Tsp,
+            "el" | "esslöffel" => Unit::Tbsp,
+            "tasse" | "tassen" => Unit::Cup,
+            "gramm" => Unit::G,
+            "kilogramm" => Unit::Kg,
+            "oz" | "ounce" | "ounces" => Unit::Oz,
+            "lb" | "lbs" | "pound" | "pounds" => Unit::Lb,
+            _ => return None,
+        };
+        Some(unit)
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Unit::Tsp | Unit::Tbsp | Unit::Cup | Unit::Ml | Unit::L => Dimension::Volume,
+            Unit::G | Unit::Kg | Unit::Oz | Unit::Lb => Dimension::Mass,
+        }
+    }
+
+    /// Size in milliliters or grams
+    fn base(&self) -> f64 {
+        match self {
+            Unit::Tsp => 4.928_92,
+            Unit::Tbsp => 14.786_8,
+            Unit::Cup => 236.588,
+            Unit::Ml => 1.0,
+            Unit::L => 1000.0,
+            Unit::G => 1.0,
+            Unit::Kg => 1000.0,
+            Unit::Oz => 28.349_5,
+            Unit::Lb => 453.592,
+        }
+    }
+
+    fn is_metric(&self) -> bool {
+        matches!(self, Unit::Ml | Unit::L | Unit::G | Unit::Kg)
+    }
+
+    /// The unit a cook would use for `base` ml or g
+    fn display_for(dimension: Dimension, base: f64, metric: bool) -> Unit {
+        match (dimension, metric) {
+            (Dimension::Volume, true) if base >= 1000.0 => Unit::L,
+            (Dimension::Volume, true) => Unit::Ml,
+            (Dimension::Volume, false) if base >= Unit::Cup.base() / 4.0 => Unit::Cup,
+            (Dimension::Volume, false) if base >= Unit::Tbsp.base() => Unit::Tbsp,
+            (Dimension::Volume, false) => Unit::Tsp,
+            (Dimension::Mass, true) if base >= 1000.0 => Unit::Kg,
+            (Dimension::Mass, true) => Unit::G,
+            (Dimension::Mass, false) if base >= Unit::Lb.base() => Unit::Lb,
+            (Dimension::Mass, false) => Unit::Oz,
+        }
+    }
+
+    fn name(&self, plural: bool) -> &'static str {
+        match self {
+            Unit::Tsp => "tsp",
+            Unit::Tbsp => "tbsp",
+            Unit::Cup if plural => "cups",
+            Unit::Cup => "cup",
+            Unit::Ml => "ml",
+            Unit::L => "l",
+            Unit::G => "g",
+            Unit::Kg => "kg",
+            Unit::Oz => "oz",
+            Unit::Lb => "lb",
+        }
+    }
+}
+
+/// An amount, possibly a range ("2-3"), with an optional unit.  Without a
+/// unit it's a count ("2 onions").
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantity {
+    pub low: f64,
+    pub high: f64,
+    pub unit: Option<Unit>,
+}
+
+impl Quantity {
+    /// Parses a leading quantity, returning it and the rest of the text
+    pub fn parse(text: &str) -> Option<(Quantity, &str)> {
+        let (low, rest) = parse_amount(text)?;
+        let (high, rest) = match parse_range_end(rest) {
+            Some((high, rest)) if high >= low => (high, rest),
+            _ => (low, rest),
+        };
+        let rest = rest.trim_start();
+        let word_end = rest
+            .find(|c: char| !(c.is_alphabetic() || c == '.'))
+            .unwrap_or(rest.len());
+        let (unit, rest) = match Unit::parse(&rest[..word_end]) {
+            Some(unit) => (Some(unit), &rest[word_end..]),
+            None => (None, rest),
+        };
+        Some((Quantity { low, high, unit }, rest.trim_start()))
+    }
+
+    /// The sum, in a sensible display unit, or None if the units don't mix
+    pub fn add(&self, other: &Quantity) -> Option<Quantity> {
+        match (self.unit, other.unit) {
+            (None, None) => Some(Quantity {
+                low: self.low + other.low,
+                high: self.high + other.high,
+                unit: None,
+            }),
+            (Some(a), Some(b)) if a.dimension() == b.dimension() => {
+                let low = self.low * a.base() + other.low * b.base();
+                let high = self.high * a.base() + other.high * b.base();
+                // stay metric only if both sides were
+                let unit = Unit::display_for(a.dimension(), high, a.is_metric() && b.is_metric());
+                Some(Quantity {
+                    low: low / unit.base(),
+                    high: high / unit.base(),
+                    unit: Some(unit),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_range(&self) -> bool {
+        (self.high - self.low).abs() > f64::EPSILON
+    }
+
+    /// Whether the two can be compared: both counts, or units of the same
+    /// dimension
+    fn mixes(&self, other: &Quantity) -> bool {
+        match (self.unit, other.unit) {
+            (None, None) => true,
+            (Some(a), Some(b)) => a.dimension() == b.dimension(),
+            _ => false,
+        }
+    }
+
+    /// In milliliters or grams, or as is for a count
+    fn scale(&self) -> f64 {
+        self.unit.map_or(1.0, |u| u.base())
+    }
+
+    /// Whether there's at least `needed`, or None if the units don't mix.
+    /// Takes the low end of this range and the high end of `needed`, so it
+    /// never overstates.
+    pub fn covers(&self, needed: &Quantity) -> Option<bool> {
+        if !self.mixes(needed) {
+            return None;
+        }
+        // tolerate rounding from converting between units
+        Some(self.low * self.scale() >= needed.high * needed.scale() - 1e-6)
+    }
+
+    /// What's left after `used`, in this quantity's unit and never below
+    /// zero, or None if the units don't mix.  Takes the high end of `used`.
+    pub fn sub(&self, used: &Quantity) -> Option<Quantity> {
+        if !self.mixes(used) {
+            return None;
+        }
+        // by the ratio, which is exactly 1 for the same unit, so using
+        // everything leaves exactly nothing
+        let used = used.high * (used.scale() / self.scale());
+        Some(Quantity {
+            low: (self.low - used).max(0.0),
+            high: (self.high - used).max(0.0),
+            unit: self.unit,
+        })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.high <= f64::EPSILON
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metric = self.unit.is_some_and(|u| u.is_metric());
+        if self.is_range() {
+            write!(
+                f,
+                "{}-{}",
+                format_amount(self.low, metric),
+                format_amount(self.high, metric)
+            )?;
+        } else {
+            write!(f, "{}", format_amount(self.low, metric))?;
+        }
+        if let Some(unit) = self.unit {
+            write!(f, " {}", unit.name(self.high > 1.0))?;
+        }
+        Ok(())
+    }
+}
+
+fn vulgar_fraction(c: char) -> Option<f64> {
+    let value = match c {
+        '½' => 0.5,
+        '⅓' => 1.0 / 3.0,
+        '⅔' => 2.0 / 3.0,
+        '¼' => 0.25,
+        '¾' => 0.75,
+        '⅕' => 0.2,
+        '⅛' => 0.125,
+        '⅜' => 0.375,
+        '⅝' => 0.625,
+        '⅞' => 0.875,
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn digits(text: &str) -> usize {
+    text.find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len())
+}
+
+/// A number with a decimal point or comma ("1.5", "1,5"), and whether it
+/// was a whole number.  A comma before exactly three digits groups
+/// thousands instead ("1,000").
+fn parse_number(text: &str) -> Option<(f64, bool, &str)> {
+    let end = digits(text);
+    let mut number = text[..end].to_string();
+    let mut rest = &text[end..];
+    while let Some(group) = rest
+        .strip_prefix(',')
+        .filter(|group| end > 0 && digits(group) == 3)
+    {
+        number.push_str(&group[..3]);
+        rest = &group[3..];
+    }
+    let mut whole = true;
+    if let Some(fraction) = rest.strip_prefix(['.', ',']) {
+        let len = digits(fraction);
+        if len > 0 {
+            number.push('.');
+            number.push_str(&fraction[..len]);
+            rest = &fraction[len..];
+            whole = false;
+        }
+    }
+    Some((number.parse().ok()?, whole, rest))
+}
+
+/// A number, fraction ("1/2"), or vulgar fraction, optionally attached to a
+/// whole number ("1½").  Returns whether it was a whole number.
+fn parse_simple(text: &str) -> Option<(f64, bool, &str)> {
+    let first = text.chars().next()?;
+    if let Some(v) = vulgar_fraction(first) {
+        return Some((v, false, &text[first.len_utf8()..]));
+    }
+    let (n, whole, rest) = parse_number(text)?;
+    if let Some(denominator) = rest.strip_prefix('/').filter(|_| whole) {
+        let d_end = digits(denominator);
+        let d: f64 = denominator[..d_end].parse().ok()?;
+        if d == 0.0 {
+            return None;
+        }
+        return Some((n / d, false, &denominator[d_end..]));
+    }
+    if let Some(c) = rest.chars().next() {
+        if let Some(v) = vulgar_fraction(c) {
+            return Some((n + v, false, &rest[c.len_utf8()..]));
+        }
+    }
+    Some((n, whole, rest))
+}
+
+/// A simple amount or a mixed number ("1 1/2")
+fn parse_amount(text: &str) -> Option<(f64, &str)> {
+    let (n, whole, rest) = parse_simple(text.trim_start())?;
+    if whole && rest.starts_with(' ') {
+        if let Some((fraction, false, after)) = parse_simple(rest.trim_start()) {
+            if fraction < 1.0 {
+                return Some((n + fraction, after));
+            }
+        }
+    }
+    Some((n, rest))
+}
+
+/// The end of a range: "-3", "–3", or " to 3"
+fn parse_range_end(text: &str) -> Option<(f64, &str)> {
+    let trimmed = text.trim_start();
+    let rest = trimmed
+        .strip_prefix('-')
+        .or_else(|| trimmed.strip_prefix('–'))
+        .or_else(|| trimmed.strip_prefix("to "))?;
+    parse_amount(rest)
+}
+
+/// Metric amounts as decimals, everything else to the nearest eighth
+fn format_amount(value: f64, metric: bool) -> String {
+    if metric {
+        let rounded = if value >= 10.0 {
+            value.round()
+        } else {
+            (value * 100.0).round() / 100.0
+        };
+        return format!("{}", rounded);
+    }
+    let eighths = ((value * 8.0).round() as u64).max(1);
+    let whole = eighths / 8;
+    let (num, den) = reduce(eighths % 8, 8);
+    match (whole, num) {
+        (w, 0) => format!("{}", w),
+        (0, n) => format!("{}/{}", n, den),
+        (w, n) => format!("{} {}/{}", w, n, den),
+    }
+}
+
+fn reduce(mut num: u64, mut den: u64) -> (u64, u64) {
+    while num > 0 && num.is_multiple_of(2) && den.is_multiple_of(2) {
+        num /= 2;
+        den /= 2;
+    }
+    (num, den)
+}
+
+/// Words for how an ingredient comes rather than what it is
+const FORMS: &[&str] = &["bunch", "clove", "head", "sprig", "stalk"];
+
+/// Sizes that don't change what to buy
+const SIZES: &[&str] = &["large", "medium", "small"];
+
+/// Whether `word` is one of [FORMS], singular or plural
+fn is_form(word: &str) -> bool {
+    let word = word.trim_end_matches('s');
+    FORMS.contains(&word) || word.strip_suffix('e').is_some_and(|w| FORMS.contains(&w))
+}
+
+/// One shopping list line, split into its quantity (if any) and the item
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub quantity: Option<Quantity>,
+    pub item: String,
+}
+
+impl Line {
+    pub fn parse(line: &str) -> Line {
+        match Quantity::parse(line) {
+            Some((quantity, rest)) => Line {
+                quantity: Some(quantity),
+                item: ["of ", "de "]
+                    .iter()
+                    .find_map(|word| rest.strip_prefix(word))
+                    .unwrap_or(rest)
+                    .trim()
+                    .to_string(),
+            },
+            None => Line {
+                quantity: None,
+                item: line.trim().to_string(),
+            },
+        }
+    }
+
+    /// What has to match for two lines to be the same ingredient: lowercase,
+    /// without notes after a comma or in parentheses, and singular.
+    pub fn key(&self) -> String {
+        let item = self.item.to_lowercase();
+        let item = item.split([',', '(']).next().unwrap_or("").trim();
+        item.strip_suffix("es")
+            .filter(|s| s.ends_with("tomato") || s.ends_with("potato"))
+            .or_else(|| item.strip_suffix('s'))
+            .unwrap_or(item)
+            .to_string()
+    }
+
+    /// [Line::key] without a leading size or form, or a trailing form, so
+    /// "large garlic cloves", "cloves of garlic", and "garlic" are all
+    /// garlic.  Only whole words are dropped, and never the last one:
+    /// "garlic powder" stays garlic powder, and "cloves" the spice.
+    pub fn ingredient(&self) -> String {
+        let key = self.key();
+        let mut words: Vec<&str> = key.split_whitespace().collect();
+        while words.len() > 1 && (SIZES.contains(&words[0]) || is_form(words[0])) {
+            words.remove(0);
+            if words.len() > 1 && words[0] == "of" {
+                words.remove(0);
+            }
+        }
+        while words.len() > 1 && words.last().is_some_and(|w| is_form(w)) {
+            words.pop();
+        }
+        words.join(" ")
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.quantity {
+            Some(q) => write!(f, "{} {}", q, self.item),
+            None => write!(f, "{}", self.item),
+        }
+    }
+}
+
+/// Combines lines for the same ingredient where the quantities add up,
+/// keeping the order in which ingredients first appear.  Lines that can't
+/// be combined are kept as they were written.
+pub fn consolidate(lines: &[&str]) -> Vec<String> {
+    let mut merged: Vec<(Line, &str)> = vec![];
+    for text in lines {
+        let line = Line::parse(text);
+        let key = line.key();
+        let existing = merged.iter_mut().find_map(|(other, _)| {
+            let sum = match (&other.quantity, &line.quantity) {
+                (Some(a), Some(b)) if other.key() == key => a.add(b)?,
+                _ => return None,
+            };
+            Some((other, sum))
+        });
+        match existing {
+            Some((other, sum)) => other.quantity = Some(sum),
+            None => merged.push((line, text)),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(line, original)| {
+            // untouched lines keep the author's formatting
+            if Line::parse(original) == line {
+                original.to_string()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantity(text: &str) -> (Quantity, &str) {
+        Quantity::parse(text).unwrap_or_else(|| panic!("no quantity in {:?}", text))
+    }
+
+    #[test]
+    fn decimal_commas() {
+        let (q, rest) = quantity("1,5 litros de leche");
+        assert_eq!((q.low, q.unit), (1.5, Some(Unit::L)));
+        assert_eq!(rest, "de leche");
+        let (q, _) = quantity("0,25 kg Mehl");
+        assert_eq!((q.low, q.unit), (0.25, Some(Unit::Kg)));
+        let (q, _) = quantity("2,5-3 EL Olivenöl");
+        assert_eq!((q.low, q.high, q.unit), (2.5, 3.0, Some(Unit::Tbsp)));
+    }
+
+    #[test]
+    fn thousands_commas_and_points() {
+        let (q, _) = quantity("1,000 g flour");
+        assert_eq!((q.low, q.unit), (1000.0, Some(Unit::G)));
+        let (q, _) = quantity("1.5 cups milk");
+        assert_eq!((q.low, q.unit), (1.5, Some(Unit::Cup)));
+        let (q, _) = quantity(".5 tsp salt");
+        assert_eq!(q.low, 0.5);
+        // a comma between amounts isn't a decimal
+        let (q, rest) = quantity("2, 3 onions");
+        assert_eq!((q.low, rest), (2.0, ", 3 onions"));
+    }
+
+    #[test]
+    fn fractions_still_parse() {
+        assert_eq!(quantity("1 1/2 cups").0.low, 1.5);
+        assert_eq!(quantity("1½ cups").0.low, 1.5);
+        assert_eq!(quantity("3/4 tsp").0.low, 0.75);
+        assert_eq!(Quantity::parse("1/0 cup"), None);
+    }
+
+    #[test]
+    fn spanish_and_german_units() {
+        let cases = [
+            ("2 cucharadas de aceite", Unit::Tbsp, "aceite"),
+            ("1 cucharadita de sal", Unit::Tsp, "sal"),
+            ("3 tazas de arroz", Unit::Cup, "arroz"),
+            ("500 gramos de harina", Unit::G, "harina"),
+            ("1 kilo de patatas", Unit::Kg, "patatas"),
+            ("200 ml Sahne", Unit::Ml, "Sahne"),
+            ("1 TL Salz", Unit::Tsp, "Salz"),
+            ("2 Esslöffel Zucker", Unit::Tbsp, "Zucker"),
+            ("250 Gramm Quark", Unit::G, "Quark"),
+            ("1 Tasse Milch", Unit::Cup, "Milch"),
+        ];
+        for (text, unit, item) in cases {
+            let line = Line::parse(text);
+            assert_eq!(line.quantity.and_then(|q| q.unit), Some(unit), "{}", text);
+            assert_eq!(line.item, item, "{}", text);
+        }
+    }
+
+    #[test]
+    fn localized_amounts_add_up() {
+        assert_eq!(
+            consolidate(&["1,5 litros de leche", "500 ml de leche"]),
+            ["2 l leche"]
+        );
+        assert_eq!(consolidate(&["0,5 kg Mehl", "250 g Mehl"]), ["750 g Mehl"]);
+    }
+
+    const UNITS: [Unit; 9] = [
+        Unit::Tsp,
+        Unit::Tbsp,
+        Unit::Cup,
+        Unit::Ml,
+        Unit::L,
+        Unit::G,
+        Unit::Kg,
+        Unit::Oz,
+        Unit::Lb,
+    ];
+
+    /// Every amount from 1/8 to 8 in eighths, in every unit and as a count
+    fn quantities() -> impl Iterator<Item = Quantity> {
+        (1..=64).flat_map(|eighths| {
+            let amount = eighths as f64 / 8.0;
+            UNITS
+                .iter()
+                .map(|unit| Some(*unit))
+                .chain([None])
+                .map(move |unit| Quantity {
+                    low: amount,
+                    high: amount,
+                    unit,
+                })
+        })
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    /// In milliliters or grams
+    fn total(q: &Quantity) -> f64 {
+        q.high * q.scale()
+    }
+
+    #[test]
+    fn formatting_round_trips() {
+        for q in quantities() {
+            let text = q.to_string();
+            let (parsed, rest) = quantity(&text);
+            assert_eq!(rest, "", "{}", text);
+            assert_eq!(parsed.to_string(), text);
+            // metric amounts are rounded to hundredths, the rest to eighths
+            if !q.unit.is_some_and(|u| u.is_metric()) {
+                assert_eq!(parsed, q, "{}", text);
+            }
+        }
+        for (low, high) in [(1.0, 2.0), (0.5, 0.75), (2.0, 3.5)] {
+            let q = Quantity {
+                low,
+                high,
+                unit: Some(Unit::Cup),
+            };
+            assert_eq!(quantity(&q.to_string()).0, q, "{}", q);
+        }
+    }
+
+    #[test]
+    fn sums_keep_the_total() {
+        for a in quantities().step_by(7) {
+            for b in quantities().step_by(5) {
+                let sum = a.add(&b);
+                assert_eq!(sum.is_some(), a.mixes(&b), "{} + {}", a, b);
+                let Some(sum) = sum else {
+                    continue;
+                };
+                let other_way = b.add(&a).unwrap();
+                assert!(close(total(&sum), total(&other_way)), "{} + {}", a, b);
+                assert!(close(total(&sum), total(&a) + total(&b)), "{} + {}", a, b);
+                assert_eq!(
+                    sum.unit.map(|u| u.dimension()),
+                    a.unit.map(|u| u.dimension())
+                );
+                assert_eq!(sum.covers(&a), Some(true), "{} + {}", a, b);
+                assert_eq!(sum.covers(&b), Some(true), "{} + {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn what_is_left_is_never_negative() {
+        for a in quantities().step_by(7) {
+            for b in quantities().step_by(5) {
+                let Some(left) = a.sub(&b) else {
+                    assert!(!a.mixes(&b), "{} - {}", a, b);
+                    continue;
+                };
+                assert_eq!(left.unit, a.unit);
+                assert!(left.low >= 0.0 && left.high >= 0.0, "{} - {}", a, b);
+                if a.covers(&b) == Some(false) {
+                    assert!(left.is_zero(), "{} - {}", a, b);
+                }
+            }
+            assert!(a.sub(&a).unwrap().is_zero(), "{}", a);
+            assert_eq!(a.covers(&a), Some(true), "{}", a);
+        }
+    }
+
+    #[test]
+    fn consolidating_twice_changes_nothing() {
+        let lines = [
+            "1 cup rice",
+            "2 tbsp olive oil",
+            "1/2 cup rice",
+            "3 cloves garlic",
+            "1 tsp olive oil",
+            "200 g flour",
+            "1 lb flour",
+            "2 onions",
+            "1 onion",
+            "salt to taste",
+            "1 1/2 cups stock",
+            "2 cups stock",
+        ];
+        let once = consolidate(&lines);
+        let refs: Vec<&str> = once.iter().map(String::as_str).collect();
+        assert_eq!(consolidate(&refs), once);
+        assert_eq!(once.len(), 7, "{:?}", once);
+    }
+
+    #[test]
+    fn any_prefix_parses_without_panicking() {
+        for text in [
+            "1 1/2 cups flour",
+            "2½–3 tazas de arroz",
+            "1,000 g Mehl",
+            "3/0 cup",
+            "½",
+            "1 to 2 tbsp. butter",
+            "12,5 kg",
+            "1/",
+            ",5 l",
+        ] {
+            for (end, _) in text.char_indices().chain([(text.len(), ' ')]) {
+                let prefix = &text[..end];
+                if let Some((q, _)) = Quantity::parse(prefix) {
+                    assert!(q.low <= q.high, "{:?}", prefix);
+                    assert!(q.low.is_finite() && q.high.is_finite(), "{:?}", prefix);
+                }
+                let _ = Line::parse(prefix).ingredient();
+            }
+        }
+    }
+}
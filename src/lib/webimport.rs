@@ -0,0 +1,946 @@
+//! Recipes from web pages, for `adapt-url`.
+//!
+//! A page is only fetched when asked for, with our user agent and limits
+//! on time and size.  The recipe is taken from schema.org `Recipe` JSON-LD
+//! where the page has it, as most recipe sites do for search engines, then
+//! from schema.org microdata, and otherwise from the text of the page's
+//! article with scripts, navigation and the like left out.  There's no
+//! HTML parser here, only enough scanning for those three, which is all a
+//! summary for the user and a text for the model need.
+use std::fmt;
+#[cfg(feature = "http")]
+use std::time::Duration;
+
+use serde_json::Value;
+
+/// Don't download more than this
+pub const MAX_PAGE_BYTES: usize = 2 * 1024 * 1024;
+#[cfg(feature = "http")]
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+#[cfg(feature = "http")]
+const USER_AGENT: &str = concat!("gourmand/", env!("CARGO_PKG_VERSION"), " (recipe import)");
+
+/// Article text beyond this is cut, so one long page can't fill the context
+pub const MAX_ARTICLE_CHARS: usize = 12_000;
+
+/// Less article text than this isn't a recipe
+const MIN_ARTICLE_CHARS: usize = 200;
+
+/// What the part of a page that's left says when the rest is for subscribers
+static PAYWALL_MARKERS: &[&str] = &[
+    "subscribe to continue",
+    "subscribe to read",
+    "subscribers only",
+    "already a subscriber",
+    "sign in to continue",
+    "log in to continue",
+    "create a free account to",
+];
+
+/// Elements whose text is never part of the recipe
+static SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "nav", "header", "footer", "aside", "form",
+];
+
+/// Elements that start a new line of text
+static BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "br",
+    "li",
+    "ol",
+    "ul",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "tr",
+    "section",
+    "article",
+    "table",
+    "blockquote",
+];
+
+/// Where a recipe came from in the page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extraction {
+    JsonLd,
+    Microdata,
+    /// The page's text, with no structure to go by
+    Article,
+}
+
+impl fmt::Display for Extraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Extraction::JsonLd => write!(f, "schema.org JSON-LD"),
+            Extraction::Microdata => write!(f, "schema.org microdata"),
+            Extraction::Article => write!(f, "the page text"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebRecipe {
+    pub url: String,
+    pub extraction: Extraction,
+    pub title: Option<String>,
+    /// e.g. "4 servings"
+    pub yields: Option<String>,
+    /// e.g. "1 h 30 min"
+    pub total_time: Option<String>,
+    pub ingredients: Vec<String>,
+    pub instructions: Vec<String>,
+    /// The article text, for [Extraction::Article]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    /// Not an http or https URL
+    BadUrl(String),
+    /// Built without the http feature
+    NoHttp,
+    /// No response: DNS, connection, TLS, or the time limit
+    Fetch {
+        url: String,
+        reason: String,
+    },
+    Status {
+        url: String,
+        status: u16,
+    },
+    /// The page wants a login or a subscription
+    Paywalled(String),
+    TooLarge {
+        url: String,
+        limit: usize,
+    },
+    /// The page came back, but without anything that looks like a recipe
+    NoRecipe(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::BadUrl(url) => {
+                write!(f, "{} isn't a web page address (http:// or https://)", url)
+            }
+            ImportError::NoHttp => write!(f, "built without http support (feature http)"),
+            ImportError::Fetch { url, reason } => write!(f, "couldn't fetch {}: {}", url, reason),
+            ImportError::Status { url, status } => {
+                write!(f, "{} answered with HTTP {}", url, status)
+            }
+            ImportError::Paywalled(url) => write!(
+                f,
+                "{} needs a login or subscription; copy the recipe into a prompt instead",
+                url
+            ),
+            ImportError::TooLarge { url, limit } => {
+                write!(f, "{} is larger than {} bytes", url, limit)
+            }
+            ImportError::NoRecipe(url) => write!(
+                f,
+                "found no recipe on {}; copy the recipe into a prompt instead",
+                url
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+fn check_url(url: &str) -> Result<(), ImportError> {
+    let lower = url.trim().to_lowercase();
+    if lower.starts_with("https://") || lower.starts_with("http://") {
+        Ok(())
+    } else {
+        Err(ImportError::BadUrl(url.to_string()))
+    }
+}
+
+#[cfg(not(feature = "http"))]
+pub async fn fetch(url: &str) -> Result<String, ImportError> {
+    check_url(url)?;
+    Err(ImportError::NoHttp)
+}
+
+/// The page at `url`, as text
+#[cfg(feature = "http")]
+pub async fn fetch(url: &str) -> Result<String, ImportError> {
+    check_url(url)?;
+    let failed = |e: reqwest::Error| ImportError::Fetch {
+        url: url.to_string(),
+        reason: if e.is_timeout() {
+            format!("no response within {}s", FETCH_TIMEOUT.as_secs())
+        } else {
+            e.to_string()
+        },
+    };
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(failed)?;
+    let mut response = client.get(url.trim()).send().await.map_err(failed)?;
+    let status = response.status();
+    if matches!(status.as_u16(), 401 | 402) {
+        return Err(ImportError::Paywalled(url.to_string()));
+    }
+    if !status.is_success() {
+        return Err(ImportError::Status {
+            url: url.to_string(),
+            status: status.as_u16(),
+        });
+    }
+    let too_large = || ImportError::TooLarge {
+        url: url.to_string(),
+        limit: MAX_PAGE_BYTES,
+    };
+    if response
+        .content_length()
+        .is_some_and(|n| n > MAX_PAGE_BYTES as u64)
+    {
+        return Err(too_large());
+    }
+    let mut bytes = vec![];
+    while let Some(chunk) = response.chunk().await.map_err(failed)? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() > MAX_PAGE_BYTES {
+            return Err(too_large());
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The recipe in a page: JSON-LD, then microdata, then the article text
+pub fn extract(url: &str, html: &str) -> Result<WebRecipe, ImportError> {
+    let structured = from_json_ld(html)
+        .map(|r| (Extraction::JsonLd, r))
+        .or_else(|| from_microdata(html).map(|r| (Extraction::Microdata, r)));
+    if let Some((extraction, recipe)) = structured {
+        return Ok(WebRecipe {
+            url: url.to_string(),
+            extraction,
+            ..recipe
+        });
+    }
+    let text = article_text(html);
+    let lower = text.to_lowercase();
+    if text.chars().count() < MIN_ARTICLE_CHARS || !lower.contains("ingredient") {
+        return Err(if PAYWALL_MARKERS.iter().any(|m| lower.contains(m)) {
+            ImportError::Paywalled(url.to_string())
+        } else {
+            ImportError::NoRecipe(url.to_string())
+        });
+    }
+    let title = element(html, "h1", 0)
+        .or_else(|| element(html, "title", 0))
+        .map(|(_, inner)| inline_text(inner))
+        .filter(|t| !t.is_empty());
+    Ok(WebRecipe {
+        url: url.to_string(),
+        extraction: Extraction::Article,
+        title,
+        yields: None,
+        total_time: None,
+        ingredients: vec![],
+        instructions: vec![],
+        text: Some(clip(&text, MAX_ARTICLE_CHARS)),
+    })
+}
+
+impl WebRecipe {
+    /// A few lines for the user to confirm before anything is sent
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "{} (from {})",
+            self.title.as_deref().unwrap_or("untitled recipe"),
+            self.extraction
+        )];
+        let mut facts = vec![];
+        if let Some(text) = &self.text {
+            facts.push(format!("{} characters of page text", text.chars().count()));
+        } else {
+            facts.push(format!(
+                "{} ingredients, {} steps",
+                self.ingredients.len(),
+                self.instructions.len()
+            ));
+        }
+        if let Some(yields) = &self.yields {
+            facts.push(format!("makes {}", yields));
+        }
+        if let Some(time) = &self.total_time {
+            facts.push(format!("ready in {}", time));
+        }
+        lines.push(facts.join(", "));
+        if !self.ingredients.is_empty() {
+            let first: Vec<&str> = self
+                .ingredients
+                .iter()
+                .take(5)
+                .map(String::as_str)
+                .collect();
+            let more = match self.ingredients.len().saturating_sub(first.len()) {
+                0 => String::new(),
+                n => format!(", and {} more", n),
+            };
+            lines.push(format!("ingredients: {}{}", first.join("; "), more));
+        }
+        lines.join("\n")
+    }
+
+    /// The recipe as plain text, in the sections our recipes use
+    pub fn to_text(&self) -> String {
+        let mut out = vec![];
+        if let Some(title) = &self.title {
+            // the article text usually starts with it already
+            if !self
+                .text
+                .as_ref()
+                .is_some_and(|t| t.starts_with(title.as_str()))
+            {
+                out.push(title.clone());
+            }
+        }
+        if let Some(yields) = &self.yields {
+            out.push(format!("Makes: {}", yields));
+        }
+        if let Some(time) = &self.total_time {
+            out.push(format!("Total time: {}", time));
+        }
+        if !self.ingredients.is_empty() {
+            let lines: Vec<String> = self
+                .ingredients
+                .iter()
+                .map(|i| format!("- {}", i))
+                .collect();
+            out.push(format!("\nIngredients:\n{}", lines.join("\n")));
+        }
+        if !self.instructions.is_empty() {
+            let lines: Vec<String> = self
+                .instructions
+                .iter()
+                .enumerate()
+                .map(|(idx, step)| format!("{}. {}", idx + 1, step))
+                .collect();
+            out.push(format!("\nInstructions:\n{}", lines.join("\n")));
+        }
+        if let Some(text) = &self.text {
+            out.push(format!("\n{}", text));
+        }
+        out.join("\n")
+    }
+}
+
+/// Filled in by the extractors, which leave the url to [extract]
+fn empty() -> WebRecipe {
+    WebRecipe {
+        url: String::new(),
+        extraction: Extraction::JsonLd,
+        title: None,
+        yields: None,
+        total_time: None,
+        ingredients: vec![],
+        instructions: vec![],
+        text: None,
+    }
+}
+
+fn from_json_ld(html: &str) -> Option<WebRecipe> {
+    let lower = html.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some((start, inner)) = element(html, "script", from) {
+        from = start + 1;
+        let open = &lower[start..start + lower[start..].find('>').unwrap_or(0)];
+        if !open.contains("ld+json") {
+            continue;
+        }
+        let Ok(value) = serde_json::from_str::<Value>(inner.trim()) else {
+            continue;
+        };
+        if let Some(recipe) = find_recipe(&value) {
+            return Some(from_schema(recipe));
+        }
+    }
+    None
+}
+
+/// The first object typed Recipe, at the top, in `@graph`, or as a page's
+/// `mainEntity`
+fn find_recipe(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Array(items) => items.iter().find_map(find_recipe),
+        Value::Object(map) => {
+            let is_recipe = match map.get("@type") {
+                Some(Value::String(t)) => is_recipe_type(t),
+                Some(Value::Array(types)) => {
+                    types.iter().any(|t| t.as_str().is_some_and(is_recipe_type))
+                }
+                _ => false,
+            };
+            if is_recipe {
+                return Some(value);
+            }
+            map.get("@graph")
+                .and_then(find_recipe)
+                .or_else(|| map.get("mainEntity").and_then(find_recipe))
+        }
+        _ => None,
+    }
+}
+
+fn is_recipe_type(t: &str) -> bool {
+    t == "Recipe" || t.ends_with("/Recipe")
+}
+
+fn from_schema(recipe: &Value) -> WebRecipe {
+    let mut out = empty();
+    out.title = recipe
+        .get("name")
+        .and_then(Value::as_str)
+        .map(inline_text)
+        .filter(|t| !t.is_empty());
+    out.yields = match recipe.get("recipeYield") {
+        Some(Value::Array(items)) => items.iter().find_map(scalar),
+        Some(other) => scalar(other),
+        None => None,
+    };
+    out.total_time = recipe
+        .get("totalTime")
+        .and_then(Value::as_str)
+        .map(|t| format_duration(t).unwrap_or_else(|| t.to_string()));
+    if let Some(Value::Array(items)) = recipe
+        .get("recipeIngredient")
+        .or_else(|| recipe.get("ingredients"))
+    {
+        out.ingredients = items
+            .iter()
+            .filter_map(Value::as_str)
+            .map(inline_text)
+            .filter(|i| !i.is_empty())
+            .collect();
+    }
+    if let Some(instructions) = recipe.get("recipeInstructions") {
+        steps(instructions, &mut out.instructions);
+    }
+    out
+}
+
+/// A string or number as text
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(inline_text(s)).filter(|s| !s.is_empty()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Flattens recipeInstructions: a string, a list of strings, HowToSteps,
+/// or HowToSections of them, with each section's name as a step of its own
+fn steps(value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::String(text) => out.extend(step_lines(text)),
+        Value::Array(items) => items.iter().for_each(|item| steps(item, out)),
+        Value::Object(map) => {
+            if let Some(items) = map.get("itemListElement") {
+                if let Some(name) = map.get("name").and_then(Value::as_str) {
+                    out.push(format!("{}:", inline_text(name)));
+                }
+                steps(items, out);
+            } else if let Some(text) = map.get("text").or_else(|| map.get("name")) {
+                steps(text, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn from_microdata(html: &str) -> Option<WebRecipe> {
+    let lower = html.to_ascii_lowercase();
+    let at = lower
+        .find("schema.org/recipe\"")
+        .or_else(|| lower.find("schema.org/recipe'"))?;
+    let mut out = empty();
+    let mut from = at;
+    while let Some(found) = lower[from..].find("itemprop=") {
+        let attr = from + found;
+        from = attr + 1;
+        let Some(prop) = attribute_at(html, attr + "itemprop=".len()) else {
+            continue;
+        };
+        let Some(start) = lower[..attr].rfind('<') else {
+            continue;
+        };
+        let open_end = start + lower[start..].find('>').unwrap_or(lower.len() - start);
+        let open = &html[start..open_end];
+        // meta and time elements carry their value in an attribute
+        let (value, inner) =
+            match attribute(open, "content").or_else(|| attribute(open, "datetime")) {
+                Some(value) => (inline_text(&value), None),
+                None => match element(html, &tag_name(open), start) {
+                    Some((_, inner)) => (inline_text(inner), Some(inner)),
+                    None => continue,
+                },
+            };
+        match prop.as_str() {
+            "name" if out.title.is_none() => out.title = Some(value).filter(|v| !v.is_empty()),
+            "recipeYield" if out.yields.is_none() => out.yields = Some(value),
+            "totalTime" if out.total_time.is_none() => {
+                out.total_time = Some(format_duration(&value).unwrap_or(value))
+            }
+            "recipeIngredient" | "ingredients" => out.ingredients.push(value),
+            "recipeInstructions" => match inner {
+                Some(inner) => out.instructions.extend(step_lines(inner)),
+                None => out.instructions.push(value),
+            },
+            _ => {}
+        }
+    }
+    out.ingredients.retain(|i| !i.is_empty());
+    (!out.ingredients.is_empty() || !out.instructions.is_empty()).then_some(out)
+}
+
+/// The name of the element an opening tag starts, lowercase
+fn tag_name(open: &str) -> String {
+    open.trim_start_matches('<')
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// A quoted attribute value starting at `at`, just after the `=`
+fn attribute_at(html: &str, at: usize) -> Option<String> {
+    let rest = html.get(at..)?;
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let end = rest[1..].find(quote)?;
+    Some(decode_entities(&rest[1..1 + end]))
+}
+
+/// The value of `name` in an opening tag
+fn attribute(open: &str, name: &str) -> Option<String> {
+    let lower = open.to_ascii_lowercase();
+    let pattern = format!(" {}=", name);
+    let at = lower.find(&pattern)? + pattern.len();
+    attribute_at(open, at)
+}
+
+/// The first `tag` element at or after `from`, as the offset of its
+/// opening tag and its inner HTML.  Nested elements of the same name are
+/// counted, so a div's inner HTML runs to its own closing tag.
+fn element<'a>(html: &'a str, tag: &str, from: usize) -> Option<(usize, &'a str)> {
+    let lower = html.to_ascii_lowercase();
+    let open_pattern = format!("<{}", tag);
+    let close_pattern = format!("</{}", tag);
+    let is_tag_at = |at: usize, pattern: &str| {
+        lower[at..].starts_with(pattern)
+            && lower[at + pattern.len()..]
+                .chars()
+                .next()
+                .is_some_and(|c| !c.is_ascii_alphanumeric())
+    };
+    let mut start = from;
+    let start = loop {
+        let at = start + lower.get(start..)?.find(&open_pattern)?;
+        if is_tag_at(at, &open_pattern) {
+            break at;
+        }
+        start = at + 1;
+    };
+    let inner_start = start + lower[start..].find('>')? + 1;
+    let mut depth = 1;
+    let mut at = inner_start;
+    while let Some(found) = lower[at..].find('<') {
+        let tag_at = at + found;
+        if is_tag_at(tag_at, &close_pattern) {
+            depth -= 1;
+            if depth == 0 {
+                return Some((start, &html[inner_start..tag_at]));
+            }
+        } else if is_tag_at(tag_at, &open_pattern) {
+            depth += 1;
+        }
+        at = tag_at + 1;
+    }
+    Some((start, &html[inner_start..]))
+}
+
+/// The text of the page's article, or its main content, or its body
+fn article_text(html: &str) -> String {
+    let content = ["article", "main", "body"]
+        .iter()
+        .find_map(|tag| element(html, tag, 0))
+        .map_or(html, |(_, inner)| inner);
+    html_to_text(content)
+}
+
+/// Text with a line per block element and list items as `- ` lines, and
+/// skipped elements and comments left out
+pub fn html_to_text(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::new();
+    let mut at = 0;
+    while at < html.len() {
+        let Some(found) = lower[at..].find('<') else {
+            out.push_str(&html[at..]);
+            break;
+        };
+        let tag_at = at + found;
+        out.push_str(&html[at..tag_at]);
+        if lower[tag_at..].starts_with("<!--") {
+            at = lower[tag_at..]
+                .find("-->")
+                .map_or(html.len(), |end| tag_at + end + 3);
+            continue;
+        }
+        let Some(end) = lower[tag_at..].find('>') else {
+            break;
+        };
+        let open = &lower[tag_at..tag_at + end + 1];
+        let name = tag_name(open.trim_start_matches("</"));
+        at = tag_at + end + 1;
+        if !open.starts_with("</") && SKIPPED.contains(&name.as_str()) {
+            let close = format!("</{}", name);
+            at = lower[at..].find(&close).map_or(html.len(), |end| {
+                let after = at + end;
+                after + lower[after..].find('>').map_or(0, |gt| gt + 1)
+            });
+            continue;
+        }
+        if name == "li" && !open.starts_with("</") {
+            out.push_str("\n- ");
+        } else if BLOCKS.contains(&name.as_str()) {
+            out.push('\n');
+        } else {
+            out.push(' ');
+        }
+    }
+    decode_entities(&out)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty() && line != "-")
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// One step per list item if `html` is a list, and otherwise per line
+fn step_lines(html: &str) -> Vec<String> {
+    let mut items = vec![];
+    let mut from = 0;
+    while let Some((start, inner)) = element(html, "li", from) {
+        items.push(inline_text(inner));
+        // past this item, so a list nested in it isn't read twice
+        from = start + html[start..].find('>').map_or(0, |gt| gt + 1) + inner.len();
+    }
+    if items.is_empty() {
+        items = html_to_text(html).lines().map(str::to_string).collect();
+    }
+    items.retain(|i| !i.is_empty());
+    items
+}
+
+/// Text on one line, for names and ingredients
+fn inline_text(html: &str) -> String {
+    html_to_text(html)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&semi| semi <= 10)
+            .and_then(|semi| Some((entity(&rest[1..1 + semi])?, semi + 2)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "deg" => '°',
+        "frac12" => '½',
+        "frac14" => '¼',
+        "frac34" => '¾',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "hellip" => '…',
+        "times" => '×',
+        _ => return None,
+    })
+}
+
+/// An ISO 8601 duration such as `PT1H30M` as "1 h 30 min"
+fn format_duration(iso: &str) -> Option<String> {
+    let rest = iso.trim().strip_prefix('P')?;
+    let (days, time) = match rest.split_once('T') {
+        Some((days, time)) => (days, time),
+        None => (rest, ""),
+    };
+    let mut minutes = 0u64;
+    for (part, units) in [
+        (days, [('D', 24 * 60), ('W', 7 * 24 * 60)]),
+        (time, [('H', 60), ('M', 1)]),
+    ] {
+        let mut number = String::new();
+        for c in part.chars() {
+            match units.iter().find(|(unit, _)| *unit == c) {
+                Some((_, scale)) => {
+                    minutes += number.parse::<f64>().ok()?.round() as u64 * scale;
+                    number.clear();
+                }
+                None if c.is_ascii_digit() || c == '.' => number.push(c),
+                // seconds don't matter in a recipe
+                None if c == 'S' => number.clear(),
+                None => return None,
+            }
+        }
+    }
+    Some(match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{} min", m),
+        (h, 0) => format!("{} h", h),
+        (h, m) => format!("{} h {} min", h, m),
+    })
+}
+
+fn clip(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((idx, _)) => format!("{}\n[cut off]", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    const URL: &str = "https://food.example.com/recipe";
+
+    fn page(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/lib/testdata/webimport")
+            .join(name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn json_ld_in_a_graph_with_sections() {
+        let recipe = extract(URL, &page("json_ld_graph.html")).unwrap();
+        assert_eq!(recipe.url, URL);
+        assert_eq!(recipe.extraction, Extraction::JsonLd);
+        assert_eq!(recipe.title.as_deref(), Some("Weeknight Chili & Cornbread"));
+        assert_eq!(recipe.yields.as_deref(), Some("6"));
+        assert_eq!(recipe.total_time.as_deref(), Some("1 h 15 min"));
+        assert_eq!(
+            recipe.ingredients,
+            strings(&[
+                "1 lb ground beef",
+                "2 (15 oz) cans kidney beans",
+                "1½ cups cornmeal",
+            ])
+        );
+        assert_eq!(
+            recipe.instructions,
+            strings(&[
+                "Chili:",
+                "Brown the beef.",
+                "Add the beans and simmer for 45 minutes.",
+                "Cornbread:",
+                "Bake at 400°F for 20 minutes.",
+            ])
+        );
+        assert_eq!(recipe.text, None);
+        assert_eq!(
+            recipe.summary(),
+            "Weeknight Chili & Cornbread (from schema.org JSON-LD)\n\
+             3 ingredients, 5 steps, makes 6, ready in 1 h 15 min\n\
+             ingredients: 1 lb ground beef; 2 (15 oz) cans kidney beans; 1½ cups cornmeal"
+        );
+        assert_eq!(
+            recipe.to_text(),
+            "Weeknight Chili & Cornbread\n\
+             Makes: 6\n\
+             Total time: 1 h 15 min\n\
+             \n\
+             Ingredients:\n\
+             - 1 lb ground beef\n\
+             - 2 (15 oz) cans kidney beans\n\
+             - 1½ cups cornmeal\n\
+             \n\
+             Instructions:\n\
+             1. Chili:\n\
+             2. Brown the beef.\n\
+             3. Add the beans and simmer for 45 minutes.\n\
+             4. Cornbread:\n\
+             5. Bake at 400°F for 20 minutes."
+        );
+    }
+
+    #[test]
+    fn json_ld_typed_with_a_list_after_a_broken_script() {
+        let recipe = extract(URL, &page("json_ld_type_array.html")).unwrap();
+        assert_eq!(recipe.extraction, Extraction::JsonLd);
+        assert_eq!(recipe.title.as_deref(), Some("Lemon Bars"));
+        assert_eq!(recipe.yields.as_deref(), Some("16"));
+        assert_eq!(recipe.total_time.as_deref(), Some("24 h 30 min"));
+        assert_eq!(
+            recipe.ingredients,
+            strings(&["1 cup butter", "4 eggs", "2 lemons"])
+        );
+        assert_eq!(
+            recipe.instructions,
+            strings(&[
+                "Make the crust.",
+                "Whisk the lemon filling together.",
+                "Bake and chill overnight.",
+            ])
+        );
+    }
+
+    #[test]
+    fn microdata() {
+        let recipe = extract(URL, &page("microdata.html")).unwrap();
+        assert_eq!(recipe.extraction, Extraction::Microdata);
+        assert_eq!(recipe.title.as_deref(), Some("Fluffy Pancakes"));
+        assert_eq!(recipe.yields.as_deref(), Some("8 pancakes"));
+        assert_eq!(recipe.total_time.as_deref(), Some("25 min"));
+        assert_eq!(
+            recipe.ingredients,
+            strings(&["1½ cups flour", "2 tbsp sugar", "1 cup milk"])
+        );
+        assert_eq!(
+            recipe.instructions,
+            strings(&[
+                "Whisk the dry ingredients.",
+                "Stir in the milk.",
+                "Cook on a hot griddle.",
+            ])
+        );
+    }
+
+    #[test]
+    fn article_text_without_the_rest_of_the_page() {
+        let recipe = extract(URL, &page("article.html")).unwrap();
+        assert_eq!(recipe.extraction, Extraction::Article);
+        assert_eq!(recipe.title.as_deref(), Some("Grandma’s Tomato Soup"));
+        assert!(recipe.ingredients.is_empty() && recipe.instructions.is_empty());
+        assert_eq!(
+            recipe.text.as_deref(),
+            Some(
+                "Grandma’s Tomato Soup\n\
+                 This is the soup my grandmother made every winter, and it only takes half an hour.\n\
+                 Ingredients\n\
+                 - 2 lb ripe tomatoes\n\
+                 - 1 onion, chopped\n\
+                 - 2 cups stock\n\
+                 Method\n\
+                 Soften the onion in butter, add the tomatoes and stock, and simmer for 20 minutes.\n\
+                 Blend until smooth."
+            )
+        );
+        // the title starts the text, so it isn't repeated
+        assert!(recipe.to_text().starts_with("\nGrandma’s Tomato Soup\n"));
+    }
+
+    #[test]
+    fn pages_without_a_recipe() {
+        assert_eq!(
+            extract(URL, &page("paywall.html")),
+            Err(ImportError::Paywalled(URL.to_string()))
+        );
+        assert_eq!(
+            extract(URL, &page("no_recipe.html")),
+            Err(ImportError::NoRecipe(URL.to_string()))
+        );
+    }
+
+    #[test]
+    fn durations() {
+        for (iso, expected) in [
+            ("PT45M", Some("45 min")),
+            ("PT2H", Some("2 h")),
+            ("PT1H15M", Some("1 h 15 min")),
+            ("PT1H30M30S", Some("1 h 30 min")),
+            ("PT90M", Some("1 h 30 min")),
+            ("P1DT30M", Some("24 h 30 min")),
+            ("PT0.5H", Some("1 h")),
+            (" PT10M ", Some("10 min")),
+            ("45 minutes", None),
+            ("PT1X", None),
+        ] {
+            assert_eq!(format_duration(iso).as_deref(), expected, "{:?}", iso);
+        }
+    }
+
+    #[test]
+    fn urls() {
+        for (url, ok) in [
+            ("https://example.com/r", true),
+            ("HTTP://example.com", true),
+            ("  https://example.com", true),
+            ("ftp://example.com", false),
+            ("example.com/recipe", false),
+            ("file:///etc/passwd", false),
+        ] {
+            assert_eq!(check_url(url).is_ok(), ok, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn entities() {
+        for (text, expected) in [
+            ("Mac &amp; cheese", "Mac & cheese"),
+            ("350&deg;F", "350°F"),
+            ("&#189; cup", "½ cup"),
+            ("&#x2153; cup", "⅓ cup"),
+            ("salt &amp pepper", "salt &amp pepper"),
+            ("&unknown;", "&unknown;"),
+            ("AT&T", "AT&T"),
+        ] {
+            assert_eq!(decode_entities(text), expected, "{:?}", text);
+        }
+    }
+}
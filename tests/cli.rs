@@ -0,0 +1,7 @@
+//! The command line's help and argument errors, from the cases in
+//! tests/cmd.  These run without AWS: clap answers before anything is
+//! loaded.
+#[test]
+fn cli() {
+    trycmd::TestCases::new().case("tests/cmd/*.toml");
+}